@@ -3,6 +3,8 @@ use crate::config_service::ConfigService;
 use crate::router::{start_router, RouterHandle};
 use crate::terminal::Terminal;
 use crate::terminal_state::TerminalState;
+use shell_parser::ast::Span;
+use shell_parser::Completion;
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen_futures::spawn_local;
@@ -17,6 +19,22 @@ struct SubmitState {
     input: UseStateHandle<String>,
 }
 
+#[derive(Clone)]
+struct CompleteState {
+    terminal: Rc<RefCell<Option<Terminal>>>,
+    input: UseStateHandle<String>,
+    cycle: Rc<RefCell<Option<CompletionCycle>>>,
+}
+
+/// Candidates that repeated Tab presses step through.
+struct CompletionCycle {
+    completion: Completion,
+    /// Candidate currently inserted; `None` while the common prefix is shown.
+    index: Option<usize>,
+    /// Prompt text after the last insertion; any edit ends the cycle.
+    shown: String,
+}
+
 #[function_component(App)]
 pub fn app() -> Html {
     let terminal_state = use_reducer(TerminalState::default);
@@ -24,6 +42,7 @@ pub fn app() -> Html {
     let terminal = use_mut_ref(|| Option::<Terminal>::None);
     let terminal_ready = use_state(|| false);
     let router_handle = use_mut_ref(|| Option::<RouterHandle>::None);
+    let completion_cycle = use_mut_ref(|| Option::<CompletionCycle>::None);
 
     {
         let terminal = terminal.clone();
@@ -64,7 +83,32 @@ pub fn app() -> Html {
         Callback::from(move |_| handle_submit(submit_state.clone()))
     };
 
+    let on_complete = {
+        let complete_state = CompleteState {
+            terminal: terminal.clone(),
+            input: input.clone(),
+            cycle: completion_cycle.clone(),
+        };
+        Callback::from(move |cursor: usize| handle_complete(complete_state.clone(), cursor))
+    };
+
+    let on_interrupt = {
+        let terminal = terminal.clone();
+        let input = input.clone();
+        Callback::from(move |_| {
+            input.set(String::new());
+            if let Some(terminal) = terminal.borrow().as_ref() {
+                terminal.interrupt();
+            }
+        })
+    };
+
     let displayed_lines = (*terminal_state).lines.clone();
+    let highlights = terminal
+        .borrow()
+        .as_ref()
+        .map(|terminal| terminal.highlight(&input))
+        .unwrap_or_default();
 
     let on_history_nav = {
         let terminal = terminal.clone();
@@ -105,9 +149,12 @@ pub fn app() -> Html {
             <TerminalWindow
                 lines={displayed_lines}
                 input={(*input).clone()}
+                highlights={highlights}
                 on_input={on_input}
                 on_submit={on_submit}
                 on_history_nav={on_history_nav}
+                on_complete={on_complete}
+                on_interrupt={on_interrupt}
                 show_window={show_window}
             />
         </>
@@ -128,3 +175,63 @@ fn handle_submit(state: SubmitState) {
 
     terminal.process_command(trimmed);
 }
+
+/// Complete the word before `cursor`.
+///
+/// A single candidate is inserted outright. Several candidates are listed in
+/// the output and their common prefix inserted; pressing Tab again cycles
+/// through them in place.
+fn handle_complete(state: CompleteState, cursor: usize) {
+    let value = (*state.input).clone();
+    let mut cycle = state.cycle.borrow_mut();
+
+    if let Some(current) = cycle.as_mut().filter(|current| current.shown == value) {
+        let candidates = &current.completion.candidates;
+        let index = current
+            .index
+            .map_or(0, |index| (index + 1) % candidates.len());
+        let candidate = &candidates[index];
+        let replace = current.completion.replace;
+        let next = splice(&value, replace, candidate);
+        current.completion.replace = Span::new(replace.start, replace.start + candidate.len());
+        current.index = Some(index);
+        current.shown = next.clone();
+        state.input.set(next);
+        return;
+    }
+    *cycle = None;
+
+    let Some(terminal) = state.terminal.borrow().clone() else {
+        return;
+    };
+    let completion = terminal.complete(&value, cursor);
+    let replace = completion.replace;
+    match completion.candidates.as_slice() {
+        [] => {}
+        [candidate] => {
+            let separator = if candidate.ends_with('/') { "" } else { " " };
+            state
+                .input
+                .set(splice(&value, replace, &format!("{candidate}{separator}")));
+        }
+        candidates => {
+            terminal.push_text(candidates.join("  "));
+            let prefix = completion.common_prefix().to_string();
+            let next = splice(&value, replace, &prefix);
+            *cycle = Some(CompletionCycle {
+                completion: Completion {
+                    replace: Span::new(replace.start, replace.start + prefix.len()),
+                    candidates: completion.candidates,
+                },
+                index: None,
+                shown: next.clone(),
+            });
+            state.input.set(next);
+        }
+    }
+}
+
+/// `value` with the bytes in `span` replaced by `text`.
+fn splice(value: &str, span: Span, text: &str) -> String {
+    format!("{}{text}{}", &value[..span.start], &value[span.end..])
+}
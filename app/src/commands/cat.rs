@@ -1,63 +1,79 @@
 use crate::commands::fetch::fetch_text_with_cache;
 use crate::commands::{parse_cli, CommandContext};
-use crate::utils::run_async;
+use crate::utils::with_loading;
 use crate::vfs_data::{find_node, format_path, resolve_path, VfsKind};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
-use yew::html;
+use shell_parser::{CommandIo, ExitStatus};
 
+/// Print file contents.
 #[derive(Parser, Debug, Default)]
-#[command(name = "cat", about = "Print file contents")]
+#[command(name = "cat")]
 pub struct CatCommand {
-    #[arg(positional, help = "Path to file")]
-    path: String,
+    /// Path to file; reads piped input when omitted.
+    #[arg(positional)]
+    path: Option<String>,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for CatCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<CatCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<CatCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
-        let ctx = ctx.clone();
-        run_async(ctx.clone(), async move {
-            run_cat(cli, ctx).await;
-        });
-        Ok(())
+        let Some(target) = cli.path.as_deref() else {
+            let Some(input) = io.stdin.take() else {
+                io.stderr.write_line("cat: missing file operand");
+                return Ok(ExitStatus::FAILURE);
+            };
+            io.stdout
+                .write_line(input.strip_suffix('\n').unwrap_or(&input));
+            return Ok(ExitStatus::SUCCESS);
+        };
+        let path = resolve_path(&ctx.terminal.cwd(), target);
+        if let Some(contents) = ctx.fs.file(&path) {
+            io.stdout
+                .write_line(contents.strip_suffix('\n').unwrap_or(&contents));
+            return Ok(ExitStatus::SUCCESS);
+        }
+        match with_loading(&ctx.terminal, read_data_file(&path, ctx)).await {
+            Ok(text) => {
+                io.stdout
+                    .write_line(text.strip_suffix('\n').unwrap_or(&text));
+                Ok(ExitStatus::SUCCESS)
+            }
+            Err(err) => {
+                io.stderr.write_line(format!("cat: {err}"));
+                Ok(ExitStatus::FAILURE)
+            }
+        }
     }
 }
 
-async fn run_cat(cli: CatCommand, ctx: CommandContext) {
-    let target = &cli.path;
-
-    let path = resolve_path(&ctx.terminal.cwd(), target);
-    let Some(node) = find_node(&ctx.vfs, &path) else {
-        ctx.terminal
-            .push_error(format!("cat: {}: no such file", format_path(&path)));
-        return;
+/// Contents of a file from the site data, fetched through the cache.
+pub(crate) async fn read_data_file(
+    path: &[String],
+    ctx: &CommandContext,
+) -> Result<String, String> {
+    let Some(node) = find_node(&ctx.vfs, path) else {
+        return Err(format!("{}: no such file", format_path(path)));
     };
 
     if node.kind != VfsKind::File {
-        ctx.terminal
-            .push_error(format!("cat: {}: is a directory", format_path(&path)));
-        return;
+        return Err(format!("{}: is a directory", format_path(path)));
     }
 
     let Some(cache) = ctx.cache.clone() else {
-        ctx.terminal
-            .push_error("cat: cache unavailable (OPFS init failed)");
-        return;
+        return Err("cache unavailable (OPFS init failed)".into());
     };
 
     let uri = format!("/data/{}", path.join("/"));
-
-    match fetch_text_with_cache(&uri, &cache).await {
-        Ok(text) => {
-            ctx.terminal.push_component(html! {
-                <span class="whitespace-break-spaces">{text}</span>
-            });
-        }
-        Err(err) => {
-            ctx.terminal.push_error(format!("cat: {err}"));
-        }
-    }
+    fetch_text_with_cache(&uri, &cache, &ctx.cancellation()).await
 }
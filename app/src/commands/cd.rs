@@ -1,35 +1,48 @@
 use crate::commands::{parse_cli, CommandContext};
 use crate::vfs_data::{find_node, format_path, resolve_path, VfsKind};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 
+/// Change directory.
 #[derive(Parser, Debug, Default)]
-#[command(name = "cd", about = "Change directory")]
+#[command(name = "cd")]
 pub struct CdCommand {
-    #[arg(positional, help = "Directory path")]
+    /// Directory path.
+    #[arg(positional)]
     path: Option<String>,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for CdCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<CdCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<CdCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
         let target = cli.path.as_deref().unwrap_or("/");
         let path = resolve_path(&ctx.terminal.cwd(), target);
         match find_node(&ctx.vfs, &path) {
             Some(node) if node.kind == VfsKind::Directory => {
                 ctx.terminal.set_cwd(path.clone());
+                Ok(ExitStatus::SUCCESS)
             }
             Some(_) => {
-                ctx.terminal
-                    .push_error(format!("cd: {}: not a directory", format_path(&path)));
+                io.stderr
+                    .write_line(format!("cd: {}: not a directory", format_path(&path)));
+                Ok(ExitStatus::FAILURE)
             }
             None => {
-                ctx.terminal
-                    .push_error(format!("cd: {}: no such directory", format_path(&path)));
+                io.stderr
+                    .write_line(format!("cd: {}: no such directory", format_path(&path)));
+                Ok(ExitStatus::FAILURE)
             }
         }
-        Ok(())
     }
 }
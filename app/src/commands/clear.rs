@@ -1,22 +1,29 @@
 use crate::commands::{parse_cli, CommandContext};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 
+/// Clear the terminal.
 #[derive(Parser, Debug, Default)]
-#[command(name = "clear", about = "Clear the terminal")]
+#[command(name = "clear")]
 pub struct ClearCommand {
-    #[arg(
-        short = 'n',
-        long = "number",
-        help = "Clear the {number} output counting from the end"
-    )]
+    /// Clear the Nth output counting from the end.
+    #[arg(short = 'n', long = "number", value_name = "N")]
     num: Option<usize>,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for ClearCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<ClearCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<ClearCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
 
         if let Some(num) = cli.num {
@@ -25,6 +32,6 @@ impl ExecutableCommand<CommandContext> for ClearCommand {
             ctx.terminal.clear(None);
         }
 
-        Ok(())
+        Ok(ExitStatus::SUCCESS)
     }
 }
@@ -1,19 +1,30 @@
 use crate::commands::{parse_cli, CommandContext};
 use crate::vfs_data::{du_bytes, find_node, format_path, resolve_path};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 
+/// Disk usage.
 #[derive(Parser, Debug, Default)]
-#[command(name = "du", about = "Disk usage")]
+#[command(name = "du")]
 pub struct DuCommand {
-    #[arg(positional, help = "Path to inspect")]
+    /// Path to inspect.
+    #[arg(positional)]
     path: Option<String>,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for DuCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<DuCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<DuCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
         let target = cli.path.as_deref().unwrap_or(".");
         let path = resolve_path(&ctx.terminal.cwd(), target);
@@ -21,14 +32,15 @@ impl ExecutableCommand<CommandContext> for DuCommand {
         match find_node(&ctx.vfs, &path) {
             Some(node) => {
                 let bytes = du_bytes(node);
-                ctx.terminal
-                    .push_text(format!("{} => {} bytes", format_path(&path), bytes));
+                io.stdout
+                    .write_line(format!("{} => {} bytes", format_path(&path), bytes));
+                Ok(ExitStatus::SUCCESS)
             }
             None => {
-                ctx.terminal
-                    .push_error(format!("du: {}: not found", format_path(&path)));
+                io.stderr
+                    .write_line(format!("du: {}: not found", format_path(&path)));
+                Ok(ExitStatus::FAILURE)
             }
         }
-        Ok(())
     }
 }
@@ -1,23 +1,34 @@
 use crate::commands::{parse_cli, CommandContext};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 use web_sys::console;
 
+/// Print the given text to the console.
 #[derive(Parser, Debug, Default)]
-#[command(name = "echo", about = "Print the given text to the console")]
+#[command(name = "echo")]
 pub struct EchoCommand {
-    #[arg(positional, help = "Text to echo")]
+    /// Text to echo.
+    #[arg(positional)]
     message: Vec<String>,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for EchoCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<EchoCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<EchoCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
         let msg = cli.message.join(" ");
         console::log_1(&msg.clone().into());
-        ctx.terminal.push_text(msg);
-        Ok(())
+        io.stdout.write_line(msg);
+        Ok(ExitStatus::SUCCESS)
     }
 }
@@ -1,38 +1,43 @@
 use crate::commands::{parse_cli, CommandContext};
+use async_trait::async_trait;
 use js_sys::encode_uri_component;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 use web_sys::window;
 
+/// Display or send an email to the configured author.
 #[derive(Parser, Debug, Default)]
-#[command(
-    name = "email",
-    about = "Display or send an email to the configured author"
-)]
+#[command(name = "email")]
 pub struct EmailCommand {
-    #[arg(
-        positional,
-        help = "Optional subject/body to include in the mailto link"
-    )]
+    /// Optional subject/body to include in the mailto link.
+    #[arg(positional)]
     message: Vec<String>,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for EmailCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<EmailCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<EmailCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
 
         let email = ctx.config.author.email.trim();
         if email.is_empty() {
-            ctx.terminal
-                .push_error("email: author.email is empty in App.toml");
-            return Ok(());
+            io.stderr
+                .write_line("email: author.email is empty in App.toml");
+            return Ok(ExitStatus::FAILURE);
         }
 
         if cli.message.is_empty() {
-            ctx.terminal.push_text(email.to_string());
-            return Ok(());
+            io.stdout.write_line(email);
+            return Ok(ExitStatus::SUCCESS);
         }
 
         let subject = cli.message.join(" ");
@@ -41,14 +46,15 @@ impl ExecutableCommand<CommandContext> for EmailCommand {
 
         match window().and_then(|win| win.location().set_href(&mailto).ok()) {
             Some(_) => {
-                ctx.terminal.push_text(format!(
+                io.stdout.write_line(format!(
                     "Opening mailto for {email} with subject \"{subject}\""
                 ));
+                Ok(ExitStatus::SUCCESS)
             }
             None => {
-                ctx.terminal.push_error("email: failed to open mailto link");
+                io.stderr.write_line("email: failed to open mailto link");
+                Ok(ExitStatus::FAILURE)
             }
         }
-        Ok(())
     }
 }
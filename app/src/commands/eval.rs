@@ -1,65 +1,76 @@
-use crate::commands::fetch::fetch_text_with_cache;
+use crate::commands::cat::read_data_file;
 use crate::commands::{parse_cli, CommandContext};
-use crate::vfs_data::{find_node, format_path, resolve_path, VfsKind};
+use crate::terminal::{format_cli_error, TerminalHandle};
+use crate::vfs_data::{format_path, resolve_path};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
-use wasm_bindgen_futures::spawn_local;
+use shell_parser::{CommandIo, ExitStatus, ShellOptions};
 
+/// Execute commands from a file.
+///
+/// The script runs in the current shell, so the variables and functions it
+/// defines stay after it finishes; options it changes with `set` do not.
 #[derive(Parser, Debug, Default)]
-#[command(name = "eval", about = "Execute commands from a file")]
+#[command(name = "eval")]
 pub struct EvalCommand {
-    #[arg(positional, help = "Path to the script file")]
+    /// Path to the script file.
+    #[arg(positional)]
     path: String,
+    /// Arguments passed to the script as $1, $2, ...
+    #[arg(positional)]
+    args: Vec<String>,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for EvalCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<EvalCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<EvalCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
 
-        let ctx = ctx.clone();
-        spawn_local(async move {
-            run_eval(cli, ctx).await;
-        });
-
-        Ok(())
+        let path = resolve_path(&ctx.terminal.cwd(), &cli.path);
+        let text = match read_data_file(&path, ctx).await {
+            Ok(text) => text,
+            Err(err) => {
+                io.stderr.write_line(format!("eval: {err}"));
+                return Ok(ExitStatus::FAILURE);
+            }
+        };
+        let script = text.trim();
+        if script.is_empty() {
+            io.stderr
+                .write_line(format!("eval: {}: file is empty", format_path(&path)));
+            return Ok(ExitStatus::FAILURE);
+        }
+        // Options the script sets with `set` only last until it returns.
+        let _restore = RestoreOptions {
+            terminal: &ctx.terminal,
+            options: ctx.terminal.shell_options(),
+        };
+        let outcome = ctx.terminal.run_script(script, cli.args).await;
+        // Errors go to stderr, so `2>/dev/null` and `2>&1 |` apply to them too.
+        Ok(outcome.unwrap_or_else(|err| {
+            io.stderr.write_line(format_cli_error(err, Some(script)));
+            ExitStatus::FAILURE
+        }))
     }
 }
 
-async fn run_eval(cli: EvalCommand, ctx: CommandContext) {
-    let path = resolve_path(&ctx.terminal.cwd(), &cli.path);
-    let Some(node) = find_node(&ctx.vfs, &path) else {
-        ctx.terminal
-            .push_error(format!("eval: {}: no such file", format_path(&path)));
-        return;
-    };
-
-    if node.kind != VfsKind::File {
-        ctx.terminal
-            .push_error(format!("eval: {}: is a directory", format_path(&path)));
-        return;
-    }
-
-    let Some(cache) = ctx.cache.clone() else {
-        ctx.terminal
-            .push_error("eval: cache unavailable (OPFS init failed)");
-        return;
-    };
+/// Puts the shell options back when the script returns or is cancelled with Ctrl+C.
+struct RestoreOptions<'a> {
+    terminal: &'a TerminalHandle,
+    options: ShellOptions,
+}
 
-    let uri = format!("/data/{}", path.join("/"));
-    match fetch_text_with_cache(&uri, &cache).await {
-        Ok(text) => {
-            let script = text.trim();
-            if script.is_empty() {
-                ctx.terminal
-                    .push_error(format!("eval: {}: file is empty", format_path(&path)));
-                return;
-            }
-            ctx.terminal.execute_command(script);
-        }
-        Err(err) => {
-            ctx.terminal.push_error(format!("eval: {err}"));
-        }
+impl Drop for RestoreOptions<'_> {
+    fn drop(&mut self) {
+        self.terminal.set_shell_options(self.options);
     }
 }
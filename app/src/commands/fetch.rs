@@ -1,23 +1,33 @@
 use crate::cache_service::CacheService;
 use crate::commands::{parse_cli, CommandContext};
+use crate::utils::CancellationToken;
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 use std::rc::Rc;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::{spawn_local, JsFuture};
 
+/// Fetch a remote resource.
 #[derive(Parser, Debug, Default)]
-#[command(name = "fetch", about = "Fetch a remote resource")]
+#[command(name = "fetch")]
 pub struct FetchCommand {
-    #[arg(positional, help = "URI to fetch")]
+    /// URI to fetch.
+    #[arg(positional)]
     uri: String,
 }
 
-pub async fn fetch_text_with_cache(uri: &str, cache: &Rc<CacheService>) -> Result<String, String> {
+pub async fn fetch_text_with_cache(
+    uri: &str,
+    cache: &Rc<CacheService>,
+    cancellation: &CancellationToken,
+) -> Result<String, String> {
     let uri_refresh = uri.to_string();
     let cache_for_refresh = cache.clone();
+    let cancellation_for_refresh = cancellation.clone();
     spawn_local(async move {
-        if let Ok(bytes) = fetch_bytes(&uri_refresh).await {
+        if let Ok(bytes) = fetch_bytes(&uri_refresh, &cancellation_for_refresh).await {
             let _ = cache_for_refresh.put(&uri_refresh, bytes).await;
         }
     });
@@ -26,7 +36,7 @@ pub async fn fetch_text_with_cache(uri: &str, cache: &Rc<CacheService>) -> Resul
         return Ok(bytes_to_text(&bytes));
     }
 
-    match fetch_bytes(uri).await {
+    match fetch_bytes(uri, cancellation).await {
         Ok(bytes) => {
             let text = bytes_to_text(&bytes);
             let _ = cache.put(uri, bytes).await;
@@ -36,33 +46,46 @@ pub async fn fetch_text_with_cache(uri: &str, cache: &Rc<CacheService>) -> Resul
     }
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for FetchCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<FetchCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<FetchCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
-
         let Some(cache) = ctx.cache.clone() else {
-            ctx.terminal
-                .push_error("fetch: cache unavailable (OPFS init failed)");
-            return Ok(());
+            io.stderr
+                .write_line("fetch: cache unavailable (OPFS init failed)");
+            return Ok(ExitStatus::FAILURE);
         };
 
-        let ctx = ctx.clone();
-        spawn_local(async move {
-            match fetch_text_with_cache(&cli.uri, &cache).await {
-                Ok(text) => ctx.terminal.push_text(text),
-                Err(err) => ctx.terminal.push_error(format!("fetch: {err}")),
-            };
-        });
-
-        Ok(())
+        match fetch_text_with_cache(&cli.uri, &cache, &ctx.cancellation()).await {
+            Ok(text) => {
+                io.stdout.write_line(text);
+                Ok(ExitStatus::SUCCESS)
+            }
+            Err(err) => {
+                io.stderr.write_line(format!("fetch: {err}"));
+                Ok(ExitStatus::FAILURE)
+            }
+        }
     }
 }
 
-pub(crate) async fn fetch_bytes(url: &str) -> Result<Vec<u8>, wasm_bindgen::JsValue> {
+/// Fetch `url`, aborting the request if `cancellation` is cancelled.
+pub(crate) async fn fetch_bytes(
+    url: &str,
+    cancellation: &CancellationToken,
+) -> Result<Vec<u8>, wasm_bindgen::JsValue> {
     let window = web_sys::window().ok_or_else(|| js_sys::Error::new("no window"))?;
-    let resp_value = JsFuture::from(window.fetch_with_str(url)).await?;
+    let init = web_sys::RequestInit::new();
+    init.set_signal(cancellation.abort_signal().as_ref());
+    let resp_value = JsFuture::from(window.fetch_with_str_and_init(url, &init)).await?;
     let resp: web_sys::Response = resp_value.dyn_into()?;
 
     if !resp.ok() {
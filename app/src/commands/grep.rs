@@ -0,0 +1,81 @@
+use crate::commands::{parse_cli, CommandContext};
+use async_trait::async_trait;
+use micro_cli::Parser;
+use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
+
+/// Print piped lines containing a pattern.
+#[derive(Parser, Debug, Default)]
+#[command(name = "grep", example = "cat /posts/hello.md | grep -in rust")]
+pub struct GrepCommand {
+    /// Text to search for.
+    #[arg(positional)]
+    pattern: String,
+
+    /// Ignore case distinctions.
+    #[arg(short = 'i', long = "ignore-case")]
+    ignore_case: bool,
+
+    /// Select non-matching lines.
+    #[arg(short = 'v', long = "invert-match")]
+    invert_match: bool,
+
+    /// Prefix each line with its number.
+    #[arg(short = 'n', long = "line-number")]
+    line_number: bool,
+
+    /// Print only the number of selected lines.
+    #[arg(short = 'c', long = "count", conflicts_with = "line_number")]
+    count: bool,
+}
+
+#[async_trait(?Send)]
+impl ExecutableCommand<CommandContext> for GrepCommand {
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<GrepCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
+        };
+        let Some(input) = io.stdin.take() else {
+            io.stderr
+                .write_line("grep: no input; pipe text into grep or redirect a file with <");
+            return Ok(ExitStatus::FAILURE);
+        };
+
+        let pattern = cli.normalize(&cli.pattern);
+        let selected: Vec<(usize, &str)> = input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| cli.normalize(line).contains(&pattern) != cli.invert_match)
+            .collect();
+
+        if cli.count {
+            io.stdout.write_line(selected.len().to_string());
+        } else {
+            for (idx, line) in &selected {
+                if cli.line_number {
+                    io.stdout.write_line(format!("{}:{line}", idx + 1));
+                } else {
+                    io.stdout.write_line(line);
+                }
+            }
+        }
+
+        Ok(ExitStatus::from(!selected.is_empty()))
+    }
+}
+
+impl GrepCommand {
+    fn normalize(&self, text: &str) -> String {
+        if self.ignore_case {
+            text.to_lowercase()
+        } else {
+            text.to_string()
+        }
+    }
+}
@@ -1,21 +1,33 @@
 use crate::commands::CommandContext;
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::ExecutableCommand;
+use shell_parser::{CommandIo, ExitStatus};
 use yew::html;
 
+/// Print the given text to the console.
 #[derive(Parser, Debug, Default)]
-#[command(name = "help", about = "Print the given text to the console")]
+#[command(name = "help")]
 pub struct HelpCommand;
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for HelpCommand {
-    fn run(&self, _args: &[String], ctx: &CommandContext) -> Result<(), String> {
+    async fn run(
+        &self,
+        _args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
         match ctx.terminal.help() {
-            Ok(help_message) => ctx.terminal.push_component(html! {
-                <span class="whitespace-break-spaces">{help_message}</span>
-            }),
+            Ok(help_message) if io.stdout.is_terminal() => {
+                ctx.terminal.push_component(html! {
+                    <span class="whitespace-break-spaces">{help_message}</span>
+                });
+            }
+            Ok(help_message) => io.stdout.write_line(help_message.trim_end()),
             Err(err) => return Err(format!("Failed to get help message: {}", err)),
         };
 
-        Ok(())
+        Ok(ExitStatus::SUCCESS)
     }
 }
@@ -1,27 +1,34 @@
 use crate::commands::{parse_cli, CommandContext};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 use wasm_bindgen::JsValue;
 use web_sys::window;
 
+/// Control the browser history API.
+///
+/// Pushing or replacing an entry only changes the address bar; use
+/// `navigate` to also run the route of the path.
 #[derive(Parser, Debug, Default)]
-#[command(name = "history", about = "Control the browser history API")]
+#[command(
+    name = "history",
+    example = "history --push /posts",
+    example = "history -b"
+)]
+#[group(multiple = false, required = true)]
 pub struct HistoryCommand {
-    #[arg(
-        short = 'p',
-        long = "push",
-        help = "Push a new entry onto the history stack"
-    )]
+    /// Push a new entry onto the history stack.
+    #[arg(short = 'p', long = "push")]
     push: Option<String>,
-    #[arg(
-        short = 'r',
-        long = "replace",
-        help = "Replace the current history entry with the given path"
-    )]
+    /// Replace the current history entry with the given path.
+    #[arg(short = 'r', long = "replace")]
     replace: Option<String>,
-    #[arg(short = 'b', long = "back", help = "Navigate back one entry")]
+    /// Navigate back one entry.
+    #[arg(short = 'b', long = "back")]
     back: bool,
-    #[arg(short = 'f', long = "forward", help = "Navigate forward one entry")]
+    /// Navigate forward one entry.
+    #[arg(short = 'f', long = "forward")]
     forward: bool,
 }
 
@@ -34,58 +41,49 @@ enum HistoryAction {
 
 impl HistoryCommand {
     fn action(&self) -> Result<HistoryAction, String> {
-        let mut action: Option<HistoryAction> = None;
-
-        let mut set_action = |next: HistoryAction| -> Result<(), String> {
-            if action.is_some() {
-                return Err(
-                    "history: specify only one of --push, --replace, --back, or --forward"
-                        .to_string(),
-                );
-            }
-            action = Some(next);
-            Ok(())
-        };
-
         if let Some(path) = self.push.as_deref() {
-            let trimmed = path.trim();
-            if trimmed.is_empty() {
-                return Err("history: --push requires a non-empty path".to_string());
-            }
-            set_action(HistoryAction::Push(trimmed.to_string()))?;
+            return non_empty_path("--push", path).map(HistoryAction::Push);
         }
-
         if let Some(path) = self.replace.as_deref() {
-            let trimmed = path.trim();
-            if trimmed.is_empty() {
-                return Err("history: --replace requires a non-empty path".to_string());
-            }
-            set_action(HistoryAction::Replace(trimmed.to_string()))?;
+            return non_empty_path("--replace", path).map(HistoryAction::Replace);
         }
-
         if self.back {
-            set_action(HistoryAction::Back)?;
+            return Ok(HistoryAction::Back);
         }
-
         if self.forward {
-            set_action(HistoryAction::Forward)?;
+            return Ok(HistoryAction::Forward);
         }
+        // The option group already requires one of them.
+        Err("history: specify --push, --replace, --back or --forward".to_string())
+    }
+}
 
-        action.ok_or_else(|| "history: specify --push, --replace, --back, or --forward".to_string())
+fn non_empty_path(option: &str, path: &str) -> Result<String, String> {
+    let trimmed = path.trim();
+    if trimmed.is_empty() {
+        return Err(format!("history: {option} requires a non-empty path"));
     }
+    Ok(trimmed.to_string())
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for HistoryCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<HistoryCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<HistoryCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
 
         let action = match cli.action() {
             Ok(action) => action,
             Err(err) => {
-                ctx.terminal.push_error(err);
-                return Ok(());
+                io.stderr.write_line(err);
+                return Ok(ExitStatus::FAILURE);
             }
         };
 
@@ -100,14 +98,14 @@ impl ExecutableCommand<CommandContext> for HistoryCommand {
 
         match result {
             Ok(message) => {
-                ctx.terminal.push_text(format!("history: {message}"));
+                io.stdout.write_line(format!("history: {message}"));
+                Ok(ExitStatus::SUCCESS)
             }
             Err(err) => {
-                ctx.terminal.push_error(err);
+                io.stderr.write_line(err);
+                Ok(ExitStatus::FAILURE)
             }
         }
-
-        Ok(())
     }
 }
 
@@ -1,96 +1,118 @@
 use crate::commands::{parse_cli, CommandContext};
 use crate::components::PostItem;
 use crate::vfs_data::{find_node, format_path, resolve_path, VfsKind, VfsNode};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 use std::cmp::Ordering;
 use yew::prelude::*;
 
+/// List directory contents.
 #[derive(Parser, Debug, Default)]
-#[command(name = "ls", about = "List directory contents")]
+#[command(name = "ls")]
 pub struct LsCommand {
-    #[arg(positional, help = "Path to list")]
+    /// Path to list.
+    #[arg(positional)]
     path: Option<String>,
-    #[arg(
-        short = 'p',
-        long = "posts",
-        help = "List markdown posts in the directory"
-    )]
+    /// List markdown posts in the directory.
+    #[arg(short = 'p', long = "posts")]
     posts: bool,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for LsCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<LsCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<LsCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
         let target = cli.path.as_deref().unwrap_or(".");
         let path = resolve_path(&ctx.terminal.cwd(), target);
 
         if cli.posts {
-            return self.list_posts(ctx, &path);
+            return self.list_posts(io, ctx, &path);
         }
 
         match find_node(&ctx.vfs, &path) {
-            Some(node) if node.kind == VfsKind::Directory => match &node.children {
-                Some(children) if !children.is_empty() => {
-                    let mut entries: Vec<&VfsNode> = children.iter().collect();
-                    entries.sort_by(|a, b| a.name.cmp(&b.name));
-                    let rendered = html! {
-                        <div class="grid grid-cols-[repeat(auto-fit,minmax(8rem,1fr))] gap-x-2">
-                            { for entries.into_iter().map(|child| {
-                                let class = if child.kind == VfsKind::Directory {
-                                    "text-emerald-400 font-bold"
-                                } else {
-                                    "text-slate-100"
-                                };
-                                let label = if child.kind == VfsKind::Directory {
-                                    format!("{}/", child.name)
-                                } else {
-                                    child.name.clone()
-                                };
-                                html! {
-                                    <span class={class}>{label}</span>
-                                }
-                            }) }
-                        </div>
-                    };
-                    ctx.terminal.push_component(rendered);
+            Some(node) if node.kind == VfsKind::Directory => {
+                match directory_entries(ctx, node, &path) {
+                    entries if !entries.is_empty() => {
+                        if !io.stdout.is_terminal() {
+                            for (name, _) in entries {
+                                io.stdout.write_line(name);
+                            }
+                            return Ok(ExitStatus::SUCCESS);
+                        }
+                        let rendered = html! {
+                            <div class="grid grid-cols-[repeat(auto-fit,minmax(8rem,1fr))] gap-x-2">
+                                { for entries.into_iter().map(|(name, is_dir)| {
+                                    let class = if is_dir {
+                                        "text-emerald-400 font-bold"
+                                    } else {
+                                        "text-slate-100"
+                                    };
+                                    let label = if is_dir {
+                                        format!("{name}/")
+                                    } else {
+                                        name
+                                    };
+                                    html! {
+                                        <span class={class}>{label}</span>
+                                    }
+                                }) }
+                            </div>
+                        };
+                        ctx.terminal.push_component(rendered);
+                        Ok(ExitStatus::SUCCESS)
+                    }
+                    _ => {
+                        io.stderr.write_line("ls: empty directory");
+                        Ok(ExitStatus::SUCCESS)
+                    }
                 }
-                _ => {
-                    ctx.terminal.push_error("ls: empty directory");
-                }
-            },
+            }
             Some(_) => {
-                ctx.terminal
-                    .push_error(format!("ls: {}: not a directory", format_path(&path)));
+                io.stderr
+                    .write_line(format!("ls: {}: not a directory", format_path(&path)));
+                Ok(ExitStatus::FAILURE)
             }
             None => {
-                ctx.terminal.push_error(format!(
+                io.stderr.write_line(format!(
                     "ls: {}: no such file or directory",
                     format_path(&path)
                 ));
+                Ok(ExitStatus::FAILURE)
             }
         }
-        Ok(())
     }
 }
 
 impl LsCommand {
-    fn list_posts(&self, ctx: &CommandContext, path: &[String]) -> Result<(), String> {
+    fn list_posts(
+        &self,
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+        path: &[String],
+    ) -> Result<ExitStatus, String> {
         let Some(node) = find_node(&ctx.vfs, path) else {
-            ctx.terminal.push_error(format!(
+            io.stderr.write_line(format!(
                 "ls --posts: {}: no such directory",
                 format_path(path)
             ));
-            return Ok(());
+            return Ok(ExitStatus::FAILURE);
         };
 
         let mut posts: Vec<PostEntry> = match node.kind {
             VfsKind::Directory => {
                 let Some(children) = &node.children else {
-                    ctx.terminal.push_error("ls --posts: empty directory");
-                    return Ok(());
+                    io.stderr.write_line("ls --posts: empty directory");
+                    return Ok(ExitStatus::SUCCESS);
                 };
                 children
                     .iter()
@@ -99,18 +121,17 @@ impl LsCommand {
             }
             VfsKind::File if is_markdown(node) => vec![PostEntry::from_node(node).unwrap()],
             _ => {
-                ctx.terminal.push_error(format!(
+                io.stderr.write_line(format!(
                     "ls --posts: {}: not a markdown file or directory",
                     format_path(path)
                 ));
-                return Ok(());
+                return Ok(ExitStatus::FAILURE);
             }
         };
 
         if posts.is_empty() {
-            ctx.terminal
-                .push_error("ls --posts: no markdown posts found");
-            return Ok(());
+            io.stderr.write_line("ls --posts: no markdown posts found");
+            return Ok(ExitStatus::SUCCESS);
         }
 
         posts.sort_by(|a, b| match (&a.metadata.modified, &b.metadata.modified) {
@@ -120,6 +141,13 @@ impl LsCommand {
             (None, None) => a.metadata.name.cmp(&b.metadata.name),
         });
 
+        if !io.stdout.is_terminal() {
+            for post in &posts {
+                io.stdout.write_line(format!("/{}", post.metadata.path));
+            }
+            return Ok(ExitStatus::SUCCESS);
+        }
+
         let on_post_click = {
             let terminal = ctx.terminal.clone();
             Callback::from(move |metadata: VfsNode| {
@@ -130,10 +158,28 @@ impl LsCommand {
 
         ctx.terminal
             .push_component(render_posts(&posts, on_post_click));
-        Ok(())
+        Ok(ExitStatus::SUCCESS)
     }
 }
 
+/// Names in a directory, bundled and written this session, with whether each is a directory.
+fn directory_entries(ctx: &CommandContext, node: &VfsNode, path: &[String]) -> Vec<(String, bool)> {
+    let mut entries: Vec<(String, bool)> = node
+        .children
+        .iter()
+        .flatten()
+        .map(|child| (child.name.clone(), child.kind == VfsKind::Directory))
+        .collect();
+    entries.extend(
+        ctx.fs
+            .file_names(path)
+            .into_iter()
+            .map(|name| (name, false)),
+    );
+    entries.sort();
+    entries
+}
+
 struct PostEntry {
     metadata: VfsNode,
 }
@@ -7,6 +7,7 @@ mod echo;
 mod email;
 mod eval;
 mod fetch;
+mod grep;
 mod help;
 mod history;
 mod ls;
@@ -16,14 +17,18 @@ mod render;
 mod sleep;
 mod stat;
 mod theme;
+mod wc;
 mod whoami;
 
 use crate::cache_service::CacheService;
 use crate::config_service::AppConfig;
+use crate::session_fs::SessionFs;
 use crate::terminal::TerminalHandle;
+use crate::utils::CancellationToken;
 use crate::vfs_data::VfsNode;
 use micro_cli::{CliError, Parser};
 use shell_parser::integration::ExecutableCommand;
+use shell_parser::{CommandIo, ExitStatus};
 use std::rc::Rc;
 
 pub use boa::BoaCommand;
@@ -35,6 +40,7 @@ pub use echo::EchoCommand;
 pub use email::EmailCommand;
 pub use eval::EvalCommand;
 pub use fetch::FetchCommand;
+pub use grep::GrepCommand;
 pub use help::HelpCommand;
 pub use history::HistoryCommand;
 pub use ls::LsCommand;
@@ -44,26 +50,44 @@ pub use render::RenderCommand;
 pub use sleep::SleepCommand;
 pub use stat::StatCommand;
 pub use theme::ThemeCommand;
+pub use wc::WcCommand;
 pub use whoami::WhoAmICommand;
 
 #[derive(Clone)]
 pub struct CommandContext {
     pub vfs: Rc<VfsNode>,
     pub cache: Option<Rc<CacheService>>,
+    pub fs: Rc<SessionFs>,
     pub terminal: TerminalHandle,
     pub config: &'static AppConfig,
 }
 
-pub fn parse_cli<T: Parser>(args: &[String], ctx: &CommandContext, label: &str) -> Option<T> {
+impl CommandContext {
+    /// Token cancelled when Ctrl+C interrupts the script running the command,
+    /// or never for a background job; pass its abort signal to requests so
+    /// they stop too.
+    pub fn cancellation(&self) -> CancellationToken {
+        CancellationToken::current()
+    }
+}
+
+/// Parse a command's arguments, or return the status it should exit with:
+/// success after printing the help that `-h` or `--help` asked for, failure
+/// after reporting a usage error.
+pub fn parse_cli<T: Parser>(
+    args: &[String],
+    io: &mut CommandIo<'_>,
+    label: &str,
+) -> Result<T, ExitStatus> {
     match T::parse_from(args.to_vec()) {
-        Ok(parsed) => Some(parsed),
+        Ok(parsed) => Ok(parsed),
         Err(CliError::Help(text)) => {
-            ctx.terminal.push_text(text);
-            None
+            io.stdout.write_line(text);
+            Err(ExitStatus::SUCCESS)
         }
         Err(err) => {
-            ctx.terminal.push_error(format!("{label}: {err}"));
-            None
+            io.stderr.write_line(format!("{label}: {err}"));
+            Err(ExitStatus::FAILURE)
         }
     }
 }
@@ -88,6 +112,8 @@ pub fn command_handlers() -> Vec<Box<dyn ExecutableCommand<CommandContext>>> {
         Box::new(NavigateCommand::default()),
         Box::new(SleepCommand::default()),
         Box::new(HistoryCommand::default()),
+        Box::new(GrepCommand::default()),
+        Box::new(WcCommand::default()),
         Box::new(HelpCommand::default()),
     ]
 }
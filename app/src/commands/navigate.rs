@@ -1,35 +1,53 @@
 use crate::commands::{parse_cli, CommandContext};
-use crate::router::run_route;
+use crate::router::route_command;
+use crate::terminal::format_cli_error;
+use async_trait::async_trait;
 use micro_cli::Parser;
+use shell_parser::fmt;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
-use wasm_bindgen_futures::spawn_local;
+use shell_parser::{CommandIo, ExitStatus};
 
+/// Navigate to a path and execute its route.
 #[derive(Parser, Debug, Default)]
-#[command(name = "navigate", about = "Navigate to a path and execute its route")]
+#[command(name = "navigate")]
 pub struct NavigateCommand {
-    #[arg(positional, help = "Path to navigate to")]
+    /// Path to navigate to.
+    #[arg(positional)]
     path: String,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for NavigateCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<NavigateCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<NavigateCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
 
-        let path = cli.path;
-        let terminal = ctx.terminal.clone();
-
-        spawn_local(async move {
-            terminal.execute_command(&format!("history --push {path}"));
-            match terminal.to_terminal() {
-                Some(full_terminal) => run_route(&path, full_terminal),
-                None => {
-                    terminal.push_error("navigate: unable to execute route");
-                }
-            }
-        });
-
-        Ok(())
+        let push = fmt::quote(&["history".into(), "--push".into(), cli.path.clone()]);
+        let status = run_script(&push, io, ctx).await;
+        if !status.success() {
+            return Ok(status);
+        }
+        Ok(match route_command(&cli.path) {
+            Some(command) => run_script(&command, io, ctx).await,
+            None => ExitStatus::SUCCESS,
+        })
     }
 }
+
+/// Run `script` in the current shell and wait for it, reporting its error on stderr.
+async fn run_script(script: &str, io: &mut CommandIo<'_>, ctx: &CommandContext) -> ExitStatus {
+    ctx.terminal
+        .run_script(script, Vec::new())
+        .await
+        .unwrap_or_else(|err| {
+            io.stderr.write_line(format_cli_error(err, Some(script)));
+            ExitStatus::FAILURE
+        })
+}
@@ -1,16 +1,27 @@
 use crate::commands::{parse_cli, CommandContext};
 use crate::vfs_data::format_path;
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 
+/// Print working directory.
 #[derive(Parser, Debug, Default)]
-#[command(name = "pwd", about = "Print working directory")]
+#[command(name = "pwd")]
 pub struct PwdCommand;
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for PwdCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let _ = parse_cli::<PwdCommand>(args, ctx, self.command_name());
-        ctx.terminal.push_text(format_path(&ctx.terminal.cwd()));
-        Ok(())
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        if let Err(status) = parse_cli::<PwdCommand>(args, io, self.command_name()) {
+            return Ok(status);
+        }
+        io.stdout.write_line(format_path(&ctx.terminal.cwd()));
+        Ok(ExitStatus::SUCCESS)
     }
 }
@@ -5,63 +5,72 @@ use crate::components::Typewriter;
 use crate::config_service::ConfigService;
 use crate::markdown_renderer::MarkdownRenderer;
 use crate::vfs_data::{find_node, format_path, resolve_path, VfsKind, VfsNode};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
-use wasm_bindgen_futures::spawn_local;
+use shell_parser::{CommandIo, ExitStatus};
 use yew::{html, Html};
 
+/// Render markdown content to HTML.
 #[derive(Parser, Debug, Default)]
-#[command(name = "render", about = "Render markdown content to HTML")]
+#[command(
+    name = "render",
+    example = "render /posts/hello.md",
+    example = "render -t -d 10 /posts/hello.md"
+)]
 pub struct RenderCommand {
-    #[arg(positional, help = "Path to markdown file")]
+    /// Path to markdown file.
+    #[arg(positional)]
     path: String,
 
-    #[arg(
-        short = 'r',
-        long = "raw",
-        help = "Render markdown file without header"
-    )]
+    /// Render markdown file without header.
+    #[arg(short = 'r', long = "raw")]
     raw: bool,
 
-    #[arg(
-        short = 't',
-        long = "typewriter-style",
-        help = "Render markdown with typewriter effect"
-    )]
+    /// Render markdown with typewriter effect.
+    #[arg(short = 't', long = "typewriter-style")]
     typewriter_style: bool,
 
+    /// Delay between characters in typewriter effect.
     #[arg(
         short = 'd',
         long = "typewriter-delay",
-        help = "Delay between characters in typewriter effect"
+        value_name = "MILLIS",
+        requires = "typewriter_style"
     )]
     typewriter_delay: Option<u32>,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for RenderCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<RenderCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<RenderCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
-        let ctx = ctx.clone();
-        spawn_local(async move {
-            run_render(cli, ctx).await;
-        });
-        Ok(())
+        match run_render(cli, ctx).await {
+            Ok(()) => Ok(ExitStatus::SUCCESS),
+            Err(err) => {
+                io.stderr.write_line(format!("render: {err}"));
+                Ok(ExitStatus::FAILURE)
+            }
+        }
     }
 }
 
-async fn run_render(cli: RenderCommand, ctx: CommandContext) {
+/// Show the rendered file in the terminal.
+async fn run_render(cli: RenderCommand, ctx: &CommandContext) -> Result<(), String> {
     let target = &cli.path;
 
     let mut path = resolve_path(&ctx.terminal.cwd(), target);
     let mut node = match find_node(&ctx.vfs, &path) {
         Some(node) => node,
-        None => {
-            ctx.terminal
-                .push_error(format!("render: {}: no such file", format_path(&path)));
-            return;
-        }
+        None => return Err(format!("{}: no such file", format_path(&path))),
     };
 
     if node.kind == VfsKind::Directory {
@@ -79,17 +88,14 @@ async fn run_render(cli: RenderCommand, ctx: CommandContext) {
                 node = idx;
             }
             None => {
-                ctx.terminal.push_error(format!(
-                    "render: {}: is a directory without index.md",
+                return Err(format!(
+                    "{}: is a directory without index.md",
                     format_path(&path)
-                ));
-                return;
+                ))
             }
         }
     } else if node.kind != VfsKind::File {
-        ctx.terminal
-            .push_error(format!("render: {}: is not a file", format_path(&path)));
-        return;
+        return Err(format!("{}: is not a file", format_path(&path)));
     }
 
     let uri = format!("/data/{}", path.join("/"));
@@ -103,50 +109,43 @@ async fn run_render(cli: RenderCommand, ctx: CommandContext) {
     {
         ctx.terminal
             .push_html(format!(r#"<img src="{uri}" alt="rendered image" />"#));
-        return;
+        return Ok(());
     }
 
     let Some(cache) = ctx.cache.clone() else {
-        ctx.terminal
-            .push_error("render: cache unavailable (OPFS init failed)");
-        return;
+        return Err("cache unavailable (OPFS init failed)".into());
     };
 
     let author = ConfigService::get().author.clone();
 
-    match fetch_text_with_cache(&uri, &cache).await {
-        Ok(content) => {
-            let base_dir = if path.len() > 1 {
-                path[..path.len() - 1].join("/")
-            } else {
-                String::new()
-            };
-            let rendered = MarkdownRenderer::new().render_with_base_path(&content, Some(&base_dir));
-            let rendered = if cli.typewriter_style {
-                html! {<Typewriter content={rendered} delay_ms={cli.typewriter_delay.unwrap_or(10)} />}
-            } else {
-                rendered
-            };
-            let node: Html = if cli.raw {
-                rendered
-            } else {
-                html! {
-                    <div class="py-6 pb-9 text-base text-post">
-                        <Header metadata={node.clone()} />
-                        <div class="flex items-center">
-                            <Avatar name={author.name.clone()} email={author.email.clone()} />
-                            <span class="text-base text-white mt-6 ml-3">{&author.name}</span>
-                        </div>
-                        {rendered}
-                    </div>
-                }
-            };
-            ctx.terminal.push_component(node);
-        }
-        Err(err) => {
-            ctx.terminal.push_error(format!("render: {err}"));
+    let content = fetch_text_with_cache(&uri, &cache, &ctx.cancellation()).await?;
+    let base_dir = if path.len() > 1 {
+        path[..path.len() - 1].join("/")
+    } else {
+        String::new()
+    };
+    let rendered = MarkdownRenderer::new().render_with_base_path(&content, Some(&base_dir));
+    let rendered = if cli.typewriter_style {
+        html! {<Typewriter content={rendered} delay_ms={cli.typewriter_delay.unwrap_or(10)} />}
+    } else {
+        rendered
+    };
+    let node: Html = if cli.raw {
+        rendered
+    } else {
+        html! {
+            <div class="py-6 pb-9 text-base text-post">
+                <Header metadata={node.clone()} />
+                <div class="flex items-center">
+                    <Avatar name={author.name.clone()} email={author.email.clone()} />
+                    <span class="text-base text-white mt-6 ml-3">{&author.name}</span>
+                </div>
+                {rendered}
+            </div>
         }
-    }
+    };
+    ctx.terminal.push_component(node);
+    Ok(())
 }
 
 fn is_markdown(node: &VfsNode) -> bool {
@@ -1,22 +1,34 @@
+use async_trait::async_trait;
+use gloo_timers::future::TimeoutFuture;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
-use shell_parser::pause_signal;
+use shell_parser::{CommandIo, ExitStatus};
 
 use crate::commands::{parse_cli, CommandContext};
 
+/// Pause execution for a number of milliseconds.
 #[derive(Parser, Debug, Default)]
-#[command(name = "sleep", about = "Pause execution for a number of milliseconds")]
+#[command(name = "sleep", example = "sleep 500 && echo done")]
 pub struct SleepCommand {
-    #[arg(positional, help = "Duration in milliseconds")]
+    /// Duration in milliseconds.
+    #[arg(positional, value_name = "MILLIS")]
     millis: u32,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for SleepCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<SleepCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<SleepCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
 
-        Err(pause_signal(cli.millis))
+        TimeoutFuture::new(cli.millis).await;
+        Ok(ExitStatus::SUCCESS)
     }
 }
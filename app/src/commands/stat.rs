@@ -1,31 +1,43 @@
 use crate::commands::{parse_cli, CommandContext};
 use crate::vfs_data::{find_node, format_path, node_summary, resolve_path};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 
+/// Display file or directory metadata.
 #[derive(Parser, Debug, Default)]
-#[command(name = "stat", about = "Display file or directory metadata")]
+#[command(name = "stat")]
 pub struct StatCommand {
-    #[arg(positional, help = "Path to inspect")]
+    /// Path to inspect.
+    #[arg(positional)]
     path: String,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for StatCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<StatCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<StatCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
         let path = resolve_path(&ctx.terminal.cwd(), &cli.path);
         match find_node(&ctx.vfs, &path) {
             Some(node) => {
-                ctx.terminal
-                    .push_text(format!("{} => {}", format_path(&path), node_summary(node)));
+                io.stdout
+                    .write_line(format!("{} => {}", format_path(&path), node_summary(node)));
+                Ok(ExitStatus::SUCCESS)
             }
             None => {
-                ctx.terminal
-                    .push_error(format!("stat: {}: not found", format_path(&path)));
+                io.stderr
+                    .write_line(format!("stat: {}: not found", format_path(&path)));
+                Ok(ExitStatus::FAILURE)
             }
         }
-        Ok(())
     }
 }
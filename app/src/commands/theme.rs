@@ -1,69 +1,54 @@
 use crate::commands::{parse_cli, CommandContext};
-use micro_cli::Parser;
+use async_trait::async_trait;
+use micro_cli::{Parser, ValueEnum};
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
-use std::str::FromStr;
+use shell_parser::{CommandIo, ExitStatus};
 use web_sys::window;
 
 const DARK_CLASS: &str = "theme-dark";
 
+/// Get or set the UI theme.
 #[derive(Parser, Debug, Default)]
-#[command(name = "theme", about = "Get or set the UI theme")]
+#[command(name = "theme")]
 pub struct ThemeCommand {
-    #[arg(
-        short = 's',
-        long = "set",
-        help = "Set the theme to dark or light (dark|light)"
-    )]
+    /// Set the theme.
+    #[arg(short = 's', long = "set", value_enum)]
     set: Option<ThemeMode>,
 }
 
 impl ThemeCommand {}
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
 enum ThemeMode {
     Dark,
     Light,
 }
 
-impl ThemeMode {
-    fn as_str(&self) -> &'static str {
-        match self {
-            ThemeMode::Dark => "dark",
-            ThemeMode::Light => "light",
-        }
-    }
-}
-
-impl FromStr for ThemeMode {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_ascii_lowercase().as_str() {
-            "dark" => Ok(ThemeMode::Dark),
-            "light" => Ok(ThemeMode::Light),
-            _ => Err(()),
-        }
-    }
-}
-
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for ThemeCommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(cli) = parse_cli::<ThemeCommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<ThemeCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
 
         if let Some(mode) = cli.set {
             set_theme(mode)?;
-            ctx.terminal
-                .push_text(format!("theme set to {}", mode.as_str()));
-            return Ok(());
+            io.stdout
+                .write_line(format!("theme set to {}", mode.to_value()));
+            return Ok(ExitStatus::SUCCESS);
         }
 
         let mode = current_theme().unwrap_or(ThemeMode::Light);
-        ctx.terminal
-            .push_text(format!("current theme: {}", mode.as_str()));
+        io.stdout
+            .write_line(format!("current theme: {}", mode.to_value()));
 
-        Ok(())
+        Ok(ExitStatus::SUCCESS)
     }
 }
 
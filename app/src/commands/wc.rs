@@ -0,0 +1,54 @@
+use crate::commands::{parse_cli, CommandContext};
+use async_trait::async_trait;
+use micro_cli::Parser;
+use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
+
+/// Count lines, words and bytes of piped text.
+#[derive(Parser, Debug, Default)]
+#[command(name = "wc")]
+pub struct WcCommand {
+    /// Print the line count.
+    #[arg(short = 'l', long = "lines")]
+    lines: bool,
+
+    /// Print the word count.
+    #[arg(short = 'w', long = "words")]
+    words: bool,
+
+    /// Print the byte count.
+    #[arg(short = 'c', long = "bytes")]
+    bytes: bool,
+}
+
+#[async_trait(?Send)]
+impl ExecutableCommand<CommandContext> for WcCommand {
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let cli = match parse_cli::<WcCommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
+        };
+        let input = io.stdin.take().unwrap_or_default();
+        let show_all = !(cli.lines || cli.words || cli.bytes);
+
+        let mut counts = Vec::new();
+        if show_all || cli.lines {
+            counts.push(input.lines().count());
+        }
+        if show_all || cli.words {
+            counts.push(input.split_whitespace().count());
+        }
+        if show_all || cli.bytes {
+            counts.push(input.len());
+        }
+
+        let counts: Vec<String> = counts.iter().map(ToString::to_string).collect();
+        io.stdout.write_line(counts.join(" "));
+        Ok(ExitStatus::SUCCESS)
+    }
+}
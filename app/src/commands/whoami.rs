@@ -1,23 +1,33 @@
 use crate::commands::{parse_cli, CommandContext};
+use async_trait::async_trait;
 use micro_cli::Parser;
 use shell_parser::integration::{CommandInfo, ExecutableCommand};
+use shell_parser::{CommandIo, ExitStatus};
 
+/// Show configured author name.
 #[derive(Parser, Debug, Default)]
-#[command(name = "whoami", about = "Show configured author name")]
+#[command(name = "whoami")]
 pub struct WhoAmICommand;
 
+#[async_trait(?Send)]
 impl ExecutableCommand<CommandContext> for WhoAmICommand {
-    fn run(&self, args: &[String], ctx: &CommandContext) -> Result<(), String> {
-        let Some(_cli) = parse_cli::<WhoAmICommand>(args, ctx, self.command_name()) else {
-            return Ok(());
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        ctx: &CommandContext,
+    ) -> Result<ExitStatus, String> {
+        let _cli = match parse_cli::<WhoAmICommand>(args, io, self.command_name()) {
+            Ok(cli) => cli,
+            Err(status) => return Ok(status),
         };
         let name = &ctx.config.author.name;
         if name.is_empty() {
-            ctx.terminal
-                .push_error("whoami: author.name is empty in App.toml");
-        } else {
-            ctx.terminal.push_text(name.clone());
+            io.stderr
+                .write_line("whoami: author.name is empty in App.toml");
+            return Ok(ExitStatus::FAILURE);
         }
-        Ok(())
+        io.stdout.write_line(name.clone());
+        Ok(ExitStatus::SUCCESS)
     }
 }
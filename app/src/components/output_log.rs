@@ -25,7 +25,8 @@ fn render_line(line: &TermLine) -> Html {
     };
 
     let text_class = match line.kind {
-        OutputKind::Error => "text-rose-300",
+        OutputKind::Error => "text-rose-300 whitespace-break-spaces",
+        OutputKind::Text if !line.accent => "text-slate-100 whitespace-break-spaces",
         _ => {
             if line.accent {
                 "text-emerald-300"
@@ -1,3 +1,4 @@
+use shell_parser::{HighlightKind, HighlightSpan};
 use web_sys::{HtmlInputElement, KeyboardEvent};
 use yew::prelude::*;
 
@@ -6,9 +7,15 @@ use crate::config_service::ConfigService;
 #[derive(Properties, PartialEq)]
 pub struct PromptLineProps {
     pub value: String,
+    /// Syntax highlighting of `value`, drawn over the input.
+    pub highlights: Vec<HighlightSpan>,
     pub on_input: Callback<String>,
     pub on_submit: Callback<()>,
     pub on_history_nav: Callback<HistoryDirection>,
+    /// Tab was pressed; carries the cursor as a byte offset into `value`.
+    pub on_complete: Callback<usize>,
+    /// Ctrl+C was pressed with nothing selected.
+    pub on_interrupt: Callback<()>,
 }
 
 #[derive(Clone, PartialEq)]
@@ -19,6 +26,15 @@ pub enum HistoryDirection {
 
 #[function_component(PromptLine)]
 pub fn prompt_line(props: &PromptLineProps) -> Html {
+    // The overlay follows the input when long lines scroll horizontally.
+    let scroll_left = use_state(|| 0);
+    let on_scroll = {
+        let scroll_left = scroll_left.clone();
+        Callback::from(move |e: Event| {
+            scroll_left.set(e.target_unchecked_into::<HtmlInputElement>().scroll_left());
+        })
+    };
+
     let on_input = {
         let on_input = props.on_input.clone();
         Callback::from(move |e: InputEvent| {
@@ -29,8 +45,29 @@ pub fn prompt_line(props: &PromptLineProps) -> Html {
 
     let on_keydown = {
         let on_history_nav = props.on_history_nav.clone();
+        let on_complete = props.on_complete.clone();
+        let on_interrupt = props.on_interrupt.clone();
 
         Callback::from(move |e: KeyboardEvent| match e.key().as_str() {
+            "c" if e.ctrl_key() => {
+                let input = e.target_unchecked_into::<HtmlInputElement>();
+                // Leave copying a selection alone.
+                if input.selection_start().ok() != input.selection_end().ok() {
+                    return;
+                }
+                e.prevent_default();
+                on_interrupt.emit(());
+            }
+            "Tab" => {
+                e.prevent_default();
+                let input = e.target_unchecked_into::<HtmlInputElement>();
+                let value = input.value();
+                let cursor = match input.selection_start() {
+                    Ok(Some(units)) => byte_offset(&value, units as usize),
+                    _ => value.len(),
+                };
+                on_complete.emit(cursor);
+            }
             "ArrowUp" => {
                 e.prevent_default();
                 on_history_nav.emit(HistoryDirection::Previous);
@@ -54,16 +91,77 @@ pub fn prompt_line(props: &PromptLineProps) -> Html {
     html! {
         <form onsubmit={on_submit} class="flex items-center mt-4 sm:mt-3 gap-2 sm:gap-3 font-mono text-base sm:text-sm text-slate-100">
             <span class="text-emerald-400">{ ConfigService::get().app.prompt_symbol.clone() }</span>
-            <input
-                class="flex-1 bg-transparent text-slate-100 outline-none placeholder:text-slate-600 text-base sm:text-sm"
-                type="text"
-                value={props.value.clone()}
-                oninput={on_input}
-                onkeydown={on_keydown}
-                placeholder={ConfigService::get().app.prompt_placeholder.clone()}
-                autocomplete="off"
-                spellcheck="false"
-            />
+            <div class="relative flex-1 overflow-hidden">
+                <div
+                    aria-hidden="true"
+                    class="pointer-events-none absolute inset-0 whitespace-pre text-base sm:text-sm"
+                    style={format!("transform: translateX(-{}px)", *scroll_left)}
+                >
+                    { render_highlighted(&props.value, &props.highlights) }
+                </div>
+                <input
+                    class="relative w-full bg-transparent text-transparent caret-slate-100 outline-none placeholder:text-slate-600 text-base sm:text-sm"
+                    type="text"
+                    value={props.value.clone()}
+                    oninput={on_input}
+                    onkeydown={on_keydown}
+                    onscroll={on_scroll}
+                    placeholder={ConfigService::get().app.prompt_placeholder.clone()}
+                    autocomplete="off"
+                    spellcheck="false"
+                />
+            </div>
         </form>
     }
 }
+
+/// Byte offset of the UTF-16 code unit offset `units`, as reported by the input.
+fn byte_offset(value: &str, units: usize) -> usize {
+    let mut seen = 0;
+    for (idx, ch) in value.char_indices() {
+        if seen >= units {
+            return idx;
+        }
+        seen += ch.len_utf16();
+    }
+    value.len()
+}
+
+/// Split `value` into coloured spans; text outside `highlights` keeps the default colour.
+fn render_highlighted(value: &str, highlights: &[HighlightSpan]) -> Html {
+    let mut nodes = Vec::new();
+    let mut at = 0;
+    for highlight in highlights {
+        let (start, end) = (highlight.span.start, highlight.span.end);
+        if start < at || !value.is_char_boundary(start) || !value.is_char_boundary(end) {
+            continue;
+        }
+        if at < start {
+            nodes.push(html! { <span>{ &value[at..start] }</span> });
+        }
+        nodes.push(html! {
+            <span class={highlight_class(highlight.kind)}>{ &value[start..end] }</span>
+        });
+        at = end;
+    }
+    if at < value.len() {
+        nodes.push(html! { <span>{ &value[at..] }</span> });
+    }
+    html! { <>{ for nodes }</> }
+}
+
+fn highlight_class(kind: HighlightKind) -> &'static str {
+    match kind {
+        HighlightKind::Command => "text-emerald-300",
+        HighlightKind::UnknownCommand => "text-rose-400",
+        HighlightKind::Alias => "text-cyan-300",
+        HighlightKind::Function => "text-sky-300",
+        HighlightKind::Keyword => "text-violet-300",
+        HighlightKind::Option => "text-amber-300",
+        HighlightKind::String => "text-lime-300",
+        HighlightKind::Operator => "text-slate-400",
+        HighlightKind::Comment => "text-slate-500",
+        HighlightKind::Variable => "text-fuchsia-300",
+        HighlightKind::Error => "text-rose-400 underline decoration-wavy",
+    }
+}
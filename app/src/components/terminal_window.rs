@@ -1,14 +1,18 @@
 use crate::components::{HeaderBar, OutputLog, PromptLine};
 use crate::types::TermLine;
+use shell_parser::HighlightSpan;
 use yew::prelude::*;
 
 #[derive(Properties, PartialEq)]
 pub struct TerminalWindowProps {
     pub lines: Vec<TermLine>,
     pub input: String,
+    pub highlights: Vec<HighlightSpan>,
     pub on_input: Callback<String>,
     pub on_submit: Callback<()>,
     pub on_history_nav: Callback<crate::components::HistoryDirection>,
+    pub on_complete: Callback<usize>,
+    pub on_interrupt: Callback<()>,
     pub show_window: bool,
 }
 
@@ -19,9 +23,12 @@ pub fn terminal_window(props: &TerminalWindowProps) -> Html {
             <OutputLog lines={props.lines.clone()} />
             <PromptLine
                 value={props.input.clone()}
+                highlights={props.highlights.clone()}
                 on_input={props.on_input.clone()}
                 on_submit={props.on_submit.clone()}
                 on_history_nav={props.on_history_nav.clone()}
+                on_complete={props.on_complete.clone()}
+                on_interrupt={props.on_interrupt.clone()}
             />
         </>
     };
@@ -20,10 +20,21 @@ pub struct AuthorSection {
     pub avatar: String,
 }
 
+/// Bounds on each script the terminal runs; a missing field means no limit.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LimitsSection {
+    pub max_commands: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub max_output_bytes: Option<usize>,
+    pub max_time_ms: Option<u64>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
     pub app: AppSection,
     pub author: AuthorSection,
+    #[serde(default)]
+    pub limits: LimitsSection,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -8,6 +8,7 @@ pub mod highlight_engine;
 pub mod highlight_service;
 pub mod markdown_renderer;
 pub mod router;
+pub mod session_fs;
 pub mod terminal;
 pub mod terminal_state;
 pub mod types;
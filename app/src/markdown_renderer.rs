@@ -434,7 +434,7 @@ fn render_inline_code_html(content: &str) -> String {
     )
 }
 
-fn escape_html(input: &str) -> String {
+pub(crate) fn escape_html(input: &str) -> String {
     let mut escaped = String::with_capacity(input.len());
     for ch in input.chars() {
         match ch {
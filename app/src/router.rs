@@ -1,5 +1,6 @@
 use crate::config_service::{ConfigService, RouteSection};
 use crate::terminal::Terminal;
+use shell_parser::fmt;
 use std::collections::HashMap;
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
@@ -31,14 +32,17 @@ pub fn start_router(terminal: Terminal) -> Option<RouterHandle> {
 }
 
 pub fn run_route(path: &str, terminal: Terminal) {
-    let normalized = normalize_path(path);
-    let routes = &ConfigService::get().app.routes;
-
-    if let Some(command) = resolve_command(&normalized, routes) {
+    if let Some(command) = route_command(path) {
         terminal.execute_command(&command);
     }
 }
 
+/// Command of the first route matching `path`, if any.
+pub fn route_command(path: &str) -> Option<String> {
+    let normalized = normalize_path(path);
+    resolve_command(&normalized, &ConfigService::get().app.routes)
+}
+
 fn resolve_command(path: &str, routes: &[RouteSection]) -> Option<String> {
     for route in routes {
         if let Some(command) = match_route(path, route) {
@@ -95,11 +99,14 @@ fn match_route(path: &str, route: &RouteSection) -> Option<String> {
     Some(apply_params(&route.command, &params))
 }
 
+/// Substitute captured segments into a route command. Each value is quoted as
+/// a single shell word, so placeholders must not be quoted in the template.
 fn apply_params(template: &str, params: &HashMap<String, String>) -> String {
     let mut resolved = template.to_string();
     for (key, value) in params {
-        resolved = resolved.replace(&format!("{{{key}}}"), value);
-        resolved = resolved.replace(&format!("{{*{key}}}"), value);
+        let word = fmt::quote_word(value);
+        resolved = resolved.replace(&format!("{{{key}}}"), &word);
+        resolved = resolved.replace(&format!("{{*{key}}}"), &word);
     }
     resolved
 }
@@ -0,0 +1,99 @@
+use crate::vfs_data::{find_node, format_path, resolve_path, VfsKind, VfsNode};
+use shell_parser::FileSystem;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Files written by redirections during this session, layered over the bundled VFS.
+///
+/// Bundled files stay read-only; new files live in memory until the page reloads.
+pub struct SessionFs {
+    vfs: Rc<VfsNode>,
+    cwd: Rc<RefCell<Vec<String>>>,
+    files: RefCell<BTreeMap<Vec<String>, String>>,
+}
+
+impl SessionFs {
+    pub fn new(vfs: Rc<VfsNode>, cwd: Rc<RefCell<Vec<String>>>) -> Self {
+        Self {
+            vfs,
+            cwd,
+            files: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Contents of a session file at an already resolved path.
+    pub fn file(&self, path: &[String]) -> Option<String> {
+        self.files.borrow().get(path).cloned()
+    }
+
+    /// Names of the session files directly inside `dir`.
+    pub fn file_names(&self, dir: &[String]) -> Vec<String> {
+        self.files
+            .borrow()
+            .keys()
+            .filter_map(|path| match path.split_last() {
+                Some((name, parent)) if parent == dir => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn resolve(&self, path: &str) -> Vec<String> {
+        resolve_path(&self.cwd.borrow(), path)
+    }
+}
+
+impl FileSystem for SessionFs {
+    fn read_file(&self, path: &str) -> Result<String, String> {
+        let path = self.resolve(path);
+        if let Some(contents) = self.file(&path) {
+            return Ok(contents);
+        }
+        match find_node(&self.vfs, &path) {
+            Some(node) if node.kind == VfsKind::Directory => Err("is a directory".into()),
+            Some(_) => Err("only files written in this session can be redirected".into()),
+            None => Err("no such file".into()),
+        }
+    }
+
+    fn write_file(&self, path: &str, contents: &str, append: bool) -> Result<(), String> {
+        let path = self.resolve(path);
+        let Some((_, parent)) = path.split_last() else {
+            return Err("is a directory".into());
+        };
+        match find_node(&self.vfs, &path) {
+            Some(node) if node.kind == VfsKind::Directory => return Err("is a directory".into()),
+            Some(_) => return Err("read-only file system".into()),
+            None => {}
+        }
+        match find_node(&self.vfs, parent) {
+            Some(node) if node.kind == VfsKind::Directory => {}
+            _ => return Err(format!("{}: no such directory", format_path(parent))),
+        }
+
+        let mut files = self.files.borrow_mut();
+        let file = files.entry(path).or_default();
+        if !append {
+            file.clear();
+        }
+        file.push_str(contents);
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, String> {
+        let path = self.resolve(path);
+        let node = find_node(&self.vfs, &path).ok_or("no such directory")?;
+        if node.kind != VfsKind::Directory {
+            return Err("not a directory".into());
+        }
+        let mut names: Vec<String> = node
+            .children
+            .iter()
+            .flatten()
+            .map(|child| child.name.clone())
+            .collect();
+        names.extend(self.file_names(&path));
+        Ok(names)
+    }
+}
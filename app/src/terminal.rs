@@ -2,14 +2,22 @@ use crate::cache_service::CacheService;
 use crate::commands::{command_handlers, CommandContext};
 use crate::commands_history_service::CommandHistory;
 use crate::config_service::ConfigService;
+use crate::markdown_renderer::escape_html;
+use crate::session_fs::SessionFs;
 use crate::terminal_state::{TerminalAction, TerminalState};
 use crate::types::{OutputKind, TermLine};
+use crate::utils::CancellationToken;
 use crate::vfs_data::{load_vfs, VfsNode};
-use gloo_timers::future::TimeoutFuture;
-use shell_parser::{with_cli, CliRunner, CommandInvocation, ScriptResult, ShellParseError};
+use shell_parser::{
+    with_cli, CliRunner, CommandRecord, Completion, ExitStatus, HighlightSpan, Limits, Middleware,
+    ShellOptions, ShellParseError,
+};
 use std::cell::RefCell;
+use std::future::Future;
 use std::ops::Deref;
+use std::pin::Pin;
 use std::rc::{Rc, Weak};
+use std::time::Duration;
 use uuid::Uuid;
 use wasm_bindgen_futures::spawn_local;
 use yew::{html, UseReducerHandle};
@@ -18,9 +26,12 @@ struct TerminalCore {
     state: UseReducerHandle<TerminalState>,
     vfs: Rc<VfsNode>,
     cache: Option<Rc<CacheService>>,
+    fs: Rc<SessionFs>,
     history: Rc<RefCell<CommandHistory>>,
     cwd: Rc<RefCell<Vec<String>>>,
     runner: RefCell<Option<Weak<CliRunner<CommandContext>>>>,
+    /// Cancelled by Ctrl+C, then replaced for the scripts started after it.
+    foreground: RefCell<CancellationToken>,
 }
 
 #[derive(Clone)]
@@ -46,7 +57,21 @@ impl Terminal {
         let vfs = Rc::new(load_vfs());
         let history = Rc::new(RefCell::new(CommandHistory::new(cache.clone()).await));
         let handle = TerminalHandle::new(state, vfs, cache, history);
-        let runner = Rc::new(with_cli(handle.command_context(), command_handlers()));
+        let runner = Rc::new(
+            with_cli(handle.command_context(), command_handlers())
+                .with_stdout(|ctx, text| {
+                    ctx.terminal.push_text(text);
+                })
+                .with_stderr(|ctx, text| {
+                    ctx.terminal.push_error(text);
+                })
+                .with_fs(handle.inner.fs.clone())
+                .with_spawner(spawn_job)
+                .with_limits(script_limits())
+                .with_middleware(CommandLog)
+                .with_clock(|| Duration::from_secs_f64(js_sys::Date::now() / 1000.0))
+                .with_timer(|wait| Box::pin(gloo_timers::future::sleep(wait))),
+        );
         handle.set_runner(&runner);
 
         Self {
@@ -80,14 +105,18 @@ impl TerminalHandle {
         cache: Option<Rc<CacheService>>,
         history: Rc<RefCell<CommandHistory>>,
     ) -> Self {
+        let cwd = Rc::new(RefCell::new(Vec::new()));
+        let fs = Rc::new(SessionFs::new(vfs.clone(), cwd.clone()));
         Self {
             inner: Rc::new(TerminalCore {
                 state,
                 vfs,
                 cache,
+                fs,
                 history,
-                cwd: Rc::new(RefCell::new(Vec::new())),
+                cwd,
                 runner: RefCell::new(None),
+                foreground: RefCell::default(),
             }),
         }
     }
@@ -96,6 +125,7 @@ impl TerminalHandle {
         CommandContext {
             vfs: self.inner.vfs.clone(),
             cache: self.inner.cache.clone(),
+            fs: self.inner.fs.clone(),
             terminal: self.clone(),
             config: ConfigService::get(),
         }
@@ -126,6 +156,19 @@ impl TerminalHandle {
         Ok(runner)
     }
 
+    /// Options scripts set with `set`; the defaults until the runner is ready.
+    pub fn shell_options(&self) -> ShellOptions {
+        self.runner()
+            .map(|runner| runner.options())
+            .unwrap_or_default()
+    }
+
+    pub fn set_shell_options(&self, options: ShellOptions) {
+        if let Some(runner) = self.runner() {
+            runner.set_options(options);
+        }
+    }
+
     pub fn push_line(&self, line: TermLine) -> Uuid {
         let id = line.id;
         self.inner.state.dispatch(TerminalAction::PushLine(line));
@@ -184,59 +227,77 @@ impl TerminalHandle {
         Ok(self.runner_else()?.help())
     }
 
-    pub fn execute_command(&self, input: &str) {
-        let outcome = self.run_script(input);
-        self.handle_execution_result(outcome);
-    }
-
-    fn execute_invocations(&self, commands: Vec<CommandInvocation>) {
-        if commands.is_empty() {
-            return;
-        }
-        let result = self.run_parsed(&commands);
-        self.handle_execution_result(result);
+    /// Highlight spans for the prompt; empty until the runner is ready.
+    pub fn highlight(&self, input: &str) -> Vec<HighlightSpan> {
+        self.runner()
+            .map(|runner| runner.highlight(input))
+            .unwrap_or_default()
     }
 
-    fn handle_execution_result(
-        &self,
-        outcome: Result<ScriptResult, shell_parser::integration::ShellCliError>,
-    ) {
-        match outcome {
-            Ok(ScriptResult::Completed) => {}
-            Ok(ScriptResult::Paused {
-                delay_ms,
-                remainder,
-            }) => self.schedule_resume(delay_ms, remainder),
-            Err(err) => {
-                tracing::error!("{:?}", &err);
-                self.push_error(format_cli_error(err));
-            }
-        }
+    /// Completion candidates for the prompt; paths resolve against the cwd.
+    pub fn complete(&self, input: &str, cursor: usize) -> Completion {
+        self.runner()
+            .map(|runner| runner.complete(input, cursor))
+            .unwrap_or_default()
     }
 
-    fn schedule_resume(&self, delay_ms: u32, remainder: Vec<CommandInvocation>) {
-        if remainder.is_empty() {
-            return;
-        }
+    /// Run `input` in the background; its commands run one after another as
+    /// each finishes, until they are done or Ctrl+C interrupts them.
+    pub fn execute_command(&self, input: &str) {
         let terminal = self.clone();
+        let input = input.to_string();
+        let cancellation = self.cancellation();
         spawn_local(async move {
-            TimeoutFuture::new(delay_ms).await;
-            terminal.execute_invocations(remainder);
+            cancellation
+                .run(terminal.execute_script(&input, Vec::new()))
+                .await;
         });
     }
 
-    fn run_script(
+    /// Token of the scripts started from the prompt since the last Ctrl+C.
+    fn cancellation(&self) -> CancellationToken {
+        self.inner.foreground.borrow().clone()
+    }
+
+    /// Stop the foreground scripts and their requests, as Ctrl+C does;
+    /// background jobs keep running.
+    pub fn interrupt(&self) {
+        self.inner.foreground.take().cancel();
+        self.push_text("^C");
+    }
+
+    /// Run a script with `args` as its positional parameters `$1`, `$2`, ...,
+    /// returning the status of its last command.
+    pub async fn execute_script(&self, script: &str, args: Vec<String>) -> ExitStatus {
+        let outcome = self.run_script(script, args).await;
+        self.report(outcome, script)
+    }
+
+    /// Like [`TerminalHandle::execute_script`], but hands a failure back to
+    /// the caller, which can show it with [`format_cli_error`].
+    pub async fn run_script(
         &self,
-        input: &str,
-    ) -> Result<ScriptResult, shell_parser::integration::ShellCliError> {
-        self.runner_else()?.run_script(input)
+        script: &str,
+        args: Vec<String>,
+    ) -> Result<ExitStatus, shell_parser::integration::ShellCliError> {
+        self.runner_else()?.run_script_with_args(script, args).await
     }
 
-    fn run_parsed(
+    /// Show a script's error, if any; `source` is the script that was run,
+    /// used to point parse errors at their line.
+    fn report(
         &self,
-        invocations: &[CommandInvocation],
-    ) -> Result<ScriptResult, shell_parser::integration::ShellCliError> {
-        self.runner_else()?.run_invocations(invocations)
+        outcome: Result<ExitStatus, shell_parser::integration::ShellCliError>,
+        source: &str,
+    ) -> ExitStatus {
+        match outcome {
+            Ok(status) => status,
+            Err(err) => {
+                tracing::error!("{:?}", &err);
+                self.push_error(format_cli_error(err, Some(source)));
+                ExitStatus::FAILURE
+            }
+        }
     }
 
     pub fn to_terminal(&self) -> Option<Terminal> {
@@ -247,14 +308,66 @@ impl TerminalHandle {
     }
 }
 
-fn format_cli_error(err: shell_parser::integration::ShellCliError) -> String {
+/// Logs every command with how long it took, including the ones run from
+/// routes, `eval` and `.shrc` that never reach the history.
+struct CommandLog;
+
+impl Middleware<CommandContext> for CommandLog {
+    fn after(&self, record: &CommandRecord<'_>, _: &CommandContext) {
+        tracing::debug!(
+            command = %record.invocation.name,
+            args = ?record.invocation.args,
+            duration = ?record.duration,
+            outcome = ?record.outcome,
+            "command finished"
+        );
+    }
+}
+
+/// Run a background job started with `&` under a token of its own, so its
+/// requests outlive a Ctrl+C meant for the foreground.
+fn spawn_job(job: Pin<Box<dyn Future<Output = ()>>>) {
+    spawn_local(async move {
+        CancellationToken::default().run(job).await;
+    });
+}
+
+fn script_limits() -> Limits {
+    let limits = &ConfigService::get().limits;
+    Limits {
+        max_commands: limits.max_commands,
+        max_depth: limits.max_depth,
+        max_output_bytes: limits.max_output_bytes,
+        max_time: limits.max_time_ms.map(Duration::from_millis),
+    }
+}
+
+/// Message shown for a script's error, with snippets of `source` for parse errors.
+pub fn format_cli_error(
+    err: shell_parser::integration::ShellCliError,
+    source: Option<&str>,
+) -> String {
     match err {
-        shell_parser::integration::ShellCliError::Parse(parse_err) => match parse_err {
-            ShellParseError::UnknownCommand { name, .. } => format!("Unknown command {name}"),
-            other => format!("parse error: {other}"),
+        shell_parser::integration::ShellCliError::Parse(parse_err) => match (parse_err, source) {
+            // A typo at the prompt reads better without a snippet of the line just typed.
+            (ShellParseError::UnknownCommand { name, .. }, source)
+                if source.is_none_or(|source| !source.contains('\n')) =>
+            {
+                format!("Unknown command {name}")
+            }
+            (other, Some(source)) => other
+                .diagnostics(source)
+                .iter()
+                .map(|diagnostic| escape_html(&diagnostic.render(source)))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            (other, None) => format!("parse error: {other}"),
         },
         shell_parser::integration::ShellCliError::Execution { command, message } => {
             format!("{command}: {message}")
         }
+        shell_parser::integration::ShellCliError::Limit(limit) => {
+            format!("script stopped, {limit}")
+        }
     }
 }
@@ -0,0 +1,89 @@
+use std::cell::{Cell, RefCell};
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+use web_sys::{AbortController, AbortSignal};
+
+thread_local! {
+    /// Token of the script or job being polled, innermost first.
+    static CURRENT: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+/// Cancels a script or background job, as Ctrl+C does for the foreground one.
+///
+/// Scripts raced against the token with [`CancellationToken::run`] are dropped
+/// mid-command, and requests that took an [`CancellationToken::abort_signal`]
+/// are aborted. Each background job runs under a token of its own, so
+/// interrupting the foreground leaves it alone.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Rc<TokenState>,
+}
+
+#[derive(Default)]
+struct TokenState {
+    cancelled: Cell<bool>,
+    wakers: RefCell<Vec<Waker>>,
+    controllers: RefCell<Vec<AbortController>>,
+}
+
+impl CancellationToken {
+    /// Token of the script or job whose commands are running right now; a
+    /// token that is never cancelled outside of [`CancellationToken::run`].
+    pub fn current() -> Self {
+        CURRENT.with_borrow(|current| current.clone().unwrap_or_default())
+    }
+
+    pub fn cancel(&self) {
+        if self.inner.cancelled.replace(true) {
+            return;
+        }
+        for controller in self.inner.controllers.take() {
+            controller.abort();
+        }
+        for waker in self.inner.wakers.take() {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.get()
+    }
+
+    /// Signal for a `fetch` that aborts it when the token is cancelled.
+    pub fn abort_signal(&self) -> Option<AbortSignal> {
+        let controller = AbortController::new().ok()?;
+        let signal = controller.signal();
+        if self.is_cancelled() {
+            controller.abort();
+        } else {
+            self.inner.controllers.borrow_mut().push(controller);
+        }
+        Some(signal)
+    }
+
+    /// Run `future` until it finishes, or drop it and return `None` once the
+    /// token is cancelled. The token is [`CancellationToken::current`] while
+    /// `future` is polled.
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            if self.is_cancelled() {
+                return Poll::Ready(None);
+            }
+            let outer = CURRENT.replace(Some(self.clone()));
+            let poll = future.as_mut().poll(cx);
+            CURRENT.set(outer);
+            if let Poll::Ready(output) = poll {
+                return Poll::Ready(Some(output));
+            }
+            let mut wakers = self.inner.wakers.borrow_mut();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
@@ -0,0 +1,32 @@
+use crate::{components::SVGIcon, terminal::TerminalHandle};
+use std::future::Future;
+use uuid::Uuid;
+use yew::html;
+
+/// Show a loading indicator in the terminal while `fut` runs.
+pub async fn with_loading<Fut>(terminal: &TerminalHandle, fut: Fut) -> Fut::Output
+where
+    Fut: Future,
+{
+    let _indicator = Indicator {
+        terminal,
+        id: terminal.push_component(html! {
+            <div>
+                <SVGIcon src={include_str!("../icons/loading.svg")}></SVGIcon>
+            </div>
+        }),
+    };
+    fut.await
+}
+
+/// Removes the indicator when the command finishes or is cancelled with Ctrl+C.
+struct Indicator<'a> {
+    terminal: &'a TerminalHandle,
+    id: Uuid,
+}
+
+impl Drop for Indicator<'_> {
+    fn drop(&mut self) {
+        self.terminal.remove(self.id);
+    }
+}
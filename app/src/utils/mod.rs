@@ -1,10 +1,12 @@
+pub mod cancellation;
 pub mod formula;
+pub mod loading;
 pub mod path;
 pub mod reading;
-pub mod run_async;
 pub mod time_format;
 
+pub use cancellation::CancellationToken;
+pub use loading::with_loading;
 pub use path::get_cover_path;
 pub use reading::estimate_reading_minutes;
-pub use run_async::run_async;
 pub use time_format::format_timestamp_short;
@@ -1,5 +1,6 @@
 use micro_cli::{CliError, Parser};
 use shell_parser::integration::ExecutableCommand;
+use shell_parser::{async_trait, CommandIo, ExitStatus};
 
 use super::RunnerContext;
 
@@ -12,46 +13,26 @@ pub struct AddCli {
     pub rhs: i32,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<RunnerContext> for AddCli {
-    fn run(&self, args: &[String], context: &RunnerContext) -> Result<(), String> {
-        let parsed = match AddCli::parse_from(args.to_vec()) {
-            Ok(ok) => ok,
-            Err(CliError::Help(text)) => {
-                println!("{text}");
-                return Ok(());
-            }
-            Err(err) => return Err(err.to_string()),
-        };
-        println!(
-            "{} {} - {}",
-            context.prefix,
-            <AddCli as Parser>::name(),
-            <AddCli as Parser>::description()
-        );
-        println!(
-            "{} + {} = {}",
-            parsed.lhs,
-            parsed.rhs,
-            parsed.lhs + parsed.rhs
-        );
-        Ok(())
-    }
-
-    fn run_with_input(
+    async fn run(
         &self,
         args: &[String],
-        input: Option<String>,
+        io: &mut CommandIo<'_>,
         context: &RunnerContext,
-    ) -> Result<Option<String>, String> {
+    ) -> Result<ExitStatus, String> {
         let parsed = match AddCli::parse_from(args.to_vec()) {
             Ok(ok) => ok,
             Err(CliError::Help(text)) => {
-                println!("{text}");
-                return Ok(None);
+                io.stdout.write_line(text);
+                return Ok(ExitStatus::SUCCESS);
             }
             Err(err) => return Err(err.to_string()),
         };
-        let lhs = input
+        let lhs = io
+            .stdin
+            .as_deref()
+            .and_then(|v| v.lines().last())
             .and_then(|v| v.trim().parse::<i32>().ok())
             .unwrap_or(parsed.lhs);
         let rhs = parsed.rhs;
@@ -63,6 +44,7 @@ impl ExecutableCommand<RunnerContext> for AddCli {
             <AddCli as Parser>::description()
         );
         println!("{} + {} = {}", lhs, rhs, sum);
-        Ok(Some(sum.to_string()))
+        io.stdout.write_line(sum.to_string());
+        Ok(ExitStatus::SUCCESS)
     }
 }
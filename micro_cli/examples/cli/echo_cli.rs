@@ -1,5 +1,6 @@
 use micro_cli::{CliError, Parser};
 use shell_parser::integration::ExecutableCommand;
+use shell_parser::{async_trait, CommandIo, ExitStatus};
 
 use super::RunnerContext;
 
@@ -17,56 +18,31 @@ pub struct EchoCli {
     pub count: u8,
 }
 
+#[async_trait(?Send)]
 impl ExecutableCommand<RunnerContext> for EchoCli {
-    fn run(&self, args: &[String], context: &RunnerContext) -> Result<(), String> {
-        let parsed = match EchoCli::parse_from(args.to_vec()) {
-            Ok(ok) => ok,
-            Err(CliError::Help(text)) => {
-                println!("{text}");
-                return Ok(());
-            }
-            Err(err) => return Err(err.to_string()),
-        };
-        println!(
-            "{} {} - {}",
-            context.prefix,
-            <EchoCli as Parser>::name(),
-            <EchoCli as Parser>::description()
-        );
-        for _ in 0..parsed.count {
-            println!("Hello {}!", parsed.name);
-        }
-        Ok(())
-    }
-
-    fn run_with_input(
+    async fn run(
         &self,
         args: &[String],
-        _input: Option<String>,
+        io: &mut CommandIo<'_>,
         context: &RunnerContext,
-    ) -> Result<Option<String>, String> {
+    ) -> Result<ExitStatus, String> {
         let parsed = match EchoCli::parse_from(args.to_vec()) {
             Ok(ok) => ok,
             Err(CliError::Help(text)) => {
-                println!("{text}");
-                return Ok(None);
+                io.stdout.write_line(text);
+                return Ok(ExitStatus::SUCCESS);
             }
             Err(err) => return Err(err.to_string()),
         };
-        let mut outputs = Vec::new();
-        for _ in 0..parsed.count {
-            outputs.push(parsed.name.clone());
-        }
-        let combined = outputs.join("\n");
         println!(
             "{} {} - {}",
             context.prefix,
             <EchoCli as Parser>::name(),
             <EchoCli as Parser>::description()
         );
-        for line in combined.lines() {
-            println!("Hello {}!", line);
+        for _ in 0..parsed.count {
+            io.stdout.write_line(format!("Hello {}!", parsed.name));
         }
-        Ok(Some(format!("Hello {}!", parsed.name)))
+        Ok(ExitStatus::SUCCESS)
     }
 }
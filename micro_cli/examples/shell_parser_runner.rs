@@ -11,8 +11,8 @@ mod cli {
 use cli::add_cli::AddCli;
 use cli::echo_cli::EchoCli;
 use cli::RunnerContext;
+use shell_parser::block_on;
 use shell_parser::integration::{with_cli, ExecutableCommand};
-use shell_parser::ScriptResult;
 
 fn main() {
     let context = RunnerContext {
@@ -20,7 +20,9 @@ fn main() {
     };
     let commands: Vec<Box<dyn ExecutableCommand<RunnerContext>>> =
         vec![Box::new(EchoCli::default()), Box::new(AddCli::default())];
-    let runner = with_cli(context, commands);
+    let runner = with_cli(context, commands)
+        .with_stdout(|_, text| println!("{text}"))
+        .with_stderr(|_, text| eprintln!("{text}"));
 
     // Execute a script through shell_parser and dispatch to the derived CLIs.
     let script = r#"
@@ -38,17 +40,8 @@ fn main() {
         add -h
     "#;
 
-    match runner.run_script(script) {
-        Ok(ScriptResult::Completed) => {}
-        Ok(ScriptResult::Paused {
-            delay_ms,
-            remainder,
-        }) => {
-            println!(
-                "script paused for {delay_ms}ms; {} commands remaining",
-                remainder.len()
-            );
-        }
+    match block_on(runner.run_script(script)) {
+        Ok(status) => println!("script finished with status {status}"),
         Err(err) => eprintln!("error: {err}"),
     }
 
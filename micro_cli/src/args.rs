@@ -0,0 +1,67 @@
+use crate::error::CliError;
+
+/// Command-line argument after splitting clustered short options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arg {
+    /// `--name`, `--name=value` or a single short option such as `-r`.
+    Option(String),
+    /// Value attached to the short option before it, as in `-d10`.
+    Value(String),
+    /// Argument that is not an option, including everything after `--`.
+    Positional(String),
+}
+
+impl Arg {
+    pub fn into_string(self) -> String {
+        match self {
+            Arg::Option(text) | Arg::Value(text) | Arg::Positional(text) => text,
+        }
+    }
+}
+
+/// Split `args` for the derive: `-rt` becomes `-r -t`, `-d10` becomes `-d`
+/// with the value `10` when `d` is in `value_shorts`, and `--` ends the
+/// options so later arguments are positional even if they start with `-`.
+pub fn split_args<I, T>(args: I, value_shorts: &[char]) -> Vec<Arg>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<String>,
+{
+    let mut split = Vec::new();
+    let mut args = args.into_iter().map(Into::into);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            split.extend(args.by_ref().map(Arg::Positional));
+            break;
+        }
+        let Some(shorts) = arg
+            .strip_prefix('-')
+            .filter(|rest| !rest.is_empty() && !rest.starts_with('-'))
+        else {
+            split.push(if arg.starts_with("--") {
+                Arg::Option(arg)
+            } else {
+                Arg::Positional(arg)
+            });
+            continue;
+        };
+        for (idx, short) in shorts.char_indices() {
+            split.push(Arg::Option(format!("-{short}")));
+            let rest = &shorts[idx + short.len_utf8()..];
+            if value_shorts.contains(&short) {
+                if !rest.is_empty() {
+                    split.push(Arg::Value(rest.to_string()));
+                }
+                break;
+            }
+        }
+    }
+    split
+}
+
+/// Value of the option `option`: the text attached to it or the next argument.
+pub fn take_value(args: &mut impl Iterator<Item = Arg>, option: &str) -> Result<String, CliError> {
+    args.next()
+        .map(Arg::into_string)
+        .ok_or_else(|| CliError::MissingOptionValue(option.to_string()))
+}
@@ -8,6 +8,18 @@ pub enum CliError {
     MissingOptionValue(String),
     #[error("missing argument: {0}")]
     MissingArgument(&'static str),
+    #[error("invalid value '{value}' for {arg}: expected {expected}")]
+    InvalidValue {
+        arg: String,
+        value: String,
+        expected: String,
+    },
+    #[error("{arg} cannot be used with {other}")]
+    Conflict { arg: String, other: String },
+    #[error("{arg} requires {required}")]
+    MissingDependency { arg: String, required: String },
+    #[error("{0} is required")]
+    MissingRequired(String),
     #[error("help")]
     Help(String),
 }
@@ -1,22 +1,30 @@
 //! Tiny macro-friendly CLI framework with basic options, subcommands, and help generation.
 //! Commands are defined declaratively with the [`command!`] macro and assembled into an app
 //! using [`cli!`]. Execution is library-only; callers provide their own I/O or host environment.
+//! Structs can instead `#[derive(Parser)]`, with enums of accepted values deriving [`ValueEnum`].
+//! A derived parser also describes itself as a [`CommandSchema`].
 
 extern crate self as micro_cli;
 
+mod args;
 mod command;
 mod error;
 mod help;
 pub mod macros;
 mod parser;
+mod schema;
 #[cfg(test)]
 mod tests;
+mod value;
 
+pub use args::{split_args, take_value, Arg};
 pub use command::{
     CliApp, Command, CommandContext, CommandHandler, OptionKind, OptionSpec, ParsedOptions,
 };
 pub use error::CliError;
-pub use micro_cli_derive::Parser;
+pub use micro_cli_derive::{Parser, ValueEnum};
+pub use schema::{ArgKind, CommandSchema, OptionSchema, PositionalSchema};
+pub use value::{parse_value, UnknownValue, ValueEnum};
 
 /// Trait implemented by the derive macro for struct- and enum-based CLIs.
 pub trait Parser: Sized {
@@ -25,7 +33,14 @@ pub trait Parser: Sized {
     where
         I: IntoIterator<Item = T>,
         T: Into<String>;
+    /// Help printed for `-h`.
     fn help() -> String;
+    /// Help printed for `--help`, which adds the longer descriptions.
+    fn long_help() -> String {
+        Self::help()
+    }
     fn description() -> String;
     fn name() -> &'static str;
+    /// Options, positionals and subcommands the parser accepts.
+    fn schema() -> CommandSchema;
 }
@@ -42,9 +42,9 @@ pub(crate) fn parse_command<'a>(
         }
 
         if arg.starts_with("--") {
-            handle_long_option(command, arg, &args, &mut idx, &mut options)?;
+            handle_long_option(command, arg, args, &mut idx, &mut options)?;
         } else if arg.starts_with('-') && arg.len() > 1 {
-            handle_short_option(command, arg, &mut options, &args, &mut idx)?;
+            handle_short_option(command, arg, &mut options, args, &mut idx)?;
         } else {
             positionals.push(arg.clone());
         }
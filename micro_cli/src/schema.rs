@@ -0,0 +1,79 @@
+use serde::Serialize;
+
+/// Everything a derived [`Parser`](crate::Parser) accepts, returned by
+/// [`Parser::schema`](crate::Parser::schema) for completion, help UIs and
+/// generated docs. It serialises to JSON with `serde`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandSchema {
+    pub name: String,
+    pub about: String,
+    pub options: Vec<OptionSchema>,
+    pub positionals: Vec<PositionalSchema>,
+    pub subcommands: Vec<CommandSchema>,
+}
+
+impl CommandSchema {
+    /// Fewest arguments the command takes: one for each required positional.
+    /// Options and their values are not counted.
+    pub fn min_args(&self) -> usize {
+        self.positionals
+            .iter()
+            .map(|positional| positional.min)
+            .sum()
+    }
+
+    /// Most arguments the command takes, or `None` when a repeated positional
+    /// leaves it open. Options and their values are not counted.
+    pub fn max_args(&self) -> Option<usize> {
+        self.positionals
+            .iter()
+            .try_fold(0, |total, positional| Some(total + positional.max?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OptionSchema {
+    pub short: Option<char>,
+    /// Long name, without the dashes.
+    pub long: String,
+    pub kind: ArgKind,
+    /// Rust type of each value; `None` for flags and counters.
+    #[serde(rename = "type")]
+    pub value_type: Option<String>,
+    pub default: Option<String>,
+    /// Values a `value_enum` option accepts; empty when any value parses.
+    pub possible_values: Vec<String>,
+    pub required: bool,
+    pub help: String,
+    /// Set by `#[arg(hide)]`: the option parses but is left out of help.
+    pub hidden: bool,
+}
+
+/// How an option is given on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgKind {
+    /// Takes no value, as for a `bool` field.
+    Flag,
+    /// Takes no value and counts how often it is given.
+    Count,
+    /// Takes one value; given again, the last one wins.
+    Value,
+    /// Takes a value each time it is given, as for a `Vec` field.
+    Repeated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PositionalSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub value_type: String,
+    /// Fewest values it takes: 1 when required, otherwise 0.
+    pub min: usize,
+    /// Most values it takes, `None` for a `Vec`.
+    pub max: Option<usize>,
+    pub default: Option<String>,
+    pub possible_values: Vec<String>,
+    pub help: String,
+    pub hidden: bool,
+}
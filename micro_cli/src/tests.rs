@@ -1,6 +1,8 @@
 use std::sync::{Arc, Mutex};
 
-use crate::{cli, command, CliError, CommandContext, OptionSpec, Parser};
+use shell_parser::integration::CommandInfo;
+
+use crate::{cli, command, ArgKind, CliError, CommandContext, OptionSpec, Parser, ValueEnum};
 
 #[test]
 fn runs_command_with_flag_and_args() {
@@ -31,7 +33,7 @@ fn runs_command_with_flag_and_args() {
         commands: [echo]
     };
 
-    app.run(&vec![
+    app.run(&[
         "echo".to_string(),
         "-n".to_string(),
         "hello".to_string(),
@@ -71,12 +73,10 @@ fn handles_subcommands_and_help() {
         commands: [parent]
     };
 
-    app.run(&vec!["parent".into(), "child".into()]).unwrap();
+    app.run(&["parent".into(), "child".into()]).unwrap();
     assert!(*run_flag.lock().unwrap());
 
-    let err = app
-        .run(&vec!["parent".into(), "--help".into()])
-        .unwrap_err();
+    let err = app.run(&["parent".into(), "--help".into()]).unwrap_err();
     match err {
         CliError::Help(text) => {
             assert!(text.contains("Usage"));
@@ -162,4 +162,356 @@ fn derives_parser_style() {
 
     assert_eq!(GreetArgs::name(), "GreetArgs");
     assert_eq!(GreetArgs::description(), "Greet");
+    assert_eq!(
+        args.command_spec().options,
+        vec!["-n", "--name", "-c", "--count", "-h", "--help"]
+    );
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Dark,
+    Light,
+    #[value(name = "high-contrast")]
+    HighContrast,
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "paint", about = "Paint")]
+struct PaintArgs {
+    #[arg(short = 'm', long = "mode", value_enum, help = "Color mode")]
+    mode: Option<Mode>,
+    #[arg(positional, default_value_t = 1, help = "Coats")]
+    coats: u8,
+}
+
+#[test]
+fn reports_invalid_values_and_value_enums() {
+    let args = PaintArgs::parse_from(["--mode", "LIGHT", "3"]).unwrap();
+    assert_eq!(args.mode, Some(Mode::Light));
+    assert_eq!(args.coats, 3);
+    assert_eq!("high-contrast".parse(), Ok(Mode::HighContrast));
+    assert_eq!(
+        Mode::possible_values(),
+        vec!["dark", "light", "high-contrast"]
+    );
+
+    let err = PaintArgs::parse_from(["--mode", "drak"]).unwrap_err();
+    assert_eq!(
+        err,
+        CliError::InvalidValue {
+            arg: "--mode".into(),
+            value: "drak".into(),
+            expected: "one of dark, light, high-contrast (did you mean 'dark'?)".into(),
+        }
+    );
+    let err = PaintArgs::parse_from(["--mode", "blue"]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value 'blue' for --mode: expected one of dark, light, high-contrast"
+    );
+    let err = PaintArgs::parse_from(["abc"]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value 'abc' for coats: expected a whole number"
+    );
+
+    assert!(PaintArgs::help().contains("Color mode [possible values: dark, light, high-contrast]"));
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "render", about = "Render")]
+struct RenderArgs {
+    #[arg(positional, help = "Files")]
+    paths: Vec<String>,
+    #[arg(short = 'r', long = "raw", help = "Raw")]
+    raw: bool,
+    #[arg(short = 't', long = "typewriter", help = "Typewriter")]
+    typewriter: bool,
+    #[arg(short = 'd', long = "delay", default_value_t = 30, help = "Delay")]
+    delay: u32,
+    #[arg(long = "tag", help = "Tag")]
+    tags: Vec<String>,
+    #[arg(short = 'w', long = "width", help = "Widths")]
+    widths: Vec<u16>,
+    #[arg(short = 'v', long = "verbose", count, help = "Verbosity")]
+    verbose: u8,
+}
+
+#[test]
+fn splits_clustered_and_repeated_options() {
+    let args = RenderArgs::parse_from([
+        "-rtd10",
+        "--tag",
+        "a",
+        "--tag=b",
+        "-vvv",
+        "-w80",
+        "--verbose",
+        "one.md",
+        "--",
+        "-draft.md",
+        "--tag",
+    ])
+    .unwrap();
+    assert!(args.raw && args.typewriter);
+    assert_eq!(args.delay, 10);
+    assert_eq!(args.tags, vec!["a", "b"]);
+    assert_eq!(args.widths, vec![80]);
+    assert_eq!(args.verbose, 4);
+    assert_eq!(args.paths, vec!["one.md", "-draft.md", "--tag"]);
+
+    let args = RenderArgs::parse_from(["-d", "5", "-", "two.md"]).unwrap();
+    assert_eq!(args.delay, 5);
+    assert_eq!(args.paths, vec!["-", "two.md"]);
+    assert!(args.tags.is_empty() && args.verbose == 0);
+
+    assert_eq!(
+        RenderArgs::parse_from(["-rx"]).unwrap_err(),
+        CliError::UnknownOption("-x".into())
+    );
+    assert_eq!(
+        RenderArgs::parse_from(["-rd"]).unwrap_err(),
+        CliError::MissingOptionValue("-d".into())
+    );
+    assert_eq!(
+        RenderArgs::parse_from(["-w", "wide"])
+            .unwrap_err()
+            .to_string(),
+        "invalid value 'wide' for --width: expected a whole number"
+    );
+    assert!(matches!(
+        RenderArgs::parse_from(["-rh"]).unwrap_err(),
+        CliError::Help(_)
+    ));
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "nav", about = "Navigate")]
+#[group(id = "mode", multiple = false, required = true)]
+struct NavArgs {
+    #[arg(short = 'p', long = "push", group = "mode", help = "Push")]
+    push: Option<String>,
+    #[arg(short = 'b', long = "back", group = "mode", help = "Back")]
+    back: bool,
+    #[arg(long = "title", requires = "push", help = "Title")]
+    title: Option<String>,
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        conflicts_with = "verbose",
+        help = "Quiet"
+    )]
+    quiet: bool,
+    #[arg(short = 'v', long = "verbose", help = "Verbose")]
+    verbose: bool,
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "open", about = "Open")]
+struct OpenArgs {
+    #[arg(long = "file", required_unless_present = "url", help = "File")]
+    file: Option<String>,
+    #[arg(long = "url", help = "URL")]
+    url: Option<String>,
+}
+
+#[test]
+fn enforces_argument_relations() {
+    let args = NavArgs::parse_from(["--push", "/a", "--title", "A", "-v"]).unwrap();
+    assert_eq!(args.push.as_deref(), Some("/a"));
+    assert_eq!(
+        (args.title.as_deref(), args.quiet, args.verbose),
+        (Some("A"), false, true)
+    );
+    assert!(NavArgs::parse_from(["-b"]).unwrap().back);
+
+    assert_eq!(
+        NavArgs::parse_from(["-p", "/a", "-b"]).unwrap_err(),
+        CliError::Conflict {
+            arg: "--back".into(),
+            other: "--push".into(),
+        }
+    );
+    assert_eq!(
+        NavArgs::parse_from(["-b", "-qv"]).unwrap_err().to_string(),
+        "--quiet cannot be used with --verbose"
+    );
+    assert_eq!(
+        NavArgs::parse_from(["-b", "--title", "A"])
+            .unwrap_err()
+            .to_string(),
+        "--title requires --push"
+    );
+    assert_eq!(
+        NavArgs::parse_from(["-q"]).unwrap_err().to_string(),
+        "one of --push or --back is required"
+    );
+    assert!(matches!(
+        NavArgs::parse_from(Vec::<String>::new()).unwrap_err(),
+        CliError::Help(_)
+    ));
+
+    let help = NavArgs::help();
+    assert!(help.contains("Title (requires --push)"));
+    assert!(help.contains("Quiet (conflicts with --verbose)"));
+    assert!(help.contains("Exactly one of --push or --back is required"));
+
+    let args = OpenArgs::parse_from(["--url", "x"]).unwrap();
+    assert_eq!((args.file, args.url.as_deref()), (None, Some("x")));
+    assert_eq!(
+        OpenArgs::parse_from(["--file"]).unwrap_err(),
+        CliError::MissingOptionValue("--file".into())
+    );
+    assert!(OpenArgs::help().contains("File (required unless --url is given)"));
+    assert!(matches!(
+        OpenArgs::parse_from(Vec::<String>::new()).unwrap_err(),
+        CliError::Help(_)
+    ));
+}
+
+/// Wait before going on.
+///
+/// Blocks the shell until the time is up.
+#[derive(Parser, Debug)]
+#[command(
+    name = "pause",
+    example = "pause 500",
+    example = "pause --label boot 1000",
+    after_help = "Press Ctrl-C to stop early."
+)]
+struct PauseArgs {
+    /// Time to wait.
+    ///
+    /// Rounded to the next frame.
+    #[arg(positional, value_name = "MILLIS")]
+    millis: u64,
+    /// Text shown while waiting
+    #[arg(short = 'l', long = "label")]
+    label: Option<String>,
+    #[arg(long = "trace", hide)]
+    trace: bool,
+}
+
+#[test]
+fn builds_help_from_doc_comments() {
+    let args = PauseArgs::parse_from(["--trace", "10"]).unwrap();
+    assert_eq!(
+        (args.millis, args.label.as_deref(), args.trace),
+        (10, None, true)
+    );
+    assert_eq!(PauseArgs::description(), "Wait before going on");
+
+    let help = PauseArgs::help();
+    assert!(help.starts_with("Usage: pause [OPTIONS] <MILLIS>\nWait before going on\n"));
+    assert!(help.contains("  -l, --label <LABEL> Text shown while waiting\n"));
+    assert!(help.contains("  -h, --help          Show help\n"));
+    assert!(help.contains("pause <MILLIS>, Time to wait\n"));
+    assert!(help.contains("Examples:\n  pause 500\n  pause --label boot 1000\n"));
+    assert!(help.ends_with("\nPress Ctrl-C to stop early.\n"));
+    assert!(!help.contains("Blocks the shell"));
+    assert!(!help.contains("Rounded"));
+    assert!(!help.contains("--trace"));
+    assert_eq!(
+        args.command_spec().options,
+        vec!["-l", "--label", "-h", "--help"]
+    );
+
+    let long_help = PauseArgs::long_help();
+    assert!(long_help.contains("Wait before going on\n\nBlocks the shell until the time is up.\n"));
+    assert!(long_help.contains("Time to wait\n    Rounded to the next frame.\n"));
+    assert_eq!(
+        PauseArgs::parse_from(["--help"]).unwrap_err(),
+        CliError::Help(long_help)
+    );
+    assert_eq!(
+        PauseArgs::parse_from(["-h"]).unwrap_err(),
+        CliError::Help(help)
+    );
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "copy", about = "Copy")]
+struct CopyArgs {
+    #[arg(positional, help = "Source")]
+    from: String,
+    #[arg(positional, help = "Target")]
+    to: Option<String>,
+}
+
+#[test]
+fn describes_commands_as_schema() {
+    let args = CopyArgs::parse_from(["a", "b"]).unwrap();
+    assert_eq!((args.from.as_str(), args.to.as_deref()), ("a", Some("b")));
+    let schema = CopyArgs::schema();
+    assert_eq!((schema.min_args(), schema.max_args()), (1, Some(2)));
+    let spec = args.command_spec();
+    assert_eq!((spec.min_args, spec.max_args), (1, Some(2)));
+
+    // The shell counts only positionals, leaving out a lone `--`, options
+    // and their values, and lets help through whatever the count.
+    let paint = PaintArgs::parse_from(["2"]).unwrap().command_spec();
+    assert_eq!((paint.min_args, paint.max_args), (0, Some(1)));
+    let parser = shell_parser::ShellParser::with_commands([spec, paint]);
+    let parsed = parser.parse("copy -- -x").unwrap();
+    let copy = CopyArgs::parse_from(parsed[0].args.clone()).unwrap();
+    assert_eq!((copy.from.as_str(), copy.to), ("-x", None));
+    assert!(parser.parse("copy").is_err());
+    assert!(parser.parse("copy -- a b c").is_err());
+    assert!(parser.parse("copy --help; paint -h 1 2").is_ok());
+    assert!(parser
+        .parse("paint --mode dark 3; paint -mdark 3; paint")
+        .is_ok());
+    assert!(parser.parse("paint -m dark 2 3").is_err());
+    assert!(parser.parse("paint 3 4").is_err());
+
+    let schema = PaintArgs::schema();
+    assert_eq!((schema.min_args(), schema.max_args()), (0, Some(1)));
+    let json = serde_json::to_value(&schema).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "name": "paint",
+            "about": "Paint",
+            "options": [{
+                "short": "m",
+                "long": "mode",
+                "kind": "value",
+                "type": "Mode",
+                "default": null,
+                "possible_values": ["dark", "light", "high-contrast"],
+                "required": false,
+                "help": "Color mode",
+                "hidden": false,
+            }],
+            "positionals": [{
+                "name": "coats",
+                "type": "u8",
+                "min": 0,
+                "max": 1,
+                "default": "1",
+                "possible_values": [],
+                "help": "Coats",
+                "hidden": false,
+            }],
+            "subcommands": [],
+        })
+    );
+
+    let schema = RenderArgs::schema();
+    let kinds: Vec<_> = schema.options.iter().map(|option| option.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            ArgKind::Flag,
+            ArgKind::Flag,
+            ArgKind::Value,
+            ArgKind::Repeated,
+            ArgKind::Repeated,
+            ArgKind::Count,
+        ]
+    );
+    assert_eq!(schema.positionals[0].max, None);
+    assert_eq!(schema.options[4].value_type.as_deref(), Some("u16"));
+    assert!(PauseArgs::schema().options[1].hidden);
 }
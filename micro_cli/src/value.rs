@@ -0,0 +1,121 @@
+use std::any::Any;
+use std::fmt;
+use std::num::{IntErrorKind, ParseFloatError, ParseIntError};
+use std::str::{FromStr, ParseBoolError};
+
+use crate::error::CliError;
+
+/// Enum whose unit variants are the accepted values of an argument.
+///
+/// Derive it with `#[derive(ValueEnum)]`, which also implements `FromStr`; mark
+/// the field `#[arg(value_enum)]` to list the values in help.
+pub trait ValueEnum: Sized + Clone + 'static {
+    /// Every variant, in the order values are listed.
+    fn value_variants() -> &'static [Self];
+
+    /// Name of the variant on the command line.
+    fn to_value(&self) -> &'static str;
+
+    fn possible_values() -> Vec<&'static str> {
+        Self::value_variants().iter().map(Self::to_value).collect()
+    }
+
+    /// Variant named `value`, ignoring case.
+    fn from_value(value: &str) -> Result<Self, UnknownValue> {
+        Self::value_variants()
+            .iter()
+            .find(|variant| variant.to_value().eq_ignore_ascii_case(value))
+            .cloned()
+            .ok_or_else(|| UnknownValue::new(value, Self::possible_values()))
+    }
+}
+
+/// Error of a [`ValueEnum`] given a value that names no variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownValue {
+    pub possible: Vec<&'static str>,
+    /// Closest possible value, when one is close enough to be a typo.
+    pub suggestion: Option<&'static str>,
+}
+
+impl UnknownValue {
+    pub fn new(value: &str, possible: Vec<&'static str>) -> Self {
+        let value = value.to_ascii_lowercase();
+        let suggestion = possible
+            .iter()
+            .map(|candidate| (edit_distance(&value, candidate), *candidate))
+            .filter(|(distance, candidate)| *distance <= candidate.len() / 3 + 1)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate);
+        Self {
+            possible,
+            suggestion,
+        }
+    }
+}
+
+impl fmt::Display for UnknownValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "one of {}", self.possible.join(", "))?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean '{suggestion}'?)")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnknownValue {}
+
+/// Parse the value given for `arg`, used by the derive for every typed field.
+///
+/// Failures become [`CliError::InvalidValue`]; numbers and booleans say what
+/// they expect, other types use the `Display` of their `FromStr` error.
+pub fn parse_value<T>(arg: &str, value: String) -> Result<T, CliError>
+where
+    T: FromStr,
+    T::Err: fmt::Display + 'static,
+{
+    value.parse().map_err(|err: T::Err| {
+        let any: &dyn Any = &err;
+        let expected = if let Some(err) = any.downcast_ref::<ParseIntError>() {
+            match err.kind() {
+                IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                    "a whole number in range".to_string()
+                }
+                _ => "a whole number".to_string(),
+            }
+        } else if any.is::<ParseFloatError>() {
+            "a number".to_string()
+        } else if any.is::<ParseBoolError>() {
+            "true or false".to_string()
+        } else {
+            err.to_string()
+        };
+        CliError::InvalidValue {
+            arg: arg.to_string(),
+            value,
+            expected,
+        }
+    })
+}
+
+/// Number of single-character insertions, deletions and substitutions that
+/// turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
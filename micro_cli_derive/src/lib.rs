@@ -5,7 +5,7 @@ use syn::{
     Fields, Ident, Type,
 };
 
-#[proc_macro_derive(Parser, attributes(command, arg))]
+#[proc_macro_derive(Parser, attributes(command, arg, group))]
 pub fn derive_parser(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     match expand(&input) {
@@ -20,10 +20,37 @@ pub fn derive_parser(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Implements `micro_cli::ValueEnum` and `FromStr` for an enum of unit variants.
+///
+/// Each variant is named by its lowercased identifier, or by
+/// `#[value(name = "...")]`.
+#[proc_macro_derive(ValueEnum, attributes(value))]
+pub fn derive_value_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_value_enum(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
 #[derive(Default, Clone)]
 struct CommandMeta {
     name: Option<syn::LitStr>,
     about: Option<syn::LitStr>,
+    /// Doc comment paragraphs after the first, shown by `--help`.
+    long_about: Option<String>,
+    after_help: Option<syn::LitStr>,
+    examples: Vec<syn::LitStr>,
+    groups: Vec<GroupMeta>,
+}
+
+/// `#[group(...)]` on a struct: options of which at most one, or at least one,
+/// may be given. Without an `id` the group holds every option of the struct.
+#[derive(Clone)]
+struct GroupMeta {
+    id: Option<syn::LitStr>,
+    multiple: bool,
+    required: bool,
 }
 
 #[derive(Default, Clone)]
@@ -31,9 +58,19 @@ struct ArgMeta {
     short: Option<char>,
     long: Option<String>,
     help: Option<String>,
+    long_help: Option<String>,
+    /// Placeholder for the value in help, such as `MILLIS` for `<MILLIS>`.
+    value_name: Option<String>,
+    hide: bool,
     default_value: Option<syn::Expr>,
     is_subcommand: bool,
     positional: bool,
+    value_enum: bool,
+    count: bool,
+    conflicts_with: Vec<syn::LitStr>,
+    requires: Vec<syn::LitStr>,
+    required_unless_present: Vec<syn::LitStr>,
+    group: Option<syn::LitStr>,
 }
 
 fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
@@ -53,9 +90,56 @@ fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     }
 }
 
+/// Text of the `///` comments in `attrs`: the first paragraph on one line,
+/// without the period ending a single sentence, and the paragraphs after it.
+fn doc_text(attrs: &[Attribute]) -> (Option<String>, Option<String>) {
+    let mut lines = Vec::new();
+    for attr in attrs {
+        if !attr.path().is_ident("doc") {
+            continue;
+        }
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(line),
+                    ..
+                }),
+            ..
+        }) = &attr.meta
+        {
+            let line = line.value();
+            lines.push(
+                line.strip_prefix(' ')
+                    .unwrap_or(&line)
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+    }
+    let text = lines.join("\n");
+    let text = text.trim();
+    if text.is_empty() {
+        return (None, None);
+    }
+    let (first, rest) = text.split_once("\n\n").unwrap_or((text, ""));
+    let mut short = first.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+    if short.ends_with('.') && !short.ends_with("..") && !short.contains(". ") {
+        short.pop();
+    }
+    let rest = rest.trim();
+    (Some(short), (!rest.is_empty()).then(|| rest.to_string()))
+}
+
 fn parse_command_meta(attrs: &[Attribute]) -> syn::Result<CommandMeta> {
     let mut meta = CommandMeta::default();
+    let (about, long_about) = doc_text(attrs);
+    meta.about = about.map(|text| syn::LitStr::new(&text, proc_macro2::Span::call_site()));
+    meta.long_about = long_about;
     for attr in attrs {
+        if attr.path().is_ident("group") {
+            meta.groups.push(parse_group_meta(attr)?);
+            continue;
+        }
         if !attr.path().is_ident("command") {
             continue;
         }
@@ -66,6 +150,10 @@ fn parse_command_meta(attrs: &[Attribute]) -> syn::Result<CommandMeta> {
             } else if nested.path.is_ident("name") {
                 let lit: syn::LitStr = nested.value()?.parse()?;
                 meta.name = Some(lit);
+            } else if nested.path.is_ident("after_help") {
+                meta.after_help = Some(nested.value()?.parse()?);
+            } else if nested.path.is_ident("example") {
+                meta.examples.push(nested.value()?.parse()?);
             } else if nested.path.is_ident("version") {
                 return Err(syn::Error::new(
                     nested.path.span(),
@@ -78,8 +166,30 @@ fn parse_command_meta(attrs: &[Attribute]) -> syn::Result<CommandMeta> {
     Ok(meta)
 }
 
+fn parse_group_meta(attr: &Attribute) -> syn::Result<GroupMeta> {
+    let mut group = GroupMeta {
+        id: None,
+        multiple: true,
+        required: false,
+    };
+    attr.parse_nested_meta(|nested| {
+        if nested.path.is_ident("id") {
+            group.id = Some(nested.value()?.parse()?);
+        } else if nested.path.is_ident("multiple") {
+            group.multiple = nested.value()?.parse::<syn::LitBool>()?.value;
+        } else if nested.path.is_ident("required") {
+            group.required = nested.value()?.parse::<syn::LitBool>()?.value;
+        } else {
+            return Err(nested.error("expected `id`, `multiple` or `required`"));
+        }
+        Ok(())
+    })?;
+    Ok(group)
+}
+
 fn parse_arg_meta(attrs: &[Attribute]) -> syn::Result<ArgMeta> {
     let mut meta = ArgMeta::default();
+    (meta.help, meta.long_help) = doc_text(attrs);
     for attr in attrs {
         if !(attr.path().is_ident("arg") || attr.path().is_ident("command")) {
             continue;
@@ -105,10 +215,27 @@ fn parse_arg_meta(attrs: &[Attribute]) -> syn::Result<ArgMeta> {
             } else if nested.path.is_ident("help") {
                 let s: syn::LitStr = nested.value()?.parse()?;
                 meta.help = Some(s.value());
+            } else if nested.path.is_ident("value_name") {
+                let s: syn::LitStr = nested.value()?.parse()?;
+                meta.value_name = Some(s.value());
+            } else if nested.path.is_ident("hide") {
+                meta.hide = true;
             } else if nested.path.is_ident("subcommand") {
                 meta.is_subcommand = true;
             } else if nested.path.is_ident("positional") {
                 meta.positional = true;
+            } else if nested.path.is_ident("value_enum") {
+                meta.value_enum = true;
+            } else if nested.path.is_ident("count") {
+                meta.count = true;
+            } else if nested.path.is_ident("conflicts_with") {
+                meta.conflicts_with.push(nested.value()?.parse()?);
+            } else if nested.path.is_ident("requires") {
+                meta.requires.push(nested.value()?.parse()?);
+            } else if nested.path.is_ident("required_unless_present") {
+                meta.required_unless_present.push(nested.value()?.parse()?);
+            } else if nested.path.is_ident("group") {
+                meta.group = Some(nested.value()?.parse()?);
             }
             Ok(())
         })?;
@@ -116,20 +243,212 @@ fn parse_arg_meta(attrs: &[Attribute]) -> syn::Result<ArgMeta> {
     Ok(meta)
 }
 
-fn is_vec_string(ty: &Type) -> bool {
+/// `T` when `ty` is `wrapper<T>`, such as `Vec<T>` or `Option<T>`.
+fn inner_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
     if let Type::Path(p) = ty {
-        let mut segments = p.path.segments.iter();
-        if let Some(seg) = segments.next() {
-            if seg.ident == "Vec" {
+        if let Some(seg) = p.path.segments.last() {
+            if seg.ident == wrapper {
                 if let syn::PathArguments::AngleBracketed(args) = &seg.arguments {
-                    if let Some(syn::GenericArgument::Type(Type::Path(inner))) = args.args.first() {
-                        return inner.path.is_ident("String");
+                    if let Some(syn::GenericArgument::Type(inner)) = args.args.first() {
+                        return Some(inner);
                     }
                 }
             }
         }
     }
-    false
+    None
+}
+
+/// Name of `ty` as written, such as `u32` or `String`, for the schema.
+fn type_name(ty: &Type) -> String {
+    quote!(#ty).to_string().replace(' ', "")
+}
+
+/// Type of each value: `T` for `Option<T>` and `Vec<T>`, otherwise the type itself.
+fn value_type(ty: &Type) -> &Type {
+    inner_type(ty, "Option")
+        .or_else(|| inner_type(ty, "Vec"))
+        .unwrap_or(ty)
+}
+
+/// Checks for `conflicts_with`, `requires`, `required_unless_present` and
+/// groups, run once every argument is read, and what help says about them.
+#[derive(Default)]
+struct Relations {
+    checks: Vec<proc_macro2::TokenStream>,
+    /// Help notes of each option, by field name.
+    notes: Vec<(String, Vec<String>)>,
+    group_lines: Vec<String>,
+    /// Whether some option must be given, so no arguments at all shows help.
+    required: bool,
+}
+
+impl Relations {
+    fn note(&self, field: &str) -> Option<String> {
+        self.notes
+            .iter()
+            .find(|(name, notes)| name == field && !notes.is_empty())
+            .map(|(_, notes)| notes.join("; "))
+    }
+}
+
+fn relations(data: &DataStruct, meta: &CommandMeta) -> syn::Result<Relations> {
+    let mut options = Vec::new();
+    for field in data.fields.iter() {
+        let arg = parse_arg_meta(&field.attrs)?;
+        let name = field
+            .ident
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        if arg.positional || arg.is_subcommand {
+            let related = !arg.conflicts_with.is_empty()
+                || !arg.requires.is_empty()
+                || !arg.required_unless_present.is_empty()
+                || arg.group.is_some();
+            if related {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "only options and flags can have relations",
+                ));
+            }
+            continue;
+        }
+        let flag = format!("--{}", arg.long.clone().unwrap_or_else(|| name.clone()));
+        options.push((name, flag, arg));
+    }
+    let flag_of = |lit: &syn::LitStr| {
+        options
+            .iter()
+            .find(|(name, ..)| *name == lit.value())
+            .map(|(_, flag, _)| flag.clone())
+            .ok_or_else(|| syn::Error::new(lit.span(), "no option or flag with this name"))
+    };
+
+    let mut relations = Relations::default();
+    let mut conflicts = Vec::new();
+    let mut requires = Vec::new();
+    let mut required = Vec::new();
+    for (name, flag, arg) in &options {
+        let mut notes = Vec::new();
+        for other in &arg.conflicts_with {
+            let other_flag = flag_of(other)?;
+            conflicts.push(quote! {
+                if present.contains(&#name) && present.contains(&#other) {
+                    return Err(::micro_cli::CliError::Conflict {
+                        arg: #flag.to_string(),
+                        other: #other_flag.to_string(),
+                    });
+                }
+            });
+            notes.push(format!("conflicts with {}", other_flag));
+        }
+        for other in &arg.requires {
+            let other_flag = flag_of(other)?;
+            requires.push(quote! {
+                if present.contains(&#name) && !present.contains(&#other) {
+                    return Err(::micro_cli::CliError::MissingDependency {
+                        arg: #flag.to_string(),
+                        required: #other_flag.to_string(),
+                    });
+                }
+            });
+            notes.push(format!("requires {}", other_flag));
+        }
+        if !arg.required_unless_present.is_empty() {
+            let others = &arg.required_unless_present;
+            let other_flags = others
+                .iter()
+                .map(flag_of)
+                .collect::<syn::Result<Vec<_>>>()?;
+            let alternatives = join_or(&[vec![flag.clone()], other_flags.clone()].concat());
+            required.push(quote! {
+                if !present.contains(&#name) #(&& !present.contains(&#others))* {
+                    return Err(::micro_cli::CliError::MissingRequired(#alternatives.to_string()));
+                }
+            });
+            notes.push(format!(
+                "required unless {} is given",
+                join_or(&other_flags)
+            ));
+            relations.required = true;
+        }
+        relations.notes.push((name.clone(), notes));
+    }
+
+    for (_, _, arg) in &options {
+        if let Some(group) = &arg.group {
+            let declared = meta.groups.iter().any(|declared| {
+                declared.id.as_ref().map(syn::LitStr::value) == Some(group.value())
+            });
+            if !declared {
+                return Err(syn::Error::new(
+                    group.span(),
+                    "no #[group(id = ...)] with this id",
+                ));
+            }
+        }
+    }
+    for group in &meta.groups {
+        let members: Vec<_> = options
+            .iter()
+            .filter(|(_, _, arg)| match &group.id {
+                Some(id) => arg.group.as_ref().map(syn::LitStr::value) == Some(id.value()),
+                None => true,
+            })
+            .collect();
+        let names: Vec<_> = members.iter().map(|(name, ..)| name).collect();
+        let flags: Vec<_> = members.iter().map(|(_, flag, _)| flag.clone()).collect();
+        let list = join_or(&flags);
+        if !group.multiple {
+            conflicts.push(quote! {
+                let given: Vec<&str> = [#((#names, #flags)),*]
+                    .iter()
+                    .filter(|(name, _)| present.contains(name))
+                    .map(|(_, flag)| *flag)
+                    .collect();
+                if given.len() > 1 {
+                    return Err(::micro_cli::CliError::Conflict {
+                        arg: given[1].to_string(),
+                        other: given[0].to_string(),
+                    });
+                }
+            });
+        }
+        if group.required {
+            let one_of = format!("one of {}", list);
+            required.push(quote! {
+                if #(!present.contains(&#names))&&* {
+                    return Err(::micro_cli::CliError::MissingRequired(#one_of.to_string()));
+                }
+            });
+            relations.required = true;
+        }
+        match (group.multiple, group.required) {
+            (false, true) => relations
+                .group_lines
+                .push(format!("Exactly one of {} is required", list)),
+            (false, false) => relations
+                .group_lines
+                .push(format!("At most one of {} may be given", list)),
+            (true, true) => relations
+                .group_lines
+                .push(format!("At least one of {} is required", list)),
+            (true, false) => {}
+        }
+    }
+
+    relations.checks = [conflicts, requires, required].concat();
+    Ok(relations)
+}
+
+/// `a`, `a or b`, `a, b or c`.
+fn join_or(items: &[String]) -> String {
+    match items.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        Some((last, _)) => last.clone(),
+        None => String::new(),
+    }
 }
 
 fn expand_struct(
@@ -143,7 +462,13 @@ fn expand_struct(
     let mut field_inits = Vec::new();
     let mut option_help_lines = Vec::new();
     let mut positional_help_lines = Vec::new();
-    let mut positional_names: Vec<syn::LitStr> = Vec::new();
+    let mut positional_names: Vec<String> = Vec::new();
+    let mut option_flags: Vec<syn::LitStr> = Vec::new();
+    let mut value_shorts: Vec<char> = Vec::new();
+    let mut value_flags: Vec<syn::LitStr> = Vec::new();
+    let mut option_schemas = Vec::new();
+    let mut positional_schemas = Vec::new();
+    let mut subcommand_schemas = Vec::new();
     let name_lit = meta
         .name
         .clone()
@@ -152,9 +477,18 @@ fn expand_struct(
         .about
         .clone()
         .unwrap_or_else(|| syn::LitStr::new(&format!("{} options", ident), ident.span()));
+    let long_about = meta.long_about.clone().unwrap_or_default();
+    let after_help = meta
+        .after_help
+        .as_ref()
+        .map(syn::LitStr::value)
+        .unwrap_or_default();
+    let examples = &meta.examples;
     let mut subcommand_field: Option<Ident> = None;
     let mut needs_positionals_iter = false;
-    let mut has_required_fields = false;
+    let relations = relations(data, meta)?;
+    let track = !relations.checks.is_empty();
+    let mut has_required_fields = relations.required;
 
     for field in data.fields.iter() {
         let fname = field
@@ -168,24 +502,59 @@ fn expand_struct(
         let positional_lit = syn::LitStr::new(&long, field.span());
         let short = arg_meta.short;
         let short_lit = short.map(|c| syn::LitStr::new(&format!("-{}", c), field.span()));
-        let short_opt_expr = if let Some(ch) = short {
-            quote! { Some(#ch) }
-        } else {
-            quote! { None }
-        };
-        let help_text = arg_meta
+        let mut help_text = arg_meta
             .help
             .clone()
             .unwrap_or_else(|| format!("Set {}", long));
+        if let Some(note) = relations.note(&fname.to_string()) {
+            help_text = format!("{} ({})", help_text, note);
+        }
+        // Options given on the command line, by field name, for the relations.
+        let mark = if track {
+            let name = fname.to_string();
+            quote! { present.push(#name); }
+        } else {
+            quote! {}
+        };
+        let help_expr = if arg_meta.value_enum {
+            let value_ty = value_type(&ty);
+            quote! {
+                format!(
+                    "{} [possible values: {}]",
+                    #help_text,
+                    <#value_ty as ::micro_cli::ValueEnum>::possible_values().join(", ")
+                )
+            }
+        } else {
+            quote! { #help_text }
+        };
         let is_positional = arg_meta.positional;
 
         if arg_meta.is_subcommand {
             declarations.push(quote! { let mut #fname: Option<#ty> = None; });
             subcommand_field = Some(fname.clone());
             field_inits.push(quote! { #fname });
-            positional_names.push(syn::LitStr::new("<SUBCOMMAND>", ident.span()));
+            positional_names.push("[<SUBCOMMAND>]".to_string());
+            let value_ty = value_type(&ty);
+            let type_name = type_name(value_ty);
+            positional_schemas.push(quote! {
+                ::micro_cli::PositionalSchema {
+                    name: "subcommand".to_string(),
+                    value_type: #type_name.to_string(),
+                    min: 0,
+                    max: None,
+                    default: None,
+                    possible_values: Vec::new(),
+                    help: "Run a subcommand".to_string(),
+                    hidden: false,
+                }
+            });
+            subcommand_schemas.push(quote! { <#value_ty as ::micro_cli::Parser>::schema() });
             positional_help_lines.push(quote! {
-                positional_lines.push(format!("{} [{}], {}", #name_lit, "<SUBCOMMAND>", "Run a subcommand"));
+                positional_lines.push((
+                    format!("{} [{}], {}", #name_lit, "<SUBCOMMAND>", "Run a subcommand"),
+                    "",
+                ));
             });
             continue;
         }
@@ -197,130 +566,241 @@ fn expand_struct(
         );
         let default_expr = arg_meta.default_value.clone();
 
-        if is_bool && !is_positional {
+        let is_vec = inner_type(&ty, "Vec").is_some();
+
+        if arg_meta.count && !is_positional {
+            declarations.push(quote! { let mut #fname: #ty = 0; });
+            match_arms.push(quote! {
+                #long_lit => { #mark #fname = #fname.saturating_add(1); continue; }
+            });
+            if let Some(lit) = short_lit.clone() {
+                match_arms.push(quote! {
+                    #lit => { #mark #fname = #fname.saturating_add(1); continue; }
+                });
+            }
+        } else if is_bool && !is_positional {
             declarations.push(quote! { let mut #fname: bool = false; });
             match_arms.push(quote! {
-                #long_lit => { #fname = true; continue; }
+                #long_lit => { #mark #fname = true; continue; }
             });
             if let Some(lit) = short_lit.clone() {
                 match_arms.push(quote! {
-                    #lit => { #fname = true; continue; }
+                    #lit => { #mark #fname = true; continue; }
                 });
             }
             post_process.push(quote! { let #fname: #ty = #fname; });
         } else if is_positional {
-            let is_vec = is_vec_string(&ty);
             if is_vec {
-                declarations.push(quote! { let mut #fname: Vec<String> = Vec::new(); });
                 needs_positionals_iter = true;
                 post_process.push(quote! {
-                    #fname.extend(positionals_iter.by_ref());
-                    let #fname: #ty = #fname;
+                    let #fname: #ty = positionals_iter
+                        .by_ref()
+                        .map(|val| ::micro_cli::parse_value(#positional_lit, val))
+                        .collect::<Result<_, _>>()?;
                 });
             } else {
                 declarations.push(quote! { let mut #fname: Option<String> = None; });
                 needs_positionals_iter = true;
                 let convert = if is_option {
                     quote! {
-                        let #fname: #ty = match #fname.take() {
-                            Some(val) => Some(val.parse().map_err(|_| ::micro_cli::CliError::MissingArgument(#long))?),
-                            None => positionals_iter
-                                .next()
-                                .map(|val| val.parse().map_err(|_| ::micro_cli::CliError::MissingArgument(#long)))
-                                .transpose()?,
-                        };
+                        let #fname: #ty = #fname
+                            .take()
+                            .or_else(|| positionals_iter.next())
+                            .map(|val| ::micro_cli::parse_value(#positional_lit, val))
+                            .transpose()?;
                     }
                 } else if let Some(default) = default_expr {
                     quote! {
                         let #fname: #ty = match #fname.take().or_else(|| positionals_iter.next()) {
-                            Some(val) => val.parse().map_err(|_| ::micro_cli::CliError::MissingArgument(#long))?,
+                            Some(val) => ::micro_cli::parse_value(#positional_lit, val)?,
                             None => #default,
                         };
                     }
                 } else {
                     has_required_fields = true;
                     quote! {
-                        let #fname: #ty = #fname
-                            .take()
-                            .or_else(|| positionals_iter.next())
-                            .ok_or_else(|| ::micro_cli::CliError::MissingArgument(#long))?
-                            .parse()
-                            .map_err(|_| ::micro_cli::CliError::MissingArgument(#long))?;
+                        let #fname: #ty = ::micro_cli::parse_value(
+                            #positional_lit,
+                            #fname
+                                .take()
+                                .or_else(|| positionals_iter.next())
+                                .ok_or_else(|| ::micro_cli::CliError::MissingArgument(#long))?,
+                        )?;
                     }
                 };
                 post_process.push(convert);
             }
         } else {
-            declarations.push(quote! { let mut #fname: Option<String> = None; });
+            // Repeated options collect every value; others keep the last one.
+            let store = if is_vec {
+                declarations.push(quote! { let mut #fname: Vec<String> = Vec::new(); });
+                quote! { #mark #fname.push(value); }
+            } else {
+                declarations.push(quote! { let mut #fname: Option<String> = None; });
+                quote! { #mark #fname = Some(value); }
+            };
             match_arms.push(quote! {
                 #long_lit => {
-                    let value = iter.next().ok_or_else(|| ::micro_cli::CliError::MissingOptionValue(token.clone()))?;
-                    #fname = Some(value);
+                    let value = ::micro_cli::take_value(&mut iter, &token)?;
+                    #store
                     continue;
                 }
             });
             if let Some(lit) = short_lit.clone() {
                 match_arms.push(quote! {
                     #lit => {
-                        let value = iter.next().ok_or_else(|| ::micro_cli::CliError::MissingOptionValue(token.clone()))?;
-                        #fname = Some(value);
+                        let value = ::micro_cli::take_value(&mut iter, &token)?;
+                        #store
                         continue;
                     }
                 });
             }
+            value_shorts.extend(short);
+            value_flags.extend(short_lit.clone());
+            value_flags.push(long_lit.clone());
+            let long_eq_lit = syn::LitStr::new(&format!("--{}=", long), field.span());
             match_arms.push(quote! {
-                _ if token.starts_with(#long_lit) && token.contains('=') => {
-                    let parts: Vec<_> = token.splitn(2, '=').collect();
-                    if parts.len() == 2 {
-                        #fname = Some(parts[1].to_string());
-                        continue;
-                    }
+                _ if token.starts_with(#long_eq_lit) => {
+                    let value = token[#long_eq_lit.len()..].to_string();
+                    #store
+                    continue;
                 }
             });
 
-            let convert = if is_option {
+            let convert = if is_vec {
                 quote! {
-                    let #fname: #ty = match #fname.take() {
-                        Some(val) => val.parse().ok(),
-                        None => None,
-                    };
+                    let #fname: #ty = #fname
+                        .into_iter()
+                        .map(|val| ::micro_cli::parse_value(#long_lit, val))
+                        .collect::<Result<_, _>>()?;
+                }
+            } else if is_option {
+                quote! {
+                    let #fname: #ty = #fname
+                        .take()
+                        .map(|val| ::micro_cli::parse_value(#long_lit, val))
+                        .transpose()?;
                 }
             } else if let Some(default) = default_expr {
                 quote! {
                     let #fname: #ty = match #fname.take() {
-                        Some(val) => val.parse().map_err(|_| ::micro_cli::CliError::MissingArgument(#long))?,
+                        Some(val) => ::micro_cli::parse_value(#long_lit, val)?,
                         None => #default,
                     };
                 }
             } else {
                 has_required_fields = true;
                 quote! {
-                    let #fname: #ty = #fname
-                        .take()
-                        .ok_or_else(|| ::micro_cli::CliError::MissingArgument(#long))?
-                        .parse()
-                        .map_err(|_| ::micro_cli::CliError::MissingArgument(#long))?;
+                    let #fname: #ty = ::micro_cli::parse_value(
+                        #long_lit,
+                        #fname
+                            .take()
+                            .ok_or_else(|| ::micro_cli::CliError::MissingArgument(#long))?,
+                    )?;
                 }
             };
             post_process.push(convert);
         }
 
+        let schema_default = match &arg_meta.default_value {
+            Some(default) => quote! { Some(::std::string::ToString::to_string(&(#default))) },
+            None => quote! { None },
+        };
+        let schema_values = if arg_meta.value_enum {
+            let value_ty = value_type(&ty);
+            quote! {
+                <#value_ty as ::micro_cli::ValueEnum>::possible_values()
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            }
+        } else {
+            quote! { Vec::new() }
+        };
+        let type_name = type_name(value_type(&ty));
+        let required = !(is_option || is_vec || arg_meta.default_value.is_some());
+        let hidden = arg_meta.hide;
+        if is_positional {
+            let (min, max) = match (is_vec, required) {
+                (true, _) => (0usize, quote! { None }),
+                (false, required) => (usize::from(required), quote! { Some(1) }),
+            };
+            positional_schemas.push(quote! {
+                ::micro_cli::PositionalSchema {
+                    name: #long.to_string(),
+                    value_type: #type_name.to_string(),
+                    min: #min,
+                    max: #max,
+                    default: #schema_default,
+                    possible_values: #schema_values,
+                    help: #help_text.to_string(),
+                    hidden: #hidden,
+                }
+            });
+        } else {
+            let (kind, value_type) = if arg_meta.count {
+                (quote! { Count }, quote! { None })
+            } else if is_bool {
+                (quote! { Flag }, quote! { None })
+            } else if is_vec {
+                (quote! { Repeated }, quote! { Some(#type_name.to_string()) })
+            } else {
+                (quote! { Value }, quote! { Some(#type_name.to_string()) })
+            };
+            let required = required && !(arg_meta.count || is_bool);
+            let short = match short {
+                Some(ch) => quote! { Some(#ch) },
+                None => quote! { None },
+            };
+            option_schemas.push(quote! {
+                ::micro_cli::OptionSchema {
+                    short: #short,
+                    long: #long.to_string(),
+                    kind: ::micro_cli::ArgKind::#kind,
+                    value_type: #value_type,
+                    default: #schema_default,
+                    possible_values: #schema_values,
+                    required: #required,
+                    help: #help_text.to_string(),
+                    hidden: #hidden,
+                }
+            });
+        }
+
         field_inits.push(quote! { #fname });
+        // Hidden arguments still parse but stay out of help and completion.
+        if arg_meta.hide {
+            continue;
+        }
+        let long_help = arg_meta.long_help.clone().unwrap_or_default();
         if is_positional {
+            let placeholder = match &arg_meta.value_name {
+                Some(name) => format!("<{}>", name),
+                None => format!("[{}]", long),
+            };
             positional_help_lines.push(quote! {
-                positional_lines.push(format!("{} [{}], {}", #name_lit, #positional_lit, #help_text));
+                positional_lines.push((
+                    format!("{} {}, {}", #name_lit, #placeholder, #help_expr),
+                    #long_help,
+                ));
             });
-            positional_names.push(positional_lit.clone());
+            positional_names.push(placeholder);
         } else {
+            option_flags.extend(short_lit.clone());
+            option_flags.push(long_lit.clone());
+            let mut flags = match short {
+                Some(ch) => format!("-{}, --{}", ch, long),
+                None => format!("--{}", long),
+            };
+            if !(arg_meta.count || is_bool) {
+                let value_name = arg_meta
+                    .value_name
+                    .clone()
+                    .unwrap_or_else(|| long.to_uppercase().replace('-', "_"));
+                flags = format!("{} <{}>", flags, value_name);
+            }
             option_help_lines.push(quote! {
-                option_lines.push({
-                    let mut flags = Vec::new();
-                    if let Some(ch) = #short_opt_expr {
-                        flags.push(format!("-{}", ch));
-                    }
-                    flags.push(format!("--{}", #long));
-                    format!("{:<18} {}", flags.join(", "), #help_text)
-                });
+                option_lines.push((#flags, String::from(#help_expr), #long_help));
             });
         }
     }
@@ -335,6 +815,90 @@ fn expand_struct(
     } else {
         quote! {}
     };
+    let present_decl = if track {
+        quote! { let mut present: Vec<&'static str> = Vec::new(); }
+    } else {
+        quote! {}
+    };
+    let checks = &relations.checks;
+    let group_lines = &relations.group_lines;
+    // Body of `help` and `long_help`, which differ in `long`: the long help
+    // adds the doc comment paragraphs after the first.
+    let render_help = quote! {
+        let mut option_lines: Vec<(&str, String, &str)> = Vec::new();
+        let mut positional_lines: Vec<(String, &str)> = Vec::new();
+        #(#option_help_lines)*
+        #(#positional_help_lines)*
+
+        let mut out = String::new();
+        out.push_str("Usage: ");
+        out.push_str(#name_lit);
+        if !option_lines.is_empty() {
+            out.push_str(" [OPTIONS]");
+        }
+        let positional_names: &[&str] = &[#(#positional_names),*];
+        for name in positional_names {
+            out.push_str(&format!(" {}", name));
+        }
+        out.push('\n');
+        out.push_str(&format!("{}\n", #about_lit));
+        if long && !#long_about.is_empty() {
+            out.push_str(&format!("\n{}\n", #long_about));
+        }
+        let push_long = |out: &mut String, text: &str, indent: usize| {
+            if long {
+                for line in text.lines() {
+                    if line.is_empty() {
+                        out.push('\n');
+                    } else {
+                        out.push_str(&format!("{:indent$}{}\n", "", line));
+                    }
+                }
+            }
+        };
+
+        if !option_lines.is_empty() {
+            out.push('\n');
+            out.push_str("Global options:\n");
+            // Help starts in one column, past the longest option and at least
+            // 18 characters in.
+            let width = option_lines
+                .iter()
+                .map(|(flags, _, _)| flags.len())
+                .fold(18, usize::max);
+            for (flags, help, long_help) in &option_lines {
+                out.push_str(&format!("  {:<width$} {}\n", flags, help));
+                push_long(&mut out, long_help, width + 3);
+            }
+            out.push_str(&format!("  {:<width$} {}\n", "-h, --help", "Show help"));
+            let group_lines: &[&str] = &[#(#group_lines),*];
+            for line in group_lines {
+                out.push_str(&format!("  {}\n", line));
+            }
+        }
+
+        if !positional_lines.is_empty() {
+            out.push('\n');
+            out.push_str("Positional arguments:\n");
+            for (line, long_help) in &positional_lines {
+                out.push_str(&format!("  {}\n", line));
+                push_long(&mut out, long_help, 4);
+            }
+        }
+
+        let examples: &[&str] = &[#(#examples),*];
+        if !examples.is_empty() {
+            out.push_str("\nExamples:\n");
+            for example in examples {
+                out.push_str(&format!("  {}\n", example));
+            }
+        }
+        if !#after_help.is_empty() {
+            out.push_str(&format!("\n{}\n", #after_help));
+        }
+
+        out
+    };
     let positionals_iter_decl = if needs_positionals_iter {
         quote! { let mut positionals_iter = positionals.into_iter(); }
     } else {
@@ -353,29 +917,39 @@ fn expand_struct(
                 I: IntoIterator<Item = T>,
                 T: Into<String>,
             {
-                let mut iter = iterable.into_iter().map(Into::into).peekable();
+                let mut iter = ::micro_cli::split_args(iterable, &[#(#value_shorts),*])
+                    .into_iter()
+                    .peekable();
                 #(#declarations)*
+                #present_decl
                 let mut positionals: Vec<String> = Vec::new();
 
                 if #has_required_fields && iter.peek().is_none() {
                     return Err(::micro_cli::CliError::Help(Self::help()));
                 }
 
-                while let Some(token) = iter.next() {
-                    if token == "--help" || token == "-h" {
+                while let Some(arg) = iter.next() {
+                    let token = match arg {
+                        ::micro_cli::Arg::Option(token) => token,
+                        other => {
+                            positionals.push(other.into_string());
+                            continue;
+                        }
+                    };
+                    if token == "--help" {
+                        return Err(::micro_cli::CliError::Help(Self::long_help()));
+                    }
+                    if token == "-h" {
                         return Err(::micro_cli::CliError::Help(Self::help()));
                     }
                     match token.as_str() {
                         #(#match_arms)*
-                        _ => {
-                            if token.starts_with('-') {
-                                return Err(::micro_cli::CliError::UnknownOption(token));
-                            }
-                            positionals.push(token);
-                        }
+                        _ => return Err(::micro_cli::CliError::UnknownOption(token)),
                     }
                 }
 
+                #(#checks)*
+
                 #subcommand_parse
                 #positionals_iter_decl
                 #(#post_process)*
@@ -386,42 +960,13 @@ fn expand_struct(
             }
 
             fn help() -> String {
-                let mut option_lines: Vec<String> = Vec::new();
-                let mut positional_lines: Vec<String> = Vec::new();
-                #(#option_help_lines)*
-                #(#positional_help_lines)*
-
-                let mut out = String::new();
-                out.push_str("Usage: ");
-                out.push_str(#name_lit);
-                if !option_lines.is_empty() {
-                    out.push_str(" [OPTIONS]");
-                }
-                let positional_names: &[&str] = &[#(#positional_names),*];
-                for name in positional_names {
-                    out.push_str(&format!(" [{}]", name));
-                }
-                out.push('\n');
-                out.push_str(&format!("{}\n", #about_lit));
-
-                if !option_lines.is_empty() {
-                    out.push('\n');
-                    out.push_str("Global options:\n");
-                    for line in &option_lines {
-                        out.push_str(&format!("  {}\n", line));
-                    }
-                    out.push_str("  -h, --help         Show help\n");
-                }
-
-                if !positional_lines.is_empty() {
-                    out.push('\n');
-                    out.push_str("Positional arguments:\n");
-                    for line in &positional_lines {
-                        out.push_str(&format!("  {}\n", line));
-                    }
-                }
+                let long = false;
+                #render_help
+            }
 
-                out
+            fn long_help() -> String {
+                let long = true;
+                #render_help
             }
 
             fn description() -> String {
@@ -431,6 +976,16 @@ fn expand_struct(
             fn name() -> &'static str {
                 #name_lit
             }
+
+            fn schema() -> ::micro_cli::CommandSchema {
+                ::micro_cli::CommandSchema {
+                    name: #name_lit.to_string(),
+                    about: #about_lit.to_string(),
+                    options: vec![#(#option_schemas),*],
+                    positionals: vec![#(#positional_schemas),*],
+                    subcommands: vec![#(#subcommand_schemas),*],
+                }
+            }
         }
     };
 
@@ -443,6 +998,19 @@ fn expand_struct(
             fn command_about(&self) -> &'static str {
                 #about_lit
             }
+
+            fn command_arity(&self) -> (usize, Option<usize>) {
+                let schema = <Self as ::micro_cli::Parser>::schema();
+                (schema.min_args(), schema.max_args())
+            }
+
+            fn command_options(&self) -> &'static [&'static str] {
+                &[#(#option_flags,)* "-h", "--help"]
+            }
+
+            fn command_value_options(&self) -> Option<&'static [&'static str]> {
+                Some(&[#(#value_flags),*])
+            }
         }
     };
 
@@ -459,10 +1027,13 @@ fn expand_enum(
 ) -> syn::Result<proc_macro2::TokenStream> {
     let mut arms = Vec::new();
     let mut help_entries = Vec::new();
+    let mut subcommand_schemas = Vec::new();
     for variant in &data.variants {
         let v_ident = &variant.ident;
         let name = v_ident.to_string().to_lowercase();
         help_entries.push(quote! { format!("  {}", #name) });
+        let about = doc_text(&variant.attrs).0.unwrap_or_default();
+        let mut option_schemas = Vec::new();
         match &variant.fields {
             Fields::Unit => {
                 arms.push(quote! { #name => Ok(#ident::#v_ident), });
@@ -483,12 +1054,28 @@ fn expand_enum(
                             continue;
                         }
                     });
+                    let type_name = type_name(&ty);
+                    option_schemas.push(quote! {
+                        ::micro_cli::OptionSchema {
+                            short: None,
+                            long: #long.to_string(),
+                            kind: ::micro_cli::ArgKind::Value,
+                            value_type: Some(#type_name.to_string()),
+                            default: None,
+                            possible_values: Vec::new(),
+                            required: true,
+                            help: String::new(),
+                            hidden: false,
+                        }
+                    });
+                    let flag = format!("--{}", long);
                     finals.push(quote! {
-                        let #fname: #ty = #fname
-                            .take()
-                            .ok_or_else(|| ::micro_cli::CliError::MissingArgument(#long))?
-                            .parse()
-                            .map_err(|_| ::micro_cli::CliError::MissingArgument(#long))?;
+                        let #fname: #ty = ::micro_cli::parse_value(
+                            #flag,
+                            #fname
+                                .take()
+                                .ok_or_else(|| ::micro_cli::CliError::MissingArgument(#long))?,
+                        )?;
                     });
                 }
                 let field_inits: Vec<_> = fields
@@ -518,7 +1105,17 @@ fn expand_enum(
                 ))
             }
         }
+        subcommand_schemas.push(quote! {
+            ::micro_cli::CommandSchema {
+                name: #name.to_string(),
+                about: #about.to_string(),
+                options: vec![#(#option_schemas),*],
+                positionals: Vec::new(),
+                subcommands: Vec::new(),
+            }
+        });
     }
+    let type_name = ident.to_string();
 
     let about_lit = meta
         .about
@@ -559,6 +1156,25 @@ fn expand_enum(
             pub fn name() -> &'static str {
                 #name_lit
             }
+
+            pub fn schema() -> ::micro_cli::CommandSchema {
+                ::micro_cli::CommandSchema {
+                    name: #name_lit.to_string(),
+                    about: #about_lit.to_string(),
+                    options: Vec::new(),
+                    positionals: vec![::micro_cli::PositionalSchema {
+                        name: "subcommand".to_string(),
+                        value_type: #type_name.to_string(),
+                        min: 1,
+                        max: None,
+                        default: None,
+                        possible_values: Vec::new(),
+                        help: "Run a subcommand".to_string(),
+                        hidden: false,
+                    }],
+                    subcommands: vec![#(#subcommand_schemas),*],
+                }
+            }
         }
     };
 
@@ -571,6 +1187,15 @@ fn expand_enum(
             fn command_about(&self) -> &'static str {
                 #about_lit
             }
+
+            fn command_arity(&self) -> (usize, Option<usize>) {
+                let schema = Self::schema();
+                (schema.min_args(), schema.max_args())
+            }
+
+            fn command_value_options(&self) -> Option<&'static [&'static str]> {
+                Some(&[])
+            }
         }
     };
 
@@ -579,3 +1204,62 @@ fn expand_enum(
         #command_info_impl
     })
 }
+
+fn expand_value_enum(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.span(),
+            "ValueEnum can only be derived for enums",
+        ));
+    };
+    let ident = &input.ident;
+    let mut variants = Vec::new();
+    let mut names = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new(
+                variant.span(),
+                "ValueEnum variants cannot have fields",
+            ));
+        }
+        let mut name = variant.ident.to_string().to_lowercase();
+        for attr in &variant.attrs {
+            if !attr.path().is_ident("value") {
+                continue;
+            }
+            attr.parse_nested_meta(|nested| {
+                if nested.path.is_ident("name") {
+                    let lit: syn::LitStr = nested.value()?.parse()?;
+                    name = lit.value();
+                    Ok(())
+                } else {
+                    Err(nested.error("expected `name = \"...\"`"))
+                }
+            })?;
+        }
+        variants.push(&variant.ident);
+        names.push(name);
+    }
+
+    Ok(quote! {
+        impl ::micro_cli::ValueEnum for #ident {
+            fn value_variants() -> &'static [Self] {
+                &[#(#ident::#variants),*]
+            }
+
+            fn to_value(&self) -> &'static str {
+                match self {
+                    #(#ident::#variants => #names,)*
+                }
+            }
+        }
+
+        impl ::std::str::FromStr for #ident {
+            type Err = ::micro_cli::UnknownValue;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                <Self as ::micro_cli::ValueEnum>::from_value(value)
+            }
+        }
+    })
+}
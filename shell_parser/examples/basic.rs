@@ -2,7 +2,7 @@ use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
-use shell_parser::{CommandInvocation, CommandSpec, ShellParser};
+use shell_parser::{CommandInvocation, CommandSpec, Redirect, ShellParser};
 
 /// Minimal executor to show how callers can hook command implementations.
 struct ShellState {
@@ -25,7 +25,7 @@ impl ShellState {
     }
 
     fn cmd_cd(&mut self, invocation: CommandInvocation) {
-        let Some(target) = invocation.args.get(0) else {
+        let Some(target) = invocation.args.first() else {
             println!("cd: missing target directory");
             return;
         };
@@ -40,20 +40,15 @@ impl ShellState {
     }
 
     fn cmd_echo(&mut self, invocation: CommandInvocation) {
-        let mut parts = invocation.args.into_iter();
-        let mut message: Vec<String> = Vec::new();
-        let mut redirect: Option<String> = None;
+        let output = invocation.args.join(" ");
+        let redirect = invocation
+            .redirects
+            .into_iter()
+            .find_map(|redirect| match redirect {
+                Redirect::Output { fd: 1, path, .. } => Some(path),
+                _ => None,
+            });
 
-        while let Some(part) = parts.next() {
-            if part == ">" {
-                redirect = parts.next();
-                break;
-            } else {
-                message.push(part);
-            }
-        }
-
-        let output = message.join(" ");
         match redirect {
             Some(path) => {
                 let full_path = self.resolve(&path);
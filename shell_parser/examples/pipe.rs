@@ -65,7 +65,7 @@ impl ShellState {
     }
 
     fn cmd_save(&self, invocation: CommandInvocation, input: Option<String>) -> Option<String> {
-        let Some(path) = invocation.args.get(0) else {
+        let Some(path) = invocation.args.first() else {
             println!("save: missing target path");
             return input;
         };
@@ -0,0 +1,399 @@
+//! Syntax tree of a script as written, before anything is expanded.
+//!
+//! [`ShellParser::parse_script`](crate::ShellParser::parse_script) builds a
+//! [`Script`]: words keep their `$NAME` and `$(...)` parts, aliases and
+//! functions are not resolved, and every node carries the byte [`Span`] it was
+//! read from. Walk the tree with a [`Visitor`], or rewrite it in place with a
+//! [`VisitorMut`].
+//!
+//! ```
+//! use shell_parser::ast::{Command, Visitor, Word};
+//! use shell_parser::ShellParser;
+//!
+//! struct Names(Vec<String>);
+//!
+//! impl Visitor for Names {
+//!     fn visit_word(&mut self, word: &Word) {
+//!         self.0.push(word.text.clone());
+//!     }
+//! }
+//!
+//! let source = "for f in *.md; do render $f > out; done";
+//! let script = ShellParser::new().parse_script(source).unwrap();
+//! let Command::For(for_loop) = &script.body.items[0].pipeline.commands[0] else {
+//!     unreachable!()
+//! };
+//! assert_eq!(for_loop.name, "f");
+//! assert_eq!(&source[for_loop.span.start..for_loop.span.end], source);
+//!
+//! let mut names = Names(Vec::new());
+//! names.visit_script(&script);
+//! assert_eq!(names.0, vec!["*.md", "render", "$f", "out"]);
+//! ```
+
+pub use crate::redirect::RedirectOperator;
+use crate::separator::Separator;
+pub use crate::word::{ParameterExpansion, ParameterOp, WordPart};
+
+/// Byte range of a node in the source, `start` inclusive and `end` exclusive.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Number of bytes covered.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Whether the span covers no bytes, as for an empty list.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Span from the start of `self` to the end of `other`.
+    pub(crate) fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+/// A whole parsed input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Script {
+    pub body: List,
+    pub span: Span,
+}
+
+/// Pipelines run one after another, joined by `;`, newlines, `&&` or `||`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct List {
+    pub items: Vec<ListItem>,
+    pub span: Span,
+}
+
+/// Pipeline of a [`List`] with the separator written after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListItem {
+    pub pipeline: Pipeline,
+    /// `;`, a newline, `&&` or `||`; `None` after the last pipeline of the input,
+    /// and `|` if the input ends right after one.
+    pub separator: Option<Separator>,
+}
+
+/// Commands joined by `|`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    If(IfCommand),
+    /// `while` or `until` loop.
+    Loop(LoopCommand),
+    For(ForCommand),
+    Case(CaseCommand),
+    /// `name() { ... }` or `function name { ... }`.
+    Function(FunctionDefinition),
+}
+
+impl Command {
+    pub fn span(&self) -> Span {
+        match self {
+            Command::Simple(command) => command.span,
+            Command::If(command) => command.span,
+            Command::Loop(command) => command.span,
+            Command::For(command) => command.span,
+            Command::Case(command) => command.span,
+            Command::Function(definition) => definition.span,
+        }
+    }
+
+    /// Reserved word opening a compound command.
+    pub(crate) fn keyword(&self) -> Option<&'static str> {
+        match self {
+            Command::If(_) => Some("if"),
+            Command::Loop(command) if command.until => Some("until"),
+            Command::Loop(_) => Some("while"),
+            Command::For(_) => Some("for"),
+            Command::Case(_) => Some("case"),
+            Command::Simple(_) | Command::Function(_) => None,
+        }
+    }
+}
+
+/// Command name and arguments, plus the redirections written among them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimpleCommand {
+    pub words: Vec<Word>,
+    /// Redirections in source order.
+    pub redirects: Vec<Redirect>,
+    pub span: Span,
+}
+
+/// Word such as `"hello $NAME"/*.md`, split into literal and expandable parts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Word {
+    /// Source text with quotes and escapes removed; expansions appear as written.
+    pub text: String,
+    pub parts: Vec<WordPart>,
+    pub span: Span,
+}
+
+/// Redirection such as `> out.log`, `2>> err`, `2>&1` or `<<EOF`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    pub operator: RedirectOperator,
+    /// File the operator applies to, or the body of a `<<` here-document;
+    /// `None` for `>&` duplications.
+    pub target: Option<Word>,
+    pub span: Span,
+}
+
+/// `if` with any `elif` branches and an optional `else`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IfCommand {
+    /// The `if` branch followed by each `elif` branch.
+    pub branches: Vec<IfBranch>,
+    pub otherwise: Option<List>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IfBranch {
+    pub condition: List,
+    pub body: List,
+}
+
+/// `while CONDITION; do BODY; done`, or `until` when `until` is set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoopCommand {
+    pub until: bool,
+    pub condition: List,
+    pub body: List,
+    pub span: Span,
+}
+
+/// `for NAME in WORDS; do BODY; done`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForCommand {
+    pub name: String,
+    pub words: Vec<Word>,
+    pub body: List,
+    pub span: Span,
+}
+
+/// `case WORD in PATTERN) BODY ;; ... esac`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaseCommand {
+    pub subject: Word,
+    pub arms: Vec<CaseArm>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaseArm {
+    /// Patterns separated by `|`; the arm runs if any of them matches.
+    pub patterns: Vec<Word>,
+    pub body: List,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub body: List,
+    pub span: Span,
+}
+
+/// Read-only traversal of a [`Script`].
+///
+/// Every method defaults to visiting the node's children through the matching
+/// `walk_*` function; override the ones you care about and call the walk
+/// function to keep descending.
+pub trait Visitor {
+    fn visit_script(&mut self, script: &Script) {
+        walk_script(self, script);
+    }
+
+    fn visit_list(&mut self, list: &List) {
+        walk_list(self, list);
+    }
+
+    fn visit_pipeline(&mut self, pipeline: &Pipeline) {
+        walk_pipeline(self, pipeline);
+    }
+
+    fn visit_command(&mut self, command: &Command) {
+        walk_command(self, command);
+    }
+
+    fn visit_redirect(&mut self, redirect: &Redirect) {
+        walk_redirect(self, redirect);
+    }
+
+    fn visit_word(&mut self, _word: &Word) {}
+}
+
+pub fn walk_script<V: Visitor + ?Sized>(visitor: &mut V, script: &Script) {
+    visitor.visit_list(&script.body);
+}
+
+pub fn walk_list<V: Visitor + ?Sized>(visitor: &mut V, list: &List) {
+    for item in &list.items {
+        visitor.visit_pipeline(&item.pipeline);
+    }
+}
+
+pub fn walk_pipeline<V: Visitor + ?Sized>(visitor: &mut V, pipeline: &Pipeline) {
+    for command in &pipeline.commands {
+        visitor.visit_command(command);
+    }
+}
+
+/// Visit the words, redirections and nested lists of `command` in source order.
+pub fn walk_command<V: Visitor + ?Sized>(visitor: &mut V, command: &Command) {
+    match command {
+        Command::Simple(command) => {
+            for word in &command.words {
+                visitor.visit_word(word);
+            }
+            for redirect in &command.redirects {
+                visitor.visit_redirect(redirect);
+            }
+        }
+        Command::If(command) => {
+            for branch in &command.branches {
+                visitor.visit_list(&branch.condition);
+                visitor.visit_list(&branch.body);
+            }
+            if let Some(otherwise) = &command.otherwise {
+                visitor.visit_list(otherwise);
+            }
+        }
+        Command::Loop(command) => {
+            visitor.visit_list(&command.condition);
+            visitor.visit_list(&command.body);
+        }
+        Command::For(command) => {
+            for word in &command.words {
+                visitor.visit_word(word);
+            }
+            visitor.visit_list(&command.body);
+        }
+        Command::Case(command) => {
+            visitor.visit_word(&command.subject);
+            for arm in &command.arms {
+                for pattern in &arm.patterns {
+                    visitor.visit_word(pattern);
+                }
+                visitor.visit_list(&arm.body);
+            }
+        }
+        Command::Function(definition) => visitor.visit_list(&definition.body),
+    }
+}
+
+pub fn walk_redirect<V: Visitor + ?Sized>(visitor: &mut V, redirect: &Redirect) {
+    if let Some(target) = &redirect.target {
+        visitor.visit_word(target);
+    }
+}
+
+/// Traversal of a [`Script`] that may rewrite it in place.
+///
+/// Mirrors [`Visitor`], with `walk_*_mut` functions to keep descending.
+pub trait VisitorMut {
+    fn visit_script_mut(&mut self, script: &mut Script) {
+        walk_script_mut(self, script);
+    }
+
+    fn visit_list_mut(&mut self, list: &mut List) {
+        walk_list_mut(self, list);
+    }
+
+    fn visit_pipeline_mut(&mut self, pipeline: &mut Pipeline) {
+        walk_pipeline_mut(self, pipeline);
+    }
+
+    fn visit_command_mut(&mut self, command: &mut Command) {
+        walk_command_mut(self, command);
+    }
+
+    fn visit_redirect_mut(&mut self, redirect: &mut Redirect) {
+        walk_redirect_mut(self, redirect);
+    }
+
+    fn visit_word_mut(&mut self, _word: &mut Word) {}
+}
+
+pub fn walk_script_mut<V: VisitorMut + ?Sized>(visitor: &mut V, script: &mut Script) {
+    visitor.visit_list_mut(&mut script.body);
+}
+
+pub fn walk_list_mut<V: VisitorMut + ?Sized>(visitor: &mut V, list: &mut List) {
+    for item in &mut list.items {
+        visitor.visit_pipeline_mut(&mut item.pipeline);
+    }
+}
+
+pub fn walk_pipeline_mut<V: VisitorMut + ?Sized>(visitor: &mut V, pipeline: &mut Pipeline) {
+    for command in &mut pipeline.commands {
+        visitor.visit_command_mut(command);
+    }
+}
+
+pub fn walk_command_mut<V: VisitorMut + ?Sized>(visitor: &mut V, command: &mut Command) {
+    match command {
+        Command::Simple(command) => {
+            for word in &mut command.words {
+                visitor.visit_word_mut(word);
+            }
+            for redirect in &mut command.redirects {
+                visitor.visit_redirect_mut(redirect);
+            }
+        }
+        Command::If(command) => {
+            for branch in &mut command.branches {
+                visitor.visit_list_mut(&mut branch.condition);
+                visitor.visit_list_mut(&mut branch.body);
+            }
+            if let Some(otherwise) = &mut command.otherwise {
+                visitor.visit_list_mut(otherwise);
+            }
+        }
+        Command::Loop(command) => {
+            visitor.visit_list_mut(&mut command.condition);
+            visitor.visit_list_mut(&mut command.body);
+        }
+        Command::For(command) => {
+            for word in &mut command.words {
+                visitor.visit_word_mut(word);
+            }
+            visitor.visit_list_mut(&mut command.body);
+        }
+        Command::Case(command) => {
+            visitor.visit_word_mut(&mut command.subject);
+            for arm in &mut command.arms {
+                for pattern in &mut arm.patterns {
+                    visitor.visit_word_mut(pattern);
+                }
+                visitor.visit_list_mut(&mut arm.body);
+            }
+        }
+        Command::Function(definition) => visitor.visit_list_mut(&mut definition.body),
+    }
+}
+
+pub fn walk_redirect_mut<V: VisitorMut + ?Sized>(visitor: &mut V, redirect: &mut Redirect) {
+    if let Some(target) = &mut redirect.target {
+        visitor.visit_word_mut(target);
+    }
+}
@@ -0,0 +1,153 @@
+//! Output of the builtins that report on aliases, functions and options.
+//!
+//! The parser applies what builtins change while expanding them; the runner
+//! calls [`report`] afterwards so `alias`, `type`, `which`, `declare` and
+//! `set` can print through the command's streams like any other command.
+
+use crate::fmt;
+use crate::io::CommandIo;
+use crate::parser::{CommandType, ShellParser};
+use crate::status::ExitStatus;
+
+/// Print what builtin `name` reports for `args`.
+pub(crate) fn report(
+    parser: &ShellParser,
+    name: &str,
+    args: &[String],
+    io: &mut CommandIo<'_>,
+) -> ExitStatus {
+    match name {
+        "alias" => alias(parser, args, io),
+        "declare" => declare(parser, args, io),
+        "set" => set(parser, args, io),
+        "type" => each_name(parser, "type", args, io, describe),
+        "which" => each_name(parser, "which", args, io, which),
+        _ => ExitStatus::SUCCESS,
+    }
+}
+
+/// `alias` lists every alias; `alias NAME` prints one. Definitions were
+/// already applied by the parser.
+fn alias(parser: &ShellParser, args: &[String], io: &mut CommandIo<'_>) -> ExitStatus {
+    if args.is_empty() {
+        for (name, value) in parser.aliases() {
+            io.stdout
+                .write_line(format!("alias {name}={}", fmt::single_quote(&value)));
+        }
+        return ExitStatus::SUCCESS;
+    }
+    let mut status = ExitStatus::SUCCESS;
+    for name in args.iter().filter(|arg| !arg.contains('=')) {
+        match parser.alias(name) {
+            Some(value) => io
+                .stdout
+                .write_line(format!("alias {name}={}", fmt::single_quote(&value))),
+            None => {
+                io.stderr.write_line(format!("alias: {name}: not found"));
+                status = ExitStatus::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// `declare -f [NAME...]` prints function definitions, `declare -F [NAME...]`
+/// only their names; without names every function is listed.
+fn declare(parser: &ShellParser, args: &[String], io: &mut CommandIo<'_>) -> ExitStatus {
+    let (bodies, names) = match args.split_first() {
+        Some((flag, names)) if flag == "-f" => (true, names),
+        Some((flag, names)) if flag == "-F" => (false, names),
+        _ => {
+            io.stderr
+                .write_line("declare: usage: declare -f|-F [NAME...]");
+            return ExitStatus::new(2);
+        }
+    };
+    let names = if names.is_empty() {
+        parser.functions()
+    } else {
+        names.to_vec()
+    };
+    let mut status = ExitStatus::SUCCESS;
+    for name in names {
+        match parser.function_source(&name) {
+            Some(source) if bodies => io.stdout.write_line(source),
+            Some(_) => io.stdout.write_line(format!("declare -f {name}")),
+            None => {
+                io.stderr.write_line(format!("declare: {name}: not found"));
+                status = ExitStatus::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// `set` and `set -o` list the options; `set +o` prints the commands that
+/// restore them. Options given before a trailing `-o`/`+o` were already
+/// applied by the parser.
+fn set(parser: &ShellParser, args: &[String], io: &mut CommandIo<'_>) -> ExitStatus {
+    let options = parser.options().list();
+    match args.last().map(String::as_str) {
+        None | Some("-o") => {
+            for (name, on) in options {
+                let state = if on { "on" } else { "off" };
+                io.stdout.write_line(format!("{name:<10}{state}"));
+            }
+        }
+        Some("+o") => {
+            for (name, on) in options {
+                let sign = if on { '-' } else { '+' };
+                io.stdout.write_line(format!("set {sign}o {name}"));
+            }
+        }
+        _ => {}
+    }
+    ExitStatus::SUCCESS
+}
+
+/// Run `line` for each name in `args`, failing if any of them is unknown.
+fn each_name(
+    parser: &ShellParser,
+    builtin: &str,
+    args: &[String],
+    io: &mut CommandIo<'_>,
+    line: fn(&ShellParser, &str, CommandType) -> String,
+) -> ExitStatus {
+    let mut status = ExitStatus::SUCCESS;
+    for name in args {
+        match parser.command_type(name) {
+            Some(kind) => io.stdout.write_line(line(parser, name, kind)),
+            None => {
+                io.stderr
+                    .write_line(format!("{builtin}: {name}: not found"));
+                status = ExitStatus::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// A sentence for `type`, followed by the definition of a function.
+fn describe(parser: &ShellParser, name: &str, kind: CommandType) -> String {
+    match kind {
+        CommandType::Function => format!(
+            "{name} is a function\n{}",
+            parser.function_source(name).unwrap_or_default()
+        ),
+        CommandType::Alias(value) => format!("{name} is aliased to {}", fmt::single_quote(&value)),
+        CommandType::Builtin => format!("{name} is a shell builtin"),
+        CommandType::Command => format!("{name} is a registered command"),
+        CommandType::CommandAlias(command) => format!("{name} is an alias for {command}"),
+    }
+}
+
+/// The short form printed by `which`: just the name for a registered command.
+fn which(_: &ShellParser, name: &str, kind: CommandType) -> String {
+    match kind {
+        CommandType::Function => format!("{name}: shell function"),
+        CommandType::Alias(value) => format!("{name}: aliased to {value}"),
+        CommandType::Builtin => format!("{name}: shell builtin"),
+        CommandType::Command => name.to_string(),
+        CommandType::CommandAlias(command) => format!("{name}: alias for {command}"),
+    }
+}
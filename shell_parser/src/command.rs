@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::redirect::Redirect;
+
 /// Description of a command that the parser can validate against.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CommandSpec {
@@ -8,6 +10,13 @@ pub struct CommandSpec {
     pub min_args: usize,
     pub max_args: Option<usize>,
     pub aliases: Vec<String>,
+    /// Flags the command accepts, such as `-p` and `--posts`; used for completion.
+    pub options: Vec<String>,
+    /// Set for commands that parse their own options: the argument count then
+    /// leaves out options, their values and a lone `--`.
+    pub parses_options: bool,
+    /// Options whose value is the next argument, such as `-n` and `--name`.
+    pub value_options: Vec<String>,
 }
 
 impl CommandSpec {
@@ -19,6 +28,9 @@ impl CommandSpec {
             min_args: 0,
             max_args: None,
             aliases: Vec::new(),
+            options: Vec::new(),
+            parses_options: false,
+            value_options: Vec::new(),
         }
     }
 
@@ -50,6 +62,30 @@ impl CommandSpec {
             .extend(aliases.into_iter().map(|alias| alias.into()));
         self
     }
+
+    /// Register flags, written with their dashes, that the command accepts.
+    pub fn with_options<I, S>(mut self, options: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options
+            .extend(options.into_iter().map(|option| option.into()));
+        self
+    }
+
+    /// Count only positional arguments, skipping options and the values of
+    /// `value_options`, as a command that parses its own options sees them.
+    pub fn with_option_parsing<I, S>(mut self, value_options: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.parses_options = true;
+        self.value_options
+            .extend(value_options.into_iter().map(|option| option.into()));
+        self
+    }
 }
 
 /// Parsed invocation of a command.
@@ -59,11 +95,17 @@ pub struct CommandInvocation {
     pub args: Vec<String>,
     /// Byte offset of the command name in the original input.
     pub position: usize,
+    /// Redirections in the order they were written; they are not part of `args`.
+    pub redirects: Vec<Redirect>,
 }
 
 impl fmt::Display for CommandInvocation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {:?}", self.name, self.args)
+        write!(f, "{} {:?}", self.name, self.args)?;
+        for redirect in &self.redirects {
+            write!(f, " {redirect}")?;
+        }
+        Ok(())
     }
 }
 
@@ -0,0 +1,87 @@
+//! Completing the word under the cursor of a command line.
+//!
+//! ```
+//! use shell_parser::{CommandSpec, ShellParser};
+//!
+//! let parser = ShellParser::with_commands([
+//!     CommandSpec::new("render", "Render a post").with_options(["-r", "--raw"]),
+//!     CommandSpec::new("rm", "Remove a file"),
+//! ]);
+//! let completion = parser.complete("echo hi; re", 11);
+//! assert_eq!(completion.candidates, vec!["render"]);
+//! assert_eq!((completion.replace.start, completion.replace.end), (9, 11));
+//!
+//! let completion = parser.complete("render --", 9);
+//! assert_eq!(completion.candidates, vec!["--raw"]);
+//! ```
+
+use crate::ast::Span;
+use crate::fs::FileSystem;
+
+/// Candidates for the word under the cursor and the range they replace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completion {
+    /// Bytes of the input a chosen candidate replaces: from the start of the
+    /// word to the cursor.
+    pub replace: Span,
+    /// Replacement texts, sorted and escaped so they can be inserted as is.
+    /// Directories end with `/`.
+    pub candidates: Vec<String>,
+}
+
+impl Completion {
+    /// Longest text every candidate starts with.
+    pub fn common_prefix(&self) -> &str {
+        let Some((first, rest)) = self.candidates.split_first() else {
+            return "";
+        };
+        let mut prefix = first.as_str();
+        for candidate in rest {
+            while !candidate.starts_with(prefix) {
+                let mut chars = prefix.chars();
+                chars.next_back();
+                prefix = chars.as_str();
+            }
+        }
+        prefix
+    }
+}
+
+/// Entries of the directory named by `prefix` that complete its last component.
+///
+/// Hidden entries are only offered when the component starts with `.`.
+pub(crate) fn paths(fs: &dyn FileSystem, prefix: &str) -> Vec<String> {
+    let (dir, base) = match prefix.rfind('/') {
+        Some(idx) => prefix.split_at(idx + 1),
+        None => ("", prefix),
+    };
+    let listed = match dir {
+        "" => ".",
+        "/" => "/",
+        _ => dir.trim_end_matches('/'),
+    };
+    let Ok(names) = fs.read_dir(listed) else {
+        return Vec::new();
+    };
+    names
+        .into_iter()
+        .filter(|name| name.starts_with(base) && (base.starts_with('.') || !name.starts_with('.')))
+        .map(|name| {
+            let path = format!("{dir}{name}");
+            let suffix = if fs.read_dir(&path).is_ok() { "/" } else { "" };
+            format!("{}{suffix}", escape(&path))
+        })
+        .collect()
+}
+
+/// Backslash-escape characters the tokenizer would otherwise interpret.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if ch.is_whitespace() || "'\"\\$`;&|<>(){}*?[]#~".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
@@ -0,0 +1,101 @@
+//! Locating parse errors in their source for display.
+//!
+//! ```
+//! use shell_parser::ShellParser;
+//!
+//! let source = "echo ok\necho \"oops";
+//! let err = ShellParser::new().parse(source).unwrap_err();
+//! let diagnostic = &err.diagnostics(source)[0];
+//! assert_eq!((diagnostic.line, diagnostic.column), (2, 6));
+//! assert_eq!(
+//!     diagnostic.render(source),
+//!     "error: unterminated quote \"\n --> 2:6\n  |\n2 | echo \"oops\n  |      ^^^^^"
+//! );
+//! ```
+
+use std::fmt;
+
+use crate::ast::Span;
+use crate::error::ShellParseError;
+
+/// A single parse error with its span, line and column in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub error: ShellParseError,
+    /// Bytes of the source the error points at; never empty unless the source is.
+    pub span: Span,
+    /// 1-based line of `span.start`.
+    pub line: usize,
+    /// 1-based column of `span.start`, counted in characters.
+    pub column: usize,
+}
+
+impl Diagnostic {
+    /// Locate `error` in `source`.
+    ///
+    /// The span is the one the error recorded; an empty one, such as the
+    /// position of a word inserted by an alias, covers the character there.
+    pub fn new(error: ShellParseError, source: &str) -> Self {
+        let recorded = error.span();
+        let start = floor_char_boundary(source, recorded.start);
+        let mut end = floor_char_boundary(source, recorded.end).max(start);
+        if end == start {
+            end += source[start..].chars().next().map_or(0, char::len_utf8);
+        }
+        let before = &source[..start];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            span: Span::new(start, end),
+            error,
+        }
+    }
+
+    /// Render the error with the offending line and a caret underline.
+    ///
+    /// A span covering several lines is underlined to the end of its first line.
+    pub fn render(&self, source: &str) -> String {
+        let line_start = source[..self.span.start]
+            .rfind('\n')
+            .map_or(0, |idx| idx + 1);
+        let line_end = source[self.span.start..]
+            .find('\n')
+            .map_or(source.len(), |idx| self.span.start + idx);
+        let text = &source[line_start..line_end];
+
+        let indent: String = source[line_start..self.span.start]
+            .chars()
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = source[self.span.start..self.span.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        format!(
+            "error: {message}\n{gutter}--> {line}:{column}\n{gutter} |\n{number} | {text}\n{gutter} | {indent}{carets}",
+            message = self.error.message(),
+            line = self.line,
+            column = self.column,
+            carets = "^".repeat(width),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.error.message())
+    }
+}
+
+/// Clamp `position` into `source`, moving back to a character boundary.
+fn floor_char_boundary(source: &str, position: usize) -> usize {
+    let mut position = position.min(source.len());
+    while !source.is_char_boundary(position) {
+        position -= 1;
+    }
+    position
+}
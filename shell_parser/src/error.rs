@@ -1,89 +1,250 @@
-use thiserror::Error;
+use std::fmt;
+
+use crate::ast::Span;
+use crate::diagnostic::Diagnostic;
 
 /// Errors that can arise while parsing.
-#[derive(Debug, Clone, PartialEq, Eq, Error)]
+///
+/// Each error covers the bytes `position..end` of the source it was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ShellParseError {
-    #[error("unknown command '{name}' at {position}")]
-    UnknownCommand { name: String, position: usize },
-    #[error("invalid arity for '{name}' at {position}: expected {min_expected}..{max_expected:?}, found {found}")]
+    UnknownCommand {
+        name: String,
+        position: usize,
+        end: usize,
+    },
     InvalidArity {
         name: String,
         min_expected: usize,
         max_expected: Option<usize>,
         found: usize,
         position: usize,
+        end: usize,
+    },
+    UnterminatedQuote {
+        quote: char,
+        position: usize,
+        end: usize,
+    },
+    TrailingEscape {
+        position: usize,
+        end: usize,
+    },
+    AliasLoop {
+        name: String,
+        position: usize,
+        end: usize,
     },
-    #[error("unterminated quote {quote} at {position}")]
-    UnterminatedQuote { quote: char, position: usize },
-    #[error("trailing escape at {position}")]
-    TrailingEscape { position: usize },
-    #[error("alias/function loop detected for '{name}' at {position}")]
-    AliasLoop { name: String, position: usize },
-    #[error("invalid alias '{name}' at {position}: {message}")]
     InvalidAlias {
         name: String,
         message: String,
         position: usize,
+        end: usize,
     },
-    #[error("invalid function '{name}' at {position}: {message}")]
     InvalidFunction {
         name: String,
         message: String,
         position: usize,
+        end: usize,
+    },
+    BadSubstitution {
+        expression: String,
+        position: usize,
+        end: usize,
+    },
+    UnterminatedSubstitution {
+        position: usize,
+        end: usize,
+    },
+    UnsupportedSubstitution {
+        position: usize,
+        end: usize,
+    },
+    InvalidRedirect {
+        message: String,
+        position: usize,
+        end: usize,
+    },
+    InvalidControlFlow {
+        keyword: String,
+        message: String,
+        position: usize,
+        end: usize,
+    },
+    UnsupportedControlFlow {
+        keyword: String,
+        position: usize,
+        end: usize,
+    },
+    InvalidVariable {
+        name: String,
+        message: String,
+        position: usize,
+        end: usize,
+    },
+    /// Every problem found in one script, in source order.
+    Multiple {
+        errors: Vec<ShellParseError>,
     },
 }
 
 impl ShellParseError {
-    pub(crate) fn with_offset(self, offset: usize) -> Self {
+    /// Combine the errors found in one script; a single error is returned as is.
+    pub(crate) fn from_errors(mut errors: Vec<ShellParseError>) -> Self {
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            ShellParseError::Multiple { errors }
+        }
+    }
+
+    /// Byte offset in the source where the (first) problem starts.
+    pub fn position(&self) -> usize {
+        self.span().start
+    }
+
+    /// Bytes of the source the (first) problem covers.
+    pub fn span(&self) -> Span {
         match self {
-            ShellParseError::UnknownCommand { name, position } => ShellParseError::UnknownCommand {
-                name,
-                position: position + offset,
-            },
+            ShellParseError::UnknownCommand { position, end, .. }
+            | ShellParseError::InvalidArity { position, end, .. }
+            | ShellParseError::UnterminatedQuote { position, end, .. }
+            | ShellParseError::TrailingEscape { position, end }
+            | ShellParseError::AliasLoop { position, end, .. }
+            | ShellParseError::InvalidAlias { position, end, .. }
+            | ShellParseError::InvalidFunction { position, end, .. }
+            | ShellParseError::BadSubstitution { position, end, .. }
+            | ShellParseError::UnterminatedSubstitution { position, end }
+            | ShellParseError::UnsupportedSubstitution { position, end }
+            | ShellParseError::InvalidRedirect { position, end, .. }
+            | ShellParseError::InvalidControlFlow { position, end, .. }
+            | ShellParseError::UnsupportedControlFlow { position, end, .. }
+            | ShellParseError::InvalidVariable { position, end, .. } => Span::new(*position, *end),
+            ShellParseError::Multiple { errors } => errors
+                .first()
+                .map_or_else(Span::default, ShellParseError::span),
+        }
+    }
+
+    /// Description of the problem without its position.
+    pub fn message(&self) -> String {
+        match self {
+            ShellParseError::UnknownCommand { name, .. } => format!("unknown command '{name}'"),
             ShellParseError::InvalidArity {
                 name,
                 min_expected,
                 max_expected,
                 found,
-                position,
-            } => ShellParseError::InvalidArity {
-                name,
-                min_expected,
-                max_expected,
-                found,
-                position: position + offset,
-            },
-            ShellParseError::UnterminatedQuote { quote, position } => {
-                ShellParseError::UnterminatedQuote {
-                    quote,
-                    position: position + offset,
+                ..
+            } => format!(
+                "invalid arity for '{name}': expected {min_expected}..{max_expected:?}, found {found}"
+            ),
+            ShellParseError::UnterminatedQuote { quote, .. } => format!("unterminated quote {quote}"),
+            ShellParseError::TrailingEscape { .. } => "trailing escape".into(),
+            ShellParseError::AliasLoop { name, .. } => {
+                format!("alias/function loop detected for '{name}'")
+            }
+            ShellParseError::InvalidAlias { name, message, .. } => {
+                format!("invalid alias '{name}': {message}")
+            }
+            ShellParseError::InvalidFunction { name, message, .. } => {
+                format!("invalid function '{name}': {message}")
+            }
+            ShellParseError::BadSubstitution { expression, .. } => {
+                format!("bad substitution '{expression}'")
+            }
+            ShellParseError::UnterminatedSubstitution { .. } => {
+                "unterminated command substitution".into()
+            }
+            ShellParseError::UnsupportedSubstitution { .. } => {
+                "command substitution is not available while parsing".into()
+            }
+            ShellParseError::InvalidRedirect { message, .. } => {
+                format!("invalid redirection: {message}")
+            }
+            ShellParseError::InvalidControlFlow {
+                keyword, message, ..
+            } => format!("invalid '{keyword}': {message}"),
+            ShellParseError::UnsupportedControlFlow { keyword, .. } => {
+                format!("'{keyword}' needs commands to run; use ShellParser::next_command")
+            }
+            ShellParseError::InvalidVariable { name, message, .. } => {
+                format!("invalid variable '{name}': {message}")
+            }
+            ShellParseError::Multiple { errors } => errors
+                .iter()
+                .map(ShellParseError::message)
+                .collect::<Vec<_>>()
+                .join("; "),
+        }
+    }
+
+    /// The individual errors, with [`ShellParseError::Multiple`] flattened.
+    pub fn errors(&self) -> Vec<&ShellParseError> {
+        match self {
+            ShellParseError::Multiple { errors } => {
+                errors.iter().flat_map(ShellParseError::errors).collect()
+            }
+            other => vec![other],
+        }
+    }
+
+    /// Locate every error in `source`, the script that was parsed.
+    pub fn diagnostics(&self, source: &str) -> Vec<Diagnostic> {
+        self.errors()
+            .into_iter()
+            .map(|error| Diagnostic::new(error.clone(), source))
+            .collect()
+    }
+
+    pub(crate) fn with_offset(mut self, offset: usize) -> Self {
+        self.shift(offset);
+        self
+    }
+
+    fn shift(&mut self, offset: usize) {
+        match self {
+            ShellParseError::UnknownCommand { position, end, .. }
+            | ShellParseError::InvalidArity { position, end, .. }
+            | ShellParseError::UnterminatedQuote { position, end, .. }
+            | ShellParseError::TrailingEscape { position, end }
+            | ShellParseError::AliasLoop { position, end, .. }
+            | ShellParseError::InvalidAlias { position, end, .. }
+            | ShellParseError::InvalidFunction { position, end, .. }
+            | ShellParseError::BadSubstitution { position, end, .. }
+            | ShellParseError::UnterminatedSubstitution { position, end }
+            | ShellParseError::UnsupportedSubstitution { position, end }
+            | ShellParseError::InvalidRedirect { position, end, .. }
+            | ShellParseError::InvalidControlFlow { position, end, .. }
+            | ShellParseError::UnsupportedControlFlow { position, end, .. }
+            | ShellParseError::InvalidVariable { position, end, .. } => {
+                *position += offset;
+                *end += offset;
+            }
+            ShellParseError::Multiple { errors } => {
+                for error in errors {
+                    error.shift(offset);
                 }
             }
-            ShellParseError::TrailingEscape { position } => ShellParseError::TrailingEscape {
-                position: position + offset,
-            },
-            ShellParseError::AliasLoop { name, position } => ShellParseError::AliasLoop {
-                name,
-                position: position + offset,
-            },
-            ShellParseError::InvalidAlias {
-                name,
-                message,
-                position,
-            } => ShellParseError::InvalidAlias {
-                name,
-                message,
-                position: position + offset,
-            },
-            ShellParseError::InvalidFunction {
-                name,
-                message,
-                position,
-            } => ShellParseError::InvalidFunction {
-                name,
-                message,
-                position: position + offset,
-            },
         }
     }
 }
+
+impl fmt::Display for ShellParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShellParseError::Multiple { errors } => {
+                for (idx, error) in errors.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str("; ")?;
+                    }
+                    write!(f, "{error}")?;
+                }
+                Ok(())
+            }
+            other => write!(f, "{} at {}", other.message(), other.position()),
+        }
+    }
+}
+
+impl std::error::Error for ShellParseError {}
@@ -0,0 +1,318 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+use crate::ast::Span;
+use crate::error::ShellParseError;
+use crate::fs::FileSystem;
+use crate::glob;
+use crate::tokenizer::Token;
+use crate::variables::{self, CallFrame, Variables};
+use crate::word::{ParameterExpansion, ParameterOp, WordPart};
+
+/// Output of a command substitution, which may wait for the commands it runs.
+pub(crate) type Substitution<'a, E> = Pin<Box<dyn Future<Output = Result<String, E>> + 'a>>;
+
+/// Callback starting the script of a command substitution.
+pub(crate) type Substitute<'a, E> = &'a mut dyn FnMut(&str) -> Substitution<'a, E>;
+
+/// Finish expanding without an executor, for substitutions that never wait.
+///
+/// Synchronous callbacks are wrapped in ready futures, and expansion waits
+/// on nothing else, so the first poll always completes.
+pub(crate) fn expand_now<T>(expansion: impl Future<Output = T>) -> T {
+    let mut cx = Context::from_waker(Waker::noop());
+    match pin!(expansion).poll(&mut cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("expansion waited without an asynchronous substitution"),
+    }
+}
+
+/// Expands words against the parser's variables and an optional substitution runner.
+///
+/// Variables are borrowed only briefly so a substitution may re-enter the parser.
+/// Pathname patterns are expanded only when a file system is available.
+pub(crate) struct Expander<'a, 's, E> {
+    variables: &'a RefCell<Variables>,
+    /// Call whose positional parameters and locals the words see.
+    frame: Option<Rc<CallFrame>>,
+    fs: Option<&'a dyn FileSystem>,
+    substitute: Option<Substitute<'s, E>>,
+    /// Fail on variables that are not set, as after `set -u`.
+    nounset: bool,
+}
+
+impl<'a, 's, E> Expander<'a, 's, E>
+where
+    E: From<ShellParseError>,
+{
+    pub(crate) fn new(
+        variables: &'a RefCell<Variables>,
+        fs: Option<&'a dyn FileSystem>,
+        substitute: Option<Substitute<'s, E>>,
+    ) -> Self {
+        Self {
+            variables,
+            frame: None,
+            fs,
+            substitute,
+            nounset: false,
+        }
+    }
+
+    pub(crate) fn set_nounset(&mut self, nounset: bool) {
+        self.nounset = nounset;
+    }
+
+    pub(crate) fn set_frame(&mut self, frame: Option<Rc<CallFrame>>) {
+        self.frame = frame;
+    }
+
+    /// Expand tokens into argument fields.
+    ///
+    /// Quoted text is kept intact while unquoted expansion results are split on
+    /// whitespace, so `$EMPTY` disappears and `"$EMPTY"` yields an empty argument.
+    /// Fields with unquoted `*`, `?` or `[...]` are replaced by the matching
+    /// paths, or kept as written when nothing matches.
+    pub(crate) async fn expand_fields(&mut self, tokens: &[Token]) -> Result<Vec<String>, E> {
+        let mut fields = Fields::default();
+        for token in tokens {
+            self.expand_parts(&token.parts, false, token.span(), &mut fields)
+                .await?;
+            fields.finish();
+        }
+
+        let mut words = Vec::with_capacity(fields.done.len());
+        for field in fields.done {
+            let paths = match (&field.pattern, self.fs) {
+                (Some(pattern), Some(fs)) => glob::expand(fs, pattern),
+                _ => Vec::new(),
+            };
+            if paths.is_empty() {
+                words.push(field.text);
+            } else {
+                words.extend(paths);
+            }
+        }
+        Ok(words)
+    }
+
+    /// Expand a token into a pattern for `case`, without field splitting.
+    ///
+    /// Quoted characters are escaped so they only match themselves.
+    pub(crate) async fn expand_pattern(&mut self, token: &Token) -> Result<String, E> {
+        let mut fields = Fields {
+            keep_whitespace: true,
+            ..Fields::default()
+        };
+        self.expand_parts(&token.parts, false, token.span(), &mut fields)
+            .await?;
+        Ok(fields.pattern)
+    }
+
+    /// Expand a single token into one string without field splitting.
+    pub(crate) async fn expand_single(&mut self, token: &Token) -> Result<String, E> {
+        self.expand_to_string(&token.parts, token.span()).await
+    }
+
+    async fn expand_to_string(&mut self, parts: &[WordPart], span: Span) -> Result<String, E> {
+        let mut fields = Fields {
+            keep_whitespace: true,
+            ..Fields::default()
+        };
+        self.expand_parts(parts, true, span, &mut fields).await?;
+        Ok(fields.current)
+    }
+
+    async fn expand_parts(
+        &mut self,
+        parts: &[WordPart],
+        quoted: bool,
+        span: Span,
+        fields: &mut Fields,
+    ) -> Result<(), E> {
+        for part in parts {
+            match part {
+                WordPart::Literal(text) => fields.push_quoted(text),
+                WordPart::Glob(ch) if quoted => fields.push_quoted(&ch.to_string()),
+                WordPart::Glob(ch) => fields.push_glob(*ch),
+                WordPart::Parameter {
+                    expansion,
+                    quoted: part_quoted,
+                } => {
+                    self.expand_parameter(expansion, quoted || *part_quoted, span, fields)
+                        .await?
+                }
+                WordPart::CommandSubstitution {
+                    script,
+                    quoted: part_quoted,
+                } => {
+                    let output = self.substitute(script, span).await?;
+                    let quoted = quoted || *part_quoted;
+                    if quoted {
+                        fields.active = true;
+                    }
+                    fields.push(output.trim_end_matches('\n'), quoted);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn substitute(&mut self, script: &str, span: Span) -> Result<String, E> {
+        match self.substitute.as_deref_mut() {
+            Some(substitute) => substitute(script).await,
+            None => Err(ShellParseError::UnsupportedSubstitution {
+                position: span.start,
+                end: span.end,
+            }
+            .into()),
+        }
+    }
+
+    async fn expand_parameter(
+        &mut self,
+        expansion: &ParameterExpansion,
+        quoted: bool,
+        span: Span,
+        fields: &mut Fields,
+    ) -> Result<(), E> {
+        let frame = self.frame.as_deref();
+        // `"$@"` is one field per positional parameter, and no field without any.
+        if expansion.name == "@"
+            && expansion.op == ParameterOp::Value
+            && quoted
+            && !fields.keep_whitespace
+        {
+            let args = frame.map(CallFrame::args).unwrap_or_default();
+            for (idx, arg) in args.iter().enumerate() {
+                if idx > 0 {
+                    fields.finish();
+                }
+                fields.push_quoted(arg);
+            }
+            return Ok(());
+        }
+        if quoted {
+            fields.active = true;
+        }
+
+        let value = variables::lookup(self.variables, frame, &expansion.name);
+        let checked = matches!(expansion.op, ParameterOp::Value | ParameterOp::Length);
+        // `$?` counts as set even before anything has run.
+        if self.nounset && checked && value.is_none() && expansion.name != "?" {
+            return Err(ShellParseError::InvalidVariable {
+                name: expansion.name.clone(),
+                message: "unbound variable".into(),
+                position: span.start,
+                end: span.end,
+            }
+            .into());
+        }
+        match &expansion.op {
+            ParameterOp::Value => fields.push(&value.unwrap_or_default(), quoted),
+            ParameterOp::Length => {
+                let length = value.map(|value| value.chars().count()).unwrap_or(0);
+                fields.push_quoted(&length.to_string());
+            }
+            ParameterOp::Default { word, check_empty } => match value {
+                Some(value) if !is_missing(&value, *check_empty) => fields.push(&value, quoted),
+                // Boxed, as the word may hold parameters of its own.
+                _ => Box::pin(self.expand_parts(word, quoted, span, fields)).await?,
+            },
+            ParameterOp::Assign { word, check_empty } => match value {
+                Some(value) if !is_missing(&value, *check_empty) => fields.push(&value, quoted),
+                _ => {
+                    let assigned = Box::pin(self.expand_to_string(word, span)).await?;
+                    variables::assign(
+                        self.variables,
+                        self.frame.as_deref(),
+                        &expansion.name,
+                        &assigned,
+                    );
+                    fields.push(&assigned, quoted);
+                }
+            },
+            ParameterOp::Alternative { word, check_empty } => {
+                if matches!(value, Some(value) if !is_missing(&value, *check_empty)) {
+                    Box::pin(self.expand_parts(word, quoted, span, fields)).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_missing(value: &str, check_empty: bool) -> bool {
+    check_empty && value.is_empty()
+}
+
+#[derive(Default)]
+struct Fields {
+    done: Vec<Field>,
+    current: String,
+    /// `current` with quoted text escaped, for use as a pattern.
+    pattern: String,
+    /// Set once `current` contains an unquoted wildcard.
+    wildcard: bool,
+    active: bool,
+    /// Expand to a single word: keep unquoted whitespace and join `"$@"`.
+    keep_whitespace: bool,
+}
+
+struct Field {
+    text: String,
+    /// Pattern to expand as pathnames, if the field has unquoted wildcards.
+    pattern: Option<String>,
+}
+
+impl Fields {
+    fn push(&mut self, text: &str, quoted: bool) {
+        if quoted {
+            self.push_quoted(text);
+        } else {
+            self.push_unquoted(text);
+        }
+    }
+
+    fn push_quoted(&mut self, text: &str) {
+        self.current.push_str(text);
+        self.pattern.push_str(&glob::escape(text));
+        self.active = true;
+    }
+
+    fn push_unquoted(&mut self, text: &str) {
+        for ch in text.chars() {
+            if ch.is_whitespace() && !self.keep_whitespace {
+                self.finish();
+            } else if glob::GLOB_CHARS.contains(&ch) {
+                self.push_glob(ch);
+            } else {
+                self.current.push(ch);
+                self.pattern.push(ch);
+                self.active = true;
+            }
+        }
+    }
+
+    fn push_glob(&mut self, ch: char) {
+        self.current.push(ch);
+        self.pattern.push(ch);
+        self.wildcard = true;
+        self.active = true;
+    }
+
+    fn finish(&mut self) {
+        if self.active {
+            let pattern = std::mem::take(&mut self.pattern);
+            self.done.push(Field {
+                text: std::mem::take(&mut self.current),
+                pattern: self.wildcard.then_some(pattern),
+            });
+            self.active = false;
+            self.wildcard = false;
+        }
+    }
+}
@@ -0,0 +1,417 @@
+//! Printing syntax trees back as script text.
+//!
+//! The output parses back into the same tree: compound commands are laid out
+//! one command per line with four-space indentation, and words are requoted so
+//! that literal text stays literal. Printing that tree again gives the same
+//! text, so formatting a script twice changes nothing the second time.
+//!
+//! ```
+//! use shell_parser::{fmt, ShellParser};
+//!
+//! let script = ShellParser::new()
+//!     .parse_script("if stat a;then echo \"it's here\"; fi")
+//!     .unwrap();
+//! assert_eq!(fmt::script(&script), "if stat a; then\n    echo 'it'\\''s here'\nfi\n");
+//!
+//! let path = "/posts/my post".to_string();
+//! assert_eq!(fmt::quote(&["render".into(), path]), "render '/posts/my post'");
+//! ```
+
+use crate::ast::{
+    Command, FunctionDefinition, List, Pipeline, Redirect, Script, SimpleCommand, Word,
+};
+use crate::redirect::{RedirectOperator, STDIN, STDOUT};
+use crate::separator::Separator;
+use crate::word::{ParameterExpansion, ParameterOp, WordPart};
+
+const INDENT: &str = "    ";
+
+/// Script text of `script` in canonical form, one line per pipeline.
+///
+/// Comments and blank lines are not part of the tree, so they are dropped.
+pub fn script(script: &Script) -> String {
+    let mut printer = Printer::default();
+    printer.list(&script.body);
+    printer.out
+}
+
+/// Script text defining `definition`, as printed by `declare -f`.
+pub fn function(definition: &FunctionDefinition) -> String {
+    let mut printer = Printer::default();
+    printer.function(definition);
+    printer.out
+}
+
+/// Command text that parses back into exactly the words `args`, for building
+/// scripts out of values such as paths or user input.
+pub fn quote(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| quote_word(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `text` as one shell word, single-quoted only if it has to be.
+pub fn quote_word(text: &str) -> String {
+    if needs_quotes(text) {
+        single_quote(text)
+    } else {
+        text.to_string()
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize,
+    /// Bodies of here-documents opened on the current line, with their delimiters.
+    here_docs: Vec<(String, &'static str)>,
+}
+
+impl Printer {
+    /// End the current line and write any here-document bodies it opened.
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for (body, delimiter) in std::mem::take(&mut self.here_docs) {
+            self.out.push_str(&body);
+            self.out.push_str(delimiter);
+            self.out.push('\n');
+        }
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Write `list` as lines indented one level deeper.
+    fn lines(&mut self, list: &List) {
+        self.depth += 1;
+        self.list(list);
+        self.depth -= 1;
+    }
+
+    /// Write `list` as lines at the current depth, keeping `&&` and `||`
+    /// chains on one line.
+    fn list(&mut self, list: &List) {
+        let mut line_start = true;
+        for item in &list.items {
+            if line_start {
+                self.indent();
+            }
+            self.pipeline(&item.pipeline);
+            line_start = match item.separator {
+                Some(Separator::And) => {
+                    self.out.push_str(" && ");
+                    false
+                }
+                Some(Separator::Or) => {
+                    self.out.push_str(" || ");
+                    false
+                }
+                Some(Separator::Background) => {
+                    self.out.push_str(" &");
+                    self.newline();
+                    true
+                }
+                _ => {
+                    self.newline();
+                    true
+                }
+            };
+        }
+        if !line_start {
+            self.newline();
+        }
+    }
+
+    /// Write `list` on the current line, as in the condition of an `if`.
+    fn inline(&mut self, list: &List) {
+        let last = list.items.len().saturating_sub(1);
+        for (idx, item) in list.items.iter().enumerate() {
+            self.pipeline(&item.pipeline);
+            if idx < last {
+                self.out.push_str(match item.separator {
+                    Some(Separator::And) => " && ",
+                    Some(Separator::Or) => " || ",
+                    Some(Separator::Background) => " & ",
+                    _ => "; ",
+                });
+            }
+        }
+    }
+
+    fn pipeline(&mut self, pipeline: &Pipeline) {
+        for (idx, command) in pipeline.commands.iter().enumerate() {
+            if idx > 0 {
+                self.out.push_str(" | ");
+            }
+            self.command(command);
+        }
+    }
+
+    fn command(&mut self, command: &Command) {
+        match command {
+            Command::Simple(command) => self.simple(command),
+            Command::If(command) => {
+                for (idx, branch) in command.branches.iter().enumerate() {
+                    if idx > 0 {
+                        self.indent();
+                    }
+                    self.out.push_str(if idx == 0 { "if " } else { "elif " });
+                    self.inline(&branch.condition);
+                    self.out.push_str("; then");
+                    self.newline();
+                    self.lines(&branch.body);
+                }
+                if let Some(otherwise) = &command.otherwise {
+                    self.indent();
+                    self.out.push_str("else");
+                    self.newline();
+                    self.lines(otherwise);
+                }
+                self.close("fi");
+            }
+            Command::Loop(command) => {
+                self.out
+                    .push_str(if command.until { "until " } else { "while " });
+                self.inline(&command.condition);
+                self.out.push_str("; do");
+                self.newline();
+                self.lines(&command.body);
+                self.close("done");
+            }
+            Command::For(command) => {
+                self.out.push_str("for ");
+                self.out.push_str(&command.name);
+                if !command.words.is_empty() {
+                    self.out.push_str(" in");
+                    for word in &command.words {
+                        self.out.push(' ');
+                        self.out.push_str(&word_text(word));
+                    }
+                }
+                self.out.push_str("; do");
+                self.newline();
+                self.lines(&command.body);
+                self.close("done");
+            }
+            Command::Case(command) => {
+                self.out.push_str("case ");
+                self.out.push_str(&word_text(&command.subject));
+                self.out.push_str(" in");
+                self.newline();
+                self.depth += 1;
+                for arm in &command.arms {
+                    self.indent();
+                    let patterns: Vec<String> = arm.patterns.iter().map(word_text).collect();
+                    self.out.push_str(&patterns.join(" | "));
+                    self.out.push(')');
+                    self.newline();
+                    self.lines(&arm.body);
+                    self.depth += 1;
+                    self.indent();
+                    self.depth -= 1;
+                    self.out.push_str(";;");
+                    self.newline();
+                }
+                self.depth -= 1;
+                self.close("esac");
+            }
+            Command::Function(definition) => self.function(definition),
+        }
+    }
+
+    /// Write the keyword ending a compound command at the current depth.
+    fn close(&mut self, keyword: &str) {
+        self.indent();
+        self.out.push_str(keyword);
+    }
+
+    fn function(&mut self, definition: &FunctionDefinition) {
+        self.out.push_str(&definition.name);
+        self.out.push_str("() {");
+        self.newline();
+        self.lines(&definition.body);
+        self.close("}");
+    }
+
+    fn simple(&mut self, command: &SimpleCommand) {
+        let mut parts: Vec<String> = command.words.iter().map(word_text).collect();
+        for redirect in &command.redirects {
+            let text = self.redirect(redirect);
+            parts.push(text);
+        }
+        self.out.push_str(&parts.join(" "));
+    }
+
+    fn redirect(&mut self, redirect: &Redirect) -> String {
+        let target = redirect.target.as_ref();
+        match redirect.operator {
+            RedirectOperator::Output { fd, append } => {
+                let fd = if fd == STDOUT {
+                    String::new()
+                } else {
+                    fd.to_string()
+                };
+                let operator = if append { ">>" } else { ">" };
+                format!(
+                    "{fd}{operator} {}",
+                    target.map(word_text).unwrap_or_default()
+                )
+            }
+            RedirectOperator::Input { fd } => {
+                let fd = if fd == STDIN {
+                    String::new()
+                } else {
+                    fd.to_string()
+                };
+                format!("{fd}< {}", target.map(word_text).unwrap_or_default())
+            }
+            RedirectOperator::Duplicate { fd, target } => format!("{fd}>&{target}"),
+            RedirectOperator::HereDoc { .. } => {
+                let parts = target.map(|word| word.parts.as_slice()).unwrap_or_default();
+                let expands = parts
+                    .iter()
+                    .any(|part| !matches!(part, WordPart::Literal(_)));
+                let mut body = String::new();
+                for (idx, part) in parts.iter().enumerate() {
+                    match part {
+                        WordPart::Literal(text) if expands => {
+                            body.push_str(&escape(text, "\\$`"));
+                        }
+                        part => push_part(&mut body, part, parts.get(idx + 1)),
+                    }
+                }
+                let delimiter = here_doc_delimiter(&body);
+                self.here_docs.push((body, delimiter));
+                if expands {
+                    format!("<<{delimiter}")
+                } else {
+                    format!("<<'{delimiter}'")
+                }
+            }
+        }
+    }
+}
+
+/// A delimiter that no line of `body` consists of.
+fn here_doc_delimiter(body: &str) -> &'static str {
+    ["EOF", "END_OF_INPUT", "END_OF_HERE_DOCUMENT"]
+        .into_iter()
+        .find(|delimiter| !body.lines().any(|line| line == *delimiter))
+        .unwrap_or("END_OF_HERE_DOCUMENT_")
+}
+
+/// Script text of `word`: literal text is quoted where needed, and expansions
+/// that appeared inside double quotes are printed inside double quotes.
+fn word_text(word: &Word) -> String {
+    let text = parts_text(&word.parts);
+    if text.is_empty() {
+        "''".into()
+    } else {
+        text
+    }
+}
+
+fn parts_text(parts: &[WordPart]) -> String {
+    let mut out = String::new();
+    let mut in_quotes = false;
+    for (idx, part) in parts.iter().enumerate() {
+        let next = parts.get(idx + 1);
+        let quoted = match part {
+            WordPart::Parameter { quoted, .. } | WordPart::CommandSubstitution { quoted, .. } => {
+                *quoted
+            }
+            // Literal text means the same inside double quotes, so it joins
+            // a quoted run instead of interrupting it.
+            WordPart::Literal(text) => {
+                in_quotes || (needs_quotes(text) && next.is_some_and(is_quoted_expansion))
+            }
+            WordPart::Glob(_) => false,
+        };
+        if quoted != in_quotes {
+            out.push('"');
+            in_quotes = quoted;
+        }
+        match part {
+            WordPart::Literal(text) if in_quotes => out.push_str(&escape(text, "\\\"$`")),
+            WordPart::Literal(text) if needs_quotes(text) => out.push_str(&single_quote(text)),
+            part => push_part(&mut out, part, next),
+        }
+    }
+    if in_quotes {
+        out.push('"');
+    }
+    out
+}
+
+fn is_quoted_expansion(part: &WordPart) -> bool {
+    matches!(
+        part,
+        WordPart::Parameter { quoted: true, .. }
+            | WordPart::CommandSubstitution { quoted: true, .. }
+    )
+}
+
+/// Append `part` as written; `next` decides whether `$NAME` needs braces.
+fn push_part(out: &mut String, part: &WordPart, next: Option<&WordPart>) {
+    match part {
+        WordPart::Literal(text) => out.push_str(text),
+        WordPart::Glob(ch) => out.push(*ch),
+        WordPart::Parameter { expansion, .. } => out.push_str(&parameter_text(expansion, next)),
+        WordPart::CommandSubstitution { script, .. } => {
+            out.push_str("$(");
+            out.push_str(script);
+            out.push(')');
+        }
+    }
+}
+
+fn parameter_text(expansion: &ParameterExpansion, next: Option<&WordPart>) -> String {
+    let name = &expansion.name;
+    let (operator, word, check_empty) = match &expansion.op {
+        ParameterOp::Value => {
+            let continues_name = matches!(next, Some(WordPart::Literal(text))
+                if text.starts_with(|ch: char| ch.is_ascii_alphanumeric() || ch == '_'));
+            return if continues_name
+                || name.len() > 1 && name.starts_with(|ch: char| ch.is_ascii_digit())
+            {
+                format!("${{{name}}}")
+            } else {
+                format!("${name}")
+            };
+        }
+        ParameterOp::Length => return format!("${{#{name}}}"),
+        ParameterOp::Default { word, check_empty } => ("-", word, check_empty),
+        ParameterOp::Assign { word, check_empty } => ("=", word, check_empty),
+        ParameterOp::Alternative { word, check_empty } => ("+", word, check_empty),
+    };
+    let colon = if *check_empty { ":" } else { "" };
+    format!("${{{name}{colon}{operator}{}}}", parts_text(word))
+}
+
+/// Whether literal `text` must be quoted to be read back as the same text.
+fn needs_quotes(text: &str) -> bool {
+    text.is_empty()
+        || text
+            .chars()
+            .any(|ch| ch.is_whitespace() || "'\"\\$`;&|<>(){}*?[]#~".contains(ch))
+}
+
+pub(crate) fn single_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Backslash-escape each character of `text` found in `special`.
+fn escape(text: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if special.contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
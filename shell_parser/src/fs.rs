@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Path that discards writes and reads as empty, whatever the file system.
+pub const DEV_NULL: &str = "/dev/null";
+
+/// Files that [`CliRunner`](crate::CliRunner) redirections read from and write to.
+///
+/// Paths are passed exactly as written in the script; implementations decide
+/// how to resolve relative paths. Errors are shown to the user as
+/// `<path>: <message>`.
+pub trait FileSystem {
+    /// Contents of the file at `path`.
+    fn read_file(&self, path: &str) -> Result<String, String>;
+
+    /// Replace the file at `path` with `contents`, or add to its end when `append` is set.
+    fn write_file(&self, path: &str, contents: &str, append: bool) -> Result<(), String>;
+
+    /// Names of the entries directly inside the directory at `path`.
+    ///
+    /// Used to expand unquoted `*`, `?` and `[...]` patterns; `path` is `.` for
+    /// patterns without a directory. File systems that cannot list directories
+    /// keep the default, which leaves every pattern as written.
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, String> {
+        let _ = path;
+        Err("cannot list directories".into())
+    }
+}
+
+impl<T: FileSystem + ?Sized> FileSystem for Rc<T> {
+    fn read_file(&self, path: &str) -> Result<String, String> {
+        (**self).read_file(path)
+    }
+
+    fn write_file(&self, path: &str, contents: &str, append: bool) -> Result<(), String> {
+        (**self).write_file(path, contents, append)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, String> {
+        (**self).read_dir(path)
+    }
+}
+
+/// In-memory file system keyed by the literal path; used by default.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    files: RefCell<HashMap<String, String>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paths of every file written so far, sorted.
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self.files.borrow().keys().cloned().collect();
+        paths.sort();
+        paths
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read_file(&self, path: &str) -> Result<String, String> {
+        self.files
+            .borrow()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| "no such file".to_string())
+    }
+
+    fn write_file(&self, path: &str, contents: &str, append: bool) -> Result<(), String> {
+        let mut files = self.files.borrow_mut();
+        let file = files.entry(path.to_string()).or_default();
+        if !append {
+            file.clear();
+        }
+        file.push_str(contents);
+        Ok(())
+    }
+
+    /// Treats `/` in the stored paths as directory separators.
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, String> {
+        let prefix = match path.trim_end_matches('/') {
+            "." => String::new(),
+            "" => "/".to_string(),
+            dir => format!("{dir}/"),
+        };
+        let mut names: Vec<String> = self
+            .files
+            .borrow()
+            .keys()
+            .filter_map(|file| file.strip_prefix(prefix.as_str()))
+            .filter_map(|rest| rest.split('/').next())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        if names.is_empty() && !prefix.is_empty() {
+            return Err("no such directory".into());
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+}
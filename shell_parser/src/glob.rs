@@ -0,0 +1,176 @@
+use crate::fs::FileSystem;
+
+/// Characters that make an unquoted word a pattern.
+pub(crate) const GLOB_CHARS: &[char] = &['*', '?', '[', ']'];
+
+/// Escape `text` so every character in it matches literally.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if GLOB_CHARS.contains(&ch) || ch == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn unescape(pattern: &str) -> String {
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(ch),
+        }
+    }
+    text
+}
+
+/// Whether `text` matches a shell pattern with `*`, `?` and `[...]` classes.
+///
+/// A backslash makes the next character literal, which is how quoted parts of
+/// a word are kept from matching as wildcards.
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_from(&pattern, &text)
+}
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    let Some((&first, rest)) = pattern.split_first() else {
+        return text.is_empty();
+    };
+    match first {
+        '*' => (0..=text.len()).any(|skip| matches_from(rest, &text[skip..])),
+        '?' => !text.is_empty() && matches_from(rest, &text[1..]),
+        '[' => match match_class(rest, text.first().copied()) {
+            Some((true, after)) => matches_from(after, &text[1..]),
+            Some((false, _)) => false,
+            // An unclosed `[` is an ordinary character.
+            None => text.first() == Some(&'[') && matches_from(rest, &text[1..]),
+        },
+        '\\' => match rest.split_first() {
+            Some((&literal, rest)) => {
+                text.first() == Some(&literal) && matches_from(rest, &text[1..])
+            }
+            None => text == ['\\'],
+        },
+        _ => text.first() == Some(&first) && matches_from(rest, &text[1..]),
+    }
+}
+
+/// Match one character against the class whose `[` was consumed.
+///
+/// Returns whether it matched and the pattern after the closing `]`, or `None`
+/// when the class is never closed.
+fn match_class(pattern: &[char], ch: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, mut idx) = match pattern.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        let mut current = *pattern.get(idx)?;
+        if current == ']' && !first {
+            let matched = ch.is_some() && matched != negated;
+            return Some((matched, &pattern[idx + 1..]));
+        }
+        first = false;
+        if current == '\\' {
+            idx += 1;
+            current = *pattern.get(idx)?;
+        }
+        idx += 1;
+
+        let is_range =
+            pattern.get(idx) == Some(&'-') && pattern.get(idx + 1).is_some_and(|end| *end != ']');
+        if is_range {
+            let mut end = pattern[idx + 1];
+            idx += 2;
+            if end == '\\' {
+                end = *pattern.get(idx)?;
+                idx += 1;
+            }
+            matched |= ch.is_some_and(|ch| current <= ch && ch <= end);
+        } else {
+            matched |= ch == Some(current);
+        }
+    }
+}
+
+/// Expand a pathname pattern against `fs`, returning sorted matches.
+///
+/// Returns an empty list when nothing matches; callers keep the word as written.
+pub(crate) fn expand(fs: &dyn FileSystem, pattern: &str) -> Vec<String> {
+    if !has_wildcards(pattern) {
+        return Vec::new();
+    }
+
+    let absolute = pattern.starts_with('/');
+    let mut paths = vec![if absolute {
+        "/".to_string()
+    } else {
+        String::new()
+    }];
+    for segment in pattern.split('/').filter(|segment| !segment.is_empty()) {
+        if segment == "." || segment == ".." {
+            paths = paths.iter().map(|base| join(base, segment)).collect();
+            continue;
+        }
+        let mut next = Vec::new();
+        for base in &paths {
+            let dir = if base.is_empty() { "." } else { base.as_str() };
+            let Ok(mut names) = fs.read_dir(dir) else {
+                continue;
+            };
+            names.sort();
+            let wildcard = has_wildcards(segment);
+            for name in names {
+                let hidden = name.starts_with('.') && !segment.starts_with('.');
+                let found = if wildcard {
+                    !hidden && matches(segment, &name)
+                } else {
+                    name == unescape(segment)
+                };
+                if found {
+                    next.push(join(base, &name));
+                }
+            }
+        }
+        paths = next;
+    }
+
+    if pattern.ends_with('/') {
+        for path in &mut paths {
+            path.push('/');
+        }
+    }
+    paths
+}
+
+fn join(base: &str, name: &str) -> String {
+    if base.is_empty() {
+        name.to_string()
+    } else if base.ends_with('/') {
+        format!("{base}{name}")
+    } else {
+        format!("{base}/{name}")
+    }
+}
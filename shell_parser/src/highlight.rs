@@ -0,0 +1,623 @@
+//! Classifying the text of a command line for syntax highlighting.
+//!
+//! Unlike the parser, the scanner here never fails: unterminated quotes and
+//! substitutions run to the end of the input and unbalanced keywords are
+//! marked as errors, so a prompt can be highlighted on every keystroke.
+
+use std::collections::HashSet;
+
+use crate::ast::Span;
+
+/// What a highlighted range of the input is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HighlightKind {
+    /// Command name that resolves to a registered command or builtin.
+    Command,
+    /// Command name that would fail with an unknown-command error.
+    UnknownCommand,
+    /// Command name that is an alias, from a spec or an `alias` definition.
+    Alias,
+    /// Command name that calls a shell function, or a function being defined.
+    Function,
+    /// Reserved word such as `if`, `then` or `done`.
+    Keyword,
+    /// Argument starting with `-`.
+    Option,
+    /// Quoted text or a here-document body.
+    String,
+    /// Separator, pipe, redirection or brace.
+    Operator,
+    /// `#` comment.
+    Comment,
+    /// `$NAME`, `${...}`, `$(...)`, a backtick substitution or an assigned name.
+    Variable,
+    /// Text that can never parse, such as `fi` without `if`.
+    Error,
+}
+
+/// Range of the input and how to display it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HighlightSpan {
+    pub kind: HighlightKind,
+    pub span: Span,
+}
+
+/// Reserved words opening a block, the word closing it, and the words allowed inside.
+const BLOCKS: &[(&str, &str, &[&str])] = &[
+    ("if", "fi", &["then", "elif", "else"]),
+    ("while", "done", &["do"]),
+    ("until", "done", &["do"]),
+    ("for", "done", &["do"]),
+    ("case", "esac", &[]),
+];
+
+/// Characters that end an unquoted word.
+fn is_operator(ch: char) -> bool {
+    matches!(ch, ';' | '&' | '|' | '<' | '>' | '{' | '}')
+}
+
+fn is_name_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+/// How the next word is read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Role {
+    Command,
+    Argument,
+    /// Loop variable after `for`.
+    LoopName,
+    /// Word after `case`.
+    Subject,
+    /// `in` after `for NAME` or `case WORD`.
+    In {
+        case: bool,
+    },
+    /// `case` pattern up to its closing `)`.
+    Pattern,
+    /// Name after `function`.
+    FunctionName,
+}
+
+/// Quoted or expanded piece of a word.
+type Part = (HighlightKind, usize, usize);
+
+/// What the word at the end of the input stands for, as far as completion cares.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Slot {
+    Command,
+    /// Argument of `command`, when the command name is known.
+    Argument {
+        command: Option<String>,
+    },
+    /// Target of a redirection.
+    Path,
+    /// Anything else, such as a loop variable, a comment or a here-document line.
+    Other,
+}
+
+/// The word that ends the input, possibly empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LastWord {
+    pub(crate) start: usize,
+    /// Text of the word with quotes and escapes removed.
+    pub(crate) value: String,
+    pub(crate) slot: Slot,
+}
+
+/// What the scanner read last, before any trailing whitespace.
+enum Last {
+    Space,
+    Word(LastWord),
+    /// Comment or here-document text reaching the end of the input.
+    Text,
+}
+
+struct Scanner<'a> {
+    input: &'a str,
+    /// Byte offset of the next character.
+    at: usize,
+    spans: Vec<HighlightSpan>,
+    role: Role,
+    /// Set after a redirection operator; holds `strip_tabs` after `<<`.
+    target: Option<Option<bool>>,
+    /// Closing words of the blocks currently open, innermost last.
+    open: Vec<&'static str>,
+    /// Delimiters of here-documents whose bodies start on the next line.
+    here_docs: Vec<(String, bool)>,
+    /// Functions defined earlier in the input.
+    functions: HashSet<String>,
+    /// Name of the simple command whose arguments are being read.
+    command: Option<String>,
+    last: Last,
+}
+
+/// Classify the ranges of `input`; text between the returned spans is plain.
+///
+/// `command` decides the kind of a command name that is not a function
+/// defined earlier in the same input.
+pub(crate) fn highlight(
+    input: &str,
+    command: &dyn Fn(&str) -> HighlightKind,
+) -> Vec<HighlightSpan> {
+    let mut scanner = Scanner::new(input);
+    scanner.run(command);
+    scanner.spans
+}
+
+/// Find the word that ends `input` and what it stands for.
+///
+/// Text ending in whitespace or an operator yields an empty word at the end.
+pub(crate) fn last_word(input: &str) -> LastWord {
+    let mut scanner = Scanner::new(input);
+    scanner.run(&|_| HighlightKind::Command);
+    match scanner.last {
+        Last::Word(word) => word,
+        Last::Space => LastWord {
+            start: input.len(),
+            value: String::new(),
+            slot: scanner.slot(),
+        },
+        Last::Text => LastWord {
+            start: input.len(),
+            value: String::new(),
+            slot: Slot::Other,
+        },
+    }
+}
+
+impl<'a> Scanner<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            at: 0,
+            spans: Vec::new(),
+            role: Role::Command,
+            target: None,
+            open: Vec::new(),
+            here_docs: Vec::new(),
+            functions: HashSet::new(),
+            command: None,
+            last: Last::Space,
+        }
+    }
+
+    fn run(&mut self, command: &dyn Fn(&str) -> HighlightKind) {
+        while let Some(ch) = self.peek() {
+            let start = self.at;
+            match ch {
+                '\n' => {
+                    self.at += 1;
+                    self.last = Last::Space;
+                    self.end_command();
+                    self.read_here_docs();
+                }
+                ch if ch.is_whitespace() => {
+                    self.at += ch.len_utf8();
+                    self.last = Last::Space;
+                }
+                '#' => {
+                    self.at = self.line_end();
+                    self.last = Last::Text;
+                    self.push(HighlightKind::Comment, start, self.at);
+                }
+                ch if is_operator(ch) || self.at_redirect() => {
+                    self.last = Last::Space;
+                    self.operator();
+                }
+                _ => {
+                    let slot = self.slot();
+                    let (value, parts) = self.word();
+                    self.last = Last::Word(LastWord {
+                        start,
+                        value: value.clone(),
+                        slot,
+                    });
+                    self.classify(&value, start, parts, command);
+                }
+            }
+        }
+    }
+
+    /// What a word starting here would stand for.
+    fn slot(&self) -> Slot {
+        match (self.target, self.role) {
+            (Some(Some(_)), _) => Slot::Other,
+            (Some(None), _) => Slot::Path,
+            (None, Role::Command) => Slot::Command,
+            (None, Role::Argument | Role::In { case: false }) => Slot::Argument {
+                command: self.command.clone(),
+            },
+            _ => Slot::Other,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.at..].chars().next()
+    }
+
+    fn push(&mut self, kind: HighlightKind, start: usize, end: usize) {
+        if start < end {
+            self.spans.push(HighlightSpan {
+                kind,
+                span: Span::new(start, end),
+            });
+        }
+    }
+
+    fn push_parts(&mut self, parts: Vec<Part>) {
+        for (kind, start, end) in parts {
+            self.push(kind, start, end);
+        }
+    }
+
+    fn line_end(&self) -> usize {
+        self.input[self.at..]
+            .find('\n')
+            .map_or(self.input.len(), |idx| self.at + idx)
+    }
+
+    fn skip_digits(&mut self) {
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.at += 1;
+        }
+    }
+
+    /// After a separator the next word names a command, unless a `case` pattern comes first.
+    fn end_command(&mut self) {
+        self.target = None;
+        self.role = match self.role {
+            Role::Pattern | Role::In { case: true } => Role::Pattern,
+            _ => Role::Command,
+        };
+    }
+
+    /// Whether the input continues with digits followed by `<` or `>`, as in `2>&1`.
+    fn at_redirect(&self) -> bool {
+        let rest = &self.input[self.at..];
+        let digits = rest.len()
+            - rest
+                .trim_start_matches(|ch: char| ch.is_ascii_digit())
+                .len();
+        digits > 0 && rest[digits..].starts_with(['<', '>'])
+    }
+
+    fn operator(&mut self) {
+        let start = self.at;
+        self.skip_digits();
+        let ch = self.peek().unwrap_or_default();
+        self.at += ch.len_utf8();
+        let next = self.peek();
+        let mut kind = HighlightKind::Operator;
+        match (ch, next) {
+            (';', Some(';')) => {
+                self.at += 1;
+                self.target = None;
+                self.role = if self.open.last() == Some(&"esac") {
+                    Role::Pattern
+                } else {
+                    Role::Command
+                };
+            }
+            (';', _) => self.end_command(),
+            ('|', _) if self.role == Role::Pattern => {}
+            ('&' | '|', _) => {
+                let doubled = next == Some(ch);
+                if doubled {
+                    self.at += 1;
+                }
+                if self.role == Role::Command && (doubled || ch == '|') {
+                    kind = HighlightKind::Error;
+                }
+                self.target = None;
+                self.role = Role::Command;
+            }
+            ('<', Some('<')) => {
+                self.at += 1;
+                let strip_tabs = self.peek() == Some('-');
+                if strip_tabs {
+                    self.at += 1;
+                }
+                self.target = Some(Some(strip_tabs));
+            }
+            ('>', Some('&')) => {
+                self.at += 1;
+                self.skip_digits();
+            }
+            ('>', Some('>')) => {
+                self.at += 1;
+                self.target = Some(None);
+            }
+            ('<' | '>', _) => self.target = Some(None),
+            ('{', _) => {
+                self.open.push("}");
+                self.role = Role::Command;
+            }
+            _ => {
+                if self.open.last() == Some(&"}") {
+                    self.open.pop();
+                } else {
+                    kind = HighlightKind::Error;
+                }
+                self.role = Role::Argument;
+                self.command = None;
+            }
+        }
+        self.push(kind, start, self.at);
+    }
+
+    /// Read a word, returning its unquoted text and its quoted or expanded parts.
+    fn word(&mut self) -> (String, Vec<Part>) {
+        let mut value = String::new();
+        let mut parts = Vec::new();
+        while let Some(ch) = self.peek() {
+            if ch.is_whitespace() || is_operator(ch) {
+                break;
+            }
+            let start = self.at;
+            match ch {
+                '\\' => {
+                    self.at += 1;
+                    if let Some(escaped) = self.peek() {
+                        value.push(escaped);
+                        self.at += escaped.len_utf8();
+                    }
+                }
+                '\'' => {
+                    let rest = &self.input[start + 1..];
+                    let (text, end) = match rest.find('\'') {
+                        Some(idx) => (&rest[..idx], start + idx + 2),
+                        None => (rest, self.input.len()),
+                    };
+                    value.push_str(text);
+                    self.at = end;
+                    parts.push((HighlightKind::String, start, end));
+                }
+                '"' => self.double_quoted(&mut value, &mut parts),
+                '$' | '`' if self.substitution() => {
+                    value.push_str(&self.input[start..self.at]);
+                    parts.push((HighlightKind::Variable, start, self.at));
+                }
+                _ => {
+                    value.push(ch);
+                    self.at += ch.len_utf8();
+                }
+            }
+        }
+        (value, parts)
+    }
+
+    /// Read `"..."`, splitting it around the expansions inside.
+    fn double_quoted(&mut self, value: &mut String, parts: &mut Vec<Part>) {
+        let mut segment = self.at;
+        self.at += 1;
+        while let Some(ch) = self.peek() {
+            let start = self.at;
+            match ch {
+                '"' => {
+                    self.at += 1;
+                    break;
+                }
+                '\\' => {
+                    self.at += 1;
+                    if let Some(escaped) = self.peek() {
+                        value.push(escaped);
+                        self.at += escaped.len_utf8();
+                    }
+                }
+                '$' | '`' if self.substitution() => {
+                    parts.push((HighlightKind::String, segment, start));
+                    parts.push((HighlightKind::Variable, start, self.at));
+                    value.push_str(&self.input[start..self.at]);
+                    segment = self.at;
+                }
+                _ => {
+                    value.push(ch);
+                    self.at += ch.len_utf8();
+                }
+            }
+        }
+        parts.push((HighlightKind::String, segment, self.at));
+    }
+
+    /// Consume the expansion starting at `$` or `` ` ``; `false` leaves a literal `$`.
+    fn substitution(&mut self) -> bool {
+        let rest = &self.input[self.at..];
+        let mut chars = rest.chars();
+        let first = chars.next();
+        let end = match (first, chars.next()) {
+            (Some('`'), _) => rest[1..].find('`').map(|idx| idx + 2),
+            (Some('$'), Some('{')) => rest.find('}').map(|idx| idx + 1),
+            (Some('$'), Some('(')) => closing_paren(&rest[2..]).map(|idx| idx + 3),
+            (Some('$'), Some(ch)) if ch.is_ascii_alphabetic() || ch == '_' => Some(
+                rest[1..]
+                    .find(|ch: char| !is_name_char(ch))
+                    .map_or(rest.len(), |idx| idx + 1),
+            ),
+            (Some('$'), Some(ch)) if ch.is_ascii_digit() || "?#@*$!-".contains(ch) => Some(2),
+            _ => return false,
+        };
+        self.at += end.unwrap_or(rest.len());
+        true
+    }
+
+    fn classify(
+        &mut self,
+        value: &str,
+        start: usize,
+        parts: Vec<Part>,
+        command: &dyn Fn(&str) -> HighlightKind,
+    ) {
+        let end = self.at;
+        let plain = parts.is_empty();
+
+        if let Some(here_doc) = self.target.take() {
+            if let Some(strip_tabs) = here_doc {
+                self.here_docs.push((value.to_string(), strip_tabs));
+            }
+            self.push_parts(parts);
+            return;
+        }
+
+        match self.role {
+            Role::Pattern if !(plain && value == "esac") => {
+                if value.ends_with(')') {
+                    self.role = Role::Command;
+                }
+                self.push_parts(parts);
+            }
+            Role::LoopName => {
+                self.push(HighlightKind::Variable, start, end);
+                self.role = Role::In { case: false };
+            }
+            Role::Subject => {
+                self.push_parts(parts);
+                self.role = Role::In { case: true };
+            }
+            Role::In { case } if plain && value == "in" => {
+                self.push(HighlightKind::Keyword, start, end);
+                self.role = if case { Role::Pattern } else { Role::Argument };
+            }
+            Role::FunctionName => {
+                self.functions
+                    .insert(value.trim_end_matches("()").to_string());
+                self.push(HighlightKind::Function, start, end);
+                self.role = Role::Argument;
+            }
+            Role::Command | Role::Pattern => self.command_word(value, start, parts, command),
+            Role::Argument | Role::In { .. } => {
+                self.role = Role::Argument;
+                if self.input[start..end].starts_with('-') && end - start > 1 {
+                    self.push(HighlightKind::Option, start, end);
+                } else {
+                    self.push_parts(parts);
+                }
+            }
+        }
+    }
+
+    fn command_word(
+        &mut self,
+        value: &str,
+        start: usize,
+        parts: Vec<Part>,
+        command: &dyn Fn(&str) -> HighlightKind,
+    ) {
+        let end = self.at;
+        let plain = parts.is_empty();
+        let raw = &self.input[start..end];
+
+        let assigned = raw.find('=').filter(|idx| {
+            *idx > 0
+                && !raw.starts_with(|ch: char| ch.is_ascii_digit())
+                && raw[..*idx].chars().all(is_name_char)
+        });
+        if let Some(idx) = assigned {
+            self.push(HighlightKind::Variable, start, start + idx);
+            self.push_parts(parts);
+            return;
+        }
+
+        if plain && self.keyword(value, start, end) {
+            return;
+        }
+        if plain && value == "function" {
+            self.push(HighlightKind::Keyword, start, end);
+            self.role = Role::FunctionName;
+            return;
+        }
+
+        self.role = Role::Argument;
+        self.command = Some(value.to_string());
+        if let Some(name) = value.strip_suffix("()").filter(|name| !name.is_empty()) {
+            self.functions.insert(name.to_string());
+            self.push(HighlightKind::Function, start, end);
+            return;
+        }
+        let kind = if self.functions.contains(value) {
+            HighlightKind::Function
+        } else {
+            command(value)
+        };
+        self.push(kind, start, end);
+    }
+
+    /// Handle a reserved word in command position, checking it against the open blocks.
+    fn keyword(&mut self, word: &str, start: usize, end: usize) -> bool {
+        let mut kind = HighlightKind::Keyword;
+        if let Some((_, closer, _)) = BLOCKS.iter().find(|(opener, ..)| *opener == word) {
+            self.open.push(closer);
+            self.role = match word {
+                "for" => Role::LoopName,
+                "case" => Role::Subject,
+                _ => Role::Command,
+            };
+        } else if let Some((_, closer, _)) = BLOCKS.iter().find(|(_, closer, _)| *closer == word) {
+            if self.open.last() == Some(closer) {
+                self.open.pop();
+            } else {
+                kind = HighlightKind::Error;
+            }
+            self.role = Role::Argument;
+        } else if let Some((_, closer, _)) =
+            BLOCKS.iter().find(|(_, _, inner)| inner.contains(&word))
+        {
+            if self.open.last() != Some(closer) {
+                kind = HighlightKind::Error;
+            }
+            self.role = Role::Command;
+        } else {
+            return false;
+        }
+        self.command = None;
+        self.push(kind, start, end);
+        true
+    }
+
+    /// Highlight the bodies of here-documents opened on the line that just ended.
+    fn read_here_docs(&mut self) {
+        for (delimiter, strip_tabs) in std::mem::take(&mut self.here_docs) {
+            while self.at < self.input.len() {
+                let start = self.at;
+                let end = self.line_end();
+                self.at = (end + 1).min(self.input.len());
+                if end == self.input.len() {
+                    self.last = Last::Text;
+                }
+                let line = &self.input[start..end];
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line
+                };
+                if line == delimiter {
+                    self.push(HighlightKind::Operator, start, end);
+                    break;
+                }
+                self.push(HighlightKind::String, start, end);
+            }
+        }
+    }
+}
+
+/// Offset of the `)` closing a `$(` whose body is `text`, if it is closed.
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 1;
+    let mut quote = None;
+    for (idx, ch) in text.char_indices() {
+        match (quote, ch) {
+            (Some(open), _) if ch == open => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
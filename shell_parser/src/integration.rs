@@ -1,7 +1,33 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::future::{poll_fn, Future};
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::Duration;
 
+use async_trait::async_trait;
+
+use crate::builtins;
 use crate::command::CommandInvocation;
-use crate::{CommandSpec, ShellParseError, ShellParser};
+use crate::complete::Completion;
+use crate::expansion::Substitution;
+use crate::fmt;
+use crate::fs::{FileSystem, MemoryFs, DEV_NULL};
+use crate::highlight::HighlightSpan;
+use crate::io::{CommandIo, OutputStream};
+use crate::jobs::{self, JobState, Jobs, Task, JOB_BUILTINS};
+use crate::limits::{LimitExceeded, Limits};
+use crate::middleware::{CommandRecord, Decision, Middleware, Outcome};
+use crate::options::ShellOptions;
+use crate::parser::{CommandQueue, CommandType, Step, PARSER_BUILTINS};
+use crate::redirect::{Redirect, STDERR};
+use crate::separator::Separator;
+use crate::status::ExitStatus;
+use crate::{CommandSpec, ShellParseError, ShellParser, Variables};
 
 /// Error surfaced when wiring parsed commands into executable handlers.
 #[derive(Debug, thiserror::Error)]
@@ -10,6 +36,8 @@ pub enum ShellCliError {
     Parse(#[from] ShellParseError),
     #[error("command failed: {command}: {message}")]
     Execution { command: String, message: String },
+    #[error("{0}")]
+    Limit(#[from] LimitExceeded),
 }
 
 /// Common metadata for CLI commands.
@@ -24,102 +52,383 @@ pub trait CommandInfo {
         &[]
     }
 
+    /// Flags the command accepts, offered when completing an argument that starts with `-`.
+    fn command_options(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Fewest and most arguments the command takes, checked when the command
+    /// is parsed; a `None` maximum leaves it unbounded.
+    fn command_arity(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+
+    /// For commands that parse their own options, the options that take the
+    /// next argument as their value. When set, only positional arguments are
+    /// checked against [`command_arity`](Self::command_arity).
+    fn command_value_options(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
     /// Specification for validation.
     fn command_spec(&self) -> CommandSpec {
-        let mut spec = CommandSpec::new(self.command_name(), self.command_about());
+        let (min_args, max_args) = self.command_arity();
+        let mut spec =
+            CommandSpec::new(self.command_name(), self.command_about()).with_min_args(min_args);
+        if let Some(max_args) = max_args {
+            spec = spec.with_max_args(max_args);
+        }
         for alias in self.command_aliases() {
             spec = spec.with_alias(*alias);
         }
-        spec
+        if let Some(value_options) = self.command_value_options() {
+            spec = spec.with_option_parsing(value_options.iter().copied());
+        }
+        spec.with_options(self.command_options().iter().copied())
     }
 }
 
 /// Trait implemented by higher-level CLI commands that can be executed after parsing.
+///
+/// Implementations are written with [`async_trait`](macro@crate::async_trait):
+/// put `#[shell_parser::async_trait(?Send)]` on the `impl` block and declare
+/// `async fn run`.
+#[async_trait(?Send)]
 pub trait ExecutableCommand<C>: CommandInfo + Send + Sync {
     /// Specification (includes name) for validation.
     fn spec(&self) -> CommandSpec {
         self.command_spec()
     }
     /// Execute the command with already-parsed arguments.
-    fn run(&self, args: &[String], context: &C) -> Result<(), String>;
-
-    /// Execute with an optional piped input and return an optional piped output.
-    fn run_with_input(
+    ///
+    /// Regular output goes to `io.stdout`, which is piped into the next command,
+    /// redirected to a file or shown on the terminal; error messages go to
+    /// `io.stderr`. Piped or `<` input arrives in `io.stdin`. The returned
+    /// status drives `&&`, `||` and `$?`. `Err` aborts the whole script.
+    ///
+    /// A command may await timers or network requests; the runner waits for
+    /// it to finish before starting the next one.
+    async fn run(
         &self,
         args: &[String],
-        input: Option<String>,
+        io: &mut CommandIo<'_>,
         context: &C,
-    ) -> Result<Option<String>, String> {
-        self.run(args, context)?;
-        Ok(input)
-    }
+    ) -> Result<ExitStatus, String>;
 }
 
+/// Sink receiving terminal output that is not piped or redirected anywhere.
+type OutputSink<C> = Box<dyn Fn(&C, &str)>;
+
+/// Hook handing a background job's future to an executor.
+type Spawner = Box<dyn Fn(Pin<Box<dyn Future<Output = ()>>>)>;
+
+/// Current time since some fixed point, for [`Limits::max_time`].
+type Clock = Box<dyn Fn() -> Duration>;
+
+/// Future that completes once the given time has passed.
+type Timer = Box<dyn Fn(Duration) -> Pin<Box<dyn Future<Output = ()>>>>;
+
 /// Builder for integrating [`ShellParser`] with executable commands.
 pub struct CliRunner<C> {
+    runtime: Rc<Runtime<C>>,
+    specs: Vec<CommandSpec>,
+    last_status: Cell<ExitStatus>,
+}
+
+/// What running a pipeline needs; background jobs share it with the runner.
+struct Runtime<C> {
     parser: ShellParser,
     handlers: HashMap<String, Box<dyn ExecutableCommand<C>>>,
-    specs: Vec<CommandSpec>,
     context: C,
+    stdout: Option<OutputSink<C>>,
+    stderr: Option<OutputSink<C>>,
+    fs: Rc<dyn FileSystem>,
+    jobs: Jobs,
+    spawner: Option<Spawner>,
+    limits: Limits,
+    clock: Option<Clock>,
+    timer: Option<Timer>,
+    middleware: Vec<Box<dyn Middleware<C>>>,
+    /// Run being polled right now; a run started meanwhile, such as a
+    /// substitution or an `eval` command, nests in it.
+    active: RefCell<Option<Frame>>,
+}
+
+/// A run in progress, sharing what it uses with the top-level run it is
+/// nested in. Runs started side by side each count on their own.
+#[derive(Clone)]
+struct Frame {
+    /// 1 for a top-level run.
+    depth: usize,
+    usage: Rc<RefCell<Usage>>,
+}
+
+impl Frame {
+    /// Record that `limit` was reached, unless another limit was first; returns
+    /// the first one.
+    fn exceed(&self, limit: LimitExceeded) -> LimitExceeded {
+        *self.usage.borrow_mut().exceeded.get_or_insert(limit)
+    }
+}
+
+/// What a top-level run and the runs nested in it have used so far.
+#[derive(Default)]
+struct Usage {
+    commands: usize,
+    output_bytes: usize,
+    started: Option<Duration>,
+    /// First limit reached; it stops the run and every run nested in it.
+    exceeded: Option<LimitExceeded>,
+}
+
+/// Commands joined by `|` and the separator that ends them.
+struct Pipeline {
+    commands: Vec<CommandInvocation>,
+    separator: Option<Separator>,
+    /// Whether a condition tests the pipeline's status, so `set -e` lets it fail.
+    tested: bool,
+}
+
+/// Destination of a command's stdout or stderr once redirections are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stream {
+    Terminal,
+    Errors,
+    Pipe,
+    File(usize),
+    Null,
 }
 
-/// Result of running a script through the CLI runner.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ScriptResult {
-    Completed,
-    Paused {
-        delay_ms: u32,
-        remainder: Vec<CommandInvocation>,
-    },
+/// File opened by a `>` or `>>` redirection; written once the command finishes.
+struct RedirectFile {
+    path: String,
+    append: bool,
+    contents: RefCell<String>,
 }
 
-/// Prefix embedded in command errors to signal the runner to pause execution.
-pub const PAUSE_SIGNAL_PREFIX: &str = "__zzhack_pause__:";
+/// Streams of a single command after applying its redirections in order.
+struct RedirectPlan {
+    stdin: Option<String>,
+    stdout: Stream,
+    stderr: Stream,
+    files: Vec<RedirectFile>,
+}
+
+impl RedirectPlan {
+    fn stream(&self, fd: u32) -> Stream {
+        if fd == STDERR {
+            self.stderr
+        } else {
+            self.stdout
+        }
+    }
 
-/// Build a pause signal string for a command to interrupt script execution.
-pub fn pause_signal(delay_ms: u32) -> String {
-    format!("{PAUSE_SIGNAL_PREFIX}{delay_ms}")
+    fn set_stream(&mut self, fd: u32, stream: Stream) {
+        if fd == STDERR {
+            self.stderr = stream;
+        } else {
+            self.stdout = stream;
+        }
+    }
+
+    /// Stream writing to `path`, reusing the file if the command already opened it.
+    fn open(&mut self, path: &str, append: bool) -> Stream {
+        if path == DEV_NULL {
+            return Stream::Null;
+        }
+        if let Some(idx) = self.files.iter().position(|file| file.path == path) {
+            return Stream::File(idx);
+        }
+        self.files.push(RedirectFile {
+            path: path.to_string(),
+            append,
+            contents: RefCell::new(String::new()),
+        });
+        Stream::File(self.files.len() - 1)
+    }
 }
 
-impl<C> CliRunner<C> {
-    /// Parse and execute a full script (multiple lines/commands).
-    pub fn run_script(&self, script: &str) -> Result<ScriptResult, ShellCliError> {
-        let invocations = self.parser.parse(script)?;
-        self.run_invocation_slice(&invocations)
+impl<C: 'static> CliRunner<C> {
+    /// Show terminal output through `sink`; without one it is discarded.
+    pub fn with_stdout(mut self, sink: impl Fn(&C, &str) + 'static) -> Self {
+        self.runtime_mut().stdout = Some(Box::new(sink));
+        self
+    }
+
+    /// Show error output through `sink`; without one it is discarded.
+    pub fn with_stderr(mut self, sink: impl Fn(&C, &str) + 'static) -> Self {
+        self.runtime_mut().stderr = Some(Box::new(sink));
+        self
+    }
+
+    /// Use `fs` for `<`, `>` and `>>` redirections and for expanding `*`, `?`
+    /// and `[...]` patterns, instead of an in-memory [`MemoryFs`].
+    pub fn with_fs(mut self, fs: impl FileSystem + 'static) -> Self {
+        let runtime = self.runtime_mut();
+        runtime.fs = Rc::new(fs);
+        runtime.parser.set_file_system(runtime.fs.clone());
+        self
+    }
+
+    /// Run background jobs started with `&` by handing them to `spawn`, such
+    /// as `wasm_bindgen_futures::spawn_local`.
+    ///
+    /// Without a spawner, background jobs only make progress while the runner
+    /// is running something, for example a `wait` for them.
+    pub fn with_spawner(
+        mut self,
+        spawn: impl Fn(Pin<Box<dyn Future<Output = ()>>>) + 'static,
+    ) -> Self {
+        self.runtime_mut().spawner = Some(Box::new(spawn));
+        self
+    }
+
+    /// Stop scripts that go past `limits`, with a [`ShellCliError::Limit`]
+    /// naming the limit.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.runtime_mut().limits = limits;
+        self
+    }
+
+    /// Measure [`Limits::max_time`] with `now`, the time since any fixed point,
+    /// such as `Date.now()` in a browser.
+    pub fn with_clock(mut self, now: impl Fn() -> Duration + 'static) -> Self {
+        self.runtime_mut().clock = Some(Box::new(now));
+        self
+    }
+
+    /// Wake a command that is still waiting when its run's [`Limits::max_time`]
+    /// is up, with `sleep`, such as a `setTimeout` promise in a browser.
+    ///
+    /// Without a timer the clock is only read when a command wakes by itself.
+    pub fn with_timer(
+        mut self,
+        sleep: impl Fn(Duration) -> Pin<Box<dyn Future<Output = ()>>> + 'static,
+    ) -> Self {
+        self.runtime_mut().timer = Some(Box::new(sleep));
+        self
+    }
+
+    /// Pass every command through `middleware` before and after it runs.
+    ///
+    /// Middleware sees commands in the order it was added, and the command's
+    /// end in the reverse order, so the first one added wraps the others.
+    pub fn with_middleware(mut self, middleware: impl Middleware<C> + 'static) -> Self {
+        self.runtime_mut().middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Settings change only while building, before any job shares the runtime.
+    fn runtime_mut(&mut self) -> &mut Runtime<C> {
+        Rc::get_mut(&mut self.runtime).expect("runner is configured before it runs scripts")
+    }
+
+    /// Parse and execute a full script (multiple lines/commands), returning
+    /// the status of the last pipeline.
+    ///
+    /// Commands are expanded one at a time, so variables and command
+    /// substitutions (`$(...)`) see the effects of the commands before them.
+    /// Each command is awaited before the next one starts. Commands joined by
+    /// `|` run as a pipeline, and `&&`/`||` skip the next pipeline depending
+    /// on the status of the previous one. `if`, `while`, `until`, `for` and
+    /// `case` branch and loop on exit statuses the same way.
+    ///
+    /// A pipeline followed by `&` starts as a background job numbered `%1`,
+    /// `%2`, ... and the script carries on; the `jobs`, `fg`, `wait` and `kill`
+    /// builtins list, wait for and stop jobs, and the terminal output of a job
+    /// is tagged with its number.
+    ///
+    /// When the script is not run from inside another one, the shell
+    /// functions `preexec` and `precmd` are called around it if they are
+    /// defined: `preexec` gets the script as `$1`, and `precmd` sees the
+    /// script's status in `$?`. Neither changes `$?` for what comes after it.
+    pub async fn run_script(&self, script: &str) -> Result<ExitStatus, ShellCliError> {
+        let mut queue = self.runtime.parser.queue(script)?;
+        self.run_hooked(script, &mut queue).await
+    }
+
+    /// Like [`CliRunner::run_script`], with `args` as the script's positional
+    /// parameters `$1`, `$2`, ..., `$#` and `$@`.
+    pub async fn run_script_with_args(
+        &self,
+        script: &str,
+        args: Vec<String>,
+    ) -> Result<ExitStatus, ShellCliError> {
+        let mut queue = self.runtime.parser.queue_with_args(script, args)?;
+        self.run_hooked(script, &mut queue).await
     }
 
     /// Execute commands that have already been parsed.
-    pub fn run_invocations(
+    pub async fn run_invocations(
         &self,
         invocations: &[CommandInvocation],
-    ) -> Result<ScriptResult, ShellCliError> {
-        self.run_invocation_slice(invocations)
+    ) -> Result<ExitStatus, ShellCliError> {
+        let mut queue = CommandQueue::from_invocations(invocations);
+        self.run_queue(&mut queue, None).await
+    }
+
+    /// Run a script and return what it wrote to stdout.
+    pub async fn capture(&self, script: &str) -> Result<String, ShellCliError> {
+        let mut queue = self.runtime.parser.queue(script)?;
+        let mut output = String::new();
+        self.run_queue(&mut queue, Some(&mut output)).await?;
+        Ok(output)
+    }
+
+    /// Evaluate a `$(...)` or backtick substitution; the word waits for its output.
+    fn substitute(&self, script: &str) -> Substitution<'_, ShellCliError> {
+        let script = script.to_string();
+        Box::pin(async move { self.capture(&script).await })
     }
 
     /// Parse and execute a single command line.
-    pub fn run_line(&self, line: &str) -> Result<ScriptResult, ShellCliError> {
-        self.run_script(line)
+    pub async fn run_line(&self, line: &str) -> Result<ExitStatus, ShellCliError> {
+        self.run_script(line).await
     }
 
     /// Parse and execute a script that may contain pipelines (`|`).
-    pub fn run_pipeline_script(&self, script: &str) -> Result<ScriptResult, ShellCliError> {
-        let parsed = self.parser.parse_with_separators(script)?;
-        let mut pipeline: Vec<(String, Vec<String>)> = Vec::new();
+    pub async fn run_pipeline_script(&self, script: &str) -> Result<ExitStatus, ShellCliError> {
+        self.run_script(script).await
+    }
 
-        for item in parsed {
-            pipeline.push((item.invocation.name, item.invocation.args));
-            let end_pipeline = item.separator != Some(crate::separator::Separator::Pipe);
-            if end_pipeline {
-                self.execute_pipeline(&pipeline)?;
-                pipeline.clear();
-            }
-        }
+    /// Status of the last pipeline that ran, also available to scripts as `$?`.
+    pub fn last_status(&self) -> ExitStatus {
+        self.last_status.get()
+    }
 
-        if !pipeline.is_empty() {
-            self.execute_pipeline(&pipeline)?;
-        }
+    /// Current value of a shell variable set by scripts or [`CliRunner::set_variable`].
+    pub fn variable(&self, name: &str) -> Option<String> {
+        self.runtime.parser.variable(name)
+    }
+
+    /// Assign a shell variable visible to scripts run afterwards.
+    pub fn set_variable(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.runtime.parser.set_variable(name, value);
+    }
+
+    /// Snapshot of every shell variable currently defined.
+    pub fn variables(&self) -> Variables {
+        self.runtime.parser.variables()
+    }
 
-        Ok(ScriptResult::Completed)
+    /// Options currently set by scripts with `set`.
+    pub fn options(&self) -> ShellOptions {
+        self.runtime.parser.options()
+    }
+
+    /// Replace the options, for instance to undo what a script set.
+    pub fn set_options(&self, options: ShellOptions) {
+        self.runtime.parser.set_options(options);
+    }
+
+    /// Classify ranges of `input` for syntax highlighting; see [`ShellParser::highlight`].
+    pub fn highlight(&self, input: &str) -> Vec<HighlightSpan> {
+        self.runtime.parser.highlight(input)
+    }
+
+    /// Complete the word ending at byte `cursor` of `input`; see [`ShellParser::complete`].
+    pub fn complete(&self, input: &str, cursor: usize) -> Completion {
+        self.runtime.parser.complete(input, cursor)
     }
 
     /// Render help text listing registered commands.
@@ -133,74 +442,777 @@ impl<C> CliRunner<C> {
         out
     }
 
-    fn run_invocation(
+    /// Take the next pipeline from `queue` along with the separator that ends it.
+    ///
+    /// The end of a compound command, or the status of one that ran nothing,
+    /// is returned on its own instead of a pipeline.
+    async fn next_pipeline(
         &self,
-        name: String,
-        args: Vec<String>,
-        input: Option<String>,
-    ) -> Result<Option<String>, ShellCliError> {
-        if name == "alias" {
-            // Alias definitions are applied by the parser itself, so there is no runnable handler.
-            return Ok(input);
-        }
-
-        let handler = self
-            .handlers
-            .get(&name)
-            .ok_or_else(|| ShellCliError::Execution {
-                command: name.clone(),
-                message: "no handler registered".into(),
-            })?;
+        queue: &mut CommandQueue,
+    ) -> Result<Option<Step<Pipeline>>, ShellCliError> {
+        let mut pipeline = Pipeline {
+            commands: Vec::new(),
+            separator: None,
+            tested: false,
+        };
+        loop {
+            // Read before expanding, so `set -x` is not traced and `set +x` is.
+            let xtrace = self.options().xtrace;
+            let mut substitute = |script: &str| self.substitute(script);
+            let Some(step) = self
+                .runtime
+                .parser
+                .next_step(queue, &mut substitute)
+                .await?
+            else {
+                break;
+            };
+            let pipeline_empty = pipeline.commands.is_empty();
+            let command = match step {
+                Step::Command(command) => command,
+                Step::End(separator) if pipeline_empty => return Ok(Some(Step::End(separator))),
+                Step::Status(status) if pipeline_empty => return Ok(Some(Step::Status(status))),
+                Step::End(_) | Step::Status(_) => break,
+            };
+            if xtrace {
+                self.runtime
+                    .write_stderr(&trace(&command.parsed.invocation));
+            }
+            pipeline.tested |= command.tested;
+            pipeline.commands.push(command.parsed.invocation);
+            if command.parsed.separator != Some(Separator::Pipe) {
+                pipeline.separator = command.parsed.separator;
+                return Ok(Some(Step::Command(pipeline)));
+            }
+        }
+
+        Ok((!pipeline.commands.is_empty()).then_some(Step::Command(pipeline)))
+    }
+
+    /// Drop pipelines that `&&` or `||` rule out given the last status.
+    ///
+    /// Skipped commands are not expanded, so their substitutions never run.
+    fn skip_short_circuited(&self, queue: &mut CommandQueue, connector: &mut Option<Separator>) {
+        loop {
+            let skip = match connector {
+                Some(Separator::And) => !self.last_status().success(),
+                Some(Separator::Or) => self.last_status().success(),
+                _ => false,
+            };
+            if !skip || !queue.starts_with_command() {
+                return;
+            }
+            *connector = queue.skip_pipeline();
+        }
+    }
+
+    /// Run `script` between the `preexec` and `precmd` functions, unless it
+    /// runs inside another script.
+    ///
+    /// Scripts started while another one waits, such as a second command
+    /// typed at a prompt, are top-level runs and get their hooks too.
+    async fn run_hooked(
+        &self,
+        script: &str,
+        queue: &mut CommandQueue,
+    ) -> Result<ExitStatus, ShellCliError> {
+        if self.runtime.active.borrow().is_some() {
+            return self.run_queue(queue, None).await;
+        }
+        let status = self.last_status();
+        self.run_hook(&["preexec".into(), script.into()]).await?;
+        self.set_status(status);
+        let result = self.run_queue(queue, None).await;
+        let status = self.last_status();
+        let hook = self.run_hook(&["precmd".into()]).await;
+        self.set_status(status);
+        let status = result?;
+        hook.map(|()| status)
+    }
+
+    /// Call the shell function named by `call[0]` with the rest as its
+    /// arguments, if the script defined it.
+    async fn run_hook(&self, call: &[String]) -> Result<(), ShellCliError> {
+        if self.runtime.parser.command_type(&call[0]) != Some(CommandType::Function) {
+            return Ok(());
+        }
+        let mut queue = self.runtime.parser.queue(&fmt::quote(call))?;
+        self.run_queue(&mut queue, None).await.map(drop)
+    }
+
+    fn set_status(&self, status: ExitStatus) {
+        self.last_status.set(status);
+        self.runtime
+            .parser
+            .set_variable("?", status.code().to_string());
+    }
+
+    async fn run_queue(
+        &self,
+        queue: &mut CommandQueue,
+        capture: Option<&mut String>,
+    ) -> Result<ExitStatus, ShellCliError> {
+        let frame = self.runtime.enter()?;
+        let run = self.run_frame(queue, capture, &frame);
+        self.runtime.within(&frame, run).await
+    }
+
+    async fn run_frame(
+        &self,
+        queue: &mut CommandQueue,
+        mut capture: Option<&mut String>,
+        frame: &Frame,
+    ) -> Result<ExitStatus, ShellCliError> {
+        let mut connector = None;
+
+        loop {
+            self.runtime.check(frame)?;
+            self.skip_short_circuited(queue, &mut connector);
+            let pipeline = match self.next_pipeline(queue).await? {
+                Some(Step::Command(pipeline)) => pipeline,
+                Some(Step::End(separator)) => {
+                    connector = separator;
+                    continue;
+                }
+                Some(Step::Status(status)) => {
+                    self.set_status(status);
+                    continue;
+                }
+                None => break,
+            };
+            connector = pipeline.separator;
+
+            if pipeline.separator == Some(Separator::Background) {
+                self.start_job(pipeline.commands);
+                self.set_status(ExitStatus::SUCCESS);
+                continue;
+            }
+
+            let runtime = &self.runtime;
+            let execution =
+                runtime.execute_pipeline(pipeline.commands, capture.is_some(), None, frame);
+            match runtime.alongside_jobs(execution).await {
+                Ok((status, output)) => {
+                    self.set_status(status);
+                    if let (Some(captured), Some(output)) = (capture.as_deref_mut(), output) {
+                        captured.push_str(&output);
+                    }
+                    let checked = !pipeline.tested
+                        && !matches!(pipeline.separator, Some(Separator::And | Separator::Or));
+                    if checked && !status.success() && self.options().errexit {
+                        return Ok(status);
+                    }
+                }
+                Err(err) => {
+                    self.set_status(ExitStatus::FAILURE);
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(self.last_status())
+    }
+
+    /// Run `pipeline` as a background job, announcing it on the terminal; it
+    /// is a top-level run of its own, with its own limits.
+    fn start_job(&self, pipeline: Vec<CommandInvocation>) {
+        let command = pipeline
+            .iter()
+            .map(command_text)
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let runtime = self.runtime.clone();
+        let frame = self.runtime.top_level();
+        let id = self.runtime.jobs.start(command.clone(), |id| {
+            Box::pin(async move {
+                let execution = runtime.execute_pipeline(pipeline, false, Some(id), &frame);
+                let status = match runtime.within(&frame, execution).await {
+                    Ok((status, _)) => status,
+                    Err(err) => {
+                        runtime.write_stderr(&tagged(Some(id), &err.to_string()));
+                        ExitStatus::FAILURE
+                    }
+                };
+                runtime.announce(id, JobState::Done(status));
+                status
+            }) as Task
+        });
+        self.runtime.announce(id, JobState::Running);
+
+        if let Some(spawn) = &self.runtime.spawner {
+            let runtime = self.runtime.clone();
+            spawn(Box::pin(poll_fn(move |cx| runtime.jobs.poll(id, cx))));
+        }
+    }
+}
+
+impl<C: 'static> Runtime<C> {
+    async fn run_invocation(
+        &self,
+        invocation: &CommandInvocation,
+        io: &mut CommandIo<'_>,
+    ) -> Result<ExitStatus, ShellCliError> {
+        if JOB_BUILTINS.contains(&invocation.name.as_str()) {
+            return Ok(self
+                .run_job_builtin(&invocation.name, &invocation.args, io)
+                .await);
+        }
+        if PARSER_BUILTINS.contains(&invocation.name.as_str()) {
+            // Alias and variable definitions are applied by the parser itself, so there is no
+            // runnable handler; only the builtins that report on them print anything.
+            return Ok(builtins::report(
+                &self.parser,
+                &invocation.name,
+                &invocation.args,
+                io,
+            ));
+        }
+
+        let handler =
+            self.handlers
+                .get(&invocation.name)
+                .ok_or_else(|| ShellCliError::Execution {
+                    command: invocation.name.clone(),
+                    message: "no handler registered".into(),
+                })?;
         handler
-            .run_with_input(&args, input, &self.context)
+            .run(&invocation.args, io, &self.context)
+            .await
             .map_err(|message| ShellCliError::Execution {
-                command: name,
+                command: invocation.name.clone(),
                 message,
             })
     }
 
-    fn execute_pipeline(&self, pipeline: &[(String, Vec<String>)]) -> Result<(), ShellCliError> {
-        let mut input: Option<String> = None;
-        for (name, args) in pipeline {
-            input = self.run_invocation(name.clone(), args.clone(), input)?;
+    /// Run commands joined by `|`, feeding each stdout into the next stdin.
+    ///
+    /// The last command writes to the terminal unless `capture_output` is set,
+    /// in which case its output is returned. Terminal output of background
+    /// `job`s is tagged with the job's number.
+    async fn execute_pipeline(
+        &self,
+        pipeline: Vec<CommandInvocation>,
+        capture_output: bool,
+        job: Option<usize>,
+        frame: &Frame,
+    ) -> Result<(ExitStatus, Option<String>), ShellCliError> {
+        let terminal = |text: &str| {
+            if self.charge_output(frame, text) {
+                self.write_stdout(&tagged(job, text));
+            }
+        };
+        let errors = |text: &str| {
+            if self.charge_output(frame, text) {
+                self.write_stderr(&tagged(job, text));
+            }
+        };
+        let last = pipeline.len().saturating_sub(1);
+        let pipefail = self.parser.options().pipefail;
+        let mut stdin = None;
+        let mut status = ExitStatus::SUCCESS;
+
+        for (idx, invocation) in pipeline.into_iter().enumerate() {
+            let piped = idx < last || capture_output;
+            let (stage_status, output) = self
+                .run_stage(invocation, stdin.take(), piped, &terminal, &errors, frame)
+                .await?;
+            // With `pipefail` the last failure sticks unless a later stage fails too.
+            if !(pipefail && stage_status.success()) {
+                status = stage_status;
+            }
+            stdin = output;
+        }
+
+        Ok((status, stdin))
+    }
+
+    /// Run one pipeline stage with its redirections applied.
+    ///
+    /// Returns the stage's status and, when `piped`, what it wrote to stdout.
+    /// Redirection failures are reported on stderr and fail the stage without
+    /// running the command, as in a shell.
+    async fn run_stage(
+        &self,
+        invocation: CommandInvocation,
+        stdin: Option<String>,
+        piped: bool,
+        terminal: &dyn Fn(&str),
+        errors: &dyn Fn(&str),
+        frame: &Frame,
+    ) -> Result<(ExitStatus, Option<String>), ShellCliError> {
+        self.charge_command(frame)?;
+        let mut invocation = invocation;
+        let started = self.clock.as_ref().map(|now| now());
+        if let Some(message) = self.before_command(&mut invocation) {
+            errors(&message);
+            self.after_command(&invocation, started, Outcome::Blocked(&message));
+            return Ok((ExitStatus::FAILURE, piped.then(String::new)));
+        }
+        let stdout = if piped {
+            Stream::Pipe
+        } else {
+            Stream::Terminal
+        };
+        let mut plan = match self.plan_redirects(&invocation.redirects, stdin, stdout) {
+            Ok(plan) => plan,
+            Err(message) => {
+                errors(&message);
+                let status = ExitStatus::FAILURE;
+                self.after_command(&invocation, started, Outcome::Exited(status));
+                return Ok((status, piped.then(String::new)));
+            }
+        };
+
+        let pipe = RefCell::new(String::new());
+        let pipe_sink = |text: &str| {
+            if self.charge_output(frame, text) {
+                pipe.borrow_mut().push_str(text);
+            }
+        };
+        let file_sinks: Vec<_> = plan
+            .files
+            .iter()
+            .map(|file| {
+                move |text: &str| {
+                    if self.charge_output(frame, text) {
+                        file.contents.borrow_mut().push_str(text);
+                    }
+                }
+            })
+            .collect();
+        let discard = |_: &str| {};
+        let open = |stream: Stream| match stream {
+            Stream::Terminal => OutputStream::terminal(terminal),
+            Stream::Errors => OutputStream::terminal(errors),
+            Stream::Pipe => OutputStream::redirected(&pipe_sink),
+            Stream::File(idx) => OutputStream::redirected(&file_sinks[idx]),
+            Stream::Null => OutputStream::redirected(&discard),
+        };
+
+        let mut io = CommandIo::new(plan.stdin.take(), open(plan.stdout), open(plan.stderr));
+        let result = self
+            .against_limits(frame, self.run_invocation(&invocation, &mut io))
+            .await;
+        drop(io);
+
+        // Files are written even if the command failed, like a shell that
+        // opened them before running it.
+        let mut write_failed = false;
+        for file in &plan.files {
+            let contents = file.contents.borrow();
+            if let Err(message) = self.fs.write_file(&file.path, &contents, file.append) {
+                errors(&format!("{}: {message}", file.path));
+                write_failed = true;
+            }
+        }
+
+        let result = result.map(|status| {
+            if write_failed {
+                ExitStatus::FAILURE
+            } else {
+                status
+            }
+        });
+        let outcome = match &result {
+            Ok(status) => Outcome::Exited(*status),
+            Err(err) => Outcome::Failed(err),
+        };
+        self.after_command(&invocation, started, outcome);
+        let status = result?;
+        self.check(frame)?;
+        Ok((status, piped.then(|| pipe.into_inner())))
+    }
+
+    /// Let the middleware rewrite `invocation`; returns the message of the
+    /// middleware that blocked it, if any.
+    fn before_command(&self, invocation: &mut CommandInvocation) -> Option<String> {
+        self.middleware.iter().find_map(|middleware| {
+            match middleware.before(invocation, &self.context) {
+                Decision::Run => None,
+                Decision::Block(message) => Some(message),
+            }
+        })
+    }
+
+    /// Tell the middleware how a command that started at `started` ended.
+    fn after_command(
+        &self,
+        invocation: &CommandInvocation,
+        started: Option<Duration>,
+        outcome: Outcome<'_>,
+    ) {
+        if self.middleware.is_empty() {
+            return;
+        }
+        let record = CommandRecord {
+            invocation,
+            duration: started
+                .zip(self.clock.as_ref())
+                .map(|(started, now)| now().saturating_sub(started)),
+            outcome,
+        };
+        for middleware in self.middleware.iter().rev() {
+            middleware.after(&record, &self.context);
+        }
+    }
+
+    /// Work out where a command reads and writes, in the order redirections appear.
+    fn plan_redirects(
+        &self,
+        redirects: &[Redirect],
+        stdin: Option<String>,
+        stdout: Stream,
+    ) -> Result<RedirectPlan, String> {
+        let mut plan = RedirectPlan {
+            stdin,
+            stdout,
+            stderr: Stream::Errors,
+            files: Vec::new(),
+        };
+
+        for redirect in redirects {
+            match redirect {
+                Redirect::Input { path } if path == DEV_NULL => plan.stdin = Some(String::new()),
+                Redirect::Input { path } => {
+                    let contents = self
+                        .fs
+                        .read_file(path)
+                        .map_err(|message| format!("{path}: {message}"))?;
+                    plan.stdin = Some(contents);
+                }
+                Redirect::Output { fd, path, append } => {
+                    let stream = plan.open(path, *append);
+                    plan.set_stream(*fd, stream);
+                }
+                Redirect::Duplicate { fd, target } => {
+                    let stream = plan.stream(*target);
+                    plan.set_stream(*fd, stream);
+                }
+                Redirect::HereDoc { body } => plan.stdin = Some(body.clone()),
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Start a run nested in the active one, or a top-level run counting
+    /// from zero when none is active.
+    fn enter(&self) -> Result<Frame, LimitExceeded> {
+        let frame = match &*self.active.borrow() {
+            Some(outer) => Frame {
+                depth: outer.depth + 1,
+                usage: outer.usage.clone(),
+            },
+            None => self.top_level(),
+        };
+        match self.limits.max_depth {
+            Some(max) if frame.depth > max => Err(frame.exceed(LimitExceeded::Depth(max))),
+            _ => Ok(frame),
+        }
+    }
+
+    /// A run counting from zero, nested in nothing.
+    fn top_level(&self) -> Frame {
+        Frame {
+            depth: 1,
+            usage: Rc::new(RefCell::new(Usage {
+                started: self.clock.as_ref().map(|now| now()),
+                ..Usage::default()
+            })),
+        }
+    }
+
+    /// Poll `future` with `frame` as the active run, so runs it starts nest in it.
+    async fn within<F: Future>(&self, frame: &Frame, future: F) -> F::Output {
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            let outer = self.active.replace(Some(frame.clone()));
+            let poll = future.as_mut().poll(cx);
+            *self.active.borrow_mut() = outer;
+            poll
+        })
+        .await
+    }
+
+    /// Fail once a limit has been reached or the run has gone on too long.
+    fn check(&self, frame: &Frame) -> Result<(), LimitExceeded> {
+        let usage = frame.usage.borrow();
+        if let Some(limit) = usage.exceeded {
+            return Err(limit);
+        }
+        let (Some(max), Some(now), Some(started)) =
+            (self.limits.max_time, &self.clock, usage.started)
+        else {
+            return Ok(());
+        };
+        drop(usage);
+        if now().saturating_sub(started) > max {
+            return Err(frame.exceed(LimitExceeded::Time(max)));
         }
         Ok(())
     }
 
-    fn run_invocation_slice(
+    /// Time `frame` has left before [`Limits::max_time`].
+    fn time_left(&self, frame: &Frame) -> Option<Duration> {
+        let (max, now) = self.limits.max_time.zip(self.clock.as_ref())?;
+        let started = frame.usage.borrow().started?;
+        Some(max.saturating_sub(now().saturating_sub(started)))
+    }
+
+    /// Run `command` until it finishes or `frame` reaches a limit.
+    ///
+    /// The limits are checked each time the command is polled, and with a
+    /// timer the command is also woken once the run's time is up.
+    async fn against_limits<T>(
         &self,
-        invocations: &[CommandInvocation],
-    ) -> Result<ScriptResult, ShellCliError> {
-        for (idx, inv) in invocations.iter().enumerate() {
-            match self.run_invocation(inv.name.clone(), inv.args.clone(), None) {
-                Ok(_) => continue,
-                Err(err) => {
-                    if let Some(delay_ms) = pause_delay(&err) {
-                        let remainder = invocations[idx + 1..].to_vec();
-                        return Ok(ScriptResult::Paused {
-                            delay_ms,
-                            remainder,
-                        });
+        frame: &Frame,
+        command: impl Future<Output = Result<T, ShellCliError>>,
+    ) -> Result<T, ShellCliError> {
+        let mut command = pin!(command);
+        let mut timer = None;
+        poll_fn(|cx| {
+            if let Err(limit) = self.check(frame) {
+                return Poll::Ready(Err(limit.into()));
+            }
+            if let Poll::Ready(output) = command.as_mut().poll(cx) {
+                return Poll::Ready(output);
+            }
+            // Set once the command waits, and again if it fires before the
+            // clock says the time is up.
+            loop {
+                let sleep = match &mut timer {
+                    Some(sleep) => sleep,
+                    None => match self.timer.as_ref().zip(self.time_left(frame)) {
+                        Some((sleep, left)) => timer.insert(sleep(left)),
+                        None => break,
+                    },
+                };
+                if sleep.as_mut().poll(cx).is_pending() {
+                    break;
+                }
+                if let Err(limit) = self.check(frame) {
+                    return Poll::Ready(Err(limit.into()));
+                }
+                timer = None;
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Count a command that is about to run.
+    fn charge_command(&self, frame: &Frame) -> Result<(), LimitExceeded> {
+        self.check(frame)?;
+        let commands = {
+            let mut usage = frame.usage.borrow_mut();
+            usage.commands += 1;
+            usage.commands
+        };
+        match self.limits.max_commands {
+            Some(max) if commands > max => Err(frame.exceed(LimitExceeded::Commands(max))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Count `text` written by a command; `false` once it goes over the output
+    /// limit, in which case the text is dropped.
+    fn charge_output(&self, frame: &Frame, text: &str) -> bool {
+        let bytes = {
+            let mut usage = frame.usage.borrow_mut();
+            usage.output_bytes += text.len();
+            usage.output_bytes
+        };
+        match self.limits.max_output_bytes {
+            Some(max) if bytes > max => {
+                frame.exceed(LimitExceeded::OutputBytes(max));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn write_stdout(&self, text: &str) {
+        if let Some(stdout) = &self.stdout {
+            stdout(&self.context, text);
+        }
+    }
+
+    fn write_stderr(&self, text: &str) {
+        if let Some(stderr) = &self.stderr {
+            stderr(&self.context, text);
+        }
+    }
+
+    /// Show that job `id` started or stopped, as `[1] Done sleep 5`.
+    fn announce(&self, id: usize, state: JobState) {
+        if let Some(command) = self.jobs.command(id) {
+            self.write_stdout(&format!("[{id}] {state} {command}"));
+        }
+    }
+
+    /// Poll background jobs whenever `future` is polled, unless a spawner runs them.
+    async fn alongside_jobs<F: Future>(&self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            self.drive_jobs(cx);
+            future.as_mut().poll(cx)
+        })
+        .await
+    }
+
+    fn drive_jobs(&self, cx: &mut Context<'_>) {
+        if self.spawner.is_none() {
+            self.jobs.poll_all(cx);
+        }
+    }
+
+    /// Wait for job `id` to stop and forget it, returning its status.
+    async fn wait_for(&self, id: usize) -> ExitStatus {
+        let status = poll_fn(|cx| {
+            self.drive_jobs(cx);
+            match self.jobs.state(id).map(JobState::status) {
+                Some(None) => {
+                    self.jobs.notify(cx.waker());
+                    Poll::Pending
+                }
+                Some(Some(status)) => Poll::Ready(status),
+                None => Poll::Ready(ExitStatus::FAILURE),
+            }
+        })
+        .await;
+        self.jobs.forget(id);
+        status
+    }
+
+    /// `jobs` lists background jobs, `fg` and `wait` wait for them and `kill`
+    /// stops them; jobs are named `%N`, or `%%` for the latest.
+    async fn run_job_builtin(
+        &self,
+        name: &str,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+    ) -> ExitStatus {
+        let usage = match name {
+            "jobs" => "jobs",
+            "fg" => "fg [%JOB]",
+            "kill" => "kill %JOB...",
+            _ => "wait [%JOB...]",
+        };
+        let arity_ok = match name {
+            "jobs" => args.is_empty(),
+            "fg" => args.len() <= 1,
+            "kill" => !args.is_empty(),
+            _ => true,
+        };
+        if !arity_ok || args.iter().any(|arg| !arg.starts_with('%')) {
+            io.stderr.write_line(format!("{name}: usage: {usage}"));
+            return ExitStatus::new(2);
+        }
+        let mut ids = Vec::new();
+        for spec in args {
+            let Some(id) = jobs::parse_spec(&self.jobs, spec) else {
+                io.stderr.write_line(format!("{name}: {spec}: no such job"));
+                return ExitStatus::FAILURE;
+            };
+            ids.push(id);
+        }
+
+        match name {
+            "jobs" => {
+                for (id, state, command) in self.jobs.report() {
+                    io.stdout.write_line(format!("[{id}] {state} {command}"));
+                }
+                ExitStatus::SUCCESS
+            }
+            "fg" => {
+                let Some(id) = ids.first().copied().or_else(|| self.jobs.current()) else {
+                    io.stderr.write_line("fg: no current job");
+                    return ExitStatus::FAILURE;
+                };
+                io.stdout
+                    .write_line(self.jobs.command(id).unwrap_or_default());
+                self.wait_for(id).await
+            }
+            "kill" => {
+                let mut status = ExitStatus::SUCCESS;
+                for id in ids {
+                    if self.jobs.kill(id) {
+                        // Reported here, so it is not listed again.
+                        self.announce(id, JobState::Killed);
+                        self.jobs.forget(id);
+                    } else {
+                        io.stderr
+                            .write_line(format!("kill: %{id}: job has finished"));
+                        status = ExitStatus::FAILURE;
                     }
-                    return Err(err);
+                }
+                status
+            }
+            _ => {
+                let all = ids.is_empty();
+                let ids = if all { self.jobs.ids() } else { ids };
+                let mut status = ExitStatus::SUCCESS;
+                for id in ids {
+                    status = self.wait_for(id).await;
+                }
+                if all {
+                    ExitStatus::SUCCESS
+                } else {
+                    status
                 }
             }
         }
-        Ok(ScriptResult::Completed)
     }
 }
 
-fn pause_delay(err: &ShellCliError) -> Option<u32> {
-    match err {
-        ShellCliError::Execution { message, .. } => {
-            parse_pause_signal(message).and_then(|delay| delay.parse().ok())
-        }
-        _ => None,
+/// Line printed for `invocation` under `set -x`.
+fn trace(invocation: &CommandInvocation) -> String {
+    format!("+ {}", command_text(invocation))
+}
+
+/// `invocation` as command text, quoted so it reads back the same.
+fn command_text(invocation: &CommandInvocation) -> String {
+    let mut words = vec![invocation.name.clone()];
+    words.extend(invocation.args.iter().cloned());
+    fmt::quote(&words)
+}
+
+/// Prefix each line a background job writes with its number, as `[1] `.
+fn tagged(job: Option<usize>, text: &str) -> Cow<'_, str> {
+    match job {
+        Some(id) => text
+            .lines()
+            .map(|line| format!("[{id}] {line}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into(),
+        None => text.into(),
     }
 }
 
-fn parse_pause_signal(message: &str) -> Option<&str> {
-    message.strip_prefix(PAUSE_SIGNAL_PREFIX)
+/// Run `future` to completion on the current thread, parking it while the
+/// future waits.
+///
+/// For callers without an async runtime, such as command-line tools and
+/// tests. In a browser, spawn the future on the event loop instead.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
 }
 
 /// Create a [`CliRunner`] by registering executable commands.
@@ -217,11 +1229,27 @@ where
         specs.push(spec);
     }
 
-    let parser = ShellParser::with_commands(specs.clone());
+    let fs: Rc<dyn FileSystem> = Rc::new(MemoryFs::new());
+    let mut parser = ShellParser::with_commands(specs.clone());
+    parser.set_file_system(fs.clone());
+    parser.set_variable("?", ExitStatus::SUCCESS.code().to_string());
     CliRunner {
-        parser,
-        handlers,
+        runtime: Rc::new(Runtime {
+            parser,
+            handlers,
+            context,
+            stdout: None,
+            stderr: None,
+            fs,
+            jobs: Jobs::default(),
+            spawner: None,
+            limits: Limits::default(),
+            clock: None,
+            timer: None,
+            middleware: Vec::new(),
+            active: RefCell::default(),
+        }),
         specs,
-        context,
+        last_status: Cell::new(ExitStatus::SUCCESS),
     }
 }
@@ -0,0 +1,87 @@
+/// Standard streams handed to a command by [`CliRunner`](crate::CliRunner).
+pub struct CommandIo<'a> {
+    /// Output of the previous pipeline stage or a `<` file, if any.
+    pub stdin: Option<String>,
+    pub stdout: OutputStream<'a>,
+    /// Diagnostics; kept apart from stdout so `2>&1` and `2>/dev/null` can route them.
+    pub stderr: OutputStream<'a>,
+}
+
+impl<'a> CommandIo<'a> {
+    pub fn new(stdin: Option<String>, stdout: OutputStream<'a>, stderr: OutputStream<'a>) -> Self {
+        Self {
+            stdin,
+            stdout,
+            stderr,
+        }
+    }
+}
+
+/// Where a command writes its regular output or its errors.
+///
+/// A stream is either captured into a string, passed straight to a terminal
+/// sink registered with [`CliRunner::with_stdout`](crate::CliRunner::with_stdout)
+/// or [`CliRunner::with_stderr`](crate::CliRunner::with_stderr), or redirected
+/// to another consumer such as the next pipeline stage or a file.
+pub struct OutputStream<'a> {
+    target: Target<'a>,
+}
+
+enum Target<'a> {
+    Captured(String),
+    Terminal(&'a dyn Fn(&str)),
+    Redirected(&'a dyn Fn(&str)),
+}
+
+impl<'a> OutputStream<'a> {
+    /// Stream whose lines are collected into a string.
+    pub fn captured() -> Self {
+        Self {
+            target: Target::Captured(String::new()),
+        }
+    }
+
+    /// Stream that hands every line to `sink` as soon as it is written.
+    pub fn terminal(sink: &'a dyn Fn(&str)) -> Self {
+        Self {
+            target: Target::Terminal(sink),
+        }
+    }
+
+    /// Stream read by something other than a person, such as a pipe or a file.
+    ///
+    /// `sink` receives each line with its trailing newline.
+    pub fn redirected(sink: &'a dyn Fn(&str)) -> Self {
+        Self {
+            target: Target::Redirected(sink),
+        }
+    }
+
+    /// Whether output goes to the terminal rather than another command or a file.
+    ///
+    /// Commands can use this to render rich output only when nothing reads it.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self.target, Target::Terminal(_))
+    }
+
+    /// Write one line (or block of lines) of output.
+    pub fn write_line(&mut self, line: impl AsRef<str>) {
+        let line = line.as_ref();
+        match &mut self.target {
+            Target::Captured(buffer) => {
+                buffer.push_str(line);
+                buffer.push('\n');
+            }
+            Target::Terminal(sink) => sink(line),
+            Target::Redirected(sink) => sink(&format!("{line}\n")),
+        }
+    }
+
+    /// Text written so far, or `None` unless the stream was [`OutputStream::captured`].
+    pub fn into_captured(self) -> Option<String> {
+        match self.target {
+            Target::Captured(buffer) => Some(buffer),
+            Target::Terminal(_) | Target::Redirected(_) => None,
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Background jobs started with a trailing `&`.
+//!
+//! The runner expands a backgrounded pipeline right away and keeps the future
+//! running it in a [`Jobs`] table. A spawner registered with
+//! [`CliRunner::with_spawner`](crate::CliRunner::with_spawner) drives each job
+//! on its own; without one, jobs make progress while the runner is running
+//! something else, such as a `wait`.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use crate::status::ExitStatus;
+
+/// Builtins that manage background jobs; the runner implements them.
+pub(crate) const JOB_BUILTINS: &[&str] = &["fg", "jobs", "kill", "wait"];
+
+/// Future running a job's pipeline to its exit status.
+pub(crate) type Task = Pin<Box<dyn Future<Output = ExitStatus>>>;
+
+/// Status reported for a job stopped with `kill`, as for `SIGTERM`.
+const KILLED: ExitStatus = ExitStatus::new(143);
+
+/// Where a job is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JobState {
+    Running,
+    Done(ExitStatus),
+    Killed,
+}
+
+impl JobState {
+    /// Exit status of a finished job; `None` while it runs.
+    pub fn status(self) -> Option<ExitStatus> {
+        match self {
+            JobState::Running => None,
+            JobState::Done(status) => Some(status),
+            JobState::Killed => Some(KILLED),
+        }
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => write!(f, "Running"),
+            JobState::Done(status) if status.success() => write!(f, "Done"),
+            JobState::Done(status) => write!(f, "Exit {status}"),
+            JobState::Killed => write!(f, "Killed"),
+        }
+    }
+}
+
+struct Job {
+    id: usize,
+    /// Pipeline as shown by `jobs`.
+    command: String,
+    state: JobState,
+    /// Taken out while the job is polled and dropped once it stops.
+    task: Option<Task>,
+    /// Waker of whatever polled the job last, woken if it is killed.
+    waker: Option<Waker>,
+}
+
+/// Table of background jobs, numbered from 1 like `%1`.
+///
+/// Finished jobs stay listed until `jobs`, `fg` or `wait` reports them; `kill`
+/// reports and drops the jobs it stops.
+#[derive(Default)]
+pub(crate) struct Jobs {
+    jobs: RefCell<Vec<Job>>,
+    /// Wakers of `wait` and `fg` calls, woken whenever a job stops.
+    waiters: RefCell<Vec<Waker>>,
+}
+
+impl Jobs {
+    /// Add a job running `command`; `task` builds its future from the job's id.
+    pub fn start(&self, command: String, task: impl FnOnce(usize) -> Task) -> usize {
+        let mut jobs = self.jobs.borrow_mut();
+        let id = jobs.last().map_or(1, |job| job.id + 1);
+        jobs.push(Job {
+            id,
+            command,
+            state: JobState::Running,
+            task: Some(task(id)),
+            waker: None,
+        });
+        id
+    }
+
+    /// Poll job `id` once; ready when it has stopped.
+    pub fn poll(&self, id: usize, cx: &mut Context<'_>) -> Poll<()> {
+        // The task is polled outside the borrow, since it may run `jobs` or `kill`.
+        let Some(mut task) = self.with_job(id, |job| job.task.take()).flatten() else {
+            return Poll::Ready(());
+        };
+        let poll = task.as_mut().poll(cx);
+        self.with_job(id, |job| match poll {
+            Poll::Ready(status) => job.state = JobState::Done(status),
+            // Killed while it was being polled.
+            Poll::Pending if job.state != JobState::Running => {}
+            Poll::Pending => {
+                job.task = Some(task);
+                job.waker = Some(cx.waker().clone());
+            }
+        });
+        if poll.is_ready() {
+            self.wake_waiters();
+        }
+        poll.map(|_| ())
+    }
+
+    /// Poll every running job once.
+    pub fn poll_all(&self, cx: &mut Context<'_>) {
+        let ids: Vec<usize> = self
+            .jobs
+            .borrow()
+            .iter()
+            .filter(|job| job.task.is_some())
+            .map(|job| job.id)
+            .collect();
+        for id in ids {
+            let _ = self.poll(id, cx);
+        }
+    }
+
+    /// Stop job `id`, returning whether it was still running.
+    pub fn kill(&self, id: usize) -> bool {
+        let Some(waker) = self
+            .with_job(id, |job| {
+                if job.state != JobState::Running {
+                    return None;
+                }
+                job.state = JobState::Killed;
+                job.task = None;
+                Some(job.waker.take())
+            })
+            .flatten()
+        else {
+            return false;
+        };
+        // Let a spawned driver see that the job is gone.
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        self.wake_waiters();
+        true
+    }
+
+    pub fn state(&self, id: usize) -> Option<JobState> {
+        self.with_job(id, |job| job.state)
+    }
+
+    pub fn command(&self, id: usize) -> Option<String> {
+        self.with_job(id, |job| job.command.clone())
+    }
+
+    /// Ids of every job, oldest first.
+    pub fn ids(&self) -> Vec<usize> {
+        self.jobs.borrow().iter().map(|job| job.id).collect()
+    }
+
+    /// Most recently started job, which `%%` and a bare `fg` refer to.
+    pub fn current(&self) -> Option<usize> {
+        self.jobs.borrow().last().map(|job| job.id)
+    }
+
+    /// Every job as `(id, state, command)`, forgetting the ones that finished.
+    pub fn report(&self) -> Vec<(usize, JobState, String)> {
+        let mut jobs = self.jobs.borrow_mut();
+        let listed = jobs
+            .iter()
+            .map(|job| (job.id, job.state, job.command.clone()))
+            .collect();
+        jobs.retain(|job| job.state == JobState::Running);
+        listed
+    }
+
+    /// Drop a finished job from the table.
+    pub fn forget(&self, id: usize) {
+        self.jobs
+            .borrow_mut()
+            .retain(|job| job.id != id || job.state == JobState::Running);
+    }
+
+    /// Wake `waker` the next time any job stops.
+    pub fn notify(&self, waker: &Waker) {
+        self.waiters.borrow_mut().push(waker.clone());
+    }
+
+    fn wake_waiters(&self) {
+        for waker in std::mem::take(&mut *self.waiters.borrow_mut()) {
+            waker.wake();
+        }
+    }
+
+    fn with_job<T>(&self, id: usize, f: impl FnOnce(&mut Job) -> T) -> Option<T> {
+        self.jobs
+            .borrow_mut()
+            .iter_mut()
+            .find(|job| job.id == id)
+            .map(f)
+    }
+}
+
+/// Job a `%N`, `%%` or `%+` argument refers to.
+pub(crate) fn parse_spec(jobs: &Jobs, spec: &str) -> Option<usize> {
+    match spec.strip_prefix('%')? {
+        "" | "%" | "+" => jobs.current(),
+        number => number.parse().ok().filter(|id| jobs.state(*id).is_some()),
+    }
+}
@@ -3,12 +3,16 @@
 //!
 //! # Example
 //! ```
-//! use shell_parser::ShellParser;
+//! use shell_parser::{Redirect, ShellParser};
 //!
 //! let parser = ShellParser::new();
 //! let invocations = parser.parse(r#"echo "Hello" > ./foo.log"#).unwrap();
 //! assert_eq!(invocations[0].name, "echo");
-//! assert_eq!(invocations[0].args, vec!["Hello", ">", "./foo.log"]);
+//! assert_eq!(invocations[0].args, vec!["Hello"]);
+//! assert_eq!(
+//!     invocations[0].redirects,
+//!     vec![Redirect::Output { fd: 1, path: "./foo.log".into(), append: false }]
+//! );
 //! ```
 //!
 //! ## Aliases
@@ -35,22 +39,78 @@
 //! assert_eq!(invocations[1].name, "echo");
 //! assert_eq!(invocations[1].args, vec!["hi", "world"]);
 //! ```
+//!
+//! ## Variables
+//! ```
+//! use shell_parser::ShellParser;
+//!
+//! let parser = ShellParser::new();
+//! let script = r#"
+//!     export NAME=world
+//!     echo "hello $NAME" '$NAME' ${MISSING:-fallback} ${#NAME}
+//! "#;
+//! let invocations = parser.parse(script).unwrap();
+//! assert_eq!(invocations[1].args, vec!["hello world", "$NAME", "fallback", "5"]);
+//! assert_eq!(parser.variable("NAME").as_deref(), Some("world"));
+//! ```
+//!
+//! ## Command substitution
+//! ```
+//! use shell_parser::{ShellParseError, ShellParser};
+//!
+//! let parser = ShellParser::new();
+//! let mut run = |script: &str| -> Result<String, ShellParseError> {
+//!     Ok(format!("[{script}]\n"))
+//! };
+//! let parsed = parser
+//!     .parse_with_substitution(r#"echo "$(whoami)" `pwd`/posts"#, &mut run)
+//!     .unwrap();
+//! assert_eq!(parsed[0].invocation.args, vec!["[whoami]", "[pwd]/posts"]);
+//! ```
 
+pub mod ast;
+mod builtins;
 pub mod command;
+pub mod complete;
+pub mod diagnostic;
 pub mod error;
+mod expansion;
+pub mod fmt;
+pub mod fs;
+mod glob;
+pub mod highlight;
 pub mod integration;
+pub mod io;
+mod jobs;
+pub mod limits;
+pub mod middleware;
+pub mod options;
 mod parser;
+pub mod redirect;
 pub mod separator;
+pub mod status;
+mod syntax;
 mod tokenizer;
+pub mod variables;
+mod word;
 
 pub use crate::command::{CommandInvocation, CommandSpec, ParsedCommand};
+pub use crate::complete::Completion;
+pub use crate::diagnostic::Diagnostic;
 pub use crate::error::ShellParseError;
-pub use crate::integration::{
-    pause_signal, with_cli, CliRunner, ExecutableCommand, ScriptResult, ShellCliError,
-    PAUSE_SIGNAL_PREFIX,
-};
-pub use crate::parser::ShellParser;
+pub use crate::fs::{FileSystem, MemoryFs};
+pub use crate::highlight::{HighlightKind, HighlightSpan};
+pub use crate::integration::{block_on, with_cli, CliRunner, ExecutableCommand, ShellCliError};
+pub use crate::io::{CommandIo, OutputStream};
+pub use crate::limits::{LimitExceeded, Limits};
+pub use crate::middleware::{CommandRecord, Decision, Middleware, Outcome};
+pub use crate::options::ShellOptions;
+pub use crate::parser::{CommandQueue, CommandType, ShellParser};
+pub use crate::redirect::Redirect;
 pub use crate::separator::Separator;
+pub use crate::status::ExitStatus;
+pub use crate::variables::Variables;
+pub use async_trait::async_trait;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,40 @@
+//! Bounds on the work one script run may do.
+
+use std::time::Duration;
+
+/// Bounds on a script run, set with
+/// [`CliRunner::with_limits`](crate::CliRunner::with_limits).
+///
+/// A run includes the command substitutions and the scripts that commands run
+/// through the same runner while it is going, so a script evaluating itself
+/// stops at `max_depth`; a background job is a run of its own. Every limit is
+/// off (`None`) by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Commands run, counting each stage of a pipeline.
+    pub max_commands: Option<usize>,
+    /// Scripts running inside one another, the outermost being 1; each
+    /// `$(...)` counts as a level.
+    pub max_depth: Option<usize>,
+    /// Bytes written by commands, whether to the terminal, a pipe or a file.
+    pub max_output_bytes: Option<usize>,
+    /// Time since the run started, read from the clock given to
+    /// [`CliRunner::with_clock`](crate::CliRunner::with_clock); without a clock
+    /// there is no time limit. A command still waiting when the time is up is
+    /// woken by the timer given to
+    /// [`CliRunner::with_timer`](crate::CliRunner::with_timer), if any.
+    pub max_time: Option<Duration>,
+}
+
+/// The limit a script ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum LimitExceeded {
+    #[error("command limit reached: ran more than {0} commands")]
+    Commands(usize),
+    #[error("depth limit reached: scripts nested more than {0} deep")]
+    Depth(usize),
+    #[error("output limit reached: wrote more than {0} bytes")]
+    OutputBytes(usize),
+    #[error("time limit reached: ran longer than {0:?}")]
+    Time(Duration),
+}
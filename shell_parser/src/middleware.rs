@@ -0,0 +1,60 @@
+//! Hooks around every command a [`CliRunner`](crate::CliRunner) runs.
+
+use std::time::Duration;
+
+use crate::command::CommandInvocation;
+use crate::integration::ShellCliError;
+use crate::status::ExitStatus;
+
+/// Sees each command before and after it runs, registered with
+/// [`CliRunner::with_middleware`](crate::CliRunner::with_middleware).
+///
+/// Every pipeline stage goes through the middleware, whether it comes from the
+/// script itself, a function body, a `$(...)` substitution, a background job
+/// or a script a command runs through the same runner. Functions are seen as
+/// the commands in their body. Builtins that change the shell, such as
+/// `export`, `alias` or `set`, have already taken effect when they get here;
+/// blocking them only stops what they print.
+pub trait Middleware<C> {
+    /// Called once the command is expanded, before its redirections are
+    /// opened. Change `invocation` to rewrite the command, or return
+    /// [`Decision::Block`] to stop it from running.
+    fn before(&self, _invocation: &mut CommandInvocation, _context: &C) -> Decision {
+        Decision::Run
+    }
+
+    /// Called when the command has finished, was blocked or failed.
+    fn after(&self, _record: &CommandRecord<'_>, _context: &C) {}
+}
+
+/// Whether a command goes ahead, returned by [`Middleware::before`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Run,
+    /// Skip the command, writing the message to its stderr; the command fails
+    /// with status 1 and the middleware after this one is not asked.
+    Block(String),
+}
+
+/// A command that went through the runner, handed to [`Middleware::after`].
+#[derive(Debug)]
+pub struct CommandRecord<'a> {
+    /// The command as it ran, after any rewriting.
+    pub invocation: &'a CommandInvocation,
+    /// Time the command took, measured with the clock given to
+    /// [`CliRunner::with_clock`](crate::CliRunner::with_clock); `None`
+    /// without a clock.
+    pub duration: Option<Duration>,
+    pub outcome: Outcome<'a>,
+}
+
+/// How a command ended.
+#[derive(Debug)]
+pub enum Outcome<'a> {
+    /// The command ran, or its redirections failed, with this status.
+    Exited(ExitStatus),
+    /// Middleware blocked the command with this message.
+    Blocked(&'a str),
+    /// The command returned an error, which aborts the script.
+    Failed(&'a ShellCliError),
+}
@@ -0,0 +1,83 @@
+//! Shell options changed with the `set` builtin.
+
+/// Options changed with `set`, such as `set -e` or `set -o pipefail`; all are
+/// off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShellOptions {
+    /// `-e`: stop the script once a pipeline fails, unless an `if`, `while` or
+    /// `until` condition tests it or `&&`/`||` follows it.
+    pub errexit: bool,
+    /// `-u`: fail when expanding a variable that is not set.
+    pub nounset: bool,
+    /// `-x`: print each command after expansion, prefixed with `+ `, on the
+    /// runner's stderr before it runs.
+    pub xtrace: bool,
+    /// `-o pipefail`: a pipeline fails with the status of its last failing
+    /// command instead of the status of its last command.
+    pub pipefail: bool,
+}
+
+/// Names accepted by `set -o`, with the flag that stands for each.
+const NAMES: &[(&str, Option<char>)] = &[
+    ("errexit", Some('e')),
+    ("nounset", Some('u')),
+    ("pipefail", None),
+    ("xtrace", Some('x')),
+];
+
+impl ShellOptions {
+    /// Apply the arguments of `set`: `-e` turns an option on and `+e` off,
+    /// flags combine as in `-eux`, and `-o NAME`/`+o NAME` use the long names.
+    ///
+    /// Nothing changes if any argument is invalid. A trailing `-o` or `+o`
+    /// without a name is accepted; it asks `set` to list the options.
+    pub fn apply(&mut self, args: &[String]) -> Result<(), String> {
+        let mut options = *self;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (on, flags) = match arg.split_at_checked(1) {
+                Some(("-", flags)) if !flags.is_empty() => (true, flags),
+                Some(("+", flags)) if !flags.is_empty() => (false, flags),
+                _ => return Err(format!("{arg}: invalid option")),
+            };
+            if flags == "o" {
+                if let Some(name) = args.next() {
+                    *options
+                        .named(name)
+                        .ok_or_else(|| format!("{name}: invalid option name"))? = on;
+                }
+                continue;
+            }
+            for flag in flags.chars() {
+                let name = NAMES
+                    .iter()
+                    .find(|(_, short)| *short == Some(flag))
+                    .map(|(name, _)| *name)
+                    .ok_or_else(|| format!("-{flag}: invalid option"))?;
+                if let Some(option) = options.named(name) {
+                    *option = on;
+                }
+            }
+        }
+        *self = options;
+        Ok(())
+    }
+
+    /// Every option by name with whether it is on, as listed by `set -o`.
+    pub fn list(mut self) -> Vec<(&'static str, bool)> {
+        NAMES
+            .iter()
+            .map(|(name, _)| (*name, self.named(name).is_some_and(|on| *on)))
+            .collect()
+    }
+
+    fn named(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+}
@@ -1,9 +1,33 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
 
+use crate::ast::{
+    self, walk_redirect_mut, Command, FunctionDefinition, IfBranch, List, Script, SimpleCommand,
+    Span, VisitorMut, Word,
+};
 use crate::command::{CommandInvocation, CommandSpec, ParsedCommand};
+use crate::complete::{self, Completion};
 use crate::error::ShellParseError;
-use crate::tokenizer::{tokenize, CommandTokens, Token};
+use crate::expansion::{expand_now, Expander, Substitute, Substitution};
+use crate::fmt;
+use crate::fs::FileSystem;
+use crate::glob;
+use crate::highlight::{self, HighlightKind, HighlightSpan, Slot};
+use crate::options::ShellOptions;
+use crate::redirect::Redirect;
+use crate::separator::Separator;
+use crate::status::ExitStatus;
+use crate::syntax;
+use crate::tokenizer::{CommandTokens, Token};
+use crate::variables::{self, CallFrame, Variables};
+use crate::word::WordPart;
+
+/// Commands interpreted by the shell itself; they never need a registered spec.
+pub(crate) const PARSER_BUILTINS: &[&str] = &[
+    "alias", "declare", "export", "fg", "jobs", "kill", "local", "set", "shift", "type", "unalias",
+    "unset", "wait", "which",
+];
 
 /// Parser that can tokenize shell-like input and validate against registered commands.
 #[derive(Default)]
@@ -12,6 +36,26 @@ pub struct ShellParser {
     command_aliases: HashMap<String, String>,
     runtime_aliases: RefCell<HashMap<String, RuntimeAlias>>,
     runtime_functions: RefCell<HashMap<String, RuntimeFunction>>,
+    variables: RefCell<Variables>,
+    /// Call whose words are being expanded; command substitutions run inside it.
+    frame: RefCell<Option<Rc<CallFrame>>>,
+    options: Cell<ShellOptions>,
+    file_system: Option<Rc<dyn FileSystem>>,
+}
+
+/// What a command name runs, as reported by `type` and `which`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandType {
+    /// Function defined by a script.
+    Function,
+    /// Alias defined with `alias`; holds its value.
+    Alias(String),
+    /// Command interpreted by the shell itself, such as `export` or `builtin`.
+    Builtin,
+    /// Registered [`CommandSpec`].
+    Command,
+    /// Alias listed in a [`CommandSpec`]; holds the name of the command it runs.
+    CommandAlias(String),
 }
 
 #[derive(Clone, Debug)]
@@ -21,19 +65,282 @@ struct RuntimeAlias {
 
 #[derive(Clone, Debug)]
 struct RuntimeFunction {
-    commands: Vec<CommandTokens>,
+    body: List,
 }
 
-#[derive(Clone)]
+/// Where a queued entry came from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Scope {
+    /// Aliases and functions being expanded, outermost first; used to detect loops.
+    stack: Vec<String>,
+    /// Call whose positional parameters and locals the entry sees.
+    frame: Option<Rc<CallFrame>>,
+    /// Set inside an `if`, `while` or `until` condition, whose failures
+    /// `set -e` ignores.
+    tested: bool,
+}
+
+impl Scope {
+    /// Scope of the body of alias or function `name` expanded from this scope.
+    fn enter(&self, name: &str, frame: Option<Rc<CallFrame>>) -> Self {
+        let mut stack = self.stack.clone();
+        stack.push(name.to_string());
+        Self {
+            stack,
+            frame,
+            tested: self.tested,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 struct PendingCommand {
     tokens: CommandTokens,
-    stack: Vec<String>,
+    scope: Scope,
+}
+
+/// Entry of a [`CommandQueue`]; entries keep the scope they came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Pending {
+    /// Simple command, with the separator written after it.
+    Command(PendingCommand),
+    /// `if`, `while`, `until`, `for` or `case` command that has not started.
+    Compound(Command, Option<Separator>, Scope),
+    /// Function definition, applied when reached.
+    Function(FunctionDefinition, Option<Separator>),
+    /// Step of a compound command that is running.
+    Control(Control, Scope),
 }
 
-struct FunctionHeader {
-    name: String,
-    position: usize,
-    brace_inline: bool,
+/// Step of a running compound command, queued behind the commands it waits for.
+///
+/// Conditions are ordinary commands run by the caller; these steps look at `$?`
+/// once they have finished, so callers can take as long as they need to run them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Control {
+    /// Pick `body` if the `if`/`elif` condition succeeded, else try the next branch.
+    Branch {
+        body: List,
+        branches: VecDeque<IfBranch>,
+        otherwise: Option<List>,
+    },
+    /// Run the loop body again if the condition allows it.
+    Loop {
+        until: bool,
+        condition: List,
+        body: List,
+    },
+    /// Assign the next value to `name` and run the body, once per value.
+    For {
+        name: String,
+        values: VecDeque<String>,
+        body: List,
+        ran: bool,
+    },
+    /// The compound command finished; the separator after it applies from here.
+    End(Option<Separator>),
+}
+
+/// Commands read from a script that have not been expanded yet.
+///
+/// Created by [`ShellParser::queue`] from the script's syntax tree and drained
+/// with [`ShellParser::next_command`], so each command sees variables and
+/// command output produced by the ones before it. Running `if`, `while`, `for`
+/// and `case` commands keep their state here too, so a queue saved mid-loop
+/// resumes inside the loop.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandQueue {
+    pending: VecDeque<Pending>,
+    /// Whether the last command taken ended with `|`, so the next one reads its output.
+    piped: bool,
+}
+
+/// What [`ShellParser::next_step`] takes from a queue.
+pub(crate) enum Step<T> {
+    Command(T),
+    /// A compound command finished; `&&`/`||` after it apply from here.
+    End(Option<Separator>),
+    /// A compound command finished without running a body; this is its status.
+    Status(ExitStatus),
+}
+
+/// Command taken by [`ShellParser::next_step`].
+pub(crate) struct QueuedCommand {
+    pub parsed: ParsedCommand,
+    /// Whether a condition tests the command's status.
+    pub tested: bool,
+}
+
+impl CommandQueue {
+    /// Number of commands left before alias and function expansion.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether every command has been taken from the queue.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Whether the next entry is a command rather than a step of a running compound.
+    pub(crate) fn starts_with_command(&self) -> bool {
+        matches!(
+            self.pending.front(),
+            Some(Pending::Command(_) | Pending::Compound(..) | Pending::Function(..))
+        )
+    }
+
+    /// Drop the commands of the next pipeline without expanding them.
+    ///
+    /// Returns the separator that ended the pipeline.
+    pub(crate) fn skip_pipeline(&mut self) -> Option<Separator> {
+        while self.starts_with_command() {
+            let separator = match self.pending.pop_front() {
+                Some(Pending::Command(command)) => command.tokens.separator,
+                Some(Pending::Compound(_, separator, _) | Pending::Function(_, separator)) => {
+                    separator
+                }
+                _ => None,
+            };
+            if separator != Some(Separator::Pipe) {
+                return separator;
+            }
+        }
+        None
+    }
+
+    /// Queue already-parsed invocations so they run again without re-expansion.
+    pub(crate) fn from_invocations(invocations: &[CommandInvocation]) -> Self {
+        let pending = invocations
+            .iter()
+            .map(|invocation| {
+                Pending::Command(PendingCommand {
+                    tokens: CommandTokens {
+                        tokens: std::iter::once(&invocation.name)
+                            .chain(&invocation.args)
+                            .map(|word| Token::literal(word.as_str(), 0))
+                            .chain(
+                                invocation
+                                    .redirects
+                                    .iter()
+                                    .flat_map(|redirect| redirect.to_tokens(0)),
+                            )
+                            .collect(),
+                        separator: Some(Separator::Semicolon),
+                    },
+                    scope: Scope::default(),
+                })
+            })
+            .collect();
+        Self {
+            pending,
+            piped: false,
+        }
+    }
+}
+
+/// Queue entries that run `list`, in order.
+fn flatten(list: List, scope: &Scope) -> Vec<Pending> {
+    let mut entries = Vec::new();
+    for item in list.items {
+        let count = item.pipeline.commands.len();
+        for (idx, command) in item.pipeline.commands.into_iter().enumerate() {
+            let separator = if idx + 1 < count {
+                Some(Separator::Pipe)
+            } else {
+                item.separator
+            };
+            entries.push(match command {
+                Command::Simple(command) => Pending::Command(PendingCommand {
+                    tokens: command_tokens(command, separator),
+                    scope: scope.clone(),
+                }),
+                Command::Function(definition) => Pending::Function(definition, separator),
+                compound => Pending::Compound(compound, separator, scope.clone()),
+            });
+        }
+    }
+    entries
+}
+
+/// Tokens of a simple command, with each redirection operator before its target.
+fn command_tokens(command: SimpleCommand, separator: Option<Separator>) -> CommandTokens {
+    let mut tokens: Vec<Token> = command.words.into_iter().map(Token::from).collect();
+    for redirect in command.redirects {
+        tokens.push(Token {
+            end: redirect.span.end,
+            ..Token::redirect(redirect.operator, redirect.span.start)
+        });
+        tokens.extend(redirect.target.map(Token::from));
+    }
+    CommandTokens { tokens, separator }
+}
+
+/// Queue `list` to run next, followed by `then`.
+fn schedule(pending: &mut VecDeque<Pending>, list: List, then: Option<Control>, scope: &Scope) {
+    if let Some(control) = then {
+        pending.push_front(Pending::Control(control, scope.clone()));
+    }
+    for entry in flatten(list, scope).into_iter().rev() {
+        pending.push_front(entry);
+    }
+}
+
+/// Queue the commands of `condition` to run next, followed by `control`, which
+/// reads their status.
+fn schedule_condition(
+    pending: &mut VecDeque<Pending>,
+    condition: List,
+    control: Control,
+    scope: &Scope,
+) {
+    pending.push_front(Pending::Control(control, scope.clone()));
+    let tested = Scope {
+        tested: true,
+        ..scope.clone()
+    };
+    for entry in flatten(condition, &tested).into_iter().rev() {
+        pending.push_front(entry);
+    }
+}
+
+/// Moves every word of a function body or alias value to the name that
+/// called it, so errors point at the call.
+struct CallSite {
+    span: Span,
+}
+
+impl VisitorMut for CallSite {
+    fn visit_redirect_mut(&mut self, redirect: &mut ast::Redirect) {
+        redirect.span = self.span;
+        walk_redirect_mut(self, redirect);
+    }
+
+    fn visit_word_mut(&mut self, word: &mut Word) {
+        word.span = self.span;
+    }
+}
+
+/// Puts the call frame back when expansion ends, or is dropped while a
+/// substitution waits, such as when the script is interrupted.
+struct RestoreFrame<'a> {
+    frame: &'a RefCell<Option<Rc<CallFrame>>>,
+    outer: Option<Rc<CallFrame>>,
+}
+
+impl Drop for RestoreFrame<'_> {
+    fn drop(&mut self) {
+        *self.frame.borrow_mut() = self.outer.take();
+    }
+}
+
+/// Command whose words have been expanded against the current variables.
+struct ExpandedCommand {
+    /// Word tokens of the command; redirection tokens are removed.
+    tokens: CommandTokens,
+    words: Vec<String>,
+    redirects: Vec<Redirect>,
+    tested: bool,
 }
 
 impl ShellParser {
@@ -44,6 +351,10 @@ impl ShellParser {
             command_aliases: HashMap::new(),
             runtime_aliases: RefCell::new(HashMap::new()),
             runtime_functions: RefCell::new(HashMap::new()),
+            variables: RefCell::new(Variables::new()),
+            frame: RefCell::new(None),
+            options: Cell::default(),
+            file_system: None,
         }
     }
 
@@ -70,46 +381,412 @@ impl ShellParser {
         self.commands.insert(name, command);
     }
 
+    /// List directories through `fs` to expand unquoted `*`, `?` and `[...]` in words.
+    ///
+    /// Without a file system, patterns are passed to commands as written.
+    pub fn set_file_system(&mut self, fs: Rc<dyn FileSystem>) {
+        self.file_system = Some(fs);
+    }
+
     /// Parse a script into a list of invocations.
     ///
+    /// This flattens the script into the commands it runs, expanded in order;
+    /// use [`ShellParser::parse_script`] to see its structure instead.
+    ///
     /// The parser supports:
-    /// - Command separators: newline, `;`, `|`, or `&&`.
+    /// - Command separators: newline, `;`, `|`, `&&` or `||`.
     /// - Comments starting with `#` until the end of the line.
     /// - Quoted arguments with `'` or `"`.
     /// - Escaping with `\` to include special characters.
+    /// - Variable expansion (`$NAME`, `${NAME:-default}`, `${#NAME}`) outside
+    ///   single quotes, applied in order with `NAME=value`, `export` and `unset`.
+    /// - Redirections (`>`, `>>`, `<`, `2>&1`), collected into
+    ///   [`CommandInvocation::redirects`] instead of the arguments.
     ///
     /// If the parser has registered commands, each invocation is validated
     /// against the corresponding [`CommandSpec`]. Unknown commands are
     /// rejected only when at least one command has been registered to avoid
     /// forcing validation on unconstrained parsers.
+    ///
+    /// Command substitutions (`$(...)`) need something to run them and are
+    /// rejected here; see [`ShellParser::parse_with_substitution`]. So are
+    /// `if`, `while`, `until`, `for` and `case`, whose conditions depend on
+    /// commands running; see [`ShellParser::next_command`].
     pub fn parse(&self, input: &str) -> Result<Vec<CommandInvocation>, ShellParseError> {
-        let command_tokens = self.collect_commands(input)?;
-        let validate_commands = !self.commands.is_empty();
-        command_tokens
+        Ok(self
+            .parse_with_separators(input)?
             .into_iter()
-            .map(|tokens| self.build_invocation(tokens, validate_commands))
-            .collect()
+            .map(|parsed| parsed.invocation)
+            .collect())
     }
 
     /// Parse a script into commands while preserving separators between them.
+    ///
+    /// Parsing carries on past a command that fails to expand or validate, and
+    /// every problem is returned in a [`ShellParseError::Multiple`].
     pub fn parse_with_separators(
         &self,
         input: &str,
     ) -> Result<Vec<ParsedCommand>, ShellParseError> {
-        let command_tokens = self.collect_commands(input)?;
-        let validate_commands = !self.commands.is_empty();
-        command_tokens
-            .into_iter()
-            .map(|tokens| {
-                let separator = tokens.separator;
-                self.build_invocation(tokens, validate_commands)
-                    .map(|invocation| ParsedCommand {
-                        invocation,
-                        separator,
-                    })
-            })
-            .collect()
+        let mut queue = self.queue(input)?;
+        let mut commands = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            // A failing command has already been taken off the queue.
+            match expand_now(self.next_parsed(&mut queue, None)) {
+                Ok(Some(command)) => commands.push(command),
+                Ok(None) => break,
+                Err(error) => errors.push(error),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ShellParseError::from_errors(errors));
+        }
+        Ok(commands)
+    }
+
+    /// Parse a script, running `$(...)` and backtick substitutions through `substitute`.
+    ///
+    /// The callback receives the inner script and returns its output; trailing
+    /// newlines are removed before the output is spliced into the word.
+    pub fn parse_with_substitution<E>(
+        &self,
+        input: &str,
+        substitute: &mut dyn FnMut(&str) -> Result<String, E>,
+    ) -> Result<Vec<ParsedCommand>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        let mut queue = self.queue(input)?;
+        let mut commands = Vec::new();
+        loop {
+            let mut substitute = |script: &str| ready(substitute(script));
+            let Some(command) = expand_now(self.next_parsed(&mut queue, Some(&mut substitute)))?
+            else {
+                break;
+            };
+            commands.push(command);
+        }
+        Ok(commands)
+    }
+
+    /// Parse a script into its syntax tree without expanding or running anything.
+    ///
+    /// Words keep their `$NAME` and `$(...)` parts and aliases and functions
+    /// are left unresolved, so the tree does not depend on what earlier commands
+    /// define. The other parse methods and [`ShellParser::queue`] are built on it.
+    pub fn parse_script(&self, input: &str) -> Result<Script, ShellParseError> {
+        syntax::parse_script(input)
+    }
+
+    /// Classify ranges of `input` for syntax highlighting.
+    ///
+    /// Never fails: incomplete input such as an open quote or an `if` without
+    /// `fi` is highlighted as far as it goes. Command names are checked against
+    /// registered commands, aliases and functions defined so far; without any
+    /// registered commands every name counts as known.
+    pub fn highlight(&self, input: &str) -> Vec<HighlightSpan> {
+        highlight::highlight(input, &|name| self.command_kind(name))
+    }
+
+    fn command_kind(&self, name: &str) -> HighlightKind {
+        match self.command_type(name) {
+            Some(CommandType::Function) => HighlightKind::Function,
+            Some(CommandType::Alias(_) | CommandType::CommandAlias(_)) => HighlightKind::Alias,
+            Some(CommandType::Builtin | CommandType::Command) => HighlightKind::Command,
+            None if self.commands.is_empty() => HighlightKind::Command,
+            None => HighlightKind::UnknownCommand,
+        }
+    }
+
+    /// What running `name` as a command would do, checked in the order the
+    /// parser resolves names: functions, aliases, builtins, then registered
+    /// commands and their aliases.
+    pub fn command_type(&self, name: &str) -> Option<CommandType> {
+        if self.runtime_functions.borrow().contains_key(name) {
+            Some(CommandType::Function)
+        } else if let Some(alias) = self.alias(name) {
+            Some(CommandType::Alias(alias))
+        } else if PARSER_BUILTINS.contains(&name) || name == "builtin" {
+            Some(CommandType::Builtin)
+        } else if self.commands.contains_key(name) {
+            Some(CommandType::Command)
+        } else {
+            self.command_aliases
+                .get(name)
+                .map(|command| CommandType::CommandAlias(command.clone()))
+        }
+    }
+
+    /// Aliases defined with `alias`, as `(name, value)` pairs sorted by name.
+    pub fn aliases(&self) -> Vec<(String, String)> {
+        let mut aliases: Vec<_> = self
+            .runtime_aliases
+            .borrow()
+            .iter()
+            .map(|(name, alias)| (name.clone(), alias.value.clone()))
+            .collect();
+        aliases.sort();
+        aliases
+    }
+
+    /// Value of the alias `name` defined with `alias`.
+    pub fn alias(&self, name: &str) -> Option<String> {
+        self.runtime_aliases
+            .borrow()
+            .get(name)
+            .map(|alias| alias.value.clone())
+    }
+
+    /// Remove the alias `name`, returning whether it was defined.
+    pub fn remove_alias(&self, name: &str) -> bool {
+        self.runtime_aliases.borrow_mut().remove(name).is_some()
+    }
+
+    /// Names of the functions defined so far, sorted.
+    pub fn functions(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.runtime_functions.borrow().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Script text defining the function `name`, as printed by `declare -f`.
+    pub fn function_source(&self, name: &str) -> Option<String> {
+        let functions = self.runtime_functions.borrow();
+        let function = functions.get(name)?;
+        Some(fmt::function(&FunctionDefinition {
+            name: name.to_string(),
+            body: function.body.clone(),
+            span: Span::default(),
+        }))
+    }
+
+    /// Complete the word that ends at byte `cursor` of `input`.
+    ///
+    /// A command name completes to registered commands and their aliases,
+    /// builtins, and aliases and functions defined so far. An argument starting
+    /// with `-` completes to the flags in the command's [`CommandSpec`]; other
+    /// arguments and redirection targets complete to paths listed through the
+    /// file system set with [`ShellParser::set_file_system`].
+    pub fn complete(&self, input: &str, cursor: usize) -> Completion {
+        let mut cursor = cursor.min(input.len());
+        while !input.is_char_boundary(cursor) {
+            cursor -= 1;
+        }
+        let word = highlight::last_word(&input[..cursor]);
+        let prefix = word.value.as_str();
+        let mut candidates = match word.slot {
+            Slot::Command => self
+                .command_names()
+                .into_iter()
+                .filter(|name| name.starts_with(prefix))
+                .map(|name| complete::escape(&name))
+                .collect(),
+            Slot::Argument { command } if prefix.starts_with('-') => command
+                .map(|name| self.command_options(&name))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|option| option.starts_with(prefix))
+                .collect(),
+            Slot::Argument { .. } | Slot::Path => self
+                .file_system
+                .as_deref()
+                .map(|fs| complete::paths(fs, prefix))
+                .unwrap_or_default(),
+            Slot::Other => Vec::new(),
+        };
+        candidates.sort();
+        candidates.dedup();
+        Completion {
+            replace: Span::new(word.start, cursor),
+            candidates,
+        }
     }
+
+    /// Every name that can start a command right now.
+    fn command_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .commands
+            .keys()
+            .chain(self.command_aliases.keys())
+            .chain(self.runtime_aliases.borrow().keys())
+            .chain(self.runtime_functions.borrow().keys())
+            .cloned()
+            .collect();
+        names.extend(PARSER_BUILTINS.iter().map(|name| name.to_string()));
+        names.push("builtin".into());
+        names
+    }
+
+    /// Flags of the command `name` resolves to through spec aliases.
+    fn command_options(&self, name: &str) -> Vec<String> {
+        let name = self.command_aliases.get(name).map_or(name, String::as_str);
+        self.commands
+            .get(name)
+            .map(|spec| spec.options.clone())
+            .unwrap_or_default()
+    }
+
+    /// Parse a script into a queue whose commands are expanded one at a time.
+    ///
+    /// Inside a command substitution the script sees the positional parameters
+    /// and locals of the function it was written in.
+    pub fn queue(&self, input: &str) -> Result<CommandQueue, ShellParseError> {
+        let scope = Scope {
+            stack: Vec::new(),
+            frame: self.frame.borrow().clone(),
+            tested: false,
+        };
+        self.queue_in(input, scope)
+    }
+
+    /// Like [`ShellParser::queue`], with `args` as the positional parameters
+    /// `$1`, `$2`, ... of the script.
+    pub fn queue_with_args(
+        &self,
+        input: &str,
+        args: Vec<String>,
+    ) -> Result<CommandQueue, ShellParseError> {
+        let scope = Scope {
+            stack: Vec::new(),
+            frame: Some(Rc::new(CallFrame::new(args, None))),
+            tested: false,
+        };
+        self.queue_in(input, scope)
+    }
+
+    fn queue_in(&self, input: &str, scope: Scope) -> Result<CommandQueue, ShellParseError> {
+        let script = self.parse_script(input)?;
+        Ok(CommandQueue {
+            pending: flatten(script.body, &scope).into(),
+            piped: false,
+        })
+    }
+
+    /// Take the next command from `queue`, expanding aliases, functions and words.
+    ///
+    /// Expansion happens now rather than when the queue was built, so callers
+    /// that execute each command before asking for the next one get shell-like
+    /// ordering. Returns `None` once the queue is drained.
+    ///
+    /// `if`, `while`, `until`, `for` and `case` are run from the queue: the
+    /// commands of a condition are returned first, and the branch or loop body
+    /// is chosen from `$?` when the next command is requested. Callers should
+    /// set `?` (see [`ShellParser::set_variable`]) after running each command.
+    pub fn next_command<E>(
+        &self,
+        queue: &mut CommandQueue,
+        substitute: &mut dyn FnMut(&str) -> Result<String, E>,
+    ) -> Result<Option<ParsedCommand>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        loop {
+            let mut substitute = |script: &str| ready(substitute(script));
+            match expand_now(self.next_step(queue, &mut substitute))? {
+                Some(Step::Command(command)) => return Ok(Some(command.parsed)),
+                Some(Step::Status(status)) => self.set_variable("?", status.code().to_string()),
+                Some(Step::End(_)) => {}
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Like [`ShellParser::next_command`], but also reports where compound commands end.
+    pub(crate) async fn next_step<E>(
+        &self,
+        queue: &mut CommandQueue,
+        substitute: Substitute<'_, E>,
+    ) -> Result<Option<Step<QueuedCommand>>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        Ok(
+            match self.next_expanded(queue, Some(substitute), true).await? {
+                Some(Step::Command(command)) => Some(Step::Command(QueuedCommand {
+                    tested: command.tested,
+                    parsed: self.parse_expanded(command)?,
+                })),
+                Some(Step::End(separator)) => Some(Step::End(separator)),
+                Some(Step::Status(status)) => Some(Step::Status(status)),
+                None => None,
+            },
+        )
+    }
+
+    /// Current value of a shell variable.
+    pub fn variable(&self, name: &str) -> Option<String> {
+        self.variables.borrow().get(name).map(str::to_string)
+    }
+
+    /// Assign a shell variable visible to subsequent parses.
+    pub fn set_variable(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.variables.borrow_mut().set(name, value);
+    }
+
+    /// Remove a shell variable, returning its previous value.
+    pub fn unset_variable(&self, name: &str) -> Option<String> {
+        self.variables.borrow_mut().unset(name)
+    }
+
+    /// Snapshot of every shell variable currently defined.
+    pub fn variables(&self) -> Variables {
+        self.variables.borrow().clone()
+    }
+
+    /// Options currently set with `set`.
+    pub fn options(&self) -> ShellOptions {
+        self.options.get()
+    }
+
+    /// Replace the options, as a series of `set` commands would.
+    pub fn set_options(&self, options: ShellOptions) {
+        self.options.set(options);
+    }
+}
+
+/// Arguments counted against the arity of `spec`. A command that parses its
+/// own options counts only its positionals, split the way the derived parsers
+/// split them; `None` means it was asked for help, which any arity allows.
+fn argument_count(args: &[String], spec: &CommandSpec) -> Option<usize> {
+    if !spec.parses_options {
+        return Some(args.len());
+    }
+    let takes_value = |option: &str| spec.value_options.iter().any(|value| value == option);
+    let mut found = 0;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return Some(found + args.count());
+        }
+        if arg.starts_with("--") {
+            if arg == "--help" {
+                return None;
+            }
+            if takes_value(arg) {
+                args.next();
+            }
+            continue;
+        }
+        let Some(shorts) = arg.strip_prefix('-').filter(|rest| !rest.is_empty()) else {
+            found += 1;
+            continue;
+        };
+        // In `-vn10` the value option `-n` takes the rest of the word; given
+        // last, as in `-vn 10`, it takes the next argument.
+        for (idx, short) in shorts.char_indices() {
+            if short == 'h' {
+                return None;
+            }
+            if takes_value(&format!("-{short}")) {
+                if idx + short.len_utf8() == shorts.len() {
+                    args.next();
+                }
+                break;
+            }
+        }
+    }
+    Some(found)
 }
 
 fn validate_tokens(
@@ -117,25 +794,30 @@ fn validate_tokens(
     args: &[String],
     spec: &CommandSpec,
 ) -> Result<(), ShellParseError> {
-    let name_token = &tokens[0];
-    if args.len() < spec.min_args {
+    let span = Span::new(tokens[0].position, tokens[tokens.len() - 1].end);
+    let Some(found) = argument_count(args, spec) else {
+        return Ok(());
+    };
+    if found < spec.min_args {
         return Err(ShellParseError::InvalidArity {
             name: spec.name.clone(),
             min_expected: spec.min_args,
             max_expected: spec.max_args,
-            found: args.len(),
-            position: name_token.position,
+            found,
+            position: span.start,
+            end: span.end,
         });
     }
 
     if let Some(max_args) = spec.max_args {
-        if args.len() > max_args {
+        if found > max_args {
             return Err(ShellParseError::InvalidArity {
                 name: spec.name.clone(),
                 min_expected: spec.min_args,
                 max_expected: spec.max_args,
-                found: args.len(),
-                position: name_token.position,
+                found,
+                position: span.start,
+                end: span.end,
             });
         }
     }
@@ -146,48 +828,58 @@ fn validate_tokens(
 impl ShellParser {
     fn build_invocation(
         &self,
-        tokens: CommandTokens,
+        command: ExpandedCommand,
         validate_commands: bool,
     ) -> Result<CommandInvocation, ShellParseError> {
-        if tokens.tokens.is_empty() {
+        if command.tokens.tokens.is_empty() || command.words.is_empty() {
             return Err(ShellParseError::InvalidArity {
                 name: String::new(),
                 min_expected: 0,
                 max_expected: None,
                 found: 0,
                 position: 0,
+                end: 0,
             });
         }
 
-        let args: Vec<String> = tokens.tokens[1..].iter().map(token_value).collect();
-        self.build_command_from_tokens(&tokens.tokens, args, validate_commands)
+        let mut words = command.words.into_iter();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<String> = words.collect();
+        let mut invocation =
+            self.build_command_from_tokens(&command.tokens.tokens, name, args, validate_commands)?;
+        invocation.redirects = command.redirects;
+        Ok(invocation)
     }
 
     fn build_command_from_tokens(
         &self,
         tokens: &[Token],
+        name: String,
         args: Vec<String>,
         validate_commands: bool,
     ) -> Result<CommandInvocation, ShellParseError> {
         let name_token = &tokens[0];
 
-        if name_token.value == "builtin" {
+        if name == "builtin" {
             return self.build_builtin_invocation(tokens, args, validate_commands);
         }
 
-        let mut resolved_name = name_token.value.clone();
-        let spec = self.resolve_command(&name_token.value);
+        let mut resolved_name = name.clone();
+        let spec = self.resolve_command(&name);
 
         if let Some((command_spec, canonical_name)) = spec {
             resolved_name = canonical_name.to_string();
             if validate_commands {
                 validate_tokens(tokens, &args, command_spec)?;
             }
-        } else if validate_commands && name_token.value != "alias" && name_token.value != "function"
+        } else if validate_commands
+            && !PARSER_BUILTINS.contains(&name.as_str())
+            && name != "function"
         {
             return Err(ShellParseError::UnknownCommand {
-                name: name_token.value.clone(),
+                name,
                 position: name_token.position,
+                end: name_token.end,
             });
         }
 
@@ -195,6 +887,7 @@ impl ShellParser {
             name: resolved_name,
             args,
             position: name_token.position,
+            redirects: Vec::new(),
         })
     }
 
@@ -211,18 +904,17 @@ impl ShellParser {
                 max_expected: None,
                 found: 0,
                 position: tokens[0].position,
+                end: tokens[0].end,
             });
         }
 
         let target_name = args[0].clone();
-        let target_token_position = tokens
-            .get(1)
-            .map(|token| token.position)
-            .unwrap_or(tokens[0].position);
+        let target_token = tokens.get(1).unwrap_or(&tokens[0]);
+        let target_token_position = target_token.position;
         let mut target_tokens = Vec::with_capacity(tokens.len() - 1);
         target_tokens.push(Token {
-            value: target_name.clone(),
-            position: target_token_position,
+            end: target_token.end,
+            ..Token::literal(target_name.clone(), target_token_position)
         });
         target_tokens.extend(tokens.iter().skip(2).cloned());
         let target_args: Vec<String> = args.into_iter().skip(1).collect();
@@ -231,6 +923,7 @@ impl ShellParser {
             return Err(ShellParseError::UnknownCommand {
                 name: target_name,
                 position: target_token_position,
+                end: target_token.end,
             });
         };
 
@@ -242,6 +935,7 @@ impl ShellParser {
             name: canonical_name.to_string(),
             args: target_args,
             position: target_token_position,
+            redirects: Vec::new(),
         })
     }
 
@@ -257,170 +951,486 @@ impl ShellParser {
         })
     }
 
-    fn collect_commands(&self, input: &str) -> Result<Vec<CommandTokens>, ShellParseError> {
-        let mut pending: VecDeque<PendingCommand> = tokenize(input)?
-            .into_iter()
-            .map(|tokens| PendingCommand {
-                tokens,
-                stack: Vec::new(),
-            })
-            .collect();
-        let mut commands = Vec::new();
+    async fn next_parsed<E>(
+        &self,
+        queue: &mut CommandQueue,
+        substitute: Option<Substitute<'_, E>>,
+    ) -> Result<Option<ParsedCommand>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        // Compound commands are rejected without a runner, so only commands come back.
+        let Some(Step::Command(command)) = self.next_expanded(queue, substitute, false).await?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(self.parse_expanded(command)?))
+    }
+
+    fn parse_expanded(&self, command: ExpandedCommand) -> Result<ParsedCommand, ShellParseError> {
+        let separator = command.tokens.separator;
+        let invocation = self.build_invocation(command, !self.commands.is_empty())?;
+        Ok(ParsedCommand {
+            invocation,
+            separator,
+        })
+    }
 
-        while let Some(pending_command) = pending.pop_front() {
-            let pending_command = pending_command;
+    /// Take the next command to run, applying definitions and compound commands on the way.
+    ///
+    /// `run_control` is unset when nothing runs the commands, in which case
+    /// compound commands are rejected.
+    async fn next_expanded<E>(
+        &self,
+        queue: &mut CommandQueue,
+        substitute: Option<Substitute<'_, E>>,
+        run_control: bool,
+    ) -> Result<Option<Step<ExpandedCommand>>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        // Entries switch the active call frame; a substitution running this
+        // queue must hand the frame back to the command it was expanding.
+        let _restore = RestoreFrame {
+            frame: &self.frame,
+            outer: self.frame.borrow().clone(),
+        };
+        self.expand_next(queue, substitute, run_control).await
+    }
+
+    async fn expand_next<E>(
+        &self,
+        queue: &mut CommandQueue,
+        substitute: Option<Substitute<'_, E>>,
+        run_control: bool,
+    ) -> Result<Option<Step<ExpandedCommand>>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        let pending = &mut queue.pending;
+        let mut expander = Expander::new(&self.variables, self.file_system.as_deref(), substitute);
+        expander.set_nounset(self.options().nounset);
+
+        while let Some(entry) = pending.pop_front() {
+            if let Pending::Command(PendingCommand { scope, .. })
+            | Pending::Compound(_, _, scope)
+            | Pending::Control(_, scope) = &entry
+            {
+                *self.frame.borrow_mut() = scope.frame.clone();
+                expander.set_frame(scope.frame.clone());
+            }
+            let pending_command = match entry {
+                Pending::Command(command) => command,
+                Pending::Compound(command, separator, scope) => {
+                    if !run_control {
+                        return Err(ShellParseError::UnsupportedControlFlow {
+                            keyword: command.keyword().unwrap_or_default().to_string(),
+                            position: command.span().start,
+                            end: command.span().end,
+                        }
+                        .into());
+                    }
+                    match self
+                        .start_compound(command, separator, scope, &mut expander, pending)
+                        .await?
+                    {
+                        Some(step) => return Ok(Some(step)),
+                        None => continue,
+                    }
+                }
+                Pending::Function(definition, _) => {
+                    self.runtime_functions.borrow_mut().insert(
+                        definition.name,
+                        RuntimeFunction {
+                            body: definition.body,
+                        },
+                    );
+                    continue;
+                }
+                Pending::Control(control, scope) => {
+                    match self.resume_compound(control, scope, pending) {
+                        Some(step) => return Ok(Some(step)),
+                        None => continue,
+                    }
+                }
+            };
             if pending_command.tokens.tokens.is_empty() {
                 continue;
             }
 
-            if self.try_handle_function_definition(&pending_command.tokens, &mut pending)? {
+            if let Some(expanded) = self
+                .try_expand_runtime_function(&pending_command, queue.piped, &mut expander)
+                .await?
+            {
+                for entry in expanded.into_iter().rev() {
+                    pending.push_front(entry);
+                }
                 continue;
             }
 
-            if let Some(expanded) = self.try_expand_runtime_function(&pending_command)? {
-                for cmd in expanded.into_iter().rev() {
-                    pending.push_front(cmd);
+            if let Some(expanded) = self.try_expand_runtime_alias(&pending_command)? {
+                for entry in expanded.into_iter().rev() {
+                    pending.push_front(entry);
                 }
                 continue;
             }
 
-            if let Some(expanded) = self.try_expand_runtime_alias(&pending_command)? {
-                for cmd in expanded.into_iter().rev() {
-                    pending.push_front(cmd);
-                }
+            if self
+                .try_apply_assignments(&pending_command.tokens, &mut expander)
+                .await?
+            {
                 continue;
             }
 
-            if self.is_alias_command(&pending_command.tokens) {
-                self.apply_alias_definitions(&pending_command.tokens)?;
+            let (tokens, redirects) =
+                split_redirects(pending_command.tokens, &mut expander).await?;
+            let words = match builtin_name(&tokens) {
+                Some(builtin) => self.apply_builtin(builtin, &tokens, &mut expander).await?,
+                None => expander.expand_fields(&tokens.tokens).await?,
+            };
+            if words.is_empty() {
+                continue;
             }
 
-            commands.push(pending_command.tokens);
+            queue.piped = tokens.separator == Some(Separator::Pipe);
+            return Ok(Some(Step::Command(ExpandedCommand {
+                tokens,
+                words,
+                redirects,
+                tested: pending_command.scope.tested,
+            })));
         }
 
-        Ok(commands)
+        Ok(None)
     }
 
-    fn try_handle_function_definition(
+    /// Queue the first commands of a compound command, or report its status if it runs none.
+    async fn start_compound<T, E>(
         &self,
-        tokens: &CommandTokens,
-        pending: &mut VecDeque<PendingCommand>,
-    ) -> Result<bool, ShellParseError> {
-        let Some(header) = self.parse_function_header(tokens)? else {
-            return Ok(false);
-        };
-
-        if !header.brace_inline {
-            let Some(brace_command) = pending.pop_front() else {
-                return Err(ShellParseError::InvalidFunction {
-                    name: header.name.clone(),
-                    message: "expected '{' after function header".into(),
-                    position: header.position,
-                });
-            };
-            if !is_brace_command(&brace_command.tokens.tokens, "{") {
-                return Err(ShellParseError::InvalidFunction {
-                    name: header.name.clone(),
-                    message: "expected '{' after function header".into(),
-                    position: header.position,
-                });
+        command: Command,
+        separator: Option<Separator>,
+        scope: Scope,
+        expander: &mut Expander<'_, '_, E>,
+        pending: &mut VecDeque<Pending>,
+    ) -> Result<Option<Step<T>>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        schedule(
+            pending,
+            List::default(),
+            Some(Control::End(separator)),
+            &scope,
+        );
+        match command {
+            Command::If(command) => {
+                let mut branches = VecDeque::from(command.branches);
+                if let Some(IfBranch { condition, body }) = branches.pop_front() {
+                    let branch = Control::Branch {
+                        body,
+                        branches,
+                        otherwise: command.otherwise,
+                    };
+                    schedule_condition(pending, condition, branch, &scope);
+                }
+            }
+            Command::Loop(command) => {
+                let check = Control::Loop {
+                    until: command.until,
+                    condition: command.condition.clone(),
+                    body: command.body,
+                };
+                schedule_condition(pending, command.condition, check, &scope);
+            }
+            Command::For(command) => {
+                let words: Vec<Token> = command.words.into_iter().map(Token::from).collect();
+                let next = Control::For {
+                    name: command.name,
+                    values: expander.expand_fields(&words).await?.into(),
+                    body: command.body,
+                    ran: false,
+                };
+                schedule(pending, List::default(), Some(next), &scope);
             }
+            Command::Case(command) => {
+                let subject = expander
+                    .expand_single(&Token::from(command.subject))
+                    .await?;
+                for arm in command.arms {
+                    for pattern in arm.patterns {
+                        let pattern = expander.expand_pattern(&Token::from(pattern)).await?;
+                        if glob::matches(&pattern, &subject) {
+                            schedule(pending, arm.body, None, &scope);
+                            return Ok(None);
+                        }
+                    }
+                }
+                return Ok(Some(Step::Status(ExitStatus::SUCCESS)));
+            }
+            // `flatten` queues these as plain entries, never as compounds.
+            Command::Simple(_) | Command::Function(_) => {}
         }
+        Ok(None)
+    }
 
-        let body = self.collect_function_body(&header.name, header.position, pending)?;
-        self.runtime_functions
-            .borrow_mut()
-            .insert(header.name.clone(), RuntimeFunction { commands: body });
+    /// Continue a running compound command now that the commands before `control` ran.
+    fn resume_compound<T>(
+        &self,
+        control: Control,
+        scope: Scope,
+        pending: &mut VecDeque<Pending>,
+    ) -> Option<Step<T>> {
+        match control {
+            Control::Branch {
+                body,
+                mut branches,
+                otherwise,
+            } => {
+                if self.last_command_succeeded() {
+                    schedule(pending, body, None, &scope);
+                } else if let Some(IfBranch { condition, body }) = branches.pop_front() {
+                    let branch = Control::Branch {
+                        body,
+                        branches,
+                        otherwise,
+                    };
+                    schedule_condition(pending, condition, branch, &scope);
+                } else if let Some(otherwise) = otherwise {
+                    schedule(pending, otherwise, None, &scope);
+                } else {
+                    return Some(Step::Status(ExitStatus::SUCCESS));
+                }
+            }
+            Control::Loop {
+                until,
+                condition,
+                body,
+            } => {
+                if self.last_command_succeeded() == until {
+                    return Some(Step::Status(ExitStatus::SUCCESS));
+                }
+                let check = Control::Loop {
+                    until,
+                    condition: condition.clone(),
+                    body: body.clone(),
+                };
+                schedule_condition(pending, condition, check, &scope);
+                schedule(pending, body, None, &scope);
+            }
+            Control::For {
+                name,
+                mut values,
+                body,
+                ran,
+            } => {
+                let Some(value) = values.pop_front() else {
+                    return (!ran).then_some(Step::Status(ExitStatus::SUCCESS));
+                };
+                self.assign_variable(&name, &value);
+                let next = Control::For {
+                    name,
+                    values,
+                    body: body.clone(),
+                    ran: true,
+                };
+                schedule(pending, body, Some(next), &scope);
+            }
+            Control::End(separator) => return Some(Step::End(separator)),
+        }
+        None
+    }
 
-        Ok(true)
+    /// Whether `$?` reports success; conditions are read from it.
+    fn last_command_succeeded(&self) -> bool {
+        self.variables
+            .borrow()
+            .get("?")
+            .is_none_or(|code| code == "0")
     }
 
-    fn parse_function_header(
+    /// Apply a command made only of `NAME=value` words, returning whether it was one.
+    async fn try_apply_assignments<E>(
         &self,
         tokens: &CommandTokens,
-    ) -> Result<Option<FunctionHeader>, ShellParseError> {
-        if tokens.tokens.is_empty() {
-            return Ok(None);
-        }
-
-        let first = &tokens.tokens[0];
-        if first.value == "function" {
-            if tokens.tokens.len() < 2 {
-                return Err(ShellParseError::InvalidFunction {
-                    name: String::new(),
-                    message: "function name missing".into(),
-                    position: first.position,
-                });
-            }
-            let Some(name) = normalize_function_name(&tokens.tokens[1].value) else {
-                return Err(ShellParseError::InvalidFunction {
-                    name: tokens.tokens[1].value.clone(),
-                    message: "invalid function name".into(),
-                    position: tokens.tokens[1].position,
-                });
-            };
-            let brace_inline = validate_inline_brace(tokens, &name)?;
-            return Ok(Some(FunctionHeader {
-                name,
-                position: first.position,
-                brace_inline,
-            }));
+        expander: &mut Expander<'_, '_, E>,
+    ) -> Result<bool, E>
+    where
+        E: From<ShellParseError>,
+    {
+        if !tokens.tokens.iter().all(is_assignment_token) {
+            return Ok(false);
         }
 
-        if let Some(name) = normalize_bare_function_name(&first.value) {
-            let brace_inline = validate_inline_brace(tokens, &name)?;
-            return Ok(Some(FunctionHeader {
-                name,
-                position: first.position,
-                brace_inline,
-            }));
+        for token in &tokens.tokens {
+            let word = expander.expand_single(token).await?;
+            if let Some((name, value)) = word.split_once('=') {
+                self.define_variable(name, value, token.span())?;
+            }
         }
-
-        Ok(None)
+        Ok(true)
     }
 
-    fn collect_function_body(
+    /// Apply what a builtin such as `alias`, `export` or `local` changes in the
+    /// parser and return its expanded words.
+    async fn apply_builtin<E>(
         &self,
-        name: &str,
-        position: usize,
-        pending: &mut VecDeque<PendingCommand>,
-    ) -> Result<Vec<CommandTokens>, ShellParseError> {
-        let mut body = Vec::new();
-        let mut depth = 1;
-
-        while let Some(command) = pending.pop_front() {
-            if command.tokens.tokens.is_empty() {
-                continue;
+        builtin: &str,
+        tokens: &CommandTokens,
+        expander: &mut Expander<'_, '_, E>,
+    ) -> Result<Vec<String>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        match builtin {
+            "set" => return self.set(tokens, expander).await,
+            "shift" => return self.shift(tokens, expander).await,
+            _ => {}
+        }
+        let frame = self.frame.borrow().clone();
+        if builtin == "local" && frame.is_none() {
+            return Err(ShellParseError::InvalidControlFlow {
+                keyword: builtin.into(),
+                message: "can only be used inside a function".into(),
+                position: tokens.tokens[0].position,
+                end: tokens.tokens[0].end,
             }
+            .into());
+        }
 
-            if is_brace_command(&command.tokens.tokens, "}") {
-                depth -= 1;
-                if depth == 0 {
-                    return Ok(body);
+        let mut words = vec![builtin.to_string()];
+        for token in tokens.tokens.iter().skip(1) {
+            let word = expander.expand_single(token).await?;
+            match builtin {
+                "alias" => {
+                    if let Some((name, value)) = word.split_once('=') {
+                        self.define_alias(name, value, token.span())?;
+                    }
                 }
-                body.push(command.tokens);
-                continue;
+                "export" => match word.split_once('=') {
+                    Some((name, value)) => self.define_variable(name, value, token.span())?,
+                    None => validate_variable_name(&word, token.span())?,
+                },
+                "local" => {
+                    let (name, value) = match word.split_once('=') {
+                        Some((name, value)) => (name, Some(value)),
+                        None => (word.as_str(), None),
+                    };
+                    validate_variable_name(name, token.span())?;
+                    if let Some(frame) = &frame {
+                        frame.declare_local(name);
+                    }
+                    if let Some(value) = value {
+                        self.assign_variable(name, value);
+                    }
+                }
+                "unalias" if word == "-a" => self.runtime_aliases.borrow_mut().clear(),
+                "unalias" if !self.remove_alias(&word) => {
+                    return Err(ShellParseError::InvalidAlias {
+                        name: word,
+                        message: "no such alias".into(),
+                        position: token.position,
+                        end: token.end,
+                    }
+                    .into());
+                }
+                "unset" => {
+                    validate_variable_name(&word, token.span())?;
+                    variables::remove(&self.variables, frame.as_deref(), &word);
+                }
+                // `declare`, `type` and `which` only report and the job builtins
+                // only touch jobs; the runner handles them.
+                _ => {}
             }
+            words.push(word);
+        }
+        Ok(words)
+    }
 
-            let openings = count_open_braces(&command.tokens.tokens);
-            if openings > 0 {
-                depth += openings;
-            }
+    fn define_variable(&self, name: &str, value: &str, span: Span) -> Result<(), ShellParseError> {
+        validate_variable_name(name, span)?;
+        self.assign_variable(name, value);
+        Ok(())
+    }
+
+    /// Assign `name`, or the local of that name in the active call.
+    fn assign_variable(&self, name: &str, value: &str) {
+        variables::assign(&self.variables, self.frame.borrow().as_deref(), name, value);
+    }
 
-            body.push(command.tokens);
+    /// Apply the options given to `set`; see [`ShellOptions::apply`].
+    async fn set<E>(
+        &self,
+        tokens: &CommandTokens,
+        expander: &mut Expander<'_, '_, E>,
+    ) -> Result<Vec<String>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        let mut words = vec!["set".to_string()];
+        for token in tokens.tokens.iter().skip(1) {
+            words.push(expander.expand_single(token).await?);
         }
+        let mut options = self.options();
+        options
+            .apply(&words[1..])
+            .map_err(|message| ShellParseError::InvalidControlFlow {
+                keyword: "set".into(),
+                message,
+                position: tokens.tokens[0].position,
+                end: tokens.tokens[0].end,
+            })?;
+        self.set_options(options);
+        Ok(words)
+    }
 
-        Err(ShellParseError::InvalidFunction {
-            name: name.to_string(),
-            message: "missing closing '}' for function body".into(),
-            position,
-        })
+    /// Run `shift [n]`, dropping the first `n` (default 1) positional parameters.
+    async fn shift<E>(
+        &self,
+        tokens: &CommandTokens,
+        expander: &mut Expander<'_, '_, E>,
+    ) -> Result<Vec<String>, E>
+    where
+        E: From<ShellParseError>,
+    {
+        let mut words = vec!["shift".to_string()];
+        for token in tokens.tokens.iter().skip(1) {
+            words.push(expander.expand_single(token).await?);
+        }
+        let invalid = |message: String| ShellParseError::InvalidControlFlow {
+            keyword: "shift".into(),
+            message,
+            position: tokens.tokens[0].position,
+            end: tokens.tokens[0].end,
+        };
+        let count = match words.as_slice() {
+            [_] => 1,
+            [_, count] => count
+                .parse::<usize>()
+                .map_err(|_| invalid(format!("'{count}' is not a number")))?,
+            _ => return Err(invalid("too many arguments".into()).into()),
+        };
+        let frame = self.frame.borrow().clone();
+        if !frame.as_ref().is_some_and(|frame| frame.shift(count)) {
+            return Err(invalid(format!(
+                "cannot shift {count}: too few positional parameters"
+            ))
+            .into());
+        }
+        Ok(words)
     }
 
-    fn try_expand_runtime_function(
+    /// Queue the body of the function `pending` calls, if it names one.
+    ///
+    /// `piped` is set when the call reads the output of the command before it.
+    async fn try_expand_runtime_function<E>(
         &self,
         pending: &PendingCommand,
-    ) -> Result<Option<Vec<PendingCommand>>, ShellParseError> {
+        piped: bool,
+        expander: &mut Expander<'_, '_, E>,
+    ) -> Result<Option<Vec<Pending>>, E>
+    where
+        E: From<ShellParseError>,
+    {
         let Some(name_token) = pending.tokens.tokens.first() else {
             return Ok(None);
         };
@@ -438,30 +1448,64 @@ impl ShellParser {
             return Ok(None);
         };
 
-        if pending.stack.contains(&name_token.value) {
+        if pending.scope.stack.contains(&name_token.value) {
             return Err(ShellParseError::AliasLoop {
                 name: name_token.value.clone(),
                 position: name_token.position,
-            });
+                end: name_token.end,
+            }
+            .into());
         }
 
-        let args: Vec<String> = pending.tokens.tokens[1..].iter().map(token_value).collect();
-        let mut stack = pending.stack.clone();
-        stack.push(name_token.value.clone());
+        if let Some(token) = pending
+            .tokens
+            .tokens
+            .iter()
+            .find(|token| token.redirect.is_some())
+        {
+            return Err(ShellParseError::InvalidRedirect {
+                message: format!("cannot redirect function '{}'", name_token.value),
+                position: token.position,
+                end: token.end,
+            }
+            .into());
+        }
 
-        let mut expanded: Vec<PendingCommand> = Vec::new();
-        for command in runtime_function.commands.iter() {
-            let tokens = expand_function_tokens(&command.tokens, &args, name_token.position);
-            if tokens.is_empty() {
-                continue;
+        // The body runs as separate commands, so only one of them would be
+        // part of the pipeline.
+        if piped || pending.tokens.separator == Some(Separator::Pipe) {
+            return Err(ShellParseError::InvalidFunction {
+                name: name_token.value.clone(),
+                message: "functions cannot be piped".into(),
+                position: name_token.position,
+                end: name_token.end,
             }
-            expanded.push(PendingCommand {
-                tokens: CommandTokens {
-                    tokens,
-                    separator: command.separator,
-                },
-                stack: stack.clone(),
-            });
+            .into());
+        }
+
+        if pending.tokens.separator == Some(Separator::Background) {
+            return Err(ShellParseError::InvalidFunction {
+                name: name_token.value.clone(),
+                message: "functions cannot run in the background".into(),
+                position: name_token.position,
+                end: name_token.end,
+            }
+            .into());
+        }
+
+        // Arguments are expanded by the caller; the body sees them as `$1`, `$2`, ...
+        let args = expander.expand_fields(&pending.tokens.tokens[1..]).await?;
+        let frame = CallFrame::new(args, pending.scope.frame.clone());
+        let scope = pending.scope.enter(&name_token.value, Some(Rc::new(frame)));
+
+        let mut body = runtime_function.body;
+        let mut call = CallSite {
+            span: name_token.span(),
+        };
+        call.visit_list_mut(&mut body);
+        let mut expanded = flatten(body, &scope);
+        if let Some(last) = expanded.last_mut() {
+            set_separator(last, pending.tokens.separator);
         }
 
         Ok(Some(expanded))
@@ -470,7 +1514,7 @@ impl ShellParser {
     fn try_expand_runtime_alias(
         &self,
         pending: &PendingCommand,
-    ) -> Result<Option<Vec<PendingCommand>>, ShellParseError> {
+    ) -> Result<Option<Vec<Pending>>, ShellParseError> {
         let Some(name_token) = pending.tokens.tokens.first() else {
             return Ok(None);
         };
@@ -479,36 +1523,44 @@ impl ShellParser {
             return Ok(None);
         }
 
-        if pending.stack.contains(&name_token.value) {
+        if pending.scope.stack.contains(&name_token.value) {
             return Err(ShellParseError::AliasLoop {
                 name: name_token.value.clone(),
                 position: name_token.position,
+                end: name_token.end,
             });
         }
 
-        let Some(commands) = self.expand_alias_commands(&name_token.value, &pending.tokens)? else {
+        let Some(body) = self.alias_body(&name_token.value, name_token.span())? else {
             return Ok(None);
         };
 
-        let mut stack = pending.stack.clone();
-        stack.push(name_token.value.clone());
-
-        let expanded = commands
-            .into_iter()
-            .map(|tokens| PendingCommand {
-                tokens,
-                stack: stack.clone(),
-            })
-            .collect();
+        let scope = pending
+            .scope
+            .enter(&name_token.value, pending.scope.frame.clone());
+        let mut expanded = flatten(body, &scope);
+        let args = &pending.tokens.tokens[1..];
+        match expanded.last_mut() {
+            Some(Pending::Command(last)) => {
+                last.tokens.tokens.extend(args.iter().cloned());
+                last.tokens.separator = pending.tokens.separator;
+            }
+            Some(last) if args.is_empty() => set_separator(last, pending.tokens.separator),
+            _ => {
+                return Err(ShellParseError::InvalidAlias {
+                    name: name_token.value.clone(),
+                    message: "arguments can only follow a simple command".into(),
+                    position: args[0].position,
+                    end: args[0].end,
+                })
+            }
+        }
 
         Ok(Some(expanded))
     }
 
-    fn expand_alias_commands(
-        &self,
-        name: &str,
-        original: &CommandTokens,
-    ) -> Result<Option<Vec<CommandTokens>>, ShellParseError> {
+    /// Parse the value of alias `name`, placing its words at `span`, where it was used.
+    fn alias_body(&self, name: &str, span: Span) -> Result<Option<List>, ShellParseError> {
         let alias_value = {
             let aliases = self.runtime_aliases.borrow();
             aliases.get(name).cloned()
@@ -523,57 +1575,27 @@ impl ShellParser {
             return Ok(None);
         }
 
-        let position = original.tokens[0].position;
-        let mut commands =
-            tokenize(&runtime_alias.value).map_err(|err| err.with_offset(position))?;
+        let mut body = syntax::parse_script(&runtime_alias.value)
+            .map_err(|err| err.with_offset(span.start))?
+            .body;
 
-        if commands.is_empty() {
+        if body.items.is_empty() {
             self.runtime_aliases.borrow_mut().remove(name);
             return Ok(None);
         }
 
-        for command in commands.iter_mut() {
-            for token in command.tokens.iter_mut() {
-                token.position = position;
-            }
-        }
-
-        if let Some(last) = commands.last_mut() {
-            last.tokens.extend(original.tokens.iter().skip(1).cloned());
-            last.separator = original.separator;
-        }
-
-        Ok(Some(commands))
+        let mut call = CallSite { span };
+        call.visit_list_mut(&mut body);
+        Ok(Some(body))
     }
 
-    fn is_alias_command(&self, tokens: &CommandTokens) -> bool {
-        tokens
-            .tokens
-            .first()
-            .map(|token| token.value == "alias")
-            .unwrap_or(false)
-    }
-
-    fn apply_alias_definitions(&self, tokens: &CommandTokens) -> Result<(), ShellParseError> {
-        for token in tokens.tokens.iter().skip(1) {
-            if let Some((name, value)) = token.value.split_once('=') {
-                self.define_alias(name, value, token.position)?;
-            }
-        }
-        Ok(())
-    }
-
-    fn define_alias(
-        &self,
-        name: &str,
-        value: &str,
-        token_position: usize,
-    ) -> Result<(), ShellParseError> {
+    fn define_alias(&self, name: &str, value: &str, span: Span) -> Result<(), ShellParseError> {
         if name.is_empty() {
             return Err(ShellParseError::InvalidAlias {
                 name: String::from(name),
                 message: "alias name cannot be empty".into(),
-                position: token_position,
+                position: span.start,
+                end: span.end,
             });
         }
 
@@ -592,117 +1614,98 @@ impl ShellParser {
     }
 }
 
-fn token_value(token: &Token) -> String {
-    token.value.clone()
-}
-
-fn normalize_function_name(value: &str) -> Option<String> {
-    let trimmed = value.trim();
-    if trimmed.is_empty() {
-        return None;
-    }
-    let name = trimmed.strip_suffix("()").unwrap_or(trimmed);
-    if name.is_empty() {
-        None
-    } else {
-        Some(name.to_string())
+/// Separate redirection operators and their targets from the command's words.
+///
+/// Targets are expanded like a double-quoted word, so `> $LOG` writes to the
+/// file named by `LOG` even if it contains spaces.
+async fn split_redirects<E>(
+    tokens: CommandTokens,
+    expander: &mut Expander<'_, '_, E>,
+) -> Result<(CommandTokens, Vec<Redirect>), E>
+where
+    E: From<ShellParseError>,
+{
+    if tokens.tokens.iter().all(|token| token.redirect.is_none()) {
+        return Ok((tokens, Vec::new()));
     }
-}
 
-fn normalize_bare_function_name(value: &str) -> Option<String> {
-    let trimmed = value.trim();
-    let name = trimmed.strip_suffix("()")?;
-    if name.is_empty() {
-        None
-    } else {
-        Some(name.to_string())
+    let mut words = Vec::new();
+    let mut redirects = Vec::new();
+    let mut iter = tokens.tokens.into_iter();
+    while let Some(token) = iter.next() {
+        let Some(operator) = token.redirect else {
+            words.push(token);
+            continue;
+        };
+        let path = if operator.takes_target() {
+            match iter.next() {
+                Some(target) if target.redirect.is_none() => {
+                    expander.expand_single(&target).await?
+                }
+                _ => {
+                    return Err(ShellParseError::InvalidRedirect {
+                        message: format!("expected a file name after '{}'", token.value),
+                        position: token.position,
+                        end: token.end,
+                    }
+                    .into())
+                }
+            }
+        } else {
+            String::new()
+        };
+        redirects.push(operator.into_redirect(path));
     }
+
+    Ok((
+        CommandTokens {
+            tokens: words,
+            separator: tokens.separator,
+        },
+        redirects,
+    ))
 }
 
-fn validate_inline_brace(tokens: &CommandTokens, name: &str) -> Result<bool, ShellParseError> {
-    if let Some((idx, brace)) = tokens
-        .tokens
-        .iter()
-        .enumerate()
-        .find(|(_, token)| token.value == "{")
-    {
-        if idx != tokens.tokens.len() - 1 {
-            return Err(ShellParseError::InvalidFunction {
-                name: name.to_string(),
-                message: "opening '{' must end the line for functions".into(),
-                position: brace.position,
-            });
-        }
-        return Ok(true);
-    }
-    Ok(false)
+/// Substitution output that a synchronous callback has already produced.
+fn ready<'a, E: 'a>(output: Result<String, E>) -> Substitution<'a, E> {
+    Box::pin(std::future::ready(output))
 }
 
-fn is_brace_command(tokens: &[Token], brace: &str) -> bool {
-    tokens.len() == 1 && tokens[0].value == brace
+fn builtin_name(tokens: &CommandTokens) -> Option<&'static str> {
+    let first = tokens.tokens.first()?;
+    PARSER_BUILTINS
+        .iter()
+        .find(|builtin| first.value == **builtin)
+        .copied()
 }
 
-fn count_open_braces(tokens: &[Token]) -> usize {
-    tokens.iter().filter(|token| token.value == "{").count()
+fn is_assignment_token(token: &Token) -> bool {
+    let Some(WordPart::Literal(text)) = token.parts.first() else {
+        return false;
+    };
+    text.split_once('=')
+        .map(|(name, _)| Variables::is_valid_name(name))
+        .unwrap_or(false)
 }
 
-fn expand_function_tokens(tokens: &[Token], args: &[String], position: usize) -> Vec<Token> {
-    let mut expanded: Vec<Token> = Vec::new();
-    for token in tokens {
-        match token.value.as_str() {
-            "$@" => {
-                for arg in args {
-                    expanded.push(Token {
-                        value: arg.clone(),
-                        position,
-                    });
-                }
-            }
-            "$*" => {
-                if !args.is_empty() {
-                    expanded.push(Token {
-                        value: args.join(" "),
-                        position,
-                    });
-                }
-            }
-            "$#" => expanded.push(Token {
-                value: args.len().to_string(),
-                position,
-            }),
-            value => {
-                if let Some(idx) = positional_index(value) {
-                    if let Some(arg) = args.get(idx - 1) {
-                        if !arg.is_empty() {
-                            expanded.push(Token {
-                                value: arg.clone(),
-                                position,
-                            });
-                        }
-                    }
-                } else {
-                    let mut cloned = token.clone();
-                    cloned.position = position;
-                    expanded.push(cloned);
-                }
-            }
-        }
+fn validate_variable_name(name: &str, span: Span) -> Result<(), ShellParseError> {
+    if Variables::is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(ShellParseError::InvalidVariable {
+            name: name.to_string(),
+            message: "not a valid identifier".into(),
+            position: span.start,
+            end: span.end,
+        })
     }
-    expanded
 }
 
-fn positional_index(value: &str) -> Option<usize> {
-    if !value.starts_with('$') {
-        return None;
-    }
-    let digits = &value[1..];
-    if digits.is_empty() || digits.chars().any(|ch| !ch.is_ascii_digit()) {
-        return None;
-    }
-    let idx = digits.parse::<usize>().ok()?;
-    if idx == 0 {
-        None
-    } else {
-        Some(idx)
+/// Replace the separator written after a queued entry.
+fn set_separator(entry: &mut Pending, separator: Option<Separator>) {
+    match entry {
+        Pending::Command(command) => command.tokens.separator = separator,
+        Pending::Compound(_, current, _) | Pending::Function(_, current) => *current = separator,
+        Pending::Control(..) => {}
     }
 }
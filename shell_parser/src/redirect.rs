@@ -0,0 +1,131 @@
+use std::fmt;
+
+use crate::tokenizer::Token;
+
+/// File descriptor of standard input.
+pub const STDIN: u32 = 0;
+/// File descriptor of standard output.
+pub const STDOUT: u32 = 1;
+/// File descriptor of standard error.
+pub const STDERR: u32 = 2;
+
+/// Redirection attached to a command, such as `> out.log` or `2>&1`.
+///
+/// Redirections are listed in source order; later ones see the effect of
+/// earlier ones, so `> log 2>&1` sends both streams to `log` while
+/// `2>&1 > log` only sends stdout there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirect {
+    /// `[fd]> path`, or `[fd]>> path` when `append` is set.
+    Output { fd: u32, path: String, append: bool },
+    /// `< path`
+    Input { path: String },
+    /// `[fd]>&target`: `fd` writes wherever `target` currently does.
+    Duplicate { fd: u32, target: u32 },
+    /// `<<EOF` here-document, with `body` already expanded.
+    HereDoc { body: String },
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirect::Output { fd, path, append } => {
+                let operator = if *append { ">>" } else { ">" };
+                if *fd == STDOUT {
+                    write!(f, "{operator} {path}")
+                } else {
+                    write!(f, "{fd}{operator} {path}")
+                }
+            }
+            Redirect::Input { path } => write!(f, "< {path}"),
+            Redirect::Duplicate { fd, target } => write!(f, "{fd}>&{target}"),
+            Redirect::HereDoc { body } => write!(f, "<< {body:?}"),
+        }
+    }
+}
+
+/// Redirection operator as written in the source, before its target is expanded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectOperator {
+    /// `[fd]>` or `[fd]>>`.
+    Output { fd: u32, append: bool },
+    /// `[fd]<`.
+    Input { fd: u32 },
+    /// `[fd]>&target`.
+    Duplicate { fd: u32, target: u32 },
+    /// `[fd]<<`, or `[fd]<<-` when `strip_tabs` is set; the target word is the body.
+    HereDoc { fd: u32, strip_tabs: bool },
+}
+
+impl fmt::Display for RedirectOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedirectOperator::Output { fd, append } => {
+                write!(f, "{fd}{}", if *append { ">>" } else { ">" })
+            }
+            RedirectOperator::Input { fd } => write!(f, "{fd}<"),
+            RedirectOperator::Duplicate { fd, target } => write!(f, "{fd}>&{target}"),
+            RedirectOperator::HereDoc { fd, strip_tabs } => {
+                write!(f, "{fd}{}", if *strip_tabs { "<<-" } else { "<<" })
+            }
+        }
+    }
+}
+
+impl RedirectOperator {
+    /// Whether the operator is followed by a word naming a file.
+    pub(crate) fn takes_target(self) -> bool {
+        !matches!(self, RedirectOperator::Duplicate { .. })
+    }
+
+    /// Build the redirect once the target word (if any) has been expanded.
+    ///
+    /// For a here-document `path` is the expanded body.
+    pub(crate) fn into_redirect(self, path: String) -> Redirect {
+        match self {
+            RedirectOperator::Output { fd, append } => Redirect::Output { fd, path, append },
+            RedirectOperator::Input { .. } => Redirect::Input { path },
+            RedirectOperator::Duplicate { fd, target } => Redirect::Duplicate { fd, target },
+            RedirectOperator::HereDoc { .. } => Redirect::HereDoc { body: path },
+        }
+    }
+}
+
+impl Redirect {
+    /// Tokens that parse back into this redirect without further expansion.
+    pub(crate) fn to_tokens(&self, position: usize) -> Vec<Token> {
+        match self {
+            Redirect::Output { fd, path, append } => vec![
+                Token::redirect(
+                    RedirectOperator::Output {
+                        fd: *fd,
+                        append: *append,
+                    },
+                    position,
+                ),
+                Token::literal(path.as_str(), position),
+            ],
+            Redirect::Input { path } => vec![
+                Token::redirect(RedirectOperator::Input { fd: STDIN }, position),
+                Token::literal(path.as_str(), position),
+            ],
+            Redirect::HereDoc { body } => vec![
+                Token::redirect(
+                    RedirectOperator::HereDoc {
+                        fd: STDIN,
+                        strip_tabs: false,
+                    },
+                    position,
+                ),
+                Token::literal(body.as_str(), position),
+            ],
+            Redirect::Duplicate { fd, target } => vec![Token::redirect(
+                RedirectOperator::Duplicate {
+                    fd: *fd,
+                    target: *target,
+                },
+                position,
+            )],
+        }
+    }
+}
@@ -4,5 +4,10 @@ pub enum Separator {
     Newline,
     Semicolon,
     And,
+    Or,
     Pipe,
+    /// `&`, running the pipeline before it in the background.
+    Background,
+    /// `;;`, ending an arm of a `case` command.
+    DoubleSemicolon,
 }
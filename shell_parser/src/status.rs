@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// Exit status reported by a command; `0` means success.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ExitStatus(i32);
+
+impl ExitStatus {
+    pub const SUCCESS: Self = Self(0);
+    pub const FAILURE: Self = Self(1);
+
+    /// Wrap a numeric status code.
+    pub const fn new(code: i32) -> Self {
+        Self(code)
+    }
+
+    /// Numeric code, as exposed through `$?`.
+    pub const fn code(self) -> i32 {
+        self.0
+    }
+
+    /// Whether the status is zero.
+    pub const fn success(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<i32> for ExitStatus {
+    fn from(code: i32) -> Self {
+        Self(code)
+    }
+}
+
+impl From<bool> for ExitStatus {
+    fn from(success: bool) -> Self {
+        if success {
+            Self::SUCCESS
+        } else {
+            Self::FAILURE
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
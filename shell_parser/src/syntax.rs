@@ -0,0 +1,855 @@
+//! Building a [`Script`] tree from tokenized commands.
+//!
+//! The tokenizer knows nothing about reserved words or function bodies, so
+//! `if true; then echo; fi` arrives as three commands. This module regroups
+//! them into compound commands, function definitions, pipelines and lists.
+
+use crate::ast::{
+    CaseArm, CaseCommand, Command, ForCommand, FunctionDefinition, IfBranch, IfCommand, List,
+    ListItem, LoopCommand, Pipeline, Redirect, Script, SimpleCommand, Span, Word,
+};
+use crate::error::ShellParseError;
+use crate::redirect::{RedirectOperator, STDERR, STDIN, STDOUT};
+use crate::separator::Separator;
+use crate::tokenizer::{tokenize, CommandTokens, Token};
+use crate::variables::Variables;
+use crate::word::WordPart;
+
+/// Reserved words that start a compound command.
+const OPENERS: &[&str] = &["if", "while", "until", "for", "case"];
+/// Reserved words that end or continue a compound command.
+const CLOSERS: &[&str] = &["then", "elif", "else", "fi", "do", "done", "esac"];
+
+/// Tokenize `input` and group its commands into a tree.
+///
+/// After a syntax error the reader skips to the end of the enclosing
+/// top-level command and carries on, so every error in the script is reported.
+pub(crate) fn parse_script(input: &str) -> Result<Script, ShellParseError> {
+    let mut reader = Reader {
+        commands: tokenize(input)?.into_iter(),
+        carry: None,
+        eof: input.len(),
+        depth: 0,
+        at_line_end: true,
+    };
+    let mut errors = Vec::new();
+    let body = loop {
+        match read_list(&mut reader, &[], false) {
+            Ok((body, end)) => {
+                debug_assert!(matches!(end, ListEnd::Eof));
+                break body;
+            }
+            Err(error) => {
+                errors.push(error);
+                reader.recover();
+            }
+        }
+    };
+    if !errors.is_empty() {
+        return Err(ShellParseError::from_errors(errors));
+    }
+    Ok(Script {
+        body,
+        span: Span::new(0, input.len()),
+    })
+}
+
+/// Reserved word starting `tokens`, if any.
+///
+/// Only an unquoted word counts, so `"if"` can still be passed as an argument
+/// or name a command.
+fn keyword(tokens: &CommandTokens) -> Option<&str> {
+    let first = tokens.tokens.first()?;
+    let plain = matches!(first.parts.as_slice(), [WordPart::Literal(text)] if *text == first.value);
+    let reserved = OPENERS
+        .iter()
+        .chain(CLOSERS)
+        .any(|word| *word == first.value);
+    (plain && reserved).then_some(first.value.as_str())
+}
+
+fn invalid(keyword: &str, message: impl Into<String>, span: Span) -> ShellParseError {
+    ShellParseError::InvalidControlFlow {
+        keyword: keyword.to_string(),
+        message: message.into(),
+        position: span.start,
+        end: span.end,
+    }
+}
+
+/// Commands still to be read, plus the rest of a command whose keyword was consumed.
+struct Reader {
+    commands: std::vec::IntoIter<CommandTokens>,
+    carry: Option<CommandTokens>,
+    /// Length of the input, where an unterminated list ends.
+    eof: usize,
+    /// Compound commands and function bodies opened by the commands read so far.
+    depth: usize,
+    /// Whether the last command read ended its line.
+    at_line_end: bool,
+}
+
+impl Reader {
+    fn next(&mut self) -> Option<CommandTokens> {
+        let command = match self.carry.take() {
+            Some(rest) => rest,
+            None => {
+                let command = self.commands.next()?;
+                // A function body opens with `{` on the header line or the next
+                // one; the words after it come back as carry.
+                let opens_body = command.tokens.first().is_some_and(is_open_brace)
+                    || (is_function_header(&command) && command.tokens.iter().any(is_open_brace));
+                if opens_body {
+                    self.depth += 1;
+                }
+                command
+            }
+        };
+        match keyword(&command) {
+            Some(word) if OPENERS.contains(&word) => self.depth += 1,
+            Some("fi" | "done" | "esac") => self.depth = self.depth.saturating_sub(1),
+            _ if is_brace_command(&command.tokens, "}") => {
+                self.depth = self.depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+        self.at_line_end = matches!(command.separator, None | Some(Separator::Newline));
+        Some(command)
+    }
+
+    /// Skip to the end of the top-level command a syntax error was found in.
+    fn recover(&mut self) {
+        if let Some(rest) = self.carry.take() {
+            self.at_line_end = matches!(rest.separator, None | Some(Separator::Newline));
+        }
+        while self.depth > 0 || !self.at_line_end {
+            if self.next().is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Keep the words after a consumed keyword as the next command.
+    fn push_rest(&mut self, rest: CommandTokens) {
+        if !rest.tokens.is_empty() {
+            self.carry = Some(rest);
+        }
+    }
+}
+
+/// Where [`read_list`] stopped.
+enum ListEnd {
+    Eof,
+    /// One of the terminators, and the rest of the command it started.
+    Keyword(Token, CommandTokens),
+    /// `;;` ended a `case` arm.
+    ArmEnd,
+}
+
+/// Expect `end` to be a terminator; running out of input at `eof` means
+/// `missing` was never written, and the error covers the whole unclosed block.
+fn expect_keyword(
+    end: ListEnd,
+    opener: &Token,
+    missing: &str,
+    eof: usize,
+) -> Result<(Token, CommandTokens), ShellParseError> {
+    match end {
+        ListEnd::Keyword(keyword, rest) => Ok((keyword, rest)),
+        _ => Err(invalid(
+            &opener.value,
+            format!("missing '{missing}'"),
+            Span::new(opener.position, eof),
+        )),
+    }
+}
+
+/// Split the leading keyword off `command`, keeping the rest with its separator.
+fn split_keyword(mut command: CommandTokens) -> (Token, CommandTokens) {
+    let keyword = command.tokens.remove(0);
+    (keyword, command)
+}
+
+/// Keyword that ends the list being read, if `command` starts with one.
+///
+/// `}` only counts inside a function body, where it is one of `terminators`.
+fn closer<'a>(command: &'a CommandTokens, terminators: &[&str]) -> Option<&'a str> {
+    if terminators.contains(&"}") && is_brace_command(&command.tokens, "}") {
+        return Some("}");
+    }
+    keyword(command).filter(|word| CLOSERS.contains(word))
+}
+
+/// Read pipelines until a command starts with one of `terminators`.
+///
+/// A `;;` ends the list inside a `case` arm and separates commands like `;`
+/// anywhere else.
+fn read_list(
+    reader: &mut Reader,
+    terminators: &[&str],
+    case_arm: bool,
+) -> Result<(List, ListEnd), ShellParseError> {
+    let mut items = Vec::new();
+    let mut pipeline = Vec::new();
+
+    let end = loop {
+        let Some(command) = reader.next() else {
+            break ListEnd::Eof;
+        };
+        // A `;;` of its own, as in `cmd & ;;`.
+        if command.tokens.is_empty() && pipeline.is_empty() {
+            if case_arm && command.separator == Some(Separator::DoubleSemicolon) {
+                break ListEnd::ArmEnd;
+            }
+            continue;
+        }
+        if let Some(word) = closer(&command, terminators) {
+            let span = command.tokens[0].span();
+            if !terminators.contains(&word) {
+                let message = if terminators.is_empty() {
+                    "unexpected keyword".to_string()
+                } else {
+                    format!("expected '{}'", terminators.join("', '"))
+                };
+                return Err(invalid(word, message, span));
+            }
+            if !pipeline.is_empty() {
+                return Err(invalid(word, "expected a command after '|'", span));
+            }
+            let (keyword, rest) = split_keyword(command);
+            break ListEnd::Keyword(keyword, rest);
+        }
+
+        let (command, separator) = read_command(command, reader)?;
+        pipeline.push(command);
+        match separator {
+            Some(Separator::Pipe) => {}
+            Some(Separator::DoubleSemicolon) => {
+                items.push(list_item(
+                    std::mem::take(&mut pipeline),
+                    Some(Separator::Semicolon),
+                )?);
+                if case_arm {
+                    break ListEnd::ArmEnd;
+                }
+            }
+            separator => items.push(list_item(std::mem::take(&mut pipeline), separator)?),
+        }
+    };
+    if !pipeline.is_empty() {
+        // The input ended right after `|`.
+        items.push(list_item(pipeline, Some(Separator::Pipe))?);
+    }
+
+    let span = match (items.first(), items.last()) {
+        (Some(first), Some(last)) => first.pipeline.span.to(last.pipeline.span),
+        _ => {
+            let at = match &end {
+                ListEnd::Keyword(keyword, _) => keyword.position,
+                _ => reader.eof,
+            };
+            Span::new(at, at)
+        }
+    };
+    Ok((List { items, span }, end))
+}
+
+/// Close a pipeline; compound commands and definitions cannot be piped or
+/// run in the background yet.
+fn list_item(
+    commands: Vec<Command>,
+    separator: Option<Separator>,
+) -> Result<ListItem, ShellParseError> {
+    if separator == Some(Separator::Background) {
+        match commands
+            .iter()
+            .find(|command| !matches!(command, Command::Simple(_)))
+        {
+            Some(Command::Function(definition)) => {
+                return Err(ShellParseError::InvalidFunction {
+                    name: definition.name.clone(),
+                    message: "function definitions cannot run in the background".into(),
+                    position: definition.span.start,
+                    end: definition.span.end,
+                })
+            }
+            Some(command) => {
+                return Err(invalid(
+                    command.keyword().unwrap_or_default(),
+                    "running a compound command in the background is not supported",
+                    command.span(),
+                ))
+            }
+            None => {}
+        }
+    }
+    if commands.len() > 1 {
+        for (idx, command) in commands.iter().enumerate() {
+            let span = command.span();
+            match command {
+                Command::Simple(_) => {}
+                Command::Function(definition) => {
+                    return Err(ShellParseError::InvalidFunction {
+                        name: definition.name.clone(),
+                        message: "function definitions cannot be piped".into(),
+                        position: span.start,
+                        end: span.end,
+                    })
+                }
+                _ if idx > 0 => {
+                    return Err(invalid(
+                        "|",
+                        "piping into a compound command is not supported",
+                        span,
+                    ))
+                }
+                _ => {
+                    return Err(invalid(
+                        command.keyword().unwrap_or_default(),
+                        "piping the output of a compound command is not supported",
+                        span,
+                    ))
+                }
+            }
+        }
+    }
+
+    let span = match (commands.first(), commands.last()) {
+        (Some(first), Some(last)) => first.span().to(last.span()),
+        _ => Span::default(),
+    };
+    Ok(ListItem {
+        pipeline: Pipeline { commands, span },
+        separator,
+    })
+}
+
+/// Read the command `first` starts, returning it with the separator written after it.
+fn read_command(
+    first: CommandTokens,
+    reader: &mut Reader,
+) -> Result<(Command, Option<Separator>), ShellParseError> {
+    if keyword(&first).is_some_and(|word| OPENERS.contains(&word)) {
+        return read_compound(first, reader);
+    }
+    if let Some(header) = parse_function_header(&first)? {
+        return read_function(header, reader);
+    }
+    simple_command(first)
+}
+
+fn simple_command(command: CommandTokens) -> Result<(Command, Option<Separator>), ShellParseError> {
+    let span = match (command.tokens.first(), command.tokens.last()) {
+        (Some(first), Some(last)) => Span::new(first.position, last.end),
+        _ => Span::default(),
+    };
+    let mut words = Vec::new();
+    let mut redirects = Vec::new();
+    let separator = command.separator;
+    let mut tokens = command.tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        let Some(operator) = token.redirect else {
+            words.push(Word::from(token));
+            continue;
+        };
+        validate_redirect(operator, token.span())?;
+        let target = if operator.takes_target() {
+            match tokens.next() {
+                Some(target) if target.redirect.is_none() => Some(Word::from(target)),
+                _ => {
+                    return Err(ShellParseError::InvalidRedirect {
+                        message: format!("expected a file name after '{}'", token.value),
+                        position: token.position,
+                        end: token.end,
+                    })
+                }
+            }
+        } else {
+            None
+        };
+        let end = target.as_ref().map_or(token.end, |target| target.span.end);
+        redirects.push(Redirect {
+            operator,
+            target,
+            span: Span::new(token.position, end),
+        });
+    }
+
+    let simple = SimpleCommand {
+        words,
+        redirects,
+        span,
+    };
+    Ok((Command::Simple(simple), separator))
+}
+
+/// Reject descriptors other than stdin for `<` and `<<` and stdout/stderr for `>`.
+fn validate_redirect(operator: RedirectOperator, span: Span) -> Result<(), ShellParseError> {
+    let output_fd = |fd: u32| fd == STDOUT || fd == STDERR;
+    let bad_fd = match operator {
+        RedirectOperator::Input { fd } | RedirectOperator::HereDoc { fd, .. } if fd != STDIN => {
+            Some(fd)
+        }
+        RedirectOperator::Output { fd, .. } if !output_fd(fd) => Some(fd),
+        RedirectOperator::Duplicate { fd, .. } if !output_fd(fd) => Some(fd),
+        RedirectOperator::Duplicate { target, .. } if !output_fd(target) => Some(target),
+        _ => None,
+    };
+    match bad_fd {
+        Some(fd) => Err(ShellParseError::InvalidRedirect {
+            message: format!("bad file descriptor {fd}"),
+            position: span.start,
+            end: span.end,
+        }),
+        None => Ok(()),
+    }
+}
+
+struct FunctionHeader {
+    name: String,
+    /// Bytes of the header up to the function name.
+    span: Span,
+    /// Words after a `{` on the header line, which start the body; `None`
+    /// when the brace is on the next line.
+    body: Option<CommandTokens>,
+}
+
+fn parse_function_header(
+    tokens: &CommandTokens,
+) -> Result<Option<FunctionHeader>, ShellParseError> {
+    let Some(first) = tokens.tokens.first() else {
+        return Ok(None);
+    };
+
+    if first.value == "function" {
+        if tokens.tokens.len() < 2 {
+            return Err(ShellParseError::InvalidFunction {
+                name: String::new(),
+                message: "function name missing".into(),
+                position: first.position,
+                end: first.end,
+            });
+        }
+        let Some(name) = normalize_function_name(&tokens.tokens[1].value) else {
+            return Err(ShellParseError::InvalidFunction {
+                name: tokens.tokens[1].value.clone(),
+                message: "invalid function name".into(),
+                position: tokens.tokens[1].position,
+                end: tokens.tokens[1].end,
+            });
+        };
+        let body = header_body(tokens, 2, &name)?;
+        return Ok(Some(FunctionHeader {
+            name,
+            span: Span::new(first.position, tokens.tokens[1].end),
+            body,
+        }));
+    }
+
+    if let Some(name) = normalize_bare_function_name(&first.value) {
+        let body = header_body(tokens, 1, &name)?;
+        return Ok(Some(FunctionHeader {
+            name,
+            span: first.span(),
+            body,
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Read a function body, a brace group such as `{ echo hi; }` on one line
+/// or spread over several, up to its closing `}`.
+fn read_function(
+    header: FunctionHeader,
+    reader: &mut Reader,
+) -> Result<(Command, Option<Separator>), ShellParseError> {
+    let invalid_function = |message: &str, end: usize| ShellParseError::InvalidFunction {
+        name: header.name.clone(),
+        message: message.into(),
+        position: header.span.start,
+        end,
+    };
+
+    let rest = match header.body {
+        Some(rest) => rest,
+        None => match reader.next() {
+            Some(brace) if brace.tokens.first().is_some_and(is_open_brace) => {
+                split_keyword(brace).1
+            }
+            _ => {
+                return Err(invalid_function(
+                    "expected '{' after function header",
+                    header.span.end,
+                ))
+            }
+        },
+    };
+    reader.push_rest(rest);
+
+    let (body, end) = read_list(reader, &["}"], false)?;
+    let ListEnd::Keyword(brace, rest) = end else {
+        return Err(invalid_function(
+            "missing closing '}' for function body",
+            reader.eof,
+        ));
+    };
+    let definition = FunctionDefinition {
+        span: Span::new(header.span.start, brace.end),
+        name: header.name,
+        body,
+    };
+    Ok((Command::Function(definition), rest.separator))
+}
+
+fn normalize_function_name(value: &str) -> Option<String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let name = trimmed.strip_suffix("()").unwrap_or(trimmed);
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+fn normalize_bare_function_name(value: &str) -> Option<String> {
+    let trimmed = value.trim();
+    let name = trimmed.strip_suffix("()")?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Words after the `{` that follows the function name at `start`, or `None`
+/// when the header ends before the brace.
+fn header_body(
+    tokens: &CommandTokens,
+    start: usize,
+    name: &str,
+) -> Result<Option<CommandTokens>, ShellParseError> {
+    let Some(brace) = tokens.tokens.get(start) else {
+        return Ok(None);
+    };
+    if !is_open_brace(brace) {
+        return Err(ShellParseError::InvalidFunction {
+            name: name.to_string(),
+            message: "expected '{' after function header".into(),
+            position: brace.position,
+            end: brace.end,
+        });
+    }
+    Ok(Some(CommandTokens {
+        tokens: tokens.tokens[start + 1..].to_vec(),
+        separator: tokens.separator,
+    }))
+}
+
+/// Whether `command` starts like a function definition, before its name is checked.
+fn is_function_header(command: &CommandTokens) -> bool {
+    command
+        .tokens
+        .first()
+        .is_some_and(|first| first.value == "function" || first.value.ends_with("()"))
+}
+
+/// Unquoted `{`, which the tokenizer always splits into a token of its own.
+fn is_open_brace(token: &Token) -> bool {
+    token.value == "{" && token.end == token.position + 1
+}
+
+fn is_brace_command(tokens: &[Token], brace: &str) -> bool {
+    tokens.len() == 1 && tokens[0].value == brace
+}
+
+fn read_compound(
+    first: CommandTokens,
+    reader: &mut Reader,
+) -> Result<(Command, Option<Separator>), ShellParseError> {
+    let (opener, rest) = split_keyword(first);
+    let (command, end) = match opener.value.as_str() {
+        "if" => {
+            reader.push_rest(rest);
+            read_if(&opener, reader)?
+        }
+        "while" | "until" => {
+            reader.push_rest(rest);
+            read_loop(&opener, reader)?
+        }
+        "for" => read_for(&opener, rest, reader)?,
+        _ => read_case(&opener, rest, reader)?,
+    };
+
+    if let Some(token) = end.tokens.first() {
+        return Err(invalid(
+            &opener.value,
+            format!("unexpected '{}' after the closing keyword", token.value),
+            token.span(),
+        ));
+    }
+    Ok((command, end.separator))
+}
+
+fn read_if(
+    opener: &Token,
+    reader: &mut Reader,
+) -> Result<(Command, CommandTokens), ShellParseError> {
+    let mut branches = Vec::new();
+    let mut condition_keyword = opener.clone();
+    loop {
+        let condition = read_condition(&condition_keyword, "then", reader)?;
+        let (body, end) = read_list(reader, &["elif", "else", "fi"], false)?;
+        let (terminator, rest) = expect_keyword(end, opener, "fi", reader.eof)?;
+        branches.push(IfBranch { condition, body });
+        match terminator.value.as_str() {
+            "elif" => {
+                reader.push_rest(rest);
+                condition_keyword = terminator;
+            }
+            "else" => {
+                reader.push_rest(rest);
+                let (otherwise, end) = read_list(reader, &["fi"], false)?;
+                let (fi, rest) = expect_keyword(end, opener, "fi", reader.eof)?;
+                let command = IfCommand {
+                    branches,
+                    otherwise: Some(otherwise),
+                    span: Span::new(opener.position, fi.end),
+                };
+                return Ok((Command::If(command), rest));
+            }
+            _ => {
+                let command = IfCommand {
+                    branches,
+                    otherwise: None,
+                    span: Span::new(opener.position, terminator.end),
+                };
+                return Ok((Command::If(command), rest));
+            }
+        }
+    }
+}
+
+fn read_loop(
+    opener: &Token,
+    reader: &mut Reader,
+) -> Result<(Command, CommandTokens), ShellParseError> {
+    let condition = read_condition(opener, "do", reader)?;
+    let (body, end) = read_list(reader, &["done"], false)?;
+    let (done, rest) = expect_keyword(end, opener, "done", reader.eof)?;
+    let command = LoopCommand {
+        until: opener.value == "until",
+        condition,
+        body,
+        span: Span::new(opener.position, done.end),
+    };
+    Ok((Command::Loop(command), rest))
+}
+
+/// `for NAME [in WORDS...]`, then `do BODY done` after a `;` or newline.
+fn read_for(
+    opener: &Token,
+    header: CommandTokens,
+    reader: &mut Reader,
+) -> Result<(Command, CommandTokens), ShellParseError> {
+    let mut tokens = header.tokens.into_iter();
+    let Some(name) = tokens.next() else {
+        return Err(invalid("for", "expected a variable name", opener.span()));
+    };
+    if !Variables::is_valid_name(&name.value) {
+        return Err(invalid(
+            "for",
+            format!("'{}' is not a valid variable name", name.value),
+            name.span(),
+        ));
+    }
+    let words = match tokens.next() {
+        Some(token) if token.value == "in" => tokens.map(Word::from).collect(),
+        Some(token) => {
+            return Err(invalid(
+                "for",
+                format!("expected 'in', found '{}'", token.value),
+                token.span(),
+            ))
+        }
+        None => Vec::new(),
+    };
+
+    let command = reader.next();
+    let Some(command) = command.filter(|command| keyword(command) == Some("do")) else {
+        return Err(invalid("for", "expected 'do'", opener.span()));
+    };
+    let (_, rest) = split_keyword(command);
+    reader.push_rest(rest);
+    let (body, end) = read_list(reader, &["done"], false)?;
+    let (done, rest) = expect_keyword(end, opener, "done", reader.eof)?;
+    let command = ForCommand {
+        name: name.value,
+        words,
+        body,
+        span: Span::new(opener.position, done.end),
+    };
+    Ok((Command::For(command), rest))
+}
+
+/// `case WORD in PATTERN[|PATTERN]) BODY ;; ... esac`.
+fn read_case(
+    opener: &Token,
+    header: CommandTokens,
+    reader: &mut Reader,
+) -> Result<(Command, CommandTokens), ShellParseError> {
+    let mut tokens = header.tokens.into_iter();
+    let Some(subject) = tokens.next() else {
+        return Err(invalid("case", "expected a word to match", opener.span()));
+    };
+    if tokens.next().is_none_or(|token| token.value != "in") {
+        return Err(invalid("case", "expected 'in'", subject.span()));
+    }
+    reader.push_rest(CommandTokens {
+        tokens: tokens.collect(),
+        separator: header.separator,
+    });
+
+    let subject = Word::from(subject);
+    let mut arms = Vec::new();
+    loop {
+        let Some(command) = reader.next() else {
+            return Err(invalid(
+                "case",
+                "missing 'esac'",
+                Span::new(opener.position, reader.eof),
+            ));
+        };
+        let esac = if keyword(&command) == Some("esac") {
+            Some(split_keyword(command))
+        } else {
+            let (patterns, rest) = read_patterns(command, reader)?;
+            let at = patterns
+                .last()
+                .map_or(opener.end, |pattern| pattern.span.end);
+            let (body, esac) = read_case_body(opener, rest, at, reader)?;
+            arms.push(CaseArm { patterns, body });
+            esac
+        };
+        if let Some((esac, rest)) = esac {
+            let command = CaseCommand {
+                subject,
+                arms,
+                span: Span::new(opener.position, esac.end),
+            };
+            return Ok((Command::Case(command), rest));
+        }
+    }
+}
+
+/// Read `PATTERN|PATTERN)`; `|` separates tokenized commands, so patterns may span several.
+fn read_patterns(
+    mut command: CommandTokens,
+    reader: &mut Reader,
+) -> Result<(Vec<Word>, CommandTokens), ShellParseError> {
+    let mut patterns = Vec::new();
+    loop {
+        let span = match (command.tokens.first(), command.tokens.last()) {
+            (Some(first), Some(last)) => Span::new(first.position, last.end),
+            _ => Span::default(),
+        };
+        let mut tokens = command.tokens.into_iter();
+        while let Some(mut token) = tokens.next() {
+            if patterns.is_empty() {
+                strip_open_paren(&mut token);
+            }
+            let closed = strip_close_paren(&mut token);
+            if !token.parts.is_empty() {
+                patterns.push(Word::from(token));
+            }
+            if closed {
+                let rest = CommandTokens {
+                    tokens: tokens.collect(),
+                    separator: command.separator,
+                };
+                return Ok((patterns, rest));
+            }
+        }
+
+        let next = (command.separator == Some(Separator::Pipe))
+            .then(|| reader.next())
+            .flatten();
+        command = next.ok_or_else(|| invalid("case", "expected ')' after the pattern", span))?;
+    }
+}
+
+fn strip_open_paren(token: &mut Token) {
+    if let Some(WordPart::Literal(text)) = token.parts.first_mut() {
+        if text.starts_with('(') {
+            text.remove(0);
+            token.value.remove(0);
+            token.position += 1;
+            if text.is_empty() {
+                token.parts.remove(0);
+            }
+        }
+    }
+}
+
+fn strip_close_paren(token: &mut Token) -> bool {
+    let Some(WordPart::Literal(text)) = token.parts.last_mut() else {
+        return false;
+    };
+    if !text.ends_with(')') {
+        return false;
+    }
+    text.pop();
+    token.value.pop();
+    token.end -= 1;
+    if text.is_empty() {
+        token.parts.pop();
+    }
+    true
+}
+
+/// Read the commands of a `case` arm up to `;;`, or up to `esac` if that comes first.
+///
+/// An empty arm body is placed at `at`, the end of its patterns.
+fn read_case_body(
+    opener: &Token,
+    first: CommandTokens,
+    at: usize,
+    reader: &mut Reader,
+) -> Result<(List, Option<(Token, CommandTokens)>), ShellParseError> {
+    if first.tokens.is_empty() && first.separator == Some(Separator::DoubleSemicolon) {
+        let body = List {
+            items: Vec::new(),
+            span: Span::new(at, at),
+        };
+        return Ok((body, None));
+    }
+    reader.push_rest(first);
+
+    let (body, end) = read_list(reader, &["esac"], true)?;
+    match end {
+        ListEnd::ArmEnd => Ok((body, None)),
+        end => Ok((body, Some(expect_keyword(end, opener, "esac", reader.eof)?))),
+    }
+}
+
+/// Read a condition list ended by `terminator`; it must contain a command.
+fn read_condition(
+    keyword: &Token,
+    terminator: &str,
+    reader: &mut Reader,
+) -> Result<List, ShellParseError> {
+    let (condition, end) = read_list(reader, &[terminator], false)?;
+    let (found, rest) = expect_keyword(end, keyword, terminator, reader.eof)?;
+    if condition.items.is_empty() {
+        return Err(invalid(
+            &keyword.value,
+            format!("expected a command before '{terminator}'"),
+            found.span(),
+        ));
+    }
+    reader.push_rest(rest);
+    Ok(condition)
+}
@@ -1,5 +1,26 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::ast::{Command, RedirectOperator, Span, Visitor, Word, WordPart};
+use crate::fmt;
+use crate::fs::{FileSystem, MemoryFs};
+use crate::highlight::HighlightKind;
+use crate::integration::{
+    block_on, with_cli, CliRunner, CommandInfo, ExecutableCommand, ShellCliError,
+};
+use crate::io::CommandIo;
+use crate::limits::{LimitExceeded, Limits};
+use crate::middleware::{CommandRecord, Decision, Middleware, Outcome};
+use crate::options::ShellOptions;
+use crate::redirect::Redirect;
 use crate::separator::Separator;
-use crate::{CommandInvocation, CommandSpec, ShellParseError, ShellParser};
+use crate::{CommandInvocation, CommandSpec, ExitStatus, ShellParseError, ShellParser};
 
 fn command(name: &str, min: usize, max: Option<usize>) -> CommandSpec {
     CommandSpec {
@@ -8,6 +29,9 @@ fn command(name: &str, min: usize, max: Option<usize>) -> CommandSpec {
         min_args: min,
         max_args: max,
         aliases: Vec::new(),
+        options: Vec::new(),
+        parses_options: false,
+        value_options: Vec::new(),
     }
 }
 
@@ -23,7 +47,8 @@ fn parses_basic_commands() {
         CommandInvocation {
             name: "echo".into(),
             args: vec!["hello".into(), "world".into()],
-            position: 0
+            position: 0,
+            redirects: Vec::new(),
         }
     );
     assert_eq!(parsed[1].name, "run-task");
@@ -74,6 +99,32 @@ fn validates_registered_commands() {
             ..
         } if name == "echo"
     ));
+
+    // Every word counts unless the command parses its own options.
+    let parser = ShellParser::with_commands([command("cat", 1, Some(1))]);
+    let err = parser.parse("cat -- -x").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidArity { found: 2, .. }
+    ));
+
+    // Then options, their values and `--` are left out.
+    let cat = command("cat", 1, Some(1)).with_option_parsing(["-n", "--lines"]);
+    let parser = ShellParser::with_commands([cat]);
+    assert_eq!(parser.parse("cat -- -x").unwrap()[0].args, vec!["--", "-x"]);
+    parser
+        .parse("cat -v -n 3 a; cat --lines 3 -vn2 a; cat --lines=3 a; cat -h")
+        .unwrap();
+    let err = parser.parse("cat -- -x -y").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidArity { found: 2, .. }
+    ));
+    let err = parser.parse("cat -n 3").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidArity { found: 0, .. }
+    ));
 }
 
 #[test]
@@ -100,12 +151,17 @@ fn ignores_empty_commands_from_separators() {
 }
 
 #[test]
-fn parses_redirection_like_tokens_as_args() {
+fn parses_redirection_operators_out_of_args() {
     let parser = ShellParser::new();
-    let parsed = parser.parse(r#"echo "Hello" > ./foo.log"#).unwrap();
-    assert_eq!(parsed.len(), 1);
+    let parsed = parser
+        .parse(r#"echo "Hello" > ./foo.log; echo '>' "2>&1""#)
+        .unwrap();
+    assert_eq!(parsed.len(), 2);
     assert_eq!(parsed[0].name, "echo");
-    assert_eq!(parsed[0].args, vec!["Hello", ">", "./foo.log"]);
+    assert_eq!(parsed[0].args, vec!["Hello"]);
+    assert_eq!(parsed[0].redirects.len(), 1);
+    assert_eq!(parsed[1].args, vec![">", "2>&1"]);
+    assert!(parsed[1].redirects.is_empty());
 }
 
 #[test]
@@ -277,3 +333,1866 @@ fn parses_and_separator() {
     assert_eq!(parsed[1].separator, Some(Separator::And));
     assert_eq!(parsed[2].separator, None);
 }
+
+#[test]
+fn expands_variables_with_quoting_rules() {
+    let parser = ShellParser::new();
+    let script = r#"
+        export GREETING="hello world"
+        echo $GREETING "$GREETING" '$GREETING' \$GREETING
+    "#;
+
+    let parsed = parser.parse(script).unwrap();
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0].name, "export");
+    assert_eq!(
+        parsed[1].args,
+        vec!["hello", "world", "hello world", "$GREETING", "$GREETING"]
+    );
+}
+
+#[test]
+fn expands_parameter_operators() {
+    let parser = ShellParser::new();
+    let script = r#"
+        NAME=zzhack EMPTY=
+        echo ${NAME} ${#NAME} ${MISSING:-fallback} "${EMPTY:-empty}" ${EMPTY-set}
+        echo ${NAME:+alt} ${MISSING:+alt} ${CREATED:=made} $CREATED
+    "#;
+
+    let parsed = parser.parse(script).unwrap();
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0].args, vec!["zzhack", "6", "fallback", "empty"]);
+    assert_eq!(parsed[1].args, vec!["alt", "made", "made"]);
+}
+
+#[test]
+fn unset_and_empty_expansions_drop_unquoted_words() {
+    let parser = ShellParser::new();
+    parser.parse("export TARGET=posts").unwrap();
+    assert_eq!(parser.variable("TARGET").as_deref(), Some("posts"));
+
+    let parsed = parser
+        .parse("unset TARGET\nls $TARGET \"$TARGET\"")
+        .unwrap();
+    assert_eq!(parsed[1].name, "ls");
+    assert_eq!(parsed[1].args, vec![String::new()]);
+    assert_eq!(parser.variable("TARGET"), None);
+}
+
+#[test]
+fn variables_are_expanded_before_validation() {
+    let parser = ShellParser::with_commands([command("render", 1, Some(1))]);
+    let parsed = parser
+        .parse("export POST=\"posts/my post.md\"\nrender \"$POST\"")
+        .unwrap();
+    assert_eq!(parsed[1].args, vec!["posts/my post.md"]);
+
+    let err = parser.parse("render $POST").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidArity { found: 2, .. }
+    ));
+}
+
+#[test]
+fn rejects_bad_substitutions_and_names() {
+    let parser = ShellParser::new();
+    let err = parser.parse("echo ${NAME").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::BadSubstitution { position: 5, .. }
+    ));
+
+    let err = parser.parse("echo ${NAME%x}").unwrap_err();
+    assert!(matches!(err, ShellParseError::BadSubstitution { .. }));
+
+    let err = parser.parse("export 1NAME=value").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidVariable { name, .. } if name == "1NAME"
+    ));
+}
+
+#[test]
+fn function_arguments_expand_variables_at_call_site() {
+    let parser = ShellParser::new();
+    let script = r#"
+        greet() {
+            echo hello $1
+        }
+        WHO=reader
+        greet "$WHO"
+    "#;
+
+    let parsed = parser.parse(script).unwrap();
+    assert_eq!(parsed.len(), 1);
+    assert_eq!(parsed[0].args, vec!["hello", "reader"]);
+}
+
+#[test]
+fn parses_nested_and_backtick_substitutions() {
+    let parser = ShellParser::new();
+    let mut scripts = Vec::new();
+    let mut substitute = |script: &str| -> Result<String, ShellParseError> {
+        scripts.push(script.to_string());
+        Ok(format!("out of {script}\n\n"))
+    };
+
+    let parsed = parser
+        .parse_with_substitution(r#"echo $(a $(b) ")") "$(c)" `d \`e\``"#, &mut substitute)
+        .unwrap();
+    assert_eq!(
+        parsed[0].invocation.args,
+        vec!["out", "of", "a", "$(b)", r#"")""#, "out of c", "out", "of", "d", "`e`"]
+    );
+    assert_eq!(scripts, vec![r#"a $(b) ")""#, "c", "d `e`"]);
+
+    let err = parser.parse("echo $(pwd").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::UnterminatedSubstitution {
+            position: 5,
+            end: 10
+        }
+    ));
+
+    let err = parser.parse("cd $(pwd)/posts").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::UnsupportedSubstitution {
+            position: 3,
+            end: 15
+        }
+    ));
+}
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+struct Echo;
+
+impl CommandInfo for Echo {
+    fn command_name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn command_about(&self) -> &'static str {
+        "Write arguments"
+    }
+}
+
+#[async_trait(?Send)]
+impl ExecutableCommand<Log> for Echo {
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _: &Log,
+    ) -> Result<ExitStatus, String> {
+        io.stdout.write_line(args.join(" "));
+        Ok(ExitStatus::SUCCESS)
+    }
+}
+
+struct Count;
+
+impl CommandInfo for Count {
+    fn command_name(&self) -> &'static str {
+        "count"
+    }
+
+    fn command_about(&self) -> &'static str {
+        "Count piped lines"
+    }
+}
+
+#[async_trait(?Send)]
+impl ExecutableCommand<Log> for Count {
+    async fn run(
+        &self,
+        _args: &[String],
+        io: &mut CommandIo<'_>,
+        _: &Log,
+    ) -> Result<ExitStatus, String> {
+        let lines = io.stdin.as_deref().map(|input| input.lines().count());
+        io.stdout.write_line(lines.unwrap_or(0).to_string());
+        Ok(ExitStatus::SUCCESS)
+    }
+}
+
+struct Status;
+
+impl CommandInfo for Status {
+    fn command_name(&self) -> &'static str {
+        "status"
+    }
+
+    fn command_about(&self) -> &'static str {
+        "Exit with the given code"
+    }
+}
+
+#[async_trait(?Send)]
+impl ExecutableCommand<Log> for Status {
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _: &Log,
+    ) -> Result<ExitStatus, String> {
+        let code = args[0].parse::<i32>().map_err(|err| err.to_string())?;
+        io.stdout.write_line(format!("status {code}"));
+        Ok(ExitStatus::new(code))
+    }
+}
+
+struct Warn;
+
+impl CommandInfo for Warn {
+    fn command_name(&self) -> &'static str {
+        "warn"
+    }
+
+    fn command_about(&self) -> &'static str {
+        "Write arguments to stderr and fail"
+    }
+}
+
+#[async_trait(?Send)]
+impl ExecutableCommand<Log> for Warn {
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _: &Log,
+    ) -> Result<ExitStatus, String> {
+        io.stderr.write_line(args.join(" "));
+        Ok(ExitStatus::FAILURE)
+    }
+}
+
+struct Nap;
+
+impl CommandInfo for Nap {
+    fn command_name(&self) -> &'static str {
+        "nap"
+    }
+
+    fn command_about(&self) -> &'static str {
+        "Wait, then write a line; `nap N` waits N times"
+    }
+}
+
+#[async_trait(?Send)]
+impl ExecutableCommand<Log> for Nap {
+    async fn run(
+        &self,
+        args: &[String],
+        io: &mut CommandIo<'_>,
+        _: &Log,
+    ) -> Result<ExitStatus, String> {
+        let times: usize = args
+            .first()
+            .map_or(Ok(1), |arg| arg.parse())
+            .map_err(|err: std::num::ParseIntError| err.to_string())?;
+        for _ in 0..times {
+            YieldOnce(false).await;
+        }
+        io.stdout.write_line("woke");
+        Ok(ExitStatus::SUCCESS)
+    }
+}
+
+/// Future that is pending the first time it is polled, like a timer.
+struct YieldOnce(bool);
+
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+fn test_runner(log: &Log) -> CliRunner<Log> {
+    with_cli(
+        log.clone(),
+        [
+            Box::new(Echo) as Box<dyn ExecutableCommand<Log>>,
+            Box::new(Count),
+            Box::new(Status),
+            Box::new(Warn),
+            Box::new(Nap),
+        ],
+    )
+    .with_stdout(|log: &Log, text: &str| log.borrow_mut().push(text.to_string()))
+    .with_stderr(|log: &Log, text: &str| log.borrow_mut().push(format!("err: {text}")))
+}
+
+#[test]
+fn runner_substitutes_captured_pipeline_output() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+
+    assert_eq!(
+        block_on(runner.capture("echo a; echo b | count")).unwrap(),
+        "a\n1\n"
+    );
+
+    block_on(runner.run_script(
+        r#"
+            DIR=posts
+            echo "posts: $(echo $(echo one); echo two | count)"
+            cd_target=`echo /home`/$DIR
+            echo $cd_target
+        "#,
+    ))
+    .unwrap();
+    assert_eq!(runner.variable("cd_target").as_deref(), Some("/home/posts"));
+    assert_eq!(*log.borrow(), vec!["posts: one\n1", "/home/posts"]);
+}
+
+#[test]
+fn runner_pipes_stdout_between_commands() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script("echo one | count; count; echo a | export X=1 | count | count"))
+        .unwrap();
+
+    assert_eq!(*log.borrow(), vec!["1", "0", "1"]);
+}
+
+#[test]
+fn parses_or_separator() {
+    let parser = ShellParser::new();
+    let parsed = parser
+        .parse_with_separators("first || second | third")
+        .unwrap();
+    assert_eq!(parsed[0].separator, Some(Separator::Or));
+    assert_eq!(parsed[1].separator, Some(Separator::Pipe));
+    assert_eq!(parsed[2].separator, None);
+}
+
+#[test]
+fn runner_short_circuits_and_or_lists() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            status 1 && echo skipped $(echo never) || echo recovered $?
+            status 0 || echo skipped && echo chained
+            status 2; echo after $?
+            status 0 && status 3 && echo skipped
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "status 1",
+            "recovered 1",
+            "status 0",
+            "chained",
+            "status 2",
+            "after 2",
+            "status 0",
+            "status 3",
+        ]
+    );
+    assert_eq!(runner.last_status(), ExitStatus::new(3));
+    assert_eq!(runner.variable("?").as_deref(), Some("3"));
+}
+
+#[test]
+fn function_calls_keep_their_connector() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            fail() {
+                status 4
+            }
+            fail && echo skipped
+            fail || echo handled $?
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(*log.borrow(), vec!["status 4", "status 4", "handled 4"]);
+}
+
+#[test]
+fn parses_redirections() {
+    let parser =
+        ShellParser::with_commands([command("echo", 0, Some(1)), command("sort", 0, Some(0))]);
+    let parsed = parser
+        .parse("echo hi > out.log 2>&1\nsort < \"in file\" >>log 2>/dev/null")
+        .unwrap();
+    assert_eq!(parsed[0].args, vec!["hi"]);
+    assert_eq!(
+        parsed[0].redirects,
+        vec![
+            Redirect::Output {
+                fd: 1,
+                path: "out.log".into(),
+                append: false
+            },
+            Redirect::Duplicate { fd: 2, target: 1 },
+        ]
+    );
+    assert!(parsed[1].args.is_empty());
+    assert_eq!(
+        parsed[1].redirects,
+        vec![
+            Redirect::Input {
+                path: "in file".into()
+            },
+            Redirect::Output {
+                fd: 1,
+                path: "log".into(),
+                append: true
+            },
+            Redirect::Output {
+                fd: 2,
+                path: "/dev/null".into(),
+                append: false
+            },
+        ]
+    );
+
+    let parsed = ShellParser::new()
+        .parse(r#"FILE="my notes"; echo a2>$FILE "2">b \>c"#)
+        .unwrap();
+    assert_eq!(parsed[0].args, vec!["a2", "2", ">c"]);
+    assert_eq!(
+        parsed[0].redirects,
+        vec![
+            Redirect::Output {
+                fd: 1,
+                path: "my notes".into(),
+                append: false
+            },
+            Redirect::Output {
+                fd: 1,
+                path: "b".into(),
+                append: false
+            },
+        ]
+    );
+
+    for (script, position) in [
+        ("echo >", 5),
+        ("echo 3> x", 5),
+        ("echo >&out", 5),
+        ("echo > | x", 5),
+    ] {
+        let err = ShellParser::new().parse(script).unwrap_err();
+        assert!(
+            matches!(err, ShellParseError::InvalidRedirect { position: found, .. } if found == position),
+            "{script}: {err:?}"
+        );
+    }
+
+    for (script, operator) in [("echo x >", ">"), ("echo x 2>>", "2>>"), ("cat <", "<")] {
+        let err = ShellParser::new().parse(script).unwrap_err();
+        assert_eq!(
+            err.message(),
+            format!("invalid redirection: expected a file name after '{operator}'"),
+        );
+    }
+}
+
+#[test]
+fn runner_redirects_streams_to_files() {
+    let log = Log::default();
+    let fs = Rc::new(MemoryFs::new());
+    let runner = test_runner(&log).with_fs(fs.clone());
+    block_on(runner.run_script(
+        r#"
+            echo one > notes.txt
+            echo two >> notes.txt
+            count < notes.txt
+            warn oops > out.txt 2>&1
+            warn hidden 2>/dev/null
+            warn piped 2>&1 >/dev/null | count
+            count < missing.txt || echo fallback $?
+            CAPTURED=$(warn captured 2>&1)
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        *log.borrow(),
+        vec!["2", "1", "err: missing.txt: no such file", "fallback 1"]
+    );
+    assert_eq!(fs.read_file("notes.txt").unwrap(), "one\ntwo\n");
+    assert_eq!(fs.read_file("out.txt").unwrap(), "oops\n");
+    assert_eq!(fs.paths(), vec!["notes.txt", "out.txt"]);
+    assert_eq!(runner.variable("CAPTURED").as_deref(), Some("captured"));
+}
+
+#[test]
+fn runner_branches_on_exit_statuses() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            if status 1; then echo no; elif status 0; then
+                echo elif
+                if status 0 && status 2; then echo no; else echo nested; fi
+            else
+                echo no
+            fi && echo after $?
+            if status 3; then echo no; fi; echo none $?
+            if status 0; then status 4; fi || echo failed $?
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "status 1", "status 0", "elif", "status 0", "status 2", "nested", "after 0",
+            "status 3", "none 0", "status 0", "status 4", "failed 4",
+        ]
+    );
+}
+
+#[test]
+fn runner_runs_loops() {
+    let log = Log::default();
+    let fs = Rc::new(MemoryFs::new());
+    let runner = test_runner(&log).with_fs(fs.clone());
+    block_on(runner.run_script(
+        r#"
+            echo > posts/b.md; echo > posts/a.md; echo > posts/c.txt
+            for f in posts/*.md '*.md' [n]one*; do echo file $f; done
+            for word in $(echo one two)
+            do
+                echo $word
+            done
+            N=0
+            while status $N; do N=1; done
+            until status $N; do N=0; done
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "file posts/a.md",
+            "file posts/b.md",
+            "file *.md",
+            "file [n]one*",
+            "one",
+            "two",
+            "status 0",
+            "status 1",
+            "status 1",
+            "status 0",
+        ]
+    );
+    assert_eq!(runner.variable("word").as_deref(), Some("two"));
+}
+
+#[test]
+fn runner_awaits_each_command() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    let status = block_on(
+        runner.run_script("for n in 1 2; do echo tick $n; nap | count; done && nap && echo done"),
+    )
+    .unwrap();
+    assert_eq!(status, ExitStatus::SUCCESS);
+    assert_eq!(
+        *log.borrow(),
+        vec!["tick 1", "1", "tick 2", "1", "woke", "done"]
+    );
+
+    // Substitutions wait for the commands they run.
+    log.borrow_mut().clear();
+    block_on(runner.run_script("x=$(nap 2); echo \"got $x\"; echo $(echo $(nap) | count)"))
+        .unwrap();
+    assert_eq!(*log.borrow(), vec!["got woke", "1"]);
+}
+
+#[test]
+fn parses_background_separator() {
+    let parser = ShellParser::new();
+    let parsed = parser
+        .parse_with_separators("fetch a&fetch b && echo c&")
+        .unwrap();
+    let separators: Vec<_> = parsed.iter().map(|parsed| parsed.separator).collect();
+    assert_eq!(
+        separators,
+        vec![
+            Some(Separator::Background),
+            Some(Separator::And),
+            Some(Separator::Background)
+        ]
+    );
+    assert_eq!(parsed[0].invocation.args, vec!["a"]);
+
+    let err = parser
+        .parse_script("while true; do echo hi; done &")
+        .unwrap_err();
+    assert_eq!(
+        err.message(),
+        "invalid 'while': running a compound command in the background is not supported"
+    );
+
+    let log = Log::default();
+    let runner = test_runner(&log);
+    let err = block_on(runner.run_script("greet() {\n    echo hi\n}\ngreet &")).unwrap_err();
+    assert!(matches!(
+        err,
+        ShellCliError::Parse(ShellParseError::InvalidFunction { ref message, .. })
+            if message == "functions cannot run in the background"
+    ));
+}
+
+#[test]
+fn runner_tracks_background_jobs() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            nap 100 & nap & echo started
+            wait %2; echo waited $?
+            jobs; kill %1; jobs
+            wait; echo all $?
+            kill %1; fg; kill 1
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "[1] Running nap 100",
+            "[2] Running nap",
+            "started",
+            "[2] woke",
+            "[2] Done nap",
+            "waited 0",
+            "[1] Running nap 100",
+            "[1] Killed nap 100",
+            "all 0",
+            "err: kill: %1: no such job",
+            "err: fg: no current job",
+            "err: kill: usage: kill %JOB...",
+        ]
+    );
+    assert_eq!(runner.last_status(), ExitStatus::new(2));
+}
+
+#[test]
+fn runner_hands_background_jobs_to_spawner() {
+    type Spawned = Rc<RefCell<Vec<Pin<Box<dyn Future<Output = ()>>>>>>;
+    let log = Log::default();
+    let spawned = Spawned::default();
+    let runner = test_runner(&log).with_spawner({
+        let spawned = spawned.clone();
+        move |task| spawned.borrow_mut().push(task)
+    });
+
+    block_on(runner.run_script("nap | count & echo after; status 3 &")).unwrap();
+    assert_eq!(runner.last_status(), ExitStatus::SUCCESS);
+    assert_eq!(
+        *log.borrow(),
+        vec!["[1] Running nap | count", "after", "[2] Running status 3"]
+    );
+
+    log.borrow_mut().clear();
+    for task in spawned.take() {
+        block_on(task);
+    }
+    block_on(runner.run_script("jobs; wait %1")).unwrap();
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "[1] 1",
+            "[1] Done nap | count",
+            "[2] status 3",
+            "[2] Exit 3 status 3",
+            "[1] Done nap | count",
+            "[2] Exit 3 status 3",
+            "err: wait: %1: no such job",
+        ]
+    );
+}
+
+#[test]
+fn runner_matches_case_patterns() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            for name in notes.md data.txt "*" other; do
+                case $name in
+                    *.md | *.txt) echo text $name ;;
+                    "*")
+                        echo star
+                        ;;
+                    (*) echo other $name;;
+                esac
+            done
+            case x in y) echo no;; esac; echo unmatched $?
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "text notes.md",
+            "text data.txt",
+            "star",
+            "other other",
+            "unmatched 0",
+        ]
+    );
+}
+
+#[test]
+fn runner_applies_shell_options() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    let status = block_on(runner.run_script(
+        r#"
+            set -x -o pipefail
+            echo "two words" | count
+            status 2 | status 0; echo piped $?
+            set +x -e
+            if status 1; then echo no; fi
+            status 3 || echo recovered
+            status 4
+            echo unreachable
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(status, ExitStatus::new(4));
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "err: + echo 'two words'",
+            "err: + count",
+            "1",
+            "err: + status 2",
+            "err: + status 0",
+            "status 0",
+            "err: + echo piped 2",
+            "piped 2",
+            "err: + set +x -e",
+            "status 1",
+            "status 3",
+            "recovered",
+            "status 4",
+        ]
+    );
+    let options = runner.options();
+    assert!(options.errexit && options.pipefail && !options.xtrace && !options.nounset);
+
+    log.borrow_mut().clear();
+    runner.set_options(ShellOptions::default());
+    block_on(runner.run_script("set -o errexit +o pipefail -u; set -o; set +o")).unwrap();
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "errexit   on",
+            "nounset   on",
+            "pipefail  off",
+            "xtrace    off",
+            "set -o errexit",
+            "set -o nounset",
+            "set +o pipefail",
+            "set +o xtrace",
+        ]
+    );
+}
+
+#[test]
+fn nounset_rejects_unset_variables() {
+    let parser = ShellParser::new();
+    let script = "set -u; echo ${MISSING:-fallback} $? \"$@\" ${#NAME}";
+    let err = parser.parse(script).unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidVariable { ref name, ref message, position: 42, end: 50 }
+            if name == "NAME" && message == "unbound variable"
+    ));
+
+    parser.set_variable("NAME", "set");
+    assert_eq!(parser.parse(script).unwrap()[1].args, vec!["fallback", "3"]);
+
+    for (script, message) in [
+        ("set -q", "-q: invalid option"),
+        ("set -o nope", "nope: invalid option name"),
+        ("set e", "e: invalid option"),
+    ] {
+        let err = ShellParser::new().parse(script).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ShellParseError::InvalidControlFlow { ref keyword, message: ref found, .. }
+                    if keyword == "set" && found == message
+            ),
+            "{script}: {err:?}"
+        );
+    }
+}
+
+#[test]
+fn runner_enforces_limits() {
+    let run = |limits: Limits, script: &str| {
+        let log = Log::default();
+        let now = Rc::new(Cell::new(Duration::ZERO));
+        let runner = test_runner(&log).with_limits(limits).with_clock({
+            let now = now.clone();
+            move || {
+                // Every reading is a second later than the last.
+                now.set(now.get() + Duration::from_secs(1));
+                now.get()
+            }
+        });
+        let result = block_on(runner.run_script(script));
+        (result, log.take())
+    };
+
+    let commands = Limits {
+        max_commands: Some(3),
+        ..Limits::default()
+    };
+    let (result, log) = run(commands, "echo $(echo a) | count; echo b; echo c");
+    assert!(matches!(
+        result,
+        Err(ShellCliError::Limit(LimitExceeded::Commands(3)))
+    ));
+    assert_eq!(log, vec!["1"]);
+    assert_eq!(
+        run(commands, "echo a; echo b; echo c").0.unwrap(),
+        ExitStatus::SUCCESS
+    );
+
+    let depth = Limits {
+        max_depth: Some(2),
+        ..Limits::default()
+    };
+    let (result, _) = run(depth, "echo $(echo $(echo deep))");
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "depth limit reached: scripts nested more than 2 deep"
+    );
+
+    let output = Limits {
+        max_output_bytes: Some(6),
+        ..Limits::default()
+    };
+    let (result, log) = run(output, "echo four; echo more; echo never");
+    assert!(matches!(
+        result,
+        Err(ShellCliError::Limit(LimitExceeded::OutputBytes(6)))
+    ));
+    assert_eq!(log, vec!["four"]);
+
+    let time = Limits {
+        max_time: Some(Duration::from_secs(4)),
+        ..Limits::default()
+    };
+    let (result, log) = run(time, "while status 0; do echo loop; done");
+    assert!(matches!(
+        result,
+        Err(ShellCliError::Limit(LimitExceeded::Time(_)))
+    ));
+    assert_eq!(log, vec!["status 0"]);
+}
+
+#[test]
+fn runner_limits_each_top_level_run() {
+    let log = Log::default();
+    let now = Rc::new(Cell::new(Duration::ZERO));
+    let time = Limits {
+        max_time: Some(Duration::from_secs(60)),
+        ..Limits::default()
+    };
+    let runner = test_runner(&log).with_limits(time).with_clock({
+        let now = now.clone();
+        move || now.get()
+    });
+
+    // Scripts started while another one waits count on their own.
+    let mut first = pin!(runner.run_script("nap 2; echo first"));
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    now.set(Duration::from_secs(61));
+    block_on(runner.run_script("echo second")).unwrap();
+    assert!(matches!(
+        block_on(first),
+        Err(ShellCliError::Limit(LimitExceeded::Time(_)))
+    ));
+    // `nap` is stopped as soon as it wakes past the limit.
+    assert_eq!(log.take(), vec!["second"]);
+
+    // Background jobs are runs of their own too, so they do not use up the
+    // commands of the script that started them.
+    let commands = Limits {
+        max_commands: Some(2),
+        ..Limits::default()
+    };
+    let runner = test_runner(&log).with_limits(commands);
+    block_on(runner.run_script("nap & nap & wait")).unwrap();
+    let log = log.take();
+    assert!(log.contains(&"[1] Done nap".to_string()), "{log:?}");
+    assert!(log.contains(&"[2] Done nap".to_string()), "{log:?}");
+}
+
+/// Never finishes, like a request that gets no answer.
+struct Hang;
+
+impl CommandInfo for Hang {
+    fn command_name(&self) -> &'static str {
+        "hang"
+    }
+
+    fn command_about(&self) -> &'static str {
+        "Wait forever"
+    }
+}
+
+#[async_trait(?Send)]
+impl ExecutableCommand<Log> for Hang {
+    async fn run(
+        &self,
+        _: &[String],
+        _: &mut CommandIo<'_>,
+        _: &Log,
+    ) -> Result<ExitStatus, String> {
+        std::future::pending().await
+    }
+}
+
+#[test]
+fn runner_wakes_commands_when_the_time_is_up() {
+    let log = Log::default();
+    let now = Rc::new(Cell::new(Duration::ZERO));
+    let sleeps = Rc::new(RefCell::new(Vec::new()));
+    let time = Limits {
+        max_time: Some(Duration::from_secs(60)),
+        ..Limits::default()
+    };
+    let commands = [
+        Box::new(Echo) as Box<dyn ExecutableCommand<Log>>,
+        Box::new(Hang),
+    ];
+    let runner = with_cli(log.clone(), commands)
+        .with_stdout(|log: &Log, text: &str| log.borrow_mut().push(text.to_string()))
+        .with_limits(time)
+        .with_clock({
+            let now = now.clone();
+            move || now.get()
+        })
+        .with_timer({
+            let now = now.clone();
+            let sleeps = sleeps.clone();
+            move |wait| {
+                sleeps.borrow_mut().push(wait);
+                let now = now.clone();
+                Box::pin(async move {
+                    YieldOnce(false).await;
+                    now.set(now.get() + wait + Duration::from_millis(1));
+                })
+            }
+        });
+
+    let result = block_on(runner.run_script("echo start; hang; echo never"));
+    assert!(matches!(
+        result,
+        Err(ShellCliError::Limit(LimitExceeded::Time(_)))
+    ));
+    assert_eq!(log.take(), vec!["start"]);
+    // Only the command that waited needed a timer.
+    assert_eq!(*sleeps.borrow(), vec![Duration::from_secs(60)]);
+}
+
+/// Blocks `secret`, turns `hi` into `hello` and logs how each command ended.
+struct Audit;
+
+impl Middleware<Log> for Audit {
+    fn before(&self, invocation: &mut CommandInvocation, _: &Log) -> Decision {
+        if invocation.args.iter().any(|arg| arg == "secret") {
+            return Decision::Block("no secrets".into());
+        }
+        for arg in &mut invocation.args {
+            if arg == "hi" {
+                *arg = "hello".into();
+            }
+        }
+        Decision::Run
+    }
+
+    fn after(&self, record: &CommandRecord<'_>, log: &Log) {
+        let outcome = match record.outcome {
+            Outcome::Exited(status) => format!("exited {status}"),
+            Outcome::Blocked(message) => format!("blocked: {message}"),
+            Outcome::Failed(err) => format!("failed: {err}"),
+        };
+        let duration = record.duration.map_or(0, |duration| duration.as_secs());
+        log.borrow_mut().push(format!(
+            "after {} {outcome} in {duration}s",
+            record.invocation.name
+        ));
+    }
+}
+
+#[test]
+fn runner_passes_commands_through_middleware() {
+    let log = Log::default();
+    let now = Rc::new(Cell::new(Duration::ZERO));
+    let runner = test_runner(&log).with_middleware(Audit).with_clock({
+        let now = now.clone();
+        move || {
+            now.set(now.get() + Duration::from_secs(1));
+            now.get()
+        }
+    });
+
+    let result = block_on(runner.run_script("echo hi | count; echo secret; status 3; status x"));
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "command failed: status: invalid digit found in string"
+    );
+    assert_eq!(
+        log.take(),
+        vec![
+            "after echo exited 0 in 1s",
+            "1",
+            "after count exited 0 in 1s",
+            "err: no secrets",
+            "after echo blocked: no secrets in 1s",
+            "status 3",
+            "after status exited 3 in 1s",
+            "after status failed: command failed: status: invalid digit found in string in 1s",
+        ]
+    );
+}
+
+#[test]
+fn runner_calls_preexec_and_precmd() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            preexec() {
+                echo "running $1"
+            }
+            precmd() {
+                echo "done $?"
+            }
+        "#,
+    ))
+    .unwrap();
+    assert_eq!(log.take(), vec!["done 0"]);
+
+    let status = block_on(runner.run_script("echo $(echo inner); status 2")).unwrap();
+    assert_eq!(status, ExitStatus::new(2));
+    assert_eq!(runner.variable("?").as_deref(), Some("2"));
+    assert_eq!(
+        log.take(),
+        vec![
+            "running echo $(echo inner); status 2",
+            "inner",
+            "status 2",
+            "done 2",
+        ]
+    );
+
+    // A script started while another one waits is not nested in it.
+    let mut first = pin!(runner.run_script("nap; echo first"));
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    block_on(runner.run_script("echo second")).unwrap();
+    block_on(first).unwrap();
+    assert_eq!(
+        log.take(),
+        vec![
+            "running nap; echo first",
+            "running echo second",
+            "second",
+            "done 0",
+            "woke",
+            "first",
+            "done 0",
+        ]
+    );
+}
+
+#[test]
+fn rejects_malformed_control_flow() {
+    let err = ShellParser::new()
+        .parse("echo start; if true; then echo yes; fi")
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::UnsupportedControlFlow { ref keyword, position: 12, end: 38 } if keyword == "if"
+    ));
+
+    let log = Log::default();
+    let runner = test_runner(&log);
+    for (script, keyword) in [
+        ("if status 0; then echo", "if"),
+        ("echo hi; done", "done"),
+        ("while; do echo; done", "while"),
+        ("for 1x in a; do echo; done", "for"),
+        ("for x in a; echo; done", "for"),
+        ("case a in b echo; esac", "case"),
+        ("if status 0; then echo; fi > out", "if"),
+        ("echo | if status 0; then echo; fi", "|"),
+    ] {
+        let err = block_on(runner.run_script(script)).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ShellCliError::Parse(ShellParseError::InvalidControlFlow { keyword: ref found, .. })
+                    if found == keyword
+            ),
+            "{script}: {err:?}"
+        );
+    }
+}
+
+#[test]
+fn parses_scripts_into_a_tree() {
+    let source = "cat notes.txt | grep \"$TOPIC\" > out && echo ok\nif status 0; then echo yes; fi";
+    let script = ShellParser::new().parse_script(source).unwrap();
+    let text = |span: Span| &source[span.start..span.end];
+
+    let items = &script.body.items;
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].separator, Some(Separator::And));
+    assert_eq!(items[1].separator, Some(Separator::Newline));
+    assert_eq!(items[2].separator, None);
+
+    let pipeline = &items[0].pipeline;
+    assert_eq!(pipeline.commands.len(), 2);
+    assert_eq!(text(pipeline.span), "cat notes.txt | grep \"$TOPIC\" > out");
+    let Command::Simple(grep) = &pipeline.commands[1] else {
+        panic!("expected a simple command");
+    };
+    assert_eq!(text(grep.words[1].span), "\"$TOPIC\"");
+    assert!(matches!(
+        grep.words[1].parts.as_slice(),
+        [WordPart::Parameter { quoted: true, .. }]
+    ));
+    assert_eq!(text(grep.redirects[0].span), "> out");
+    assert_eq!(
+        grep.redirects[0].operator,
+        RedirectOperator::Output {
+            fd: 1,
+            append: false
+        }
+    );
+
+    let Command::If(branch) = &items[2].pipeline.commands[0] else {
+        panic!("expected an if command");
+    };
+    assert_eq!(text(branch.span), "if status 0; then echo yes; fi");
+    assert_eq!(text(branch.branches[0].condition.span), "status 0");
+    assert_eq!(text(branch.branches[0].body.span), "echo yes");
+    assert!(branch.otherwise.is_none());
+
+    struct Words(Vec<String>);
+    impl Visitor for Words {
+        fn visit_word(&mut self, word: &Word) {
+            self.0.push(word.text.clone());
+        }
+    }
+    let mut words = Words(Vec::new());
+    words.visit_script(&script);
+    assert_eq!(
+        words.0,
+        vec![
+            "cat",
+            "notes.txt",
+            "grep",
+            "$TOPIC",
+            "out",
+            "echo",
+            "ok",
+            "status",
+            "0",
+            "echo",
+            "yes"
+        ]
+    );
+}
+
+#[test]
+fn parses_function_definitions_into_the_tree() {
+    let source = "greet() {\n    for name in $@; do echo $name; done\n}\ngreet a b";
+    let script = ShellParser::new().parse_script(source).unwrap();
+    let items = &script.body.items;
+    assert_eq!(items.len(), 2);
+
+    let Command::Function(definition) = &items[0].pipeline.commands[0] else {
+        panic!("expected a function definition");
+    };
+    assert_eq!(definition.name, "greet");
+    let closing = source.find("}\n").unwrap();
+    assert_eq!(definition.span, Span::new(0, closing + 1));
+    assert!(matches!(
+        definition.body.items[0].pipeline.commands[0],
+        Command::For(_)
+    ));
+
+    let err = ShellParser::new().parse_script("echo; fi").unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidControlFlow { ref keyword, position: 6, .. } if keyword == "fi"
+    ));
+}
+
+#[test]
+fn formats_scripts_in_canonical_form() {
+    let source = r#"
+        # comments go away
+        greet()   {
+          for name in "$@" 'a b';do echo "hi $name"   >> log.txt ;done
+        }
+        if stat   a;then greet x && echo ok;elif stat b ; then :; else
+        cat <<EOF
+$HOME and `pwd`
+EOF
+        fi
+        case $1 in  a|b) sleep 1 & ;; *) echo "it's" ${X:-none};; esac
+    "#;
+    let parser = ShellParser::new();
+    let formatted = fmt::script(&parser.parse_script(source).unwrap());
+    assert_eq!(
+        formatted,
+        r#"greet() {
+    for name in "$@" 'a b'; do
+        echo "hi $name" >> log.txt
+    done
+}
+if stat a; then
+    greet x && echo ok
+elif stat b; then
+    :
+else
+    cat <<EOF
+$HOME and $(pwd)
+EOF
+fi
+case $1 in
+    a | b)
+        sleep 1 &
+        ;;
+    *)
+        echo 'it'\''s' ${X:-none}
+        ;;
+esac
+"#
+    );
+    let again = fmt::script(&parser.parse_script(&formatted).unwrap());
+    assert_eq!(again, formatted);
+
+    let args: Vec<String> = [
+        "history",
+        "--push",
+        "/posts/my post",
+        "it's",
+        "",
+        "$HOME",
+        "*.md",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+    let line = fmt::quote(&args);
+    assert_eq!(
+        line,
+        r#"history --push '/posts/my post' 'it'\''s' '' '$HOME' '*.md'"#
+    );
+    let parsed = ShellParser::new().parse(&line).unwrap();
+    assert_eq!(parsed[0].name, "history");
+    assert_eq!(parsed[0].args, args[1..]);
+}
+
+#[test]
+fn runner_runs_functions_and_aliases_with_compound_commands() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            each() {
+                for item in $@; do
+                    if status $item; then echo ok $item; else echo failed $item; fi
+                done
+            }
+            each 0 1
+            alias check='case $1 in 0) echo zero ;; *) echo other ;; esac'
+            check
+            if status 0; then
+                late() {
+                    echo late $1
+                }
+            fi
+            late "defined in a branch"
+        "#,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "status 0",
+            "ok 0",
+            "status 1",
+            "failed 1",
+            "other",
+            "status 0",
+            "late defined in a branch",
+        ]
+    );
+}
+
+#[test]
+fn reports_every_error_in_a_script() {
+    let parser = ShellParser::with_commands([command("echo", 0, None)]);
+    let source = "echo start\nif true; then\n    echo >\nfi\nnope\nfor 1x in a; do\n    echo\ndone\necho end; fi";
+    let err = parser.parse(source).unwrap_err();
+    let ShellParseError::Multiple { ref errors } = err else {
+        panic!("expected several errors: {err:?}");
+    };
+    assert_eq!(errors.len(), 3, "{err:?}");
+    assert!(matches!(errors[0], ShellParseError::InvalidRedirect { .. }));
+    assert!(matches!(
+        errors[1],
+        ShellParseError::InvalidControlFlow { ref keyword, .. } if keyword == "for"
+    ));
+    assert!(matches!(
+        errors[2],
+        ShellParseError::InvalidControlFlow { ref keyword, .. } if keyword == "fi"
+    ));
+
+    // Once the syntax is valid, commands that fail to validate are all reported too.
+    let err = parser.parse("nope\necho ok\nmissing a b").unwrap_err();
+    let names: Vec<_> = err
+        .errors()
+        .into_iter()
+        .map(|error| match error {
+            ShellParseError::UnknownCommand { name, .. } => name.as_str(),
+            other => panic!("unexpected error {other:?}"),
+        })
+        .collect();
+    assert_eq!(names, vec!["nope", "missing"]);
+
+    let diagnostics = err.diagnostics("nope\necho ok\nmissing a b");
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.column, diagnostic.span))
+            .collect::<Vec<_>>(),
+        vec![(1, 1, Span::new(0, 4)), (3, 1, Span::new(13, 20))]
+    );
+    assert_eq!(diagnostics[1].to_string(), "3:1: unknown command 'missing'");
+}
+
+#[test]
+fn renders_diagnostics_with_a_caret_underline() {
+    let source = "echo ok\n\tcat 'nope > out\necho done";
+    let err = ShellParser::new().parse(source).unwrap_err();
+    let diagnostics = err.diagnostics(source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 6));
+    assert_eq!(diagnostics[0].span, Span::new(13, source.len()));
+    assert_eq!(
+        diagnostics[0].render(source),
+        "error: unterminated quote '\n --> 2:6\n  |\n2 | \tcat 'nope > out\n  | \t    ^^^^^^^^^^^"
+    );
+
+    let source = "echo 1\necho 2\necho 3\necho 4\necho 5\necho 6\necho 7\necho 8\necho 9\necho >> ";
+    let err = ShellParser::new().parse(source).unwrap_err();
+    assert_eq!(
+        err.message(),
+        "invalid redirection: expected a file name after '>>'"
+    );
+    assert_eq!(
+        err.to_string(),
+        format!("{} at {}", err.message(), source.len() - 3)
+    );
+    assert_eq!(
+        err.diagnostics(source)[0].render(source),
+        "error: invalid redirection: expected a file name after '>>'\n  --> 10:6\n   |\n10 | echo >> \n   |      ^^"
+    );
+
+    // A block missing its closing keyword is covered from its opener on.
+    let source = "echo ok\nif true; then\n  echo yes";
+    let diagnostic = &ShellParser::new()
+        .parse(source)
+        .unwrap_err()
+        .diagnostics(source)[0];
+    assert_eq!(diagnostic.span, Span::new(8, source.len()));
+    assert_eq!(
+        diagnostic.render(source),
+        "error: invalid 'if': missing 'fi'\n --> 2:1\n  |\n2 | if true; then\n  | ^^^^^^^^^^^^^"
+    );
+
+    // A command with too many arguments is covered up to its last word.
+    let parser = ShellParser::with_commands([command("cd", 0, Some(1))]);
+    let source = "cd \"a b\" c";
+    let diagnostic = &parser.parse(source).unwrap_err().diagnostics(source)[0];
+    assert_eq!(diagnostic.span, Span::new(0, 10));
+}
+
+#[test]
+fn reads_here_documents() {
+    let parser = ShellParser::new();
+    let script = "NAME=world\ncat <<EOF; echo after\nhello $NAME\n  \\$HOME `echo x`\nEOF\ncat <<'EOF' | count\nhello $NAME\nEOF";
+    let parsed = parser
+        .parse_with_substitution(script, &mut |script: &str| {
+            Ok::<_, ShellParseError>(format!("[{script}]"))
+        })
+        .unwrap();
+    let commands: Vec<_> = parsed
+        .iter()
+        .map(|command| {
+            (
+                command.invocation.name.as_str(),
+                command.invocation.redirects.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        commands,
+        vec![
+            (
+                "cat",
+                vec![Redirect::HereDoc {
+                    body: "hello world\n  $HOME [echo x]\n".into()
+                }]
+            ),
+            ("echo", vec![]),
+            (
+                "cat",
+                vec![Redirect::HereDoc {
+                    body: "hello $NAME\n".into()
+                }]
+            ),
+            ("count", vec![]),
+        ]
+    );
+
+    let source = "if true; then\n\tcat <<-END\n\t\tindented\n\tEND\nfi";
+    let tree = parser.parse_script(source).unwrap();
+    let Command::If(branch) = &tree.body.items[0].pipeline.commands[0] else {
+        panic!("expected an if command");
+    };
+    let Command::Simple(cat) = &branch.branches[0].body.items[0].pipeline.commands[0] else {
+        panic!("expected a simple command");
+    };
+    assert_eq!(
+        cat.redirects[0].operator,
+        RedirectOperator::HereDoc {
+            fd: 0,
+            strip_tabs: true
+        }
+    );
+    let body = cat.redirects[0].target.as_ref().unwrap();
+    assert_eq!(body.text, "indented\n");
+    assert_eq!(&source[body.span.start..body.span.end], "\t\tindented\n");
+
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner
+        .run_script("lines() {\n\tcount <<-END\n\t\t$1\n\t\tsecond\n\tEND\n}\nlines 'first line'\ncount <<EOF\nEOF"))
+        .unwrap();
+    assert_eq!(*log.borrow(), vec!["2", "0"]);
+
+    for (script, expected, end) in [
+        (
+            "cat <<EOF\nno end",
+            "here-document is missing its closing 'EOF'",
+            16,
+        ),
+        ("cat <<", "expected a delimiter after '<<'", 6),
+        ("cat 2<<EOF\nEOF", "bad file descriptor 2", 7),
+    ] {
+        let err = parser.parse(script).unwrap_err();
+        assert!(
+            matches!(err, ShellParseError::InvalidRedirect { ref message, position: 4, end: found } if message == expected && found == end),
+            "{script}: {err:?}"
+        );
+    }
+}
+
+#[test]
+fn highlights_incomplete_command_lines() {
+    let parser = ShellParser::with_commands([
+        command("echo", 0, None),
+        CommandSpec::new("list", "List files").with_alias("ls"),
+    ]);
+    parser
+        .parse("alias hi='echo hi'\ngreet() {\n    echo hello\n}")
+        .unwrap();
+    let classify = |input: &'static str| -> Vec<(HighlightKind, &'static str)> {
+        parser
+            .highlight(input)
+            .into_iter()
+            .map(|span| (span.kind, &input[span.span.start..span.span.end]))
+            .collect()
+    };
+
+    assert_eq!(
+        classify("ls -la \"$HOME/x\" 2>&1 | nope && hi; greet # done"),
+        vec![
+            (HighlightKind::Alias, "ls"),
+            (HighlightKind::Option, "-la"),
+            (HighlightKind::String, "\""),
+            (HighlightKind::Variable, "$HOME"),
+            (HighlightKind::String, "/x\""),
+            (HighlightKind::Operator, "2>&1"),
+            (HighlightKind::Operator, "|"),
+            (HighlightKind::UnknownCommand, "nope"),
+            (HighlightKind::Operator, "&&"),
+            (HighlightKind::Alias, "hi"),
+            (HighlightKind::Operator, ";"),
+            (HighlightKind::Function, "greet"),
+            (HighlightKind::Comment, "# done"),
+        ]
+    );
+    assert_eq!(
+        classify("NAME=$(list | echo) for x in a; do echo 'open"),
+        vec![
+            (HighlightKind::Variable, "NAME"),
+            (HighlightKind::Variable, "$(list | echo)"),
+            (HighlightKind::Keyword, "for"),
+            (HighlightKind::Variable, "x"),
+            (HighlightKind::Keyword, "in"),
+            (HighlightKind::Operator, ";"),
+            (HighlightKind::Keyword, "do"),
+            (HighlightKind::Command, "echo"),
+            (HighlightKind::String, "'open"),
+        ]
+    );
+    assert_eq!(
+        classify("fi; | echo > out; case $x in a|b) say ;; esac"),
+        vec![
+            (HighlightKind::Error, "fi"),
+            (HighlightKind::Operator, ";"),
+            (HighlightKind::Error, "|"),
+            (HighlightKind::Command, "echo"),
+            (HighlightKind::Operator, ">"),
+            (HighlightKind::Operator, ";"),
+            (HighlightKind::Keyword, "case"),
+            (HighlightKind::Variable, "$x"),
+            (HighlightKind::Keyword, "in"),
+            (HighlightKind::Operator, "|"),
+            (HighlightKind::UnknownCommand, "say"),
+            (HighlightKind::Operator, ";;"),
+            (HighlightKind::Keyword, "esac"),
+        ]
+    );
+    assert_eq!(
+        classify("cat <<-EOF\n\thello $NAME\n\tEOF\nlater() { echo; }"),
+        vec![
+            (HighlightKind::UnknownCommand, "cat"),
+            (HighlightKind::Operator, "<<-"),
+            (HighlightKind::String, "\thello $NAME"),
+            (HighlightKind::Operator, "\tEOF"),
+            (HighlightKind::Function, "later()"),
+            (HighlightKind::Operator, "{"),
+            (HighlightKind::Command, "echo"),
+            (HighlightKind::Operator, ";"),
+            (HighlightKind::Operator, "}"),
+        ]
+    );
+    assert!(ShellParser::new()
+        .highlight("anything")
+        .iter()
+        .all(|span| span.kind == HighlightKind::Command));
+}
+
+#[test]
+fn completes_commands_options_and_paths() {
+    let mut parser = ShellParser::with_commands([
+        command("echo", 0, None),
+        CommandSpec::new("render", "Render a post")
+            .with_alias("rd")
+            .with_options(["-r", "--raw", "--refresh"]),
+    ]);
+    let fs = MemoryFs::new();
+    for path in [
+        "posts/demo.md",
+        "posts/draft notes.md",
+        "posts/.hidden",
+        "readme.md",
+    ] {
+        fs.write_file(path, "", false).unwrap();
+    }
+    parser.set_file_system(Rc::new(fs));
+    parser
+        .parse("alias rerun='echo again'\nrecap() {\n    echo\n}")
+        .unwrap();
+    let complete = |input: &str| {
+        let completion = parser.complete(input, input.len());
+        (completion.replace.start, completion.candidates)
+    };
+
+    assert_eq!(
+        complete("echo x | re"),
+        (9, vec!["recap".into(), "render".into(), "rerun".into()])
+    );
+    assert_eq!(
+        complete("rd --r"),
+        (3, vec!["--raw".into(), "--refresh".into()])
+    );
+    assert_eq!(
+        complete("render posts/d"),
+        (
+            7,
+            vec!["posts/demo.md".into(), "posts/draft\\ notes.md".into(),]
+        )
+    );
+    assert_eq!(complete("echo > r"), (7, vec!["readme.md".into()]));
+    assert_eq!(complete("echo \"po"), (5, vec!["posts/".into()]));
+    assert_eq!(
+        complete("render "),
+        (7, vec!["posts/".into(), "readme.md".into()])
+    );
+    assert_eq!(complete("echo # re"), (9, Vec::new()));
+    assert_eq!(complete("for re"), (4, Vec::new()));
+
+    let completion = parser.complete("render posts/d here", 14);
+    assert_eq!(completion.replace, Span::new(7, 14));
+    assert_eq!(completion.common_prefix(), "posts/d");
+}
+
+#[test]
+fn functions_and_scripts_get_positional_parameters() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            NAME=global
+            greet() {
+                local NAME="$1" unused
+                shift
+                echo "hello-$NAME" $#
+                for arg in "$@"; do echo "[$arg]"; done
+                inner x
+            }
+            inner() {
+                echo "inner sees $NAME ${1:-none} ${2:-none}"
+                echo "$(echo captured $#)"
+            }
+            greet world "two words" three
+            echo "after $NAME $# ${unused-unset}"
+        "#,
+    ))
+    .unwrap();
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "hello-world 2",
+            "[two words]",
+            "[three]",
+            "inner sees world x none",
+            "captured 1",
+            "after global 0 unset",
+        ]
+    );
+
+    log.borrow_mut().clear();
+    block_on(runner.run_script_with_args(
+        "echo $# $1; shift 2; echo $@",
+        vec!["a".into(), "b".into(), "c".into()],
+    ))
+    .unwrap();
+    assert_eq!(*log.borrow(), vec!["3 a", "c"]);
+
+    let err = block_on(runner.run_script("shift")).unwrap_err();
+    assert!(matches!(
+        err,
+        ShellCliError::Parse(ShellParseError::InvalidControlFlow { ref keyword, .. }) if keyword == "shift"
+    ));
+    let err = block_on(runner.run_script("local x=1")).unwrap_err();
+    assert!(matches!(
+        err,
+        ShellCliError::Parse(ShellParseError::InvalidControlFlow { ref keyword, .. }) if keyword == "local"
+    ));
+}
+
+#[test]
+fn dropping_a_run_leaves_the_function_call() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    {
+        // Interrupted while a substitution in the body waits.
+        let mut run = pin!(runner.run_script("f() { local X=inner; echo $(nap); }; f secret"));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(run.as_mut().poll(&mut cx).is_pending());
+    }
+    block_on(runner.run_script("echo \"1=$1 X=$X\"")).unwrap();
+    assert_eq!(log.take(), vec!["1= X="]);
+    assert!(block_on(runner.run_script("local X")).is_err());
+}
+
+#[test]
+fn defines_functions_on_one_line() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            greet() { echo hello $1; }
+            function hi { echo hi; }
+            f() { echo a; } ; f
+            g()
+            { echo "g $#"; echo done; }
+            greet world
+            hi
+            g x y
+            nested() { for x in 1; do echo "nested $1"; done; }; nested ok
+        "#,
+    ))
+    .unwrap();
+    assert_eq!(
+        *log.borrow(),
+        vec!["a", "hello world", "hi", "g 2", "done", "nested ok"]
+    );
+
+    let err = ShellParser::new()
+        .parse_script("f() x { echo a; }")
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidFunction { ref message, position: 4, .. }
+            if message == "expected '{' after function header"
+    ));
+    let err = ShellParser::new()
+        .parse_script("f() { echo a;")
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ShellParseError::InvalidFunction { ref message, .. }
+            if message == "missing closing '}' for function body"
+    ));
+}
+
+#[test]
+fn rejects_functions_in_pipelines() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    for (script, position) in [
+        ("f() { echo a; echo b; }; f | count", 25),
+        ("f() { echo a; }; echo x | f", 26),
+    ] {
+        let err = block_on(runner.run_script(script)).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ShellCliError::Parse(ShellParseError::InvalidFunction { ref message, position: found, end, .. })
+                    if message == "functions cannot be piped" && found == position && end == position + 1
+            ),
+            "{script}: {err:?}"
+        );
+    }
+    // No part of the body ran on its own.
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn inspects_and_removes_aliases_and_functions() {
+    let log = Log::default();
+    let runner = test_runner(&log);
+    block_on(runner.run_script(
+        r#"
+            alias hi="echo hi" quote="echo it's"
+            greet() {
+                local who="${1:-world}"
+                if test -n "$who" && test "$#" -gt 0; then
+                    echo "hello, $who"'!' > /dev/null
+                else
+                    echo nobody *.md 2>&1
+                fi
+                for name in a "b c"; do echo $name; done
+                case $who in
+                    w*|x) echo w;;
+                esac
+                count <<EOF
+$who is ${#who} long, costs \$5
+EOF
+            }
+            alias
+            type hi echo alias missing
+            which hi echo
+            declare -F
+            unalias hi
+            alias hi
+            "#,
+    ))
+    .unwrap();
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "alias hi='echo hi'",
+            r"alias quote='echo it'\''s'",
+            "hi is aliased to 'echo hi'",
+            "echo is a registered command",
+            "alias is a shell builtin",
+            "err: type: missing: not found",
+            "hi: aliased to echo hi",
+            "echo",
+            "declare -f greet",
+            "err: alias: hi: not found",
+        ]
+    );
+
+    let err = block_on(runner.run_script("unalias hi")).unwrap_err();
+    assert!(matches!(
+        err,
+        ShellCliError::Parse(ShellParseError::InvalidAlias { ref name, .. }) if name == "hi"
+    ));
+    block_on(runner.run_script("unalias -a")).unwrap();
+    assert!(block_on(runner.run_script("alias quote")).is_ok());
+    assert_eq!(runner.last_status(), ExitStatus::FAILURE);
+
+    // `declare -f` prints a definition that reads back into the same function.
+    let source = block_on(runner.capture("declare -f greet")).unwrap();
+    let parser = ShellParser::new();
+    parser.parse(&source).unwrap();
+    assert_eq!(parser.functions(), vec!["greet"]);
+    assert_eq!(parser.function_source("greet").unwrap() + "\n", source);
+    assert_eq!(
+        source,
+        r#"greet() {
+    local who="${1:-world}"
+    if test -n "$who" && test "$#" -gt 0; then
+        echo "hello, $who!" > /dev/null
+    else
+        echo nobody *.md 2>&1
+    fi
+    for name in a 'b c'; do
+        echo $name
+    done
+    case $who in
+        w* | x)
+            echo w
+            ;;
+    esac
+    count <<EOF
+$who is ${#who} long, costs \$5
+EOF
+}
+"#
+    );
+}
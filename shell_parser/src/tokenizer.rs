@@ -1,10 +1,72 @@
+use crate::ast::{Span, Word};
 use crate::error::ShellParseError;
+use crate::redirect::{RedirectOperator, STDIN, STDOUT};
 use crate::separator::Separator;
+use crate::word::{parse_backtick, parse_parameter, push_literal, WordPart};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Token {
+    /// Source text of the token with quotes and escapes removed.
     pub value: String,
     pub position: usize,
+    /// Byte offset just past the token in the source.
+    pub end: usize,
+    /// Literal and expandable pieces making up the token.
+    pub parts: Vec<WordPart>,
+    /// Set when the token is a redirection operator rather than a word.
+    pub redirect: Option<RedirectOperator>,
+}
+
+impl Token {
+    /// Build a token whose whole value is literal text.
+    pub fn literal(value: impl Into<String>, position: usize) -> Self {
+        let value = value.into();
+        Self {
+            parts: vec![WordPart::Literal(value.clone())],
+            value,
+            position,
+            end: position,
+            redirect: None,
+        }
+    }
+
+    /// Build a redirection operator token such as `>>` or `2>&1`.
+    pub fn redirect(operator: RedirectOperator, position: usize) -> Self {
+        Self {
+            value: operator.to_string(),
+            position,
+            end: position,
+            parts: Vec::new(),
+            redirect: Some(operator),
+        }
+    }
+
+    /// Bytes of the source the token was read from.
+    pub fn span(&self) -> Span {
+        Span::new(self.position, self.end)
+    }
+}
+
+impl From<Word> for Token {
+    fn from(word: Word) -> Self {
+        Self {
+            value: word.text,
+            position: word.span.start,
+            end: word.span.end,
+            parts: word.parts,
+            redirect: None,
+        }
+    }
+}
+
+impl From<Token> for Word {
+    fn from(token: Token) -> Self {
+        Self {
+            text: token.value,
+            parts: token.parts,
+            span: Span::new(token.position, token.end),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -13,6 +75,17 @@ pub(crate) struct CommandTokens {
     pub separator: Option<Separator>,
 }
 
+/// `<<` whose body starts on the line after the command.
+struct PendingHereDoc {
+    /// Index of the command, and of the delimiter word within it.
+    command: usize,
+    token: usize,
+    strip_tabs: bool,
+    /// Bytes of the operator, for errors.
+    position: usize,
+    end: usize,
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum Mode {
     Normal,
@@ -24,9 +97,11 @@ pub(crate) fn tokenize(input: &str) -> Result<Vec<CommandTokens>, ShellParseErro
     let mut commands: Vec<CommandTokens> = Vec::new();
     let mut current_command: Vec<Token> = Vec::new();
     let mut current_token = String::new();
+    let mut current_parts: Vec<WordPart> = Vec::new();
     let mut token_start: Option<usize> = None;
     let mut mode = Mode::Normal;
     let mut quote_start = 0;
+    let mut here_docs: Vec<PendingHereDoc> = Vec::new();
 
     let mut iter = input.char_indices().peekable();
     while let Some((idx, ch)) = iter.next() {
@@ -34,12 +109,16 @@ pub(crate) fn tokenize(input: &str) -> Result<Vec<CommandTokens>, ShellParseErro
             Mode::Normal => match ch {
                 '\\' => {
                     let Some((_, escaped)) = iter.next() else {
-                        return Err(ShellParseError::TrailingEscape { position: idx });
+                        return Err(ShellParseError::TrailingEscape {
+                            position: idx,
+                            end: idx + 1,
+                        });
                     };
                     if token_start.is_none() {
                         token_start = Some(idx);
                     }
                     current_token.push(escaped);
+                    push_literal(&mut current_parts, escaped);
                 }
                 '\'' => {
                     mode = Mode::SingleQuote;
@@ -56,7 +135,7 @@ pub(crate) fn tokenize(input: &str) -> Result<Vec<CommandTokens>, ShellParseErro
                     }
                 }
                 '#' => {
-                    if current_token.is_empty() {
+                    if current_token.is_empty() && current_parts.is_empty() {
                         while let Some((_, next)) = iter.peek() {
                             if *next == '\n' {
                                 break;
@@ -68,67 +147,181 @@ pub(crate) fn tokenize(input: &str) -> Result<Vec<CommandTokens>, ShellParseErro
                             token_start = Some(idx);
                         }
                         current_token.push('#');
+                        push_literal(&mut current_parts, '#');
+                    }
+                }
+                '$' => {
+                    if token_start.is_none() {
+                        token_start = Some(idx);
+                    }
+                    push_dollar(
+                        input,
+                        &mut iter,
+                        idx,
+                        false,
+                        &mut current_token,
+                        &mut current_parts,
+                    )?;
+                }
+                '`' => {
+                    if token_start.is_none() {
+                        token_start = Some(idx);
                     }
+                    let (part, end) = parse_backtick(&mut iter, idx, false, 0)?;
+                    current_token.push_str(&input[idx..end]);
+                    current_parts.push(part);
                 }
                 '&' => {
-                    if let Some((_, '&')) = iter.peek() {
+                    push_token(
+                        &mut current_command,
+                        &mut current_token,
+                        &mut current_parts,
+                        &mut token_start,
+                        idx,
+                    );
+                    let separator = if let Some((_, '&')) = iter.peek() {
                         iter.next(); // consume the second '&'
-                        push_token(&mut current_command, &mut current_token, &mut token_start);
-                        push_command(&mut commands, &mut current_command, Some(Separator::And));
+                        Separator::And
                     } else {
-                        if token_start.is_none() {
-                            token_start = Some(idx);
-                        }
-                        current_token.push(ch);
-                    }
+                        Separator::Background
+                    };
+                    push_command(&mut commands, &mut current_command, Some(separator));
                 }
                 ';' | '\n' | '|' => {
-                    push_token(&mut current_command, &mut current_token, &mut token_start);
+                    push_token(
+                        &mut current_command,
+                        &mut current_token,
+                        &mut current_parts,
+                        &mut token_start,
+                        idx,
+                    );
                     let separator = match ch {
+                        ';' if matches!(iter.peek(), Some((_, ';'))) => {
+                            iter.next(); // consume the second ';'
+                            Some(Separator::DoubleSemicolon)
+                        }
                         ';' => Some(Separator::Semicolon),
                         '\n' => Some(Separator::Newline),
+                        '|' if matches!(iter.peek(), Some((_, '|'))) => {
+                            iter.next(); // consume the second '|'
+                            Some(Separator::Or)
+                        }
                         '|' => Some(Separator::Pipe),
                         _ => None,
                     };
                     push_command(&mut commands, &mut current_command, separator);
+                    if ch == '\n' {
+                        read_here_docs(input, &mut iter, &mut commands, &mut here_docs)?;
+                    }
+                }
+                '>' | '<' => {
+                    let fd = take_fd(input, idx, &mut current_token, &mut current_parts);
+                    let position = token_start.unwrap_or(idx);
+                    push_token(
+                        &mut current_command,
+                        &mut current_token,
+                        &mut current_parts,
+                        &mut token_start,
+                        idx,
+                    );
+                    let operator = scan_redirect(ch, fd, &mut iter, position)?;
+                    let end = iter.peek().map_or(input.len(), |(next, _)| *next);
+                    // Keep the operator as written, so errors quote `>` rather than `1>`.
+                    current_command.push(Token {
+                        value: input[position..end].to_string(),
+                        end,
+                        ..Token::redirect(operator, position)
+                    });
+                    if let RedirectOperator::HereDoc { strip_tabs, .. } = operator {
+                        here_docs.push(PendingHereDoc {
+                            command: commands.len(),
+                            token: current_command.len(),
+                            strip_tabs,
+                            position,
+                            end,
+                        });
+                    }
                 }
                 '{' | '}' => {
-                    push_token(&mut current_command, &mut current_token, &mut token_start);
+                    push_token(
+                        &mut current_command,
+                        &mut current_token,
+                        &mut current_parts,
+                        &mut token_start,
+                        idx,
+                    );
                     current_command.push(Token {
-                        value: ch.to_string(),
-                        position: idx,
+                        end: idx + 1,
+                        ..Token::literal(ch, idx)
                     });
                 }
+                '*' | '?' | '[' | ']' => {
+                    if token_start.is_none() {
+                        token_start = Some(idx);
+                    }
+                    current_token.push(ch);
+                    current_parts.push(WordPart::Glob(ch));
+                }
                 c if c.is_whitespace() => {
-                    push_token(&mut current_command, &mut current_token, &mut token_start);
+                    push_token(
+                        &mut current_command,
+                        &mut current_token,
+                        &mut current_parts,
+                        &mut token_start,
+                        idx,
+                    );
                 }
                 _ => {
                     if token_start.is_none() {
                         token_start = Some(idx);
                     }
                     current_token.push(ch);
+                    push_literal(&mut current_parts, ch);
                 }
             },
             Mode::SingleQuote => match ch {
                 '\'' => {
                     mode = Mode::Normal;
+                    keep_empty_quotes(&mut current_parts);
                 }
                 _ => {
                     current_token.push(ch);
+                    push_literal(&mut current_parts, ch);
                 }
             },
             Mode::DoubleQuote => match ch {
                 '"' => {
                     mode = Mode::Normal;
+                    keep_empty_quotes(&mut current_parts);
                 }
                 '\\' => {
                     let Some((_, escaped)) = iter.next() else {
-                        return Err(ShellParseError::TrailingEscape { position: idx });
+                        return Err(ShellParseError::TrailingEscape {
+                            position: idx,
+                            end: idx + 1,
+                        });
                     };
                     current_token.push(escaped);
+                    push_literal(&mut current_parts, escaped);
+                }
+                '$' => {
+                    push_dollar(
+                        input,
+                        &mut iter,
+                        idx,
+                        true,
+                        &mut current_token,
+                        &mut current_parts,
+                    )?;
+                }
+                '`' => {
+                    let (part, end) = parse_backtick(&mut iter, idx, true, 0)?;
+                    current_token.push_str(&input[idx..end]);
+                    current_parts.push(part);
                 }
                 _ => {
                     current_token.push(ch);
+                    push_literal(&mut current_parts, ch);
                 }
             },
         }
@@ -136,13 +329,21 @@ pub(crate) fn tokenize(input: &str) -> Result<Vec<CommandTokens>, ShellParseErro
 
     match mode {
         Mode::Normal => {
-            push_token(&mut current_command, &mut current_token, &mut token_start);
+            push_token(
+                &mut current_command,
+                &mut current_token,
+                &mut current_parts,
+                &mut token_start,
+                input.len(),
+            );
             push_command(&mut commands, &mut current_command, None);
+            read_here_docs(input, &mut iter, &mut commands, &mut here_docs)?;
         }
         Mode::SingleQuote | Mode::DoubleQuote => {
             return Err(ShellParseError::UnterminatedQuote {
                 quote: if mode == Mode::SingleQuote { '\'' } else { '"' },
                 position: quote_start,
+                end: input.len(),
             });
         }
     }
@@ -150,18 +351,231 @@ pub(crate) fn tokenize(input: &str) -> Result<Vec<CommandTokens>, ShellParseErro
     Ok(commands)
 }
 
+fn push_dollar(
+    input: &str,
+    iter: &mut crate::word::Chars<'_>,
+    idx: usize,
+    quoted: bool,
+    current_token: &mut String,
+    current_parts: &mut Vec<WordPart>,
+) -> Result<(), ShellParseError> {
+    match parse_parameter(input, iter, idx, quoted, 0)? {
+        Some((part, end)) => {
+            current_token.push_str(&input[idx..end]);
+            current_parts.push(part);
+        }
+        None => {
+            current_token.push('$');
+            push_literal(current_parts, '$');
+        }
+    }
+    Ok(())
+}
+
+/// Take the file descriptor written right before a redirection operator.
+///
+/// Only an unquoted run of digits directly touching the operator counts, so
+/// `2>err` redirects stderr while `a2>out` and `"2">out` pass a word.
+fn take_fd(
+    input: &str,
+    idx: usize,
+    current_token: &mut String,
+    current_parts: &mut Vec<WordPart>,
+) -> Option<u32> {
+    if current_token.is_empty()
+        || !current_token.chars().all(|ch| ch.is_ascii_digit())
+        || !input[..idx].ends_with(current_token.as_str())
+        || current_parts.len() != 1
+    {
+        return None;
+    }
+    let start = idx - current_token.len();
+    if input[..start]
+        .chars()
+        .next_back()
+        .is_some_and(|ch| !ch.is_whitespace() && !matches!(ch, ';' | '|' | '&'))
+    {
+        return None;
+    }
+    let fd = current_token.parse().ok()?;
+    current_token.clear();
+    current_parts.clear();
+    Some(fd)
+}
+
+/// Read the rest of a redirection operator whose first character was `ch`.
+fn scan_redirect(
+    ch: char,
+    fd: Option<u32>,
+    iter: &mut crate::word::Chars<'_>,
+    position: usize,
+) -> Result<RedirectOperator, ShellParseError> {
+    if ch == '<' {
+        let fd = fd.unwrap_or(STDIN);
+        if !matches!(iter.peek(), Some((_, '<'))) {
+            return Ok(RedirectOperator::Input { fd });
+        }
+        iter.next();
+        let strip_tabs = matches!(iter.peek(), Some((_, '-')));
+        if strip_tabs {
+            iter.next();
+        }
+        return Ok(RedirectOperator::HereDoc { fd, strip_tabs });
+    }
+
+    let fd = fd.unwrap_or(STDOUT);
+    match iter.peek().copied() {
+        Some((_, '>')) => {
+            iter.next();
+            Ok(RedirectOperator::Output { fd, append: true })
+        }
+        Some((amp, '&')) => {
+            iter.next();
+            let mut digits = String::new();
+            let mut end = amp + 1;
+            while let Some((idx, next)) = iter.peek().copied() {
+                if !next.is_ascii_digit() {
+                    break;
+                }
+                digits.push(next);
+                end = idx + 1;
+                iter.next();
+            }
+            let target = digits
+                .parse()
+                .map_err(|_| ShellParseError::InvalidRedirect {
+                    message: "expected a file descriptor after '>&'".into(),
+                    position,
+                    end,
+                })?;
+            Ok(RedirectOperator::Duplicate { fd, target })
+        }
+        _ => Ok(RedirectOperator::Output { fd, append: false }),
+    }
+}
+
+/// Read the bodies of the here-documents opened on the line that just ended.
+///
+/// Each body runs up to a line holding only its delimiter (after leading tabs
+/// for `<<-`) and replaces the delimiter word, so the redirect's target is the
+/// body. Expansions in the body are kept unless the delimiter was quoted.
+fn read_here_docs(
+    input: &str,
+    iter: &mut crate::word::Chars<'_>,
+    commands: &mut [CommandTokens],
+    here_docs: &mut Vec<PendingHereDoc>,
+) -> Result<(), ShellParseError> {
+    for here_doc in here_docs.drain(..) {
+        let Some(delimiter) = commands
+            .get_mut(here_doc.command)
+            .and_then(|command| command.tokens.get_mut(here_doc.token))
+            .filter(|token| token.redirect.is_none())
+        else {
+            return Err(ShellParseError::InvalidRedirect {
+                message: "expected a delimiter after '<<'".into(),
+                position: here_doc.position,
+                end: here_doc.end,
+            });
+        };
+        let quoted = input[delimiter.position..delimiter.end].contains(['\'', '"', '\\']);
+
+        let start = iter.peek().map_or(input.len(), |(idx, _)| *idx);
+        let mut body = String::new();
+        let mut end = None;
+        while let Some((line_start, _)) = iter.peek().copied() {
+            let mut line_end = input.len();
+            for (idx, ch) in iter.by_ref() {
+                if ch == '\n' {
+                    line_end = idx;
+                    break;
+                }
+            }
+            let mut line = &input[line_start..line_end];
+            if here_doc.strip_tabs {
+                line = line.trim_start_matches('\t');
+            }
+            if line == delimiter.value {
+                end = Some(line_start);
+                break;
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+        let Some(end) = end else {
+            return Err(ShellParseError::InvalidRedirect {
+                message: format!("here-document is missing its closing '{}'", delimiter.value),
+                position: here_doc.position,
+                end: input.len(),
+            });
+        };
+
+        delimiter.parts = if quoted || body.is_empty() {
+            vec![WordPart::Literal(body.clone())]
+        } else {
+            here_doc_parts(&body, start)?
+        };
+        delimiter.value = body;
+        delimiter.position = start;
+        delimiter.end = end;
+    }
+    Ok(())
+}
+
+/// Split an unquoted here-document body into literal text and expansions.
+///
+/// As inside double quotes, `\` only escapes `$`, `` ` ``, `\` and a newline.
+fn here_doc_parts(body: &str, offset: usize) -> Result<Vec<WordPart>, ShellParseError> {
+    let mut parts = Vec::new();
+    let mut iter = body.char_indices().peekable();
+    while let Some((idx, ch)) = iter.next() {
+        match ch {
+            '\\' => match iter.peek().copied() {
+                Some((_, '\n')) => {
+                    iter.next();
+                }
+                Some((_, next)) if matches!(next, '$' | '`' | '\\') => {
+                    iter.next();
+                    push_literal(&mut parts, next);
+                }
+                _ => push_literal(&mut parts, ch),
+            },
+            '$' => match parse_parameter(body, &mut iter, idx, true, offset)? {
+                Some((part, _)) => parts.push(part),
+                None => push_literal(&mut parts, '$'),
+            },
+            '`' => parts.push(parse_backtick(&mut iter, idx, true, offset)?.0),
+            _ => push_literal(&mut parts, ch),
+        }
+    }
+    Ok(parts)
+}
+
+/// Give a word that so far is only empty quotes, as in `''` or `""`, a part so
+/// it still expands to one empty argument.
+fn keep_empty_quotes(parts: &mut Vec<WordPart>) {
+    if parts.is_empty() {
+        parts.push(WordPart::Literal(String::new()));
+    }
+}
+
 fn push_token(
     current_command: &mut Vec<Token>,
     current_token: &mut String,
+    current_parts: &mut Vec<WordPart>,
     token_start: &mut Option<usize>,
+    end: usize,
 ) {
-    if !current_token.is_empty() {
+    if !current_token.is_empty() || !current_parts.is_empty() {
         let position = token_start.take().unwrap_or(0);
         current_command.push(Token {
             value: std::mem::take(current_token),
             position,
+            end,
+            parts: std::mem::take(current_parts),
+            redirect: None,
         });
     } else {
+        current_parts.clear();
         *token_start = None;
     }
 }
@@ -176,5 +590,18 @@ fn push_command(
             tokens: std::mem::take(current_command),
             separator,
         });
+    } else if separator == Some(Separator::DoubleSemicolon) {
+        // `;;` on a line of its own ends the `case` arm written above it. After
+        // `&` it stands alone, so the command keeps running in the background.
+        match commands.last_mut() {
+            Some(previous) if previous.separator == Some(Separator::Background) => {
+                commands.push(CommandTokens {
+                    tokens: Vec::new(),
+                    separator,
+                });
+            }
+            Some(previous) => previous.separator = separator,
+            None => {}
+        }
     }
 }
@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Shell variables assigned by scripts (`NAME=value`, `export NAME=value`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    /// Create an empty variable store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up the value of a variable.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| value.as_str())
+    }
+
+    /// Whether the variable is set (possibly to an empty string).
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Assign a value, replacing any previous one.
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    /// Remove a variable, returning its previous value.
+    pub fn unset(&mut self, name: &str) -> Option<String> {
+        self.values.remove(name)
+    }
+
+    /// All variables sorted by name.
+    pub fn entries(&self) -> Vec<(&str, &str)> {
+        let mut entries: Vec<(&str, &str)> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    /// Whether `name` can be assigned: a letter or `_` followed by letters, digits or `_`.
+    pub fn is_valid_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        match chars.next() {
+            Some(first) if first.is_ascii_alphabetic() || first == '_' => {}
+            _ => return false,
+        }
+        chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    }
+}
+
+/// Positional parameters and `local` variables of one function call or script run.
+///
+/// Frames link to the frame of their caller, so a function sees the locals of
+/// the functions that called it, as in other shells.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct CallFrame {
+    args: RefCell<Vec<String>>,
+    /// Variables declared with `local`; `None` while declared but unset.
+    locals: RefCell<HashMap<String, Option<String>>>,
+    caller: Option<Rc<CallFrame>>,
+}
+
+impl CallFrame {
+    pub(crate) fn new(args: Vec<String>, caller: Option<Rc<CallFrame>>) -> Self {
+        Self {
+            args: RefCell::new(args),
+            locals: RefCell::new(HashMap::new()),
+            caller,
+        }
+    }
+
+    /// `$1`, `$2`, ... in order.
+    pub(crate) fn args(&self) -> Vec<String> {
+        self.args.borrow().clone()
+    }
+
+    /// Drop the first `count` positional parameters; `false` if there are fewer.
+    pub(crate) fn shift(&self, count: usize) -> bool {
+        let mut args = self.args.borrow_mut();
+        if count > args.len() {
+            return false;
+        }
+        args.drain(..count);
+        true
+    }
+
+    /// Make `name` local to this call, unset until assigned.
+    pub(crate) fn declare_local(&self, name: &str) {
+        self.locals
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_insert(None);
+    }
+
+    /// Innermost frame in the call chain where `name` is local.
+    fn owner(&self, name: &str) -> Option<&CallFrame> {
+        if self.locals.borrow().contains_key(name) {
+            return Some(self);
+        }
+        self.caller.as_deref().and_then(|caller| caller.owner(name))
+    }
+}
+
+/// Value of parameter `name` as seen from `frame`: positional and special
+/// parameters (`$1`, `$#`, `$@`, `$*`), then locals, then shell variables.
+pub(crate) fn lookup(
+    variables: &RefCell<Variables>,
+    frame: Option<&CallFrame>,
+    name: &str,
+) -> Option<String> {
+    let args = || frame.map(CallFrame::args).unwrap_or_default();
+    match name {
+        "#" => return Some(args().len().to_string()),
+        "@" | "*" => return Some(args().join(" ")),
+        _ => {}
+    }
+    if let Some(index) = positional_index(name) {
+        return args().get(index - 1).cloned();
+    }
+    match frame.and_then(|frame| frame.owner(name)) {
+        Some(owner) => owner.locals.borrow().get(name).cloned().flatten(),
+        None => variables.borrow().get(name).map(str::to_string),
+    }
+}
+
+/// Assign `name`, updating the innermost local of that name if there is one.
+pub(crate) fn assign(
+    variables: &RefCell<Variables>,
+    frame: Option<&CallFrame>,
+    name: &str,
+    value: &str,
+) {
+    match frame.and_then(|frame| frame.owner(name)) {
+        Some(owner) => {
+            owner
+                .locals
+                .borrow_mut()
+                .insert(name.to_string(), Some(value.to_string()));
+        }
+        None => variables.borrow_mut().set(name, value),
+    }
+}
+
+/// Unset `name`, leaving a local of that name declared so it stays local.
+pub(crate) fn remove(variables: &RefCell<Variables>, frame: Option<&CallFrame>, name: &str) {
+    match frame.and_then(|frame| frame.owner(name)) {
+        Some(owner) => {
+            owner.locals.borrow_mut().insert(name.to_string(), None);
+        }
+        None => {
+            variables.borrow_mut().unset(name);
+        }
+    }
+}
+
+/// Index of a positional parameter name such as `1` or `10`.
+pub(crate) fn positional_index(name: &str) -> Option<usize> {
+    if name.is_empty() || !name.chars().all(|ch| ch.is_ascii_digit()) {
+        return None;
+    }
+    name.parse().ok().filter(|index| *index > 0)
+}
@@ -0,0 +1,396 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::error::ShellParseError;
+
+/// Piece of a shell word. Quoting is kept on each part so expansion can decide
+/// whether the result is subject to field splitting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordPart {
+    /// Text taken verbatim (including the contents of quotes and escapes).
+    Literal(String),
+    /// Unquoted `*`, `?`, `[` or `]`, which make the word a pathname pattern.
+    Glob(char),
+    /// `$NAME` or `${...}`; `quoted` is set when it appeared inside double quotes.
+    Parameter {
+        expansion: ParameterExpansion,
+        quoted: bool,
+    },
+    /// `$(script)` or `` `script` ``, replaced by the script's output when expanded.
+    CommandSubstitution { script: String, quoted: bool },
+}
+
+/// Parameter reference such as `$NAME`, `${NAME:-default}` or `${#NAME}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterExpansion {
+    pub name: String,
+    pub op: ParameterOp,
+}
+
+/// Operator applied to a parameter inside `${...}`.
+///
+/// `check_empty` is set for the colon forms (`:-`, `:=`, `:+`), which treat an
+/// empty value like an unset one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParameterOp {
+    /// `$NAME` or `${NAME}`.
+    Value,
+    /// `${#NAME}`: the length of the value.
+    Length,
+    /// `${NAME-word}` / `${NAME:-word}`.
+    Default {
+        word: Vec<WordPart>,
+        check_empty: bool,
+    },
+    /// `${NAME=word}` / `${NAME:=word}`.
+    Assign {
+        word: Vec<WordPart>,
+        check_empty: bool,
+    },
+    /// `${NAME+word}` / `${NAME:+word}`.
+    Alternative {
+        word: Vec<WordPart>,
+        check_empty: bool,
+    },
+}
+
+/// Single-character special parameters recognised after `$`.
+const SPECIAL_PARAMETERS: &[char] = &['?', '#', '@', '*'];
+
+pub(crate) type Chars<'a> = Peekable<CharIndices<'a>>;
+
+pub(crate) fn push_literal(parts: &mut Vec<WordPart>, ch: char) {
+    if let Some(WordPart::Literal(text)) = parts.last_mut() {
+        text.push(ch);
+    } else {
+        parts.push(WordPart::Literal(ch.to_string()));
+    }
+}
+
+/// Parse the expansion that follows a `$` located at `dollar` in `input`.
+///
+/// The `$` itself must already be consumed from `iter`. Returns the parsed part
+/// and the byte offset just past it, or `None` when the `$` should stay literal.
+/// `offset` is added to positions reported in errors.
+pub(crate) fn parse_parameter(
+    input: &str,
+    iter: &mut Chars<'_>,
+    dollar: usize,
+    quoted: bool,
+    offset: usize,
+) -> Result<Option<(WordPart, usize)>, ShellParseError> {
+    let Some((idx, ch)) = iter.peek().copied() else {
+        return Ok(None);
+    };
+
+    if ch == '(' {
+        iter.next();
+        let end = scan_substitution(iter).ok_or(ShellParseError::UnterminatedSubstitution {
+            position: dollar + offset,
+            end: input.len() + offset,
+        })?;
+        let script = input[dollar + 2..end - 1].to_string();
+        return Ok(Some((
+            WordPart::CommandSubstitution { script, quoted },
+            end,
+        )));
+    }
+
+    let (expansion, end) = if ch == '{' {
+        iter.next();
+        let (inner, end) = scan_braced(input, iter, dollar, offset)?;
+        (parse_braced(inner, dollar + 2 + offset)?, end)
+    } else if is_name_start(ch) {
+        let mut end = idx;
+        while let Some((next_idx, next)) = iter.peek().copied() {
+            if !is_name_char(next) {
+                break;
+            }
+            end = next_idx + next.len_utf8();
+            iter.next();
+        }
+        (
+            ParameterExpansion {
+                name: input[idx..end].to_string(),
+                op: ParameterOp::Value,
+            },
+            end,
+        )
+    } else if ch.is_ascii_digit() || SPECIAL_PARAMETERS.contains(&ch) {
+        iter.next();
+        (
+            ParameterExpansion {
+                name: ch.to_string(),
+                op: ParameterOp::Value,
+            },
+            idx + ch.len_utf8(),
+        )
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some((WordPart::Parameter { expansion, quoted }, end)))
+}
+
+/// Parse a backtick command substitution whose opening `` ` `` at `start` was consumed.
+///
+/// Inside backticks `\``, `\\` and `\$` stand for the escaped character. Returns
+/// the part and the byte offset just past the closing backtick.
+pub(crate) fn parse_backtick(
+    iter: &mut Chars<'_>,
+    start: usize,
+    quoted: bool,
+    offset: usize,
+) -> Result<(WordPart, usize), ShellParseError> {
+    let mut script = String::new();
+    // Where the input ran out, for an unterminated backtick.
+    let mut end = start + 1;
+    while let Some((idx, ch)) = iter.next() {
+        end = idx + ch.len_utf8();
+        match ch {
+            '`' => return Ok((WordPart::CommandSubstitution { script, quoted }, idx + 1)),
+            '\\' => match iter.peek().copied() {
+                Some((next_idx, next)) if matches!(next, '`' | '\\' | '$') => {
+                    iter.next();
+                    end = next_idx + next.len_utf8();
+                    script.push(next);
+                }
+                _ => script.push(ch),
+            },
+            _ => script.push(ch),
+        }
+    }
+
+    Err(ShellParseError::UnterminatedQuote {
+        quote: '`',
+        position: start + offset,
+        end: end + offset,
+    })
+}
+
+/// Consume the body of `$(...)` (the `(` already consumed), returning the byte
+/// offset just past the closing `)`.
+fn scan_substitution(iter: &mut Chars<'_>) -> Option<usize> {
+    let mut depth = 1;
+    while let Some((idx, ch)) = iter.next() {
+        match ch {
+            '\\' => {
+                iter.next();
+            }
+            '\'' => skip_until(iter, '\'')?,
+            '`' => skip_until(iter, '`')?,
+            '"' => scan_double_quoted(iter)?,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn scan_double_quoted(iter: &mut Chars<'_>) -> Option<()> {
+    while let Some((_, ch)) = iter.next() {
+        match ch {
+            '\\' => {
+                iter.next();
+            }
+            '"' => return Some(()),
+            '$' if matches!(iter.peek(), Some((_, '('))) => {
+                iter.next();
+                scan_substitution(iter)?;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn skip_until(iter: &mut Chars<'_>, end: char) -> Option<()> {
+    iter.by_ref().find(|(_, ch)| *ch == end).map(|_| ())
+}
+
+/// Consume a `${...}` body (the `{` already consumed) and return its inner text.
+fn scan_braced<'a>(
+    input: &'a str,
+    iter: &mut Chars<'_>,
+    dollar: usize,
+    offset: usize,
+) -> Result<(&'a str, usize), ShellParseError> {
+    let start = dollar + 2;
+    let mut depth = 1;
+    let mut previous = '{';
+
+    while let Some((idx, ch)) = iter.next() {
+        match ch {
+            '\\' => {
+                iter.next();
+                previous = ' ';
+                continue;
+            }
+            '\'' => {
+                for (_, quoted) in iter.by_ref() {
+                    if quoted == '\'' {
+                        break;
+                    }
+                }
+            }
+            '{' if previous == '$' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((&input[start..idx], idx + 1));
+                }
+            }
+            _ => {}
+        }
+        previous = ch;
+    }
+
+    Err(ShellParseError::BadSubstitution {
+        expression: input[dollar..].to_string(),
+        position: dollar + offset,
+        end: input.len() + offset,
+    })
+}
+
+fn parse_braced(inner: &str, position: usize) -> Result<ParameterExpansion, ShellParseError> {
+    let bad_substitution = || ShellParseError::BadSubstitution {
+        expression: format!("${{{inner}}}"),
+        position: position.saturating_sub(2),
+        end: position + inner.len() + 1,
+    };
+
+    if let Some(name) = inner.strip_prefix('#') {
+        if !name.is_empty() {
+            if parameter_name_len(name) != Some(name.len()) {
+                return Err(bad_substitution());
+            }
+            return Ok(ParameterExpansion {
+                name: name.to_string(),
+                op: ParameterOp::Length,
+            });
+        }
+    }
+
+    let name_len = parameter_name_len(inner).ok_or_else(bad_substitution)?;
+    let name = inner[..name_len].to_string();
+    let rest = &inner[name_len..];
+    if rest.is_empty() {
+        return Ok(ParameterExpansion {
+            name,
+            op: ParameterOp::Value,
+        });
+    }
+
+    let (check_empty, rest) = match rest.strip_prefix(':') {
+        Some(stripped) => (true, stripped),
+        None => (false, rest),
+    };
+    let mut chars = rest.chars();
+    let operator = chars.next().ok_or_else(bad_substitution)?;
+    let word_offset = position + name_len + usize::from(check_empty) + 1;
+    let word = parse_word(chars.as_str(), word_offset)?;
+    let op = match operator {
+        '-' => ParameterOp::Default { word, check_empty },
+        '=' => ParameterOp::Assign { word, check_empty },
+        '+' => ParameterOp::Alternative { word, check_empty },
+        _ => return Err(bad_substitution()),
+    };
+
+    Ok(ParameterExpansion { name, op })
+}
+
+/// Parse the word used by `${NAME:-word}` style operators.
+fn parse_word(text: &str, offset: usize) -> Result<Vec<WordPart>, ShellParseError> {
+    let mut parts = Vec::new();
+    let mut iter = text.char_indices().peekable();
+    let mut in_double = false;
+    let mut quote_start = 0;
+
+    while let Some((idx, ch)) = iter.next() {
+        match ch {
+            '\\' => {
+                let Some((_, escaped)) = iter.next() else {
+                    return Err(ShellParseError::TrailingEscape {
+                        position: idx + offset,
+                        end: idx + offset + 1,
+                    });
+                };
+                push_literal(&mut parts, escaped);
+            }
+            '\'' if !in_double => {
+                let mut closed = false;
+                for (_, quoted) in iter.by_ref() {
+                    if quoted == '\'' {
+                        closed = true;
+                        break;
+                    }
+                    push_literal(&mut parts, quoted);
+                }
+                if !closed {
+                    return Err(ShellParseError::UnterminatedQuote {
+                        quote: '\'',
+                        position: idx + offset,
+                        end: text.len() + offset,
+                    });
+                }
+            }
+            '"' => {
+                in_double = !in_double;
+                quote_start = idx;
+            }
+            '$' => match parse_parameter(text, &mut iter, idx, in_double, offset)? {
+                Some((part, _)) => parts.push(part),
+                None => push_literal(&mut parts, '$'),
+            },
+            '`' => parts.push(parse_backtick(&mut iter, idx, in_double, offset)?.0),
+            _ => push_literal(&mut parts, ch),
+        }
+    }
+
+    if in_double {
+        return Err(ShellParseError::UnterminatedQuote {
+            quote: '"',
+            position: quote_start + offset,
+            end: text.len() + offset,
+        });
+    }
+
+    Ok(parts)
+}
+
+fn parameter_name_len(text: &str) -> Option<usize> {
+    let first = text.chars().next()?;
+    if is_name_start(first) {
+        Some(
+            text.char_indices()
+                .find(|(_, ch)| !is_name_char(*ch))
+                .map(|(idx, _)| idx)
+                .unwrap_or(text.len()),
+        )
+    } else if first.is_ascii_digit() {
+        Some(
+            text.char_indices()
+                .find(|(_, ch)| !ch.is_ascii_digit())
+                .map(|(idx, _)| idx)
+                .unwrap_or(text.len()),
+        )
+    } else if SPECIAL_PARAMETERS.contains(&first) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+fn is_name_start(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || ch == '_'
+}
+
+fn is_name_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
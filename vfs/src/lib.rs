@@ -40,8 +40,7 @@ pub fn generate_metadata(root: &Path) -> std::io::Result<Entry> {
 
 pub fn generate_metadata_json(root: &Path) -> std::io::Result<String> {
     let entry = generate_metadata(root)?;
-    let json = serde_json::to_string_pretty(&entry)
-        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    let json = serde_json::to_string_pretty(&entry).map_err(std::io::Error::other)?;
     Ok(json)
 }
 